@@ -204,7 +204,8 @@ function sidebarGuide() {
       text: 'Input Devices',
       items: [
         { text: 'Rotary Encoder', link: 'input_devices/encoder' },
-        { text: 'Joystick', link: 'input_devices/joystick' }
+        { text: 'Joystick', link: 'input_devices/joystick' },
+        { text: 'Pointing Device', link: 'input_devices/pointing_device' }
       ]
     },
     { text: 'Controller', link: 'features/controller' },
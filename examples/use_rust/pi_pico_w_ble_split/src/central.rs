@@ -107,6 +107,8 @@ async fn main(spawner: Spawner) {
         manufacturer: "Haobo",
         product_name: "RMK PicoW Split",
         serial_number: "vial:f64c2b3c:000001",
+        poll_interval_ms: 1,
+        remote_wakeup: true,
     };
 
     let vial_config = VialConfig::new(VIAL_KEYBOARD_ID, VIAL_KEYBOARD_DEF, &[(0, 0), (1, 1)]);
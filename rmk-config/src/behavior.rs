@@ -8,12 +8,16 @@ impl crate::KeyboardTomlConfig {
             Some(mut behavior) => {
                 behavior.tri_layer = match behavior.tri_layer {
                     Some(tri_layer) => {
-                        if tri_layer.upper >= layout.layers {
-                            return Err("keyboard.toml: Tri layer upper is larger than [layout.layers]".to_string());
-                        } else if tri_layer.lower >= layout.layers {
-                            return Err("keyboard.toml: Tri layer lower is larger than [layout.layers]".to_string());
-                        } else if tri_layer.adjust >= layout.layers {
-                            return Err("keyboard.toml: Tri layer adjust is larger than [layout.layers]".to_string());
+                        for rule in tri_layer.rules() {
+                            if rule.upper >= layout.layers {
+                                return Err("keyboard.toml: Tri layer upper is larger than [layout.layers]".to_string());
+                            } else if rule.lower >= layout.layers {
+                                return Err("keyboard.toml: Tri layer lower is larger than [layout.layers]".to_string());
+                            } else if rule.adjust >= layout.layers {
+                                return Err(
+                                    "keyboard.toml: Tri layer adjust is larger than [layout.layers]".to_string()
+                                );
+                            }
                         }
                         Some(tri_layer)
                     }
@@ -75,6 +79,12 @@ impl crate::KeyboardTomlConfig {
                         return Err("keyboard.toml: number of forks is greater than fork_max_num configured under [rmk] section".to_string());
                     }
                 }
+                behavior.key_override = behavior.key_override.or(default.key_override);
+                if let Some(key_override) = &behavior.key_override {
+                    if key_override.overrides.len() > self.rmk.key_override_max_num {
+                        return Err("keyboard.toml: number of key overrides is greater than key_override_max_num configured under [rmk] section".to_string());
+                    }
+                }
                 behavior.morse = behavior.morse.or(default.morse);
                 if let Some(morse) = &behavior.morse {
                     if morse.morses.len() > self.rmk.morse_max_num {
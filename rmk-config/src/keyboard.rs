@@ -15,6 +15,10 @@ pub struct Basic {
     pub product_name: String,
     /// Serial number
     pub serial_number: String,
+    /// Poll interval of the USB HID endpoints in ms
+    pub usb_poll_interval: u8,
+    /// Enable the USB remote wakeup
+    pub usb_remote_wakeup: bool,
 }
 
 impl Default for Basic {
@@ -26,6 +30,8 @@ impl Default for Basic {
             manufacturer: "RMK".to_string(),
             product_name: "RMK Keyboard".to_string(),
             serial_number: "vial:f64c2b3c:000001".to_string(),
+            usb_poll_interval: 1,
+            usb_remote_wakeup: true,
         }
     }
 }
@@ -41,6 +47,17 @@ impl KeyboardTomlConfig {
             manufacturer: keyboard.manufacturer.clone().unwrap_or(default.manufacturer),
             product_name: keyboard.product_name.clone().unwrap_or(default.product_name),
             serial_number: keyboard.serial_number.clone().unwrap_or(default.serial_number),
+            usb_poll_interval: keyboard
+                .usb_poll_interval
+                .as_ref()
+                .map(|interval| match interval.0 {
+                    1..=255 => interval.0 as u8,
+                    v => panic!(
+                        "❌ Parse `keyboard.toml` error: usb_poll_interval must be between 1ms and 255ms for full-speed USB, got {v}ms"
+                    ),
+                })
+                .unwrap_or(default.usb_poll_interval),
+            usb_remote_wakeup: keyboard.usb_remote_wakeup.unwrap_or(default.usb_remote_wakeup),
         }
     }
 
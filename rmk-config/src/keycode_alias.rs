@@ -251,6 +251,17 @@ pub static KEYCODE_ALIAS: Lazy<HashMap<&str, &str>> = Lazy::new(|| {
     add_alias!("RShift" = "r_shift", "rightshift", "right_shift", "rsft");
     add_alias!("RAlt" = "r_alt", "rightalt", "right_alt", "ropt", "algr");
     add_alias!("RGui" = "r_gui", "rightgui", "right_gui", "rcmd", "rwin");
+    add_alias!("SpaceCadetLCtrlParenthesisOpen" = "sc_lcpo", "lcpo");
+    add_alias!("SpaceCadetRCtrlParenthesisClose" = "sc_rcpc", "rcpc");
+    add_alias!("SpaceCadetLShiftParenthesisOpen" = "sc_lspo", "lspo");
+    add_alias!("SpaceCadetRShiftParenthesisClose" = "sc_rspc", "rspc");
+    add_alias!("SpaceCadetLAltParenthesisOpen" = "sc_lapo", "lapo");
+    add_alias!("SpaceCadetRAltParenthesisClose" = "sc_rapc", "rapc");
+    add_alias!("SpaceCadetRShiftEnter" = "sc_sent", "sftent");
+    add_alias!("LayerLock" = "layer_lock", "qk_llck");
+    add_alias!("PointingCpiCycle" = "pointing_cpi_cycle", "cpi_cycle");
+    add_alias!("PointingScrollMode" = "pointing_scroll_mode", "scroll_mode");
+    add_alias!("BypassToggle" = "bypass_toggle", "bypass");
 
     m
 });
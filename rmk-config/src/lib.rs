@@ -42,10 +42,21 @@ pub struct RmkConstantsConfig {
     /// Maximum number of keys pressed simultaneously in a combo
     #[serde_inline_default(4)]
     pub combo_max_length: usize,
+    /// Maximum number of leader key sequences keyboard can store
+    #[serde_inline_default(8)]
+    #[serde(deserialize_with = "check_leader_max_num")]
+    pub leader_max_num: usize,
+    /// Maximum number of keys in a leader key sequence
+    #[serde_inline_default(4)]
+    pub leader_max_length: usize,
     /// Maximum number of forks for conditional key actions
     #[serde_inline_default(8)]
     #[serde(deserialize_with = "check_fork_max_num")]
     pub fork_max_num: usize,
+    /// Maximum number of key overrides
+    #[serde_inline_default(16)]
+    #[serde(deserialize_with = "check_key_override_max_num")]
+    pub key_override_max_num: usize,
     /// Maximum number of morses keyboard can store
     #[serde_inline_default(8)]
     #[serde(deserialize_with = "check_morse_max_num")]
@@ -55,8 +66,16 @@ pub struct RmkConstantsConfig {
     #[serde(deserialize_with = "check_max_patterns_per_key")]
     pub max_patterns_per_key: usize,
     /// Macro space size in bytes for storing sequences
-    #[serde_inline_default(256)]
+    #[serde_inline_default(512)]
+    #[serde(deserialize_with = "check_macro_space_size")]
     pub macro_space_size: usize,
+    /// Number of macro slots reported to Vial
+    #[serde_inline_default(32)]
+    #[serde(deserialize_with = "check_macro_max_num")]
+    pub macro_max_num: usize,
+    /// Maximum number of key events in each dynamic macro
+    #[serde_inline_default(32)]
+    pub dynamic_macro_max_length: usize,
     /// Default debounce time in ms
     #[serde_inline_default(20)]
     pub debounce_time: u16,
@@ -93,6 +112,9 @@ pub struct RmkConstantsConfig {
     /// BLE Split Central sleep timeout in minutes (0 = disabled)
     #[serde_inline_default(0)]
     pub split_central_sleep_timeout_minutes: u32,
+    /// Interval of the heartbeat sent by serial split peripherals in milliseconds (0 = disabled)
+    #[serde_inline_default(1000)]
+    pub split_heartbeat_interval: u32,
 }
 
 fn check_combo_max_num<'de, D>(deserializer: D) -> Result<usize, D::Error>
@@ -106,6 +128,17 @@ where
     Ok(value)
 }
 
+fn check_leader_max_num<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let value = SerdeDeserialize::deserialize(deserializer)?;
+    if value > 256 {
+        panic!("❌ Parse `keyboard.toml` error: leader_max_num must be between 0 and 256, got {value}");
+    }
+    Ok(value)
+}
+
 fn check_morse_max_num<'de, D>(deserializer: D) -> Result<usize, D::Error>
 where
     D: de::Deserializer<'de>,
@@ -128,6 +161,49 @@ where
     Ok(value)
 }
 
+fn check_macro_max_num<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let value = SerdeDeserialize::deserialize(deserializer)?;
+    if value > 255 {
+        panic!("❌ Parse `keyboard.toml` error: macro_max_num must be between 0 and 255, got {value}");
+    }
+    Ok(value)
+}
+
+fn check_macro_space_size<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let value = SerdeDeserialize::deserialize(deserializer)?;
+    // Vial reads the macro buffer size as u16
+    if value == 0 || value > u16::MAX as usize {
+        panic!("❌ Parse `keyboard.toml` error: macro_space_size must be between 1 and 65535, got {value}");
+    }
+    Ok(value)
+}
+
+fn check_battery_curve<'de, D>(deserializer: D) -> Result<Option<Vec<(u16, u8)>>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let value: Option<Vec<(u16, u8)>> = SerdeDeserialize::deserialize(deserializer)?;
+    if let Some(curve) = &value
+        && !is_valid_battery_curve(curve)
+    {
+        panic!(
+            "❌ Parse `keyboard.toml` error: battery_curve must not be empty, the voltages must be descending and the levels must not increase, got {curve:?}"
+        );
+    }
+    Ok(value)
+}
+
+/// Whether the curve isn't empty, the voltages are strictly descending and the levels don't increase
+fn is_valid_battery_curve(curve: &[(u16, u8)]) -> bool {
+    !curve.is_empty() && curve.windows(2).all(|p| p[0].0 > p[1].0 && p[0].1 >= p[1].1)
+}
+
 fn check_fork_max_num<'de, D>(deserializer: D) -> Result<usize, D::Error>
 where
     D: de::Deserializer<'de>,
@@ -139,6 +215,17 @@ where
     Ok(value)
 }
 
+fn check_key_override_max_num<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let value = SerdeDeserialize::deserialize(deserializer)?;
+    if value > 256 {
+        panic!("❌ Parse `keyboard.toml` error: key_override_max_num must be between 0 and 256, got {value}");
+    }
+    Ok(value)
+}
+
 /// This separate Default impl is needed when `[rmk]` section is not set in keyboard.toml
 impl Default for RmkConstantsConfig {
     fn default() -> Self {
@@ -147,10 +234,15 @@ impl Default for RmkConstantsConfig {
             mouse_wheel_interval: 80,
             combo_max_num: 8,
             combo_max_length: 4,
+            leader_max_num: 8,
+            leader_max_length: 4,
             fork_max_num: 8,
+            key_override_max_num: 16,
             morse_max_num: 8,
             max_patterns_per_key: 8,
-            macro_space_size: 256,
+            macro_space_size: 512,
+            macro_max_num: 32,
+            dynamic_macro_max_length: 32,
             debounce_time: 20,
             event_channel_size: 16,
             controller_channel_size: 16,
@@ -163,6 +255,7 @@ impl Default for RmkConstantsConfig {
             split_message_channel_size: 4,
             ble_profiles_num: 3,
             split_central_sleep_timeout_minutes: 0,
+            split_heartbeat_interval: 1000,
         }
     }
 }
@@ -308,6 +401,10 @@ pub struct KeyboardInfo {
     pub product_name: Option<String>,
     /// Serial number
     pub serial_number: Option<String>,
+    /// Poll interval of the USB HID endpoints
+    pub usb_poll_interval: Option<DurationMillis>,
+    /// Wake up the host from the suspend when a key is pressed
+    pub usb_remote_wakeup: Option<bool>,
     /// Board name(if a supported board is used)
     pub board: Option<String>,
     /// Chip model
@@ -333,8 +430,41 @@ pub struct MatrixConfig {
     pub direct_pins: Option<Vec<Vec<String>>>,
     #[serde(default = "default_true")]
     pub direct_pin_low_active: bool,
+    /// Pull of each direct pin, in the same shape as `direct_pins`.
+    /// Pins that are not set are pulled up if `direct_pin_low_active` is true, otherwise pulled down.
+    pub direct_pin_pull: Option<Vec<Vec<PinPull>>>,
     #[serde(default = "default_false")]
     pub row2col: bool,
+    /// Debounce mode, "eager" or "defer"
+    pub debounce_mode: Option<DebounceMode>,
+    /// Track the debounce window "per_key" or "global" for the whole matrix
+    pub debounce_scope: Option<DebounceScope>,
+    /// Debounce window, the default value is `debounce_time` in `[rmk]`
+    pub debounce_window: Option<DurationMillis>,
+    /// Interval between two scans of the matrix, the default value is selected by the matrix type
+    pub scan_interval: Option<DurationMillis>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[allow(non_camel_case_types)]
+pub enum PinPull {
+    up,
+    down,
+    none,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[allow(non_camel_case_types)]
+pub enum DebounceMode {
+    eager,
+    defer,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[allow(non_camel_case_types)]
+pub enum DebounceScope {
+    per_key,
+    global,
 }
 
 /// Config for storage
@@ -352,6 +482,8 @@ pub struct StorageConfig {
     pub clear_storage: Option<bool>,
     // Clear on the layout at reboot, set this to true if you want to reset the layout
     pub clear_layout: Option<bool>,
+    // Reboot after the storage is cleared by the host(e.g. "Reset EEPROM" in Vial)
+    pub reboot_after_reset: Option<bool>,
 }
 
 #[derive(Clone, Default, Debug, Deserialize)]
@@ -363,7 +495,45 @@ pub struct BleConfig {
     pub charge_led: Option<PinConfig>,
     pub adc_divider_measured: Option<u32>,
     pub adc_divider_total: Option<u32>,
+    /// Battery discharge curve, `[millivolts, percent]` points ordered by voltage descending
+    #[serde(default, deserialize_with = "check_battery_curve")]
+    pub battery_curve: Option<Vec<(u16, u8)>>,
+    /// Window of the moving average filter applied on the battery voltage
+    pub battery_smoothing_window: Option<u8>,
+    /// Send a low battery event once when the battery level drops below this value
+    pub low_battery_threshold: Option<u8>,
     pub default_tx_power: Option<i8>,
+    /// Advertising interval, in 20ms..=10.24s
+    pub adv_interval: Option<DurationMillis>,
+    /// TX power of advertising, in dBm
+    pub adv_tx_power: Option<i8>,
+    /// Connection interval requested after connected, in 7.5ms..=4s
+    pub conn_interval: Option<DurationMillis>,
+    /// Peripheral latency requested after connected
+    pub conn_latency: Option<u16>,
+    /// Supervision timeout requested after connected
+    pub conn_supervision_timeout: Option<DurationMillis>,
+    /// Whether to request the low latency(7.5ms interval) connection parameters after the first update.
+    ///
+    /// Defaults to true, or false if any of the connection parameters above is set
+    pub conn_second_update: Option<bool>,
+    /// Enter idle mode after no key activity for this duration
+    pub idle_timeout: Option<DurationMillis>,
+    /// Connection used on startup, when both USB and BLE are available
+    pub connection_preference: Option<ConnectionPreference>,
+    /// Name advertised by each BLE profile
+    pub profile_names: Option<Vec<String>>,
+    /// Only accept the connection from the bonded host of the active profile
+    pub bonded_only: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[allow(non_camel_case_types)]
+pub enum ConnectionPreference {
+    auto_usb_first,
+    prefer_ble,
+    usb_only,
+    ble_only,
 }
 
 /// Config for lights
@@ -412,14 +582,27 @@ pub struct LayoutConfig {
 #[derive(Clone, Debug, Default, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct BehaviorConfig {
-    pub tri_layer: Option<TriLayerConfig>,
+    pub tri_layer: Option<TriLayersConfig>,
     pub tap_hold: Option<TapHoldConfig>,
     pub one_shot: Option<OneShotConfig>,
+    pub caps_word: Option<CapsWordConfig>,
+    pub auto_shift: Option<AutoShiftConfig>,
+    pub grave_escape: Option<GraveEscapeConfig>,
+    pub space_cadet: Option<SpaceCadetConfig>,
+    pub unicode: Option<UnicodeConfig>,
+    pub encoder_acceleration: Option<EncoderAccelerationConfig>,
+    pub media_key_repeat: Option<MediaKeyRepeatConfig>,
+    pub bypass: Option<BypassConfig>,
     pub combo: Option<CombosConfig>,
+    pub leader: Option<LeadersConfig>,
     #[serde(alias = "macro")]
     pub macros: Option<MacrosConfig>,
     pub fork: Option<ForksConfig>,
+    pub key_override: Option<KeyOverridesConfig>,
     pub morse: Option<MorsesConfig>,
+    pub mouse_key: Option<MouseKeyConfig>,
+    /// Whether the N-key rollover report is used by default
+    pub nkro: Option<bool>,
 }
 
 /// Configurations for tap hold
@@ -429,9 +612,19 @@ pub struct TapHoldConfig {
     pub enable_hrm: Option<bool>,
     pub permissive_hold: Option<bool>,
     pub unilateral_tap: Option<bool>,
+    /// Resolve a tap-hold key as tap when a key on the same hand is pressed
+    pub chordal_hold: Option<bool>,
+    /// Hand of each key, one string per row: `L` for left, `R` for right, `*` for no hand
+    pub hands: Option<Vec<String>>,
     pub hold_on_other_press: Option<bool>,
+    pub layer_tap_permissive_hold: Option<bool>,
+    pub layer_tap_hold_on_other_press: Option<bool>,
     pub prior_idle_time: Option<DurationMillis>,
     pub hold_timeout: Option<DurationMillis>,
+    /// Number of taps to toggle the layer of `TT(n)`
+    pub tap_toggle_count: Option<u8>,
+    /// Pressing a tap-hold key again within this time after its tap holds the tap key
+    pub quick_tap_term: Option<DurationMillis>,
 }
 
 /// Configurations for tri layer
@@ -443,6 +636,23 @@ pub struct TriLayerConfig {
     pub adjust: u8,
 }
 
+/// One tri layer rule, or a list of rules which are evaluated in order
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TriLayersConfig {
+    Single(TriLayerConfig),
+    Multiple(Vec<TriLayerConfig>),
+}
+
+impl TriLayersConfig {
+    pub fn rules(&self) -> &[TriLayerConfig] {
+        match self {
+            TriLayersConfig::Single(rule) => core::slice::from_ref(rule),
+            TriLayersConfig::Multiple(rules) => rules,
+        }
+    }
+}
+
 /// Configurations for one shot
 #[derive(Clone, Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -450,12 +660,122 @@ pub struct OneShotConfig {
     pub timeout: Option<DurationMillis>,
 }
 
+/// Configurations for caps word
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CapsWordConfig {
+    pub timeout: Option<DurationMillis>,
+}
+
+/// Configurations for the grave escape key
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GraveEscapeConfig {
+    /// Modifiers which make the key send grave, in the form of "LShift|RShift|LGui"
+    pub grave_modifiers: Option<String>,
+}
+
+/// Configurations for space cadet keys
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpaceCadetConfig {
+    /// Overrides the tap-hold timeout for space cadet keys
+    pub timeout: Option<DurationMillis>,
+    /// Key sent by tapping the left space cadet keys
+    pub left_tap: Option<String>,
+    /// Modifiers sent with `left_tap`, in the form of "LShift|LCtrl"
+    pub left_tap_modifiers: Option<String>,
+    /// Key sent by tapping the right space cadet keys
+    pub right_tap: Option<String>,
+    /// Modifiers sent with `right_tap`, in the form of "RShift|RCtrl"
+    pub right_tap_modifiers: Option<String>,
+}
+
+/// Configurations for the acceleration of rotary encoders
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncoderAccelerationConfig {
+    /// A detent within this interval after the previous detent in the same direction is accelerated
+    pub interval: Option<DurationMillis>,
+    /// Number of times the action is triggered by an accelerated detent
+    pub multiplier: Option<u8>,
+    /// Accelerated detents use the encoder actions on this layer
+    pub accelerated_layer: Option<u8>,
+}
+
+/// Configurations for the repeat of held consumer and system control keys
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MediaKeyRepeatConfig {
+    /// The delay before the first repeat
+    pub delay: Option<DurationMillis>,
+    /// The interval between repeats
+    pub interval: Option<DurationMillis>,
+}
+
+/// Configurations for the bypass mode
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BypassConfig {
+    /// Whether the bypass mode is on at startup
+    pub enable: Option<bool>,
+    /// Whether the bypass mode is saved to the storage
+    pub persist: Option<bool>,
+}
+
+/// Configurations for typing unicode characters
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnicodeConfig {
+    pub mode: Option<UnicodeMode>,
+    pub key_delay: Option<DurationMillis>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[allow(non_camel_case_types)]
+pub enum UnicodeMode {
+    linux,
+    macos,
+    windows,
+    wincompose,
+}
+
+/// Configurations for mouse keys
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MouseKeyConfig {
+    pub initial_delay: Option<DurationMillis>,
+    pub repeat_interval: Option<DurationMillis>,
+    pub move_delta: Option<u8>,
+    pub max_speed: Option<u8>,
+    pub time_to_max: Option<u8>,
+    pub move_max: Option<u8>,
+    pub wheel_initial_delay: Option<DurationMillis>,
+    pub wheel_repeat_interval: Option<DurationMillis>,
+    pub wheel_delta: Option<u8>,
+    pub wheel_max_speed_multiplier: Option<u8>,
+    pub wheel_time_to_max: Option<u8>,
+    pub wheel_max: Option<u8>,
+}
+
+/// Configurations for auto shift
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutoShiftConfig {
+    pub enable: Option<bool>,
+    pub timeout: Option<DurationMillis>,
+    pub allowlist: Option<Vec<String>>,
+    pub denylist: Option<Vec<String>>,
+}
+
 /// Configurations for combos
 #[derive(Clone, Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct CombosConfig {
     pub combos: Vec<ComboConfig>,
     pub timeout: Option<DurationMillis>,
+    /// Extra time to wait for the delayed key events, e.g. from the split peripherals
+    pub latency_allowance: Option<DurationMillis>,
 }
 
 /// Configurations for combo
@@ -466,6 +786,21 @@ pub struct ComboConfig {
     pub layer: Option<u8>,
 }
 
+/// Configurations for leader key
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LeadersConfig {
+    pub sequences: Vec<LeaderConfig>,
+    pub timeout: Option<DurationMillis>,
+}
+
+/// Configurations for leader key sequence
+#[derive(Clone, Debug, Deserialize)]
+pub struct LeaderConfig {
+    pub sequence: Vec<String>,
+    pub output: String,
+}
+
 /// Configurations for macros
 #[derive(Clone, Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -509,6 +844,24 @@ pub struct ForkConfig {
     pub bindable: Option<bool>,
 }
 
+/// Configurations for key overrides
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyOverridesConfig {
+    pub overrides: Vec<KeyOverrideConfig>,
+}
+
+/// Configurations for key override
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyOverrideConfig {
+    pub trigger: String,
+    pub trigger_modifiers: Option<String>,
+    pub negative_modifiers: Option<String>,
+    pub replacement: String,
+    pub replacement_modifiers: Option<String>,
+}
+
 /// Configurations for morse keys
 #[derive(Clone, Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -645,6 +998,25 @@ pub struct JoystickConfig {
     pub transform: Vec<Vec<i16>>,
     pub bias: Vec<i16>,
     pub resolution: u16,
+    // Axis values within the deadzone are ignored
+    pub deadzone: Option<u16>,
+    // Response curve of the axes
+    pub curve: Option<JoystickCurve>,
+    // Sensitivity in percent
+    pub sensitivity: Option<u16>,
+    // Invert the x axis
+    #[serde(default = "default_false")]
+    pub invert_x: bool,
+    // Invert the y axis
+    #[serde(default = "default_false")]
+    pub invert_y: bool,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[allow(non_camel_case_types)]
+pub enum JoystickCurve {
+    linear,
+    quadratic,
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -665,6 +1037,8 @@ pub struct EncoderConfig {
     pub resolution: Option<u8>,
     // Whether the direction of the rotary encoder is reversed.
     pub reverse: Option<bool>,
+    // Number of pulses in one physical detent, the pulses are divided so that one detent triggers one action
+    pub pulses_per_detent: Option<u8>,
     // Use MCU's internal pull-up resistor or not
     #[serde(default = "default_false")]
     pub internal_pullup: bool,
@@ -706,3 +1080,55 @@ pub struct I2cConfig {
     pub scl: String,
     pub address: u8,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_ble_config(toml: &str) -> BleConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn parse_constants(toml: &str) -> RmkConstantsConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_macro_space_size() {
+        assert_eq!(parse_constants("").macro_space_size, 512);
+        assert_eq!(parse_constants("macro_space_size = 1").macro_space_size, 1);
+        assert_eq!(parse_constants("macro_space_size = 65535").macro_space_size, 65535);
+    }
+
+    #[test]
+    #[should_panic(expected = "macro_space_size")]
+    fn test_macro_space_size_zero() {
+        parse_constants("macro_space_size = 0");
+    }
+
+    #[test]
+    #[should_panic(expected = "macro_space_size")]
+    fn test_macro_space_size_too_large() {
+        parse_constants("macro_space_size = 65536");
+    }
+
+    #[test]
+    fn test_battery_curve() {
+        let config = parse_ble_config("enabled = true\nbattery_curve = [[4200, 100], [3800, 50], [3300, 0]]");
+        assert_eq!(config.battery_curve, Some(vec![(4200, 100), (3800, 50), (3300, 0)]));
+        let config = parse_ble_config("enabled = true");
+        assert_eq!(config.battery_curve, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "battery_curve")]
+    fn test_battery_curve_not_descending() {
+        parse_ble_config("enabled = true\nbattery_curve = [[3300, 0], [4200, 100]]");
+    }
+
+    #[test]
+    #[should_panic(expected = "battery_curve")]
+    fn test_battery_curve_level_increasing() {
+        parse_ble_config("enabled = true\nbattery_curve = [[4200, 90], [3800, 100], [3300, 0]]");
+    }
+}
@@ -1,24 +1,22 @@
 //! Initialize behavior config boilerplate of RMK
 //!
 
-use quote::quote;
+use quote::{format_ident, quote};
 use rmk_config::{
-    CombosConfig, ForksConfig, KeyboardTomlConfig, MacrosConfig, MorseActionPair, MorsesConfig, OneShotConfig,
-    TapHoldConfig, TriLayerConfig,
+    AutoShiftConfig, BypassConfig, CapsWordConfig, CombosConfig, EncoderAccelerationConfig, ForksConfig,
+    GraveEscapeConfig, KeyOverridesConfig, KeyboardTomlConfig, LeadersConfig, MacrosConfig, MediaKeyRepeatConfig,
+    MorseActionPair, MorsesConfig, MouseKeyConfig, OneShotConfig, SpaceCadetConfig, TapHoldConfig, TriLayerConfig,
+    TriLayersConfig, UnicodeConfig, UnicodeMode,
 };
 
 use crate::layout::{get_key_with_alias, parse_key};
 
-fn expand_tri_layer(tri_layer: &Option<TriLayerConfig>) -> proc_macro2::TokenStream {
-    match tri_layer {
-        Some(tri_layer) => {
-            let upper = tri_layer.upper;
-            let lower = tri_layer.lower;
-            let adjust = tri_layer.adjust;
-            quote! {::core::option::Option::Some([#upper, #lower, #adjust])}
-        }
-        None => quote! {::core::option::Option::None::<[u8; 3]>},
-    }
+fn expand_tri_layer(tri_layer: &Option<TriLayersConfig>) -> proc_macro2::TokenStream {
+    let rules = tri_layer.as_ref().map(|t| t.rules()).unwrap_or_default();
+    let rules = rules.iter().map(|TriLayerConfig { upper, lower, adjust }| {
+        quote! {[#upper, #lower, #adjust]}
+    });
+    quote! {&[#(#rules),*]}
 }
 
 fn expand_one_shot(one_shot: &Option<OneShotConfig>) -> proc_macro2::TokenStream {
@@ -42,6 +40,237 @@ fn expand_one_shot(one_shot: &Option<OneShotConfig>) -> proc_macro2::TokenStream
     }
 }
 
+fn expand_caps_word(caps_word: &Option<CapsWordConfig>) -> proc_macro2::TokenStream {
+    let default = quote! {::rmk::config::CapsWordConfig::default()};
+    match caps_word {
+        Some(caps_word) => {
+            let millis = match &caps_word.timeout {
+                Some(t) => t.0,
+                None => return default,
+            };
+
+            let timeout = quote! {::embassy_time::Duration::from_millis(#millis)};
+
+            quote! {
+                ::rmk::config::CapsWordConfig {
+                    timeout: #timeout,
+                }
+            }
+        }
+        None => default,
+    }
+}
+
+fn expand_auto_shift(auto_shift: &Option<AutoShiftConfig>) -> proc_macro2::TokenStream {
+    let default = quote! {::rmk::config::AutoShiftConfig::default()};
+    match auto_shift {
+        Some(auto_shift) => {
+            // Auto shift is enabled if the section is present, unless it's explicitly disabled
+            let enable = auto_shift.enable.unwrap_or(true);
+            let timeout = match &auto_shift.timeout {
+                Some(t) => {
+                    let millis = t.0;
+                    quote! { ::embassy_time::Duration::from_millis(#millis) }
+                }
+                None => quote! { ::rmk::config::AutoShiftConfig::default().timeout },
+            };
+            let expand_keys = |keys: &Option<Vec<String>>| {
+                let keys = keys.iter().flatten().map(|k| {
+                    let key = get_key_with_alias(k.trim().to_owned());
+                    quote! { ::rmk::types::keycode::KeyCode::#key }
+                });
+                quote! { ::rmk::heapless::Vec::from_iter([#(#keys),*]) }
+            };
+            let allowlist = expand_keys(&auto_shift.allowlist);
+            let denylist = expand_keys(&auto_shift.denylist);
+
+            quote! {
+                ::rmk::config::AutoShiftConfig {
+                    enable: #enable,
+                    timeout: #timeout,
+                    allowlist: #allowlist,
+                    denylist: #denylist,
+                }
+            }
+        }
+        None => default,
+    }
+}
+
+fn expand_grave_escape(grave_escape: &Option<GraveEscapeConfig>) -> proc_macro2::TokenStream {
+    let default = quote! {::rmk::config::GraveEscapeConfig::default()};
+    match grave_escape {
+        Some(GraveEscapeConfig {
+            grave_modifiers: Some(modifiers),
+        }) => {
+            let modifiers = expand_modifiers(modifiers, "`grave_modifiers` of [behavior.grave_escape]");
+            quote! {
+                ::rmk::config::GraveEscapeConfig {
+                    grave_modifiers: #modifiers,
+                }
+            }
+        }
+        _ => default,
+    }
+}
+
+fn expand_space_cadet(space_cadet: &Option<SpaceCadetConfig>) -> proc_macro2::TokenStream {
+    let default = quote! {::rmk::config::SpaceCadetConfig::default()};
+    match space_cadet {
+        Some(space_cadet) => {
+            let field = "[behavior.space_cadet]";
+            let timeout = match &space_cadet.timeout {
+                Some(t) => {
+                    let timeout = t.0;
+                    quote! { Some(::embassy_time::Duration::from_millis(#timeout)) }
+                }
+                None => quote! { None },
+            };
+            let tap_key = |key: &Option<String>, default: &str| {
+                let key = get_key_with_alias(key.clone().unwrap_or(default.to_owned()));
+                quote! { ::rmk::types::keycode::KeyCode::#key }
+            };
+            let left_tap_key = tap_key(&space_cadet.left_tap, "Kc9");
+            let right_tap_key = tap_key(&space_cadet.right_tap, "Kc0");
+            let left_tap_modifiers =
+                expand_modifiers(space_cadet.left_tap_modifiers.as_deref().unwrap_or("LShift"), field);
+            let right_tap_modifiers =
+                expand_modifiers(space_cadet.right_tap_modifiers.as_deref().unwrap_or("RShift"), field);
+            quote! {
+                ::rmk::config::SpaceCadetConfig {
+                    timeout: #timeout,
+                    left_tap_key: #left_tap_key,
+                    left_tap_modifiers: #left_tap_modifiers,
+                    right_tap_key: #right_tap_key,
+                    right_tap_modifiers: #right_tap_modifiers,
+                }
+            }
+        }
+        None => default,
+    }
+}
+
+fn expand_encoder_acceleration(encoder_acceleration: &Option<EncoderAccelerationConfig>) -> proc_macro2::TokenStream {
+    let default = quote! {::rmk::config::EncoderAccelerationConfig::default()};
+    match encoder_acceleration {
+        Some(encoder_acceleration) => {
+            let interval = match &encoder_acceleration.interval {
+                Some(t) => {
+                    let millis = t.0;
+                    quote! { ::embassy_time::Duration::from_millis(#millis) }
+                }
+                None => quote! { ::rmk::config::EncoderAccelerationConfig::default().interval },
+            };
+            let multiplier = encoder_acceleration.multiplier.unwrap_or(1);
+            let accelerated_layer = match encoder_acceleration.accelerated_layer {
+                Some(layer) => quote! { Some(#layer) },
+                None => quote! { None },
+            };
+            quote! {
+                ::rmk::config::EncoderAccelerationConfig {
+                    interval: #interval,
+                    multiplier: #multiplier,
+                    accelerated_layer: #accelerated_layer,
+                }
+            }
+        }
+        None => default,
+    }
+}
+
+fn expand_media_key_repeat(media_key_repeat: &Option<MediaKeyRepeatConfig>) -> proc_macro2::TokenStream {
+    let default = quote! {::rmk::config::MediaKeyRepeatConfig::default()};
+    match media_key_repeat {
+        Some(media_key_repeat) => {
+            let delay = match &media_key_repeat.delay {
+                Some(t) => {
+                    let millis = t.0;
+                    quote! { ::embassy_time::Duration::from_millis(#millis) }
+                }
+                None => quote! { ::rmk::config::MediaKeyRepeatConfig::default().delay },
+            };
+            let interval = match &media_key_repeat.interval {
+                Some(t) => {
+                    let millis = t.0;
+                    quote! { ::embassy_time::Duration::from_millis(#millis) }
+                }
+                None => quote! { ::rmk::config::MediaKeyRepeatConfig::default().interval },
+            };
+            quote! {
+                ::rmk::config::MediaKeyRepeatConfig {
+                    delay: #delay,
+                    interval: #interval,
+                }
+            }
+        }
+        None => default,
+    }
+}
+
+fn expand_bypass(bypass: &Option<BypassConfig>) -> proc_macro2::TokenStream {
+    let default = quote! {::rmk::config::BypassConfig::default()};
+    match bypass {
+        Some(bypass) => {
+            let enable = bypass.enable.unwrap_or(false);
+            let persist = bypass.persist.unwrap_or(false);
+            quote! {
+                ::rmk::config::BypassConfig {
+                    enable: #enable,
+                    persist: #persist,
+                }
+            }
+        }
+        None => default,
+    }
+}
+
+/// Expand modifiers separated by `|`, such as "LCtrl|LShift", into a `ModifierCombination`
+fn expand_modifiers(modifiers: &str, field: &str) -> proc_macro2::TokenStream {
+    let modifiers = modifiers.split_terminator("|").map(|m| {
+        let modifier = match get_key_with_alias(m.trim().to_owned()).to_string().as_str() {
+            "LCtrl" => format_ident!("LCTRL"),
+            "LShift" => format_ident!("LSHIFT"),
+            "LAlt" => format_ident!("LALT"),
+            "LGui" => format_ident!("LGUI"),
+            "RCtrl" => format_ident!("RCTRL"),
+            "RShift" => format_ident!("RSHIFT"),
+            "RAlt" => format_ident!("RALT"),
+            "RGui" => format_ident!("RGUI"),
+            _ => panic!("\n❌ keyboard.toml: invalid modifier `{}` in {}", m.trim(), field),
+        };
+        quote! { ::rmk::types::modifier::ModifierCombination::#modifier }
+    });
+    quote! { ::rmk::types::modifier::ModifierCombination::new() #(| #modifiers)* }
+}
+
+fn expand_unicode(unicode: &Option<UnicodeConfig>) -> proc_macro2::TokenStream {
+    let default = quote! {::rmk::config::UnicodeConfig::default()};
+    match unicode {
+        Some(unicode) => {
+            let mode = match unicode.mode {
+                Some(UnicodeMode::linux) | None => quote! { Linux },
+                Some(UnicodeMode::macos) => quote! { MacOs },
+                Some(UnicodeMode::windows) => quote! { Windows },
+                Some(UnicodeMode::wincompose) => quote! { WinCompose },
+            };
+            let key_delay = match &unicode.key_delay {
+                Some(t) => {
+                    let millis = t.0;
+                    quote! { ::embassy_time::Duration::from_millis(#millis) }
+                }
+                None => quote! { ::rmk::config::UnicodeConfig::default().key_delay },
+            };
+            quote! {
+                ::rmk::config::UnicodeConfig {
+                    mode: ::rmk::config::UnicodeMode::#mode,
+                    key_delay: #key_delay,
+                }
+            }
+        }
+        None => default,
+    }
+}
+
 fn expand_morse_action_pair(action_pair: &MorseActionPair) -> proc_macro2::TokenStream {
     let mut pattern = 0b1u16;
     for ch in action_pair.pattern.chars() {
@@ -84,10 +313,36 @@ fn expand_tap_hold_config(tap_hold_config: &Option<TapHoldConfig>) -> proc_macro
             } else {
                 quote! { mode: ::rmk::morse::MorseMode::Normal,}
             };
+            let layer_tap_mode = if let Some(true) = tap_hold_config.layer_tap_permissive_hold {
+                quote! { layer_tap_mode: Some(::rmk::morse::MorseMode::PermissiveHold), }
+            } else if let Some(true) = tap_hold_config.layer_tap_hold_on_other_press {
+                quote! { layer_tap_mode: Some(::rmk::morse::MorseMode::HoldOnOtherPress), }
+            } else {
+                quote! {}
+            };
             let unilateral_tap = match tap_hold_config.unilateral_tap {
                 Some(enable) => quote! { unilateral_tap: #enable, },
                 None => quote! {},
             };
+            let chordal_hold = match tap_hold_config.chordal_hold {
+                Some(enable) => quote! { chordal_hold: #enable, },
+                None => quote! {},
+            };
+            let hands = match &tap_hold_config.hands {
+                Some(rows) => {
+                    let rows = rows.iter().map(|row| {
+                        let hands = row.chars().filter(|c| !c.is_whitespace()).map(|c| match c {
+                            'L' | 'l' => quote! { Some(::rmk::event::Hand::Left) },
+                            'R' | 'r' => quote! { Some(::rmk::event::Hand::Right) },
+                            '*' => quote! { None },
+                            _ => panic!("Invalid hand '{}' in `hands`, expected 'L', 'R' or '*'", c),
+                        });
+                        quote! { &[#(#hands),*] }
+                    });
+                    quote! { hands: &[#(#rows),*], }
+                }
+                None => quote! {},
+            };
             let prior_idle_time = match &tap_hold_config.prior_idle_time {
                 Some(t) => {
                     let timeout = t.0;
@@ -102,6 +357,17 @@ fn expand_tap_hold_config(tap_hold_config: &Option<TapHoldConfig>) -> proc_macro
                 }
                 None => quote! {},
             };
+            let tap_toggle_count = match tap_hold_config.tap_toggle_count {
+                Some(count) => quote! { tap_toggle_count: #count, },
+                None => quote! {},
+            };
+            let quick_tap_term = match &tap_hold_config.quick_tap_term {
+                Some(t) => {
+                    let term = t.0;
+                    quote! { quick_tap_term: ::embassy_time::Duration::from_millis(#term), }
+                }
+                None => quote! {},
+            };
 
             quote! {
                 ::rmk::config::TapHoldConfig {
@@ -109,7 +375,67 @@ fn expand_tap_hold_config(tap_hold_config: &Option<TapHoldConfig>) -> proc_macro
                     #prior_idle_time
                     #hold_timeout
                     #tap_hold_mode
+                    #layer_tap_mode
                     #unilateral_tap
+                    #chordal_hold
+                    #hands
+                    #tap_toggle_count
+                    #quick_tap_term
+                    ..Default::default()
+                }
+            }
+        }
+        None => default,
+    }
+}
+
+fn expand_mouse_key(mouse_key: &Option<MouseKeyConfig>) -> proc_macro2::TokenStream {
+    let default = quote! {::rmk::config::MouseKeyConfig::default()};
+    match mouse_key {
+        Some(mouse_key) => {
+            let mut durations = Vec::new();
+            for (field, value) in [
+                ("initial_delay_ms", &mouse_key.initial_delay),
+                ("repeat_interval_ms", &mouse_key.repeat_interval),
+                ("wheel_initial_delay_ms", &mouse_key.wheel_initial_delay),
+                ("wheel_repeat_interval_ms", &mouse_key.wheel_repeat_interval),
+            ] {
+                let Some(t) = value else { continue };
+                // The durations are stored as milliseconds in u16
+                let Ok(millis) = u16::try_from(t.0) else {
+                    let msg = format!(
+                        "keyboard.toml: `behavior.mouse_key.{}` is {}ms, which exceeds the maximum of {}ms",
+                        field.trim_end_matches("_ms"),
+                        t.0,
+                        u16::MAX
+                    );
+                    return quote! { ::core::compile_error!(#msg) };
+                };
+                let field = format_ident!("{}", field);
+                durations.push(quote! { #field: #millis, });
+            }
+            let values = [
+                ("move_delta", mouse_key.move_delta),
+                ("max_speed", mouse_key.max_speed),
+                ("time_to_max", mouse_key.time_to_max),
+                ("move_max", mouse_key.move_max),
+                ("wheel_delta", mouse_key.wheel_delta),
+                ("wheel_max_speed_multiplier", mouse_key.wheel_max_speed_multiplier),
+                ("wheel_time_to_max", mouse_key.wheel_time_to_max),
+                ("wheel_max", mouse_key.wheel_max),
+            ]
+            .into_iter()
+            .filter_map(|(field, value)| {
+                value.map(|v| {
+                    let field = format_ident!("{}", field);
+                    quote! { #field: #v, }
+                })
+            });
+
+            quote! {
+                ::rmk::config::MouseKeyConfig {
+                    #(#durations)*
+                    #(#values)*
                     ..Default::default()
                 }
             }
@@ -139,11 +465,52 @@ fn expand_combos(combos: &Option<CombosConfig>) -> proc_macro2::TokenStream {
                 }
                 None => quote! {},
             };
+            let latency_allowance = match &combos.latency_allowance {
+                Some(t) => {
+                    let millis = t.0;
+                    quote! { latency_allowance: ::embassy_time::Duration::from_millis(#millis), }
+                }
+                None => quote! {},
+            };
 
             quote! {
                 ::rmk::config::CombosConfig {
                     combos: ::rmk::heapless::Vec::from_iter([#(#combos_def),*]),
                     #timeout
+                    #latency_allowance
+                    ..Default::default()
+                }
+            }
+        }
+        None => default,
+    }
+}
+
+fn expand_leaders(leaders: &Option<LeadersConfig>) -> proc_macro2::TokenStream {
+    let default = quote! { ::core::default::Default::default() };
+    match leaders {
+        Some(leaders) => {
+            let leaders_def = leaders.sequences.iter().map(|leader| {
+                let sequence = leader.sequence.iter().map(|k| {
+                    let key = get_key_with_alias(k.trim().to_owned());
+                    quote! { ::rmk::types::keycode::KeyCode::#key }
+                });
+                let output = parse_key(leader.output.to_owned());
+                quote! { ::rmk::leader::Leader::new([#(#sequence),*], #output) }
+            });
+
+            let timeout = match &leaders.timeout {
+                Some(t) => {
+                    let millis = t.0;
+                    quote! { timeout: ::embassy_time::Duration::from_millis(#millis), }
+                }
+                None => quote! {},
+            };
+
+            quote! {
+                ::rmk::config::LeadersConfig {
+                    leaders: ::rmk::heapless::Vec::from_iter([#(#leaders_def),*]),
+                    #timeout
                     ..Default::default()
                 }
             }
@@ -406,6 +773,39 @@ fn parse_state_combination(states_str: &str) -> StateBitsMacro {
     combination
 }
 
+fn expand_key_overrides(key_overrides: &Option<KeyOverridesConfig>) -> proc_macro2::TokenStream {
+    let default = quote! { ::core::default::Default::default() };
+    match key_overrides {
+        Some(key_overrides) => {
+            let field = "[behavior.key_override]";
+            let overrides_def = key_overrides.overrides.iter().map(|o| {
+                let trigger = get_key_with_alias(o.trigger.to_owned());
+                let replacement = get_key_with_alias(o.replacement.to_owned());
+                let trigger_modifiers = expand_modifiers(o.trigger_modifiers.as_deref().unwrap_or_default(), field);
+                let negative_modifiers = expand_modifiers(o.negative_modifiers.as_deref().unwrap_or_default(), field);
+                let replacement_modifiers =
+                    expand_modifiers(o.replacement_modifiers.as_deref().unwrap_or_default(), field);
+                quote! {
+                    ::rmk::key_override::KeyOverride::new(
+                        ::rmk::types::keycode::KeyCode::#trigger,
+                        #trigger_modifiers,
+                        #negative_modifiers,
+                        ::rmk::types::keycode::KeyCode::#replacement,
+                        #replacement_modifiers,
+                    )
+                }
+            });
+
+            quote! {
+                ::rmk::config::KeyOverridesConfig {
+                    overrides: ::rmk::heapless::Vec::from_iter([#(#overrides_def),*]),
+                }
+            }
+        }
+        None => default,
+    }
+}
+
 fn expand_forks(forks: &Option<ForksConfig>) -> proc_macro2::TokenStream {
     let default = quote! { ::core::default::Default::default() };
     match forks {
@@ -442,23 +842,46 @@ pub(crate) fn expand_behavior_config(keyboard_config: &KeyboardTomlConfig) -> pr
     let tri_layer = expand_tri_layer(&behavior.tri_layer);
     let tap_hold = expand_tap_hold_config(&behavior.tap_hold);
     let one_shot = expand_one_shot(&behavior.one_shot);
+    let caps_word = expand_caps_word(&behavior.caps_word);
+    let auto_shift = expand_auto_shift(&behavior.auto_shift);
+    let grave_escape = expand_grave_escape(&behavior.grave_escape);
+    let space_cadet = expand_space_cadet(&behavior.space_cadet);
+    let unicode = expand_unicode(&behavior.unicode);
+    let encoder_acceleration = expand_encoder_acceleration(&behavior.encoder_acceleration);
+    let media_key_repeat = expand_media_key_repeat(&behavior.media_key_repeat);
+    let bypass = expand_bypass(&behavior.bypass);
     let combos = expand_combos(&behavior.combo);
+    let leaders = expand_leaders(&behavior.leader);
     let macros = expand_macros(&behavior.macros);
     let forks = expand_forks(&behavior.fork);
+    let key_override = expand_key_overrides(&behavior.key_override);
     let morse = expand_morse(&behavior.morse);
+    let mouse_key = expand_mouse_key(&behavior.mouse_key);
+    let nkro = behavior.nkro.unwrap_or(false);
 
     quote! {
         let mut behavior_config = ::rmk::config::BehaviorConfig {
-            tri_layer: #tri_layer,
+            tri_layers: #tri_layer,
             tap_hold: #tap_hold,
             one_shot: #one_shot,
+            caps_word: #caps_word,
+            auto_shift: #auto_shift,
+            grave_escape: #grave_escape,
+            space_cadet: #space_cadet,
+            unicode: #unicode,
+            encoder_acceleration: #encoder_acceleration,
+            media_key_repeat: #media_key_repeat,
+            bypass: #bypass,
             combo: #combos,
+            leader: #leaders,
             fork: #forks,
+            key_override: #key_override,
             morse: #morse,
             keyboard_macros: #macros,
             // keyboard_macros: ::rmk::config::macro_config::KeyboardMacrosConfig::default(),
-            mouse_key: ::rmk::config::MouseKeyConfig::default(),
+            mouse_key: #mouse_key,
             tap: ::rmk::config::TapConfig::default(),
+            nkro: #nkro,
         };
     }
 }
@@ -1,11 +1,101 @@
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote};
-use rmk_config::{ChipSeries, CommunicationConfig, KeyboardTomlConfig};
+use rmk_config::{ChipSeries, CommunicationConfig, ConnectionPreference, KeyboardTomlConfig};
 
 // Default implementations of ble configuration.
 // Because ble configuration in `config` is enabled by a feature gate, so this function returns two TokenStreams.
 // One for initialization ble config, another one for filling this field into `RmkConfig`.
 pub(crate) fn expand_ble_config(keyboard_config: &KeyboardTomlConfig) -> (TokenStream2, TokenStream2) {
+    let (mut init, mut fields) = expand_ble_battery_config(keyboard_config);
+    let communication = keyboard_config.get_communication_config().unwrap();
+    if let Some(ble) = communication.get_ble_config()
+        && ble.enabled
+    {
+        // Fields that are not set in `keyboard.toml` use the default values in `BleConfig`
+        let mut set_fields = TokenStream2::new();
+        if let Some(interval) = &ble.adv_interval {
+            let interval = interval.0;
+            set_fields.extend(quote! {
+                ble_config.adv_interval = ::embassy_time::Duration::from_millis(#interval);
+            });
+        }
+        if let Some(tx_power) = ble.adv_tx_power {
+            set_fields.extend(quote! {
+                ble_config.adv_tx_power = #tx_power;
+            });
+        }
+        if let Some(interval) = &ble.conn_interval {
+            let interval = interval.0;
+            set_fields.extend(quote! {
+                ble_config.conn_params.min_interval = ::embassy_time::Duration::from_millis(#interval);
+                ble_config.conn_params.max_interval = ::embassy_time::Duration::from_millis(#interval);
+            });
+        }
+        if let Some(latency) = ble.conn_latency {
+            set_fields.extend(quote! {
+                ble_config.conn_params.latency = #latency;
+            });
+        }
+        if let Some(timeout) = &ble.conn_supervision_timeout {
+            let timeout = timeout.0;
+            set_fields.extend(quote! {
+                ble_config.conn_params.supervision_timeout = ::embassy_time::Duration::from_millis(#timeout);
+            });
+        }
+        // The second update would overwrite the configured connection parameters, so it's skipped unless it's
+        // enabled explicitly
+        let conn_params_set =
+            ble.conn_interval.is_some() || ble.conn_latency.is_some() || ble.conn_supervision_timeout.is_some();
+        if ble.conn_second_update == Some(false) || (conn_params_set && ble.conn_second_update.is_none()) {
+            set_fields.extend(quote! {
+                ble_config.second_conn_params = None;
+            });
+        }
+        if let Some(timeout) = &ble.idle_timeout {
+            let timeout = timeout.0;
+            set_fields.extend(quote! {
+                ble_config.idle_timeout = Some(::embassy_time::Duration::from_millis(#timeout));
+            });
+        }
+        if let Some(preference) = ble.connection_preference {
+            let preference = match preference {
+                ConnectionPreference::auto_usb_first => quote! { AutoUsbFirst },
+                ConnectionPreference::prefer_ble => quote! { PreferBle },
+                ConnectionPreference::usb_only => quote! { UsbOnly },
+                ConnectionPreference::ble_only => quote! { BleOnly },
+            };
+            set_fields.extend(quote! {
+                ble_config.connection_preference = ::rmk::config::ConnectionPreference::#preference;
+            });
+        }
+        if let Some(names) = &ble.profile_names {
+            set_fields.extend(quote! {
+                ble_config.profile_names = &[#(#names),*];
+            });
+        }
+        if let Some(bonded_only) = ble.bonded_only {
+            set_fields.extend(quote! {
+                ble_config.bonded_only = #bonded_only;
+            });
+        }
+        if set_fields.is_empty() {
+            init.extend(quote! {
+                let ble_config = ::rmk::config::BleConfig::default();
+            });
+        } else {
+            init.extend(quote! {
+                let mut ble_config = ::rmk::config::BleConfig::default();
+                #set_fields
+            });
+        }
+        fields.extend(quote! {
+            ble_config,
+        });
+    }
+    (init, fields)
+}
+
+fn expand_ble_battery_config(keyboard_config: &KeyboardTomlConfig) -> (TokenStream2, TokenStream2) {
     let communication = keyboard_config.get_communication_config().unwrap();
     if !communication.ble_enabled() {
         return (quote! {}, quote! {});
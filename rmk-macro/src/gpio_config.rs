@@ -1,5 +1,5 @@
 use quote::{format_ident, quote};
-use rmk_config::{ChipModel, ChipSeries};
+use rmk_config::{ChipModel, ChipSeries, PinPull};
 
 pub(crate) fn convert_output_pins_to_initializers(chip: &ChipModel, pins: Vec<String>) -> proc_macro2::TokenStream {
     let mut initializers = proc_macro2::TokenStream::new();
@@ -74,21 +74,34 @@ pub(crate) fn get_output_pin_type(chip: &ChipModel) -> proc_macro2::TokenStream
 pub(crate) fn convert_direct_pins_to_initializers(
     chip: &ChipModel,
     pins: Vec<Vec<String>>,
+    pulls: Option<Vec<Vec<PinPull>>>,
     async_matrix: bool,
     low_active: bool,
 ) -> proc_macro2::TokenStream {
     let mut initializers = proc_macro2::TokenStream::new();
     let mut row_idents = vec![];
+    if let Some(pulls) = &pulls
+        && (pulls.len() != pins.len() || pulls.iter().zip(pins.iter()).any(|(p, r)| p.len() != r.len()))
+    {
+        panic!("\n❌ keyboard.toml: `direct_pin_pull` should have the same shape as `direct_pins`");
+    }
     // Process each row of pins
     for (row_idx, row_pins) in pins.into_iter().enumerate() {
         let mut col_idents = vec![];
         // Process each pin in the current row
-        let pin_initializers = row_pins.into_iter().map(|p| {
-            let ident_name = format_ident!("{}_{}_{}", p.to_lowercase(), row_idx, col_idents.len());
+        let pin_initializers = row_pins.into_iter().enumerate().map(|(col_idx, p)| {
+            let ident_name = format_ident!("{}_{}_{}", p.to_lowercase(), row_idx, col_idx);
             col_idents.push(ident_name.clone());
             if p != "_" && p.to_lowercase() != "trns" {
                 // Convert pin to Some(pin) when it's not transparent
-                let pin = convert_gpio_str_to_input_pin(chip, p, async_matrix, Some(low_active)); // low active = false == pull down
+                // Low active pins are pulled up by default
+                let pull = match pulls.as_ref().map(|pulls| pulls[row_idx][col_idx]) {
+                    Some(PinPull::up) => Some(true),
+                    Some(PinPull::down) => Some(false),
+                    Some(PinPull::none) => None,
+                    None => Some(low_active),
+                };
+                let pin = convert_gpio_str_to_input_pin(chip, p, async_matrix, pull);
                 quote! { let #ident_name = Some(#pin); }
             } else {
                 quote! { let #ident_name = None; }
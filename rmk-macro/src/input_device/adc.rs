@@ -1,5 +1,6 @@
+use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote};
-use rmk_config::{BleConfig, ChipSeries, JoystickConfig};
+use rmk_config::{BleConfig, ChipSeries, JoystickConfig, JoystickCurve};
 
 use crate::input_device::Initializer;
 
@@ -23,7 +24,7 @@ pub(crate) fn expand_adc_device(
 
             if let Some(ble) = ble_config {
                 if ble.enabled {
-                    if let Some(adc_pin) = ble.battery_adc_pin {
+                    if let Some(adc_pin) = ble.battery_adc_pin.clone() {
                         let adc_pin_def = if adc_pin == "vddh" {
                             quote! {
                                 saadc::ChannelConfig::single_ended(saadc::VddhDiv5Input.degrade_saadc())
@@ -47,10 +48,12 @@ pub(crate) fn expand_adc_device(
                                 ble.adc_divider_total.unwrap_or(1),
                             )
                         };
+                        let battery_level_config = expand_battery_level_config(&ble);
                         let bat_ident = format_ident!("battery_processor");
                         let battery_processor = Initializer {
                             initializer: quote! {
-                                let mut #bat_ident = ::rmk::input_device::battery::BatteryProcessor::new(#adc_divider_measured, #adc_divider_total, &keymap);
+                                let battery_level_config = #battery_level_config;
+                                let mut #bat_ident = ::rmk::input_device::battery::BatteryProcessor::with_level_config(#adc_divider_measured, #adc_divider_total, battery_level_config, &keymap);
                             },
                             var_name: bat_ident,
                         };
@@ -86,11 +89,29 @@ pub(crate) fn expand_adc_device(
                     transform,
                     bias,
                     resolution,
+                    deadzone,
+                    curve,
+                    sensitivity,
+                    invert_x,
+                    invert_y,
                     ..
                 } = joystick;
+                let deadzone = deadzone.unwrap_or(0);
+                let curve = match curve {
+                    Some(JoystickCurve::linear) | None => quote! { Linear },
+                    Some(JoystickCurve::quadratic) => quote! { Quadratic },
+                };
+                let sensitivity = sensitivity.unwrap_or(100);
                 let joystick_processor = Initializer {
                     initializer: quote! {
-                        let mut #joy_ident = rmk::input_device::joystick::JoystickProcessor::new([#([#(#transform),*]),*], [#(#bias),*], #resolution, &keymap);
+                        let mut #joy_ident = rmk::input_device::joystick::JoystickProcessor::new([#([#(#transform),*]),*], [#(#bias),*], #resolution, &keymap)
+                            .with_response(rmk::input_device::joystick::JoystickResponse {
+                                deadzone: #deadzone,
+                                curve: rmk::input_device::joystick::JoystickCurve::#curve,
+                                sensitivity: #sensitivity,
+                                invert_x: #invert_x,
+                                invert_y: #invert_y,
+                            });
                     },
                     var_name: joy_ident,
                 };
@@ -136,3 +157,29 @@ pub(crate) fn expand_adc_device(
         _ => (Vec::new(), Vec::new()),
     }
 }
+
+/// Expand the `BatteryLevelConfig` of the battery ADC, which is used by both the central and the peripherals
+pub(crate) fn expand_battery_level_config(ble: &BleConfig) -> TokenStream2 {
+    let curve = match &ble.battery_curve {
+        Some(curve) => {
+            let points = curve.iter().map(|(mv, level)| quote! { (#mv, #level) });
+            quote! { &[#(#points),*] }
+        }
+        None => quote! { &::rmk::input_device::battery::DEFAULT_BATTERY_CURVE },
+    };
+    let smoothing_window = match ble.battery_smoothing_window {
+        Some(window) => quote! { #window },
+        None => quote! { ::rmk::input_device::battery::BatteryLevelConfig::default().smoothing_window },
+    };
+    let low_battery_threshold = match ble.low_battery_threshold {
+        Some(threshold) => quote! { #threshold },
+        None => quote! { ::rmk::input_device::battery::BatteryLevelConfig::default().low_battery_threshold },
+    };
+    quote! {
+        ::rmk::input_device::battery::BatteryLevelConfig {
+            curve: #curve,
+            smoothing_window: #smoothing_window,
+            low_battery_threshold: #low_battery_threshold,
+        }
+    }
+}
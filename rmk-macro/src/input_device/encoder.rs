@@ -76,6 +76,15 @@ pub(crate) fn expand_encoder_device(
             }
         };
 
+        // Divide the pulses of the encoder, so that one detent triggers one action
+        let encoder_device = match encoder.pulses_per_detent {
+            Some(pulses_per_detent) => quote! {
+                #encoder_device
+                let mut #encoder_name = #encoder_name.with_pulses_per_detent(#pulses_per_detent);
+            },
+            None => encoder_device,
+        };
+
         device_initializer.push(Initializer {
             initializer: encoder_device,
             var_name: encoder_name,
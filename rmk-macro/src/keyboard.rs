@@ -17,7 +17,10 @@ use crate::import::expand_custom_imports;
 use crate::input_device::expand_input_device_config;
 use crate::keyboard_config::{expand_keyboard_info, expand_vial_config, read_keyboard_toml_config};
 use crate::layout::expand_default_keymap;
-use crate::matrix::expand_matrix_config;
+use crate::matrix::{
+    expand_debounce_config, expand_diode_direction, expand_matrix_config, expand_scan_interval,
+    get_central_matrix_config, is_row2col,
+};
 use crate::split::central::expand_split_central_config;
 
 /// List of functions that can be overwritten
@@ -128,6 +131,11 @@ fn expand_main(
     let matrix_and_keyboard = expand_matrix_and_keyboard_init(keyboard_config, rmk_features);
     let (controller_initializers, controllers) = expand_controller_init(keyboard_config, &item_mod);
     let run_rmk = expand_rmk_entry(keyboard_config, &item_mod, devices, processors, controllers);
+    let set_debounce_config = expand_debounce_config(&get_central_matrix_config(keyboard_config))
+        .map(|debounce_config| quote! { debounce_config: #debounce_config, });
+    let diode_direction = expand_diode_direction(&get_central_matrix_config(keyboard_config), rmk_features);
+    let set_scan_interval = expand_scan_interval(&get_central_matrix_config(keyboard_config))
+        .map(|scan_interval| quote! { scan_interval: #scan_interval, });
 
     let rmk_config = if keyboard_config.get_storage_config().enabled {
         quote! {
@@ -135,6 +143,9 @@ fn expand_main(
                 usb_config: KEYBOARD_USB_CONFIG,
                 vial_config: VIAL_CONFIG,
                 storage_config,
+                #set_debounce_config
+                diode_direction: #diode_direction,
+                #set_scan_interval
                 #set_ble_config
                 ..Default::default()
             };
@@ -144,6 +155,9 @@ fn expand_main(
             let rmk_config = ::rmk::config::RmkConfig {
                 usb_config: KEYBOARD_USB_CONFIG,
                 vial_config: VIAL_CONFIG,
+                #set_debounce_config
+                diode_direction: #diode_direction,
+                #set_scan_interval
                 #set_ble_config
                 ..Default::default()
             };
@@ -273,11 +287,25 @@ pub(crate) fn expand_matrix_and_keyboard_init(
         quote! { COL, ROW }
     };
 
-    let debouncer_type = if rapid_debouncer_enabled {
-        quote! { ::rmk::debounce::fast_debouncer::RapidDebouncer }
-    } else {
-        quote! { ::rmk::debounce::default_debouncer::DefaultDebouncer }
-    };
+    // `ConfigurableDebouncer` reads `debounce_config` in `RmkConfig`
+    let (debouncer_type, debouncer_config) =
+        if expand_debounce_config(&get_central_matrix_config(keyboard_config)).is_some() {
+            (
+                quote! { ::rmk::debounce::configurable_debouncer::ConfigurableDebouncer },
+                quote! { rmk_config.debounce_config },
+            )
+        } else if rapid_debouncer_enabled {
+            (quote! { ::rmk::debounce::fast_debouncer::RapidDebouncer }, quote! {})
+        } else {
+            (
+                quote! { ::rmk::debounce::default_debouncer::DefaultDebouncer },
+                quote! {},
+            )
+        };
+
+    // The scan interval is set in `RmkConfig` only if it's configured, otherwise the matrix uses its own default
+    let with_scan_interval = expand_scan_interval(&get_central_matrix_config(keyboard_config))
+        .map(|_| quote! { .with_scan_interval(rmk_config.scan_interval) });
 
     let matrix = match keyboard_config.get_board_config().unwrap() {
         BoardConfig::UniBody(UniBodyConfig {
@@ -285,21 +313,25 @@ pub(crate) fn expand_matrix_and_keyboard_init(
             input_device: _,
         }) => match matrix_config.matrix_type {
             MatrixType::normal => {
-                if matrix_config.row2col {
-                    eprintln!(
-                        "row2col is enabled, please ensure that you have updated your Cargo.toml, disabled default features(col2row is enabled as default feature)"
-                    );
-                }
+                // Output pins are rows for row2col matrix
+                let matrix_pin_num = if is_row2col(&matrix_config, rmk_features) {
+                    quote! { COL, ROW }
+                } else {
+                    quote! { ROW, COL }
+                };
                 quote! {
-                    let debouncer = #debouncer_type::<#input_output_num>::new();
-                    let mut matrix = ::rmk::matrix::Matrix::<_, _, _, #input_output_num>::new(input_pins, output_pins, debouncer);
+                    let debouncer = #debouncer_type::<#matrix_pin_num>::new(#debouncer_config);
+                    let mut matrix = ::rmk::matrix::Matrix::<_, _, _, #matrix_pin_num>::new(input_pins, output_pins, debouncer)
+                        .with_diode_direction(rmk_config.diode_direction)
+                        #with_scan_interval;
                 }
             }
             MatrixType::direct_pin => {
                 let low_active = matrix_config.direct_pin_low_active;
                 quote! {
-                    let debouncer = #debouncer_type::<COL, ROW>::new();
-                    let mut matrix = ::rmk::direct_pin::DirectPinMatrix::<_, _, #input_output_num, SIZE>::new(direct_pins, debouncer, #low_active);
+                    let debouncer = #debouncer_type::<COL, ROW>::new(#debouncer_config);
+                    let mut matrix = ::rmk::direct_pin::DirectPinMatrix::<_, _, #input_output_num, SIZE>::new(direct_pins, debouncer, #low_active)
+                        #with_scan_interval;
                 }
             }
         },
@@ -309,25 +341,26 @@ pub(crate) fn expand_matrix_and_keyboard_init(
             let central_row_offset = split_config.central.row_offset;
             let central_col = split_config.central.cols;
             let central_col_offset = split_config.central.col_offset;
-            let input_output_pin_num = if split_config.central.matrix.row2col {
-                eprintln!(
-                    "row2col is enabled, please ensure that you have updated your Cargo.toml, disabled default features(col2row is enabled as default feature)"
-                );
-                quote! { #central_row_offset, #central_col_offset, #central_col, #central_row }
+            // Output pins are rows for row2col matrix
+            let central_pin_num = if is_row2col(&split_config.central.matrix, rmk_features) {
+                quote! { #central_col, #central_row }
             } else {
-                quote! { #central_row_offset, #central_col_offset, #central_row, #central_col }
+                quote! { #central_row, #central_col }
             };
             match split_config.central.matrix.matrix_type {
                 MatrixType::normal => quote! {
-                    let debouncer = #debouncer_type::<#input_output_num>::new();
-                    let mut matrix = ::rmk::split::central::CentralMatrix::<_, _, _, #input_output_pin_num>::new(input_pins, output_pins, debouncer);
+                    let debouncer = #debouncer_type::<#central_pin_num>::new(#debouncer_config);
+                    let mut matrix = ::rmk::split::central::CentralMatrix::<_, _, _, #central_row_offset, #central_col_offset, #central_pin_num>::new(input_pins, output_pins, debouncer)
+                        .with_diode_direction(rmk_config.diode_direction)
+                        #with_scan_interval;
                 },
                 MatrixType::direct_pin => {
                     let low_active = split_config.central.matrix.direct_pin_low_active;
                     let size = split_config.central.rows * split_config.central.cols;
                     quote! {
-                        let debouncer = #debouncer_type::<COL, ROW>::new();
-                        let mut matrix = ::rmk::split::central::CentralDirectPinMatrix::<_, _, #central_row_offset, #central_col_offset, #central_row, #central_col, #size>::new(direct_pins, debouncer, #low_active);
+                        let debouncer = #debouncer_type::<COL, ROW>::new(#debouncer_config);
+                        let mut matrix = ::rmk::split::central::CentralDirectPinMatrix::<_, _, #central_row_offset, #central_col_offset, #central_row, #central_col, #size>::new(direct_pins, debouncer, #low_active)
+                        #with_scan_interval;
                     }
                 }
             }
@@ -18,6 +18,8 @@ pub(crate) fn expand_keyboard_info(keyboard_config: &KeyboardTomlConfig) -> proc
     let product_name = basic.product_name.clone();
     let manufacturer = basic.manufacturer.clone();
     let serial_number = basic.serial_number.clone();
+    let poll_interval_ms = basic.usb_poll_interval;
+    let remote_wakeup = basic.usb_remote_wakeup;
 
     let num_col = layout.cols as usize;
     let num_row = layout.rows as usize;
@@ -35,6 +37,8 @@ pub(crate) fn expand_keyboard_info(keyboard_config: &KeyboardTomlConfig) -> proc
             manufacturer: #manufacturer,
             product_name: #product_name,
             serial_number: #serial_number,
+            poll_interval_ms: #poll_interval_ms,
+            remote_wakeup: #remote_wakeup,
         };
     }
 }
@@ -54,12 +58,14 @@ pub(crate) fn expand_vial_config(config: &KeyboardTomlConfig) -> proc_macro2::To
     } else {
         quote! { &[] }
     };
+    let reboot_after_reset = config.get_storage_config().reboot_after_reset.unwrap_or(true);
     quote! {
         include!(concat!(env!("OUT_DIR"), "/config_generated.rs"));
         const VIAL_CONFIG: ::rmk::config::VialConfig = ::rmk::config::VialConfig {
             vial_keyboard_id: &VIAL_KEYBOARD_ID,
             vial_keyboard_def: &VIAL_KEYBOARD_DEF,
-            unlock_keys: #unlock_keys
+            unlock_keys: #unlock_keys,
+            reboot_after_reset: #reboot_after_reset,
         };
     }
 }
@@ -368,6 +368,37 @@ pub(crate) fn parse_key(key: String) -> TokenStream2 {
                 );
             }
         }
+        s if s.to_lowercase().starts_with("uc(") => {
+            // The code point is either a hex number like `UC(0x1F600)`/`UC(U+1F600)`, or the character itself like `UC(😀)`
+            let internal = s
+                .get(3..)
+                .and_then(|s| s.strip_suffix(")"))
+                .map(|s| s.trim())
+                .unwrap_or_default();
+            let hex = internal
+                .strip_prefix("0x")
+                .or_else(|| internal.strip_prefix("U+"))
+                .or_else(|| internal.strip_prefix("u+"));
+            let code_point = match hex {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => {
+                    let mut chars = internal.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => Some(c as u32),
+                        _ => None,
+                    }
+                }
+            };
+            match code_point.and_then(char::from_u32) {
+                Some(c) => {
+                    let code_point = c as u32;
+                    quote! { ::rmk::uc!(#code_point) }
+                }
+                None => panic!(
+                    "\n❌ keyboard.toml: UC(code_point) invalid, please check the documentation: https://rmk.rs/docs/features/configuration/layout.html"
+                ),
+            }
+        }
         s if s.to_lowercase().starts_with("td(") => {
             let index = get_number(s.clone(), s.get(0..3).unwrap(), ")");
             quote! {
@@ -380,6 +411,15 @@ pub(crate) fn parse_key(key: String) -> TokenStream2 {
                 ::rmk::m!(#index)
             }
         }
+        s if s.to_lowercase().starts_with("bt(") => {
+            let profile = get_number(s.clone(), s.get(0..3).unwrap(), ")");
+            quote! {
+                ::rmk::bt!(#profile)
+            }
+        }
+        s if s.to_lowercase() == "bt_next" => quote! { ::rmk::bt_next!() },
+        s if s.to_lowercase() == "bt_prev" => quote! { ::rmk::bt_prev!() },
+        s if s.to_lowercase() == "bt_pair" => quote! { ::rmk::bt_pair!() },
         _ => {
             let ident = get_key_with_alias(key);
             quote! { ::rmk::k!(#ident) }
@@ -401,3 +441,48 @@ pub(crate) fn get_key_with_alias(key: String) -> Ident {
     };
     format_ident!("{}", key)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_key(key: &str, expected: TokenStream2) {
+        assert_eq!(parse_key(key.to_string()).to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn test_parse_layer_tap() {
+        assert_key("LT(1, Space)", quote! { ::rmk::lt!(1u8, Space) });
+        assert_key("lt(2,Backspace)", quote! { ::rmk::lt!(2u8, Backspace) });
+        // Same as MT, the tap key isn't limited to basic keycodes
+        assert_key("LT(1, AudioVolUp)", quote! { ::rmk::lt!(1u8, AudioVolUp) });
+    }
+
+    #[test]
+    fn test_parse_modifier_tap() {
+        assert_key(
+            "MT(A, LShift)",
+            quote! { ::rmk::mt!(A, ::rmk::types::modifier::ModifierCombination::new_from(false, false, false, true, false)) },
+        );
+        assert_key(
+            "mt(Escape, LCtrl | LAlt)",
+            quote! { ::rmk::mt!(Escape, ::rmk::types::modifier::ModifierCombination::new_from(false, false, true, false, true)) },
+        );
+        assert_key(
+            "MT(AudioVolUp, RGui)",
+            quote! { ::rmk::mt!(AudioVolUp, ::rmk::types::modifier::ModifierCombination::new_from(true, true, false, false, false)) },
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_invalid_layer_tap() {
+        parse_key("LT(1)".to_string());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_modifier_tap_without_modifier() {
+        parse_key("MT(A, B)".to_string());
+    }
+}
@@ -1,7 +1,10 @@
 //! Initialize matrix initialization boilerplate of RMK
 //!
 use quote::quote;
-use rmk_config::{BoardConfig, ChipModel, ChipSeries, KeyboardTomlConfig, MatrixType, UniBodyConfig};
+use rmk_config::{
+    BoardConfig, ChipModel, ChipSeries, DebounceMode, DebounceScope, KeyboardTomlConfig, MatrixConfig, MatrixType,
+    PinPull, UniBodyConfig,
+};
 
 use crate::feature::is_feature_enabled;
 use crate::gpio_config::{
@@ -29,6 +32,7 @@ pub(crate) fn expand_matrix_config(
                 matrix_config.extend(expand_matrix_direct_pins(
                     &keyboard_config.get_chip_model().unwrap(),
                     matrix.direct_pins.clone().unwrap(),
+                    matrix.direct_pin_pull.clone(),
                     async_matrix,
                     matrix.direct_pin_low_active,
                 ));
@@ -61,6 +65,7 @@ pub(crate) fn expand_matrix_config(
                 MatrixType::direct_pin => matrix_config.extend(expand_matrix_direct_pins(
                     &keyboard_config.get_chip_model().unwrap(),
                     split_config.central.matrix.direct_pins.clone().unwrap(),
+                    split_config.central.matrix.direct_pin_pull.clone(),
                     async_matrix,
                     split_config.central.matrix.direct_pin_low_active,
                 )),
@@ -73,6 +78,7 @@ pub(crate) fn expand_matrix_config(
 pub(crate) fn expand_matrix_direct_pins(
     chip: &ChipModel,
     direct_pins: Vec<Vec<String>>,
+    pulls: Option<Vec<Vec<PinPull>>>,
     async_matrix: bool,
     low_active: bool,
 ) -> proc_macro2::TokenStream {
@@ -85,6 +91,7 @@ pub(crate) fn expand_matrix_direct_pins(
     pin_initialization.extend(convert_direct_pins_to_initializers(
         chip,
         direct_pins,
+        pulls,
         async_matrix,
         low_active,
     ));
@@ -133,3 +140,69 @@ pub(crate) fn expand_matrix_input_output_pins(
         };
     }
 }
+
+/// Expand `DebounceConfig` if any debounce option is set in the matrix config.
+/// Otherwise the default debouncer selected by the `rapid_debouncer` feature is used.
+pub(crate) fn expand_debounce_config(matrix: &MatrixConfig) -> Option<proc_macro2::TokenStream> {
+    if matrix.debounce_mode.is_none() && matrix.debounce_scope.is_none() && matrix.debounce_window.is_none() {
+        return None;
+    }
+    let mut fields = proc_macro2::TokenStream::new();
+    if let Some(mode) = matrix.debounce_mode {
+        let mode = match mode {
+            DebounceMode::eager => quote! { Eager },
+            DebounceMode::defer => quote! { Defer },
+        };
+        fields.extend(quote! { mode: ::rmk::config::DebounceMode::#mode, });
+    }
+    if let Some(scope) = matrix.debounce_scope {
+        let scope = match scope {
+            DebounceScope::per_key => quote! { PerKey },
+            DebounceScope::global => quote! { Global },
+        };
+        fields.extend(quote! { scope: ::rmk::config::DebounceScope::#scope, });
+    }
+    if let Some(window) = &matrix.debounce_window {
+        let window = window.0;
+        fields.extend(quote! { window: ::embassy_time::Duration::from_millis(#window), });
+    }
+    Some(quote! {
+        ::rmk::config::DebounceConfig {
+            #fields
+            ..Default::default()
+        }
+    })
+}
+
+/// Expand the scan interval if it's set in the matrix config
+pub(crate) fn expand_scan_interval(matrix: &MatrixConfig) -> Option<proc_macro2::TokenStream> {
+    matrix.scan_interval.as_ref().map(|interval| {
+        let interval = interval.0;
+        quote! { ::embassy_time::Duration::from_millis(#interval) }
+    })
+}
+
+/// Get the matrix config of the keyboard, or the central's matrix config for split keyboards
+pub(crate) fn get_central_matrix_config(keyboard_config: &KeyboardTomlConfig) -> MatrixConfig {
+    match keyboard_config.get_board_config().unwrap() {
+        BoardConfig::UniBody(UniBodyConfig { matrix, .. }) => matrix,
+        BoardConfig::Split(split_config) => split_config.central.matrix,
+    }
+}
+
+/// Whether the matrix is row2col, either set in `keyboard.toml` or by disabling the `col2row` feature
+pub(crate) fn is_row2col(matrix: &MatrixConfig, rmk_features: &Option<Vec<String>>) -> bool {
+    matrix.row2col || !is_feature_enabled(rmk_features, "col2row")
+}
+
+/// Expand `DiodeDirection` of the matrix
+pub(crate) fn expand_diode_direction(
+    matrix: &MatrixConfig,
+    rmk_features: &Option<Vec<String>>,
+) -> proc_macro2::TokenStream {
+    if is_row2col(matrix, rmk_features) {
+        quote! { ::rmk::config::DiodeDirection::Row2Col }
+    } else {
+        quote! { ::rmk::config::DiodeDirection::Col2Row }
+    }
+}
@@ -11,10 +11,13 @@ use crate::entry::join_all_tasks;
 use crate::feature::{get_rmk_features, is_feature_enabled};
 use crate::flash::expand_flash_init;
 use crate::import::expand_custom_imports;
-use crate::input_device::adc::expand_adc_device;
+use crate::input_device::adc::{expand_adc_device, expand_battery_level_config};
 use crate::input_device::encoder::expand_encoder_device;
 use crate::keyboard_config::read_keyboard_toml_config;
-use crate::matrix::{expand_matrix_direct_pins, expand_matrix_input_output_pins};
+use crate::matrix::{
+    expand_debounce_config, expand_diode_direction, expand_matrix_direct_pins, expand_matrix_input_output_pins,
+    expand_scan_interval, is_row2col,
+};
 use crate::split::central::expand_serial_init;
 
 /// Parse split peripheral mod and generate a valid RMK main function with all needed code
@@ -161,24 +164,36 @@ fn expand_split_peripheral(
 
     // Debouncer config
     let rapid_debouncer_enabled = is_feature_enabled(rmk_features, "rapid_debouncer");
-    let col2row_enabled = is_feature_enabled(rmk_features, "col2row");
     let col = peripheral_config.cols;
     let row = peripheral_config.rows;
-    let input_output_num = if col2row_enabled {
-        quote! { #row, #col }
-    } else {
+    // Output pins are rows for row2col matrix
+    let input_output_num = if is_row2col(&peripheral_config.matrix, rmk_features) {
         quote! { #col, #row }
-    };
-
-    let debouncer_type = if rapid_debouncer_enabled {
-        quote! { ::rmk::debounce::fast_debouncer::RapidDebouncer }
     } else {
-        quote! { ::rmk::debounce::default_debouncer::DefaultDebouncer }
+        quote! { #row, #col }
     };
+    let diode_direction = expand_diode_direction(&peripheral_config.matrix, rmk_features);
+
+    let (debouncer_type, debouncer_config) =
+        if let Some(debounce_config) = expand_debounce_config(&peripheral_config.matrix) {
+            (
+                quote! { ::rmk::debounce::configurable_debouncer::ConfigurableDebouncer },
+                debounce_config,
+            )
+        } else if rapid_debouncer_enabled {
+            (quote! { ::rmk::debounce::fast_debouncer::RapidDebouncer }, quote! {})
+        } else {
+            (
+                quote! { ::rmk::debounce::default_debouncer::DefaultDebouncer },
+                quote! {},
+            )
+        };
 
     // Matrix config
     let async_matrix = is_feature_enabled(rmk_features, "async_matrix");
     let chip = keyboard_config.get_chip_model().unwrap();
+    let with_scan_interval = expand_scan_interval(&peripheral_config.matrix)
+        .map(|scan_interval| quote! { .with_scan_interval(#scan_interval) });
     let mut matrix_config = proc_macro2::TokenStream::new();
     match &peripheral_config.matrix.matrix_type {
         MatrixType::normal => {
@@ -198,8 +213,10 @@ fn expand_split_peripheral(
             ));
 
             matrix_config.extend(quote! {
-                let debouncer = #debouncer_type::<#input_output_num>::new();
-                let mut matrix = ::rmk::matrix::Matrix::<_, _, _, #input_output_num>::new(input_pins, output_pins, debouncer);
+                let debouncer = #debouncer_type::<#input_output_num>::new(#debouncer_config);
+                let mut matrix = ::rmk::matrix::Matrix::<_, _, _, #input_output_num>::new(input_pins, output_pins, debouncer)
+                    .with_diode_direction(#diode_direction)
+                    #with_scan_interval;
             });
         }
         MatrixType::direct_pin => {
@@ -210,6 +227,7 @@ fn expand_split_peripheral(
                     .direct_pins
                     .clone()
                     .expect("split.peripheral.matrix.direct_pins is required"),
+                peripheral_config.matrix.direct_pin_pull.clone(),
                 async_matrix,
                 peripheral_config.matrix.direct_pin_low_active,
             ));
@@ -219,15 +237,18 @@ fn expand_split_peripheral(
             let low_active = peripheral_config.matrix.direct_pin_low_active;
 
             matrix_config.extend(quote! {
-                let debouncer = #debouncer_type::<#col, #row>::new();
-                let mut matrix = ::rmk::direct_pin::DirectPinMatrix::<_, _, #row, #col, #size>::new(direct_pins, debouncer, #low_active);
+                let debouncer = #debouncer_type::<#col, #row>::new(#debouncer_config);
+                let mut matrix = ::rmk::direct_pin::DirectPinMatrix::<_, _, #row, #col, #size>::new(direct_pins, debouncer, #low_active)
+                    #with_scan_interval;
             });
         }
     }
 
     // Peripherals don't need to run processors
     let (device_initialization, devices, _processors) = expand_peripheral_input_device_config(id, keyboard_config);
-    let run_rmk_peripheral = expand_split_peripheral_entry(id, &chip, split_config, peripheral_config, devices);
+    let battery = expand_peripheral_battery(keyboard_config);
+    let run_rmk_peripheral =
+        expand_split_peripheral_entry(id, &chip, split_config, peripheral_config, devices, battery);
 
     quote! {
         #imports
@@ -244,6 +265,7 @@ fn expand_split_peripheral_entry(
     split_config: &SplitConfig,
     peripheral_config: &SplitBoardConfig,
     devices: Vec<TokenStream2>,
+    battery: TokenStream2,
 ) -> TokenStream2 {
     // Add matrix to devices, and run all devices
     let mut devs = devices.clone();
@@ -256,10 +278,11 @@ fn expand_split_peripheral_entry(
 
     if split_config.connection == "ble" {
         let peripheral_run = quote! {
-            ::rmk::split::peripheral::run_rmk_split_peripheral(
+            ::rmk::split::peripheral::run_rmk_split_peripheral_with_battery(
                 #id,
                 &stack,
                 &mut storage,
+                #battery
             )
         };
         let run_rmk_peripheral = join_all_tasks(vec![device_task, peripheral_run]);
@@ -300,6 +323,28 @@ fn expand_split_peripheral_entry(
     }
 }
 
+/// Expand the battery arguments of the peripheral, which are same as the central:
+/// `(adc_divider_measured, adc_divider_total)` of the battery ADC, `None` if the battery ADC isn't configured,
+/// and the `BatteryLevelConfig`
+fn expand_peripheral_battery(keyboard_config: &KeyboardTomlConfig) -> TokenStream2 {
+    match keyboard_config.get_communication_config().unwrap() {
+        CommunicationConfig::Ble(ble_config) | CommunicationConfig::Both(_, ble_config) if ble_config.enabled => {
+            let adc_divider = match ble_config.battery_adc_pin.as_deref() {
+                Some("vddh") => quote! { Some((1, 5)) },
+                Some(_) => {
+                    let measured = ble_config.adc_divider_measured.unwrap_or(1);
+                    let total = ble_config.adc_divider_total.unwrap_or(1);
+                    quote! { Some((#measured, #total)) }
+                }
+                None => quote! { None },
+            };
+            let level_config = expand_battery_level_config(&ble_config);
+            quote! { #adc_divider, #level_config }
+        }
+        _ => quote! { None, ::rmk::input_device::battery::BatteryLevelConfig::default() },
+    }
+}
+
 /// Returns (device initializations, device_names, processor_names)
 pub(crate) fn expand_peripheral_input_device_config(
     id: usize,
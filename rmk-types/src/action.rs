@@ -76,6 +76,8 @@ pub enum KeyAction {
     TapHold(Action, Action),
     /// Morse action, references a morse configuration by index.
     Morse(u8),
+    /// Activate a layer while held, toggle the layer when it's tapped several times, like QMK's `TT`.
+    LayerTapToggle(u8),
 }
 
 impl KeyAction {
@@ -134,4 +136,15 @@ pub enum Action {
     OneShotModifier(ModifierCombination),
     /// Oneshot key, keep the key active until the next key is triggered.
     OneShotKey(KeyCode),
+    /// Type a unicode code point with the input method of the host.
+    Unicode(u32),
+    /// Select the BLE profile, processed when the key is released.
+    /// A profile without a bonded host advertises as pairable after it's selected
+    ProfileSelect(u8),
+    /// Switch to the next BLE profile, processed when the key is released
+    ProfileNext,
+    /// Switch to the previous BLE profile, processed when the key is released
+    ProfilePrevious,
+    /// Advertise the active BLE profile as pairable for 30s, processed when the key is released
+    PairingMode,
 }
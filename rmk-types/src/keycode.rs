@@ -793,6 +793,10 @@ pub enum KeyCode {
     TriLayerUpper = 0x778,
     RepeatKey = 0x779,
     AltRepeatKey = 0x77A,
+    LayerLock = 0x77B,
+    PointingCpiCycle = 0x77C,
+    PointingScrollMode = 0x77D,
+    BypassToggle = 0x77E,
     // Kb keycodes, use 0x800 ~ 0x81F
     Kb0 = 0x800,
     Kb1 = 0x801,
@@ -973,6 +977,16 @@ impl KeyCode {
         KeyCode::Macro0 <= self && self <= KeyCode::Macro31
     }
 
+    /// Returns `true` if the keycode is a space cadet keycode
+    pub fn is_space_cadet(self) -> bool {
+        KeyCode::SpaceCadetLCtrlParenthesisOpen <= self && self <= KeyCode::SpaceCadetRShiftEnter
+    }
+
+    /// Returns `true` if the keycode is a dynamic macro keycode
+    pub fn is_dynamic_macro(self) -> bool {
+        KeyCode::DynamicMacroRecordStart1 <= self && self <= KeyCode::DynamicMacroPlay2
+    }
+
     /// Returns `true` if the keycode is a backlight keycode
     pub fn is_backlight(self) -> bool {
         KeyCode::BacklightOn <= self && self <= KeyCode::BacklightToggleBreathing
@@ -985,7 +999,7 @@ impl KeyCode {
 
     /// Returns `true` if the keycode is defined by rmk to achieve special functionalities, such as reboot keyboard, goto bootloader, etc.
     pub fn is_rmk(self) -> bool {
-        KeyCode::Bootloader <= self && self <= KeyCode::AltRepeatKey
+        KeyCode::Bootloader <= self && self <= KeyCode::BypassToggle
     }
 
     /// Returns `true` if the keycode is a combo keycode
@@ -1031,6 +1045,11 @@ impl KeyCode {
         false
     }
 
+    /// Does current keycode get shifted while caps word is active
+    pub fn is_caps_word_shifted_key(self) -> bool {
+        (self >= KeyCode::A && self <= KeyCode::Z) || self == KeyCode::Minus
+    }
+
     /// Convert a keycode to usb hid media key
     pub fn as_consumer_control_usage_id(self) -> ConsumerKey {
         match self {
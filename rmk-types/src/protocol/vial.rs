@@ -64,6 +64,112 @@ impl TryFrom<u8> for ViaKeyboardInfo {
     }
 }
 
+/// Channels of via custom values, used by `CustomGetValue`/`CustomSetValue`/`CustomSave`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, FromRepr)]
+#[repr(u8)]
+pub enum ViaChannelId {
+    Custom = 0x00,
+    Backlight = 0x01,
+    RgbLight = 0x02,
+    RgbMatrix = 0x03,
+    Audio = 0x04,
+    LedMatrix = 0x05,
+}
+
+impl TryFrom<u8> for ViaChannelId {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::from_repr(value).ok_or(value)
+    }
+}
+
+/// RMK specific values on the custom channel(`ViaChannelId::Custom`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, FromRepr)]
+#[repr(u8)]
+pub enum RmkCustomValue {
+    /// Index of the active BLE profile
+    BleProfile = 0x01,
+    /// Clear the bonding information of a BLE profile, write only
+    ClearBleBond = 0x02,
+    /// Battery level of a split peripheral, read only
+    PeripheralBattery = 0x03,
+    /// A chunk of the keymap, used for backup and restore
+    KeymapChunk = 0x04,
+    /// Number of keys and the checksum of the keymap, read only
+    KeymapChecksum = 0x05,
+    /// Debounce rejection and chatter counters of keys, writing any value resets the counters
+    DebounceStats = 0x06,
+    /// The action which is triggered if a key is pressed now, resolved with the active layers, read only
+    EffectiveKeyCode = 0x07,
+    /// Usage of the storage, read only
+    StorageStats = 0x08,
+    /// RSSI of the BLE connection to the host or a split peripheral, read only
+    BleRssi = 0x09,
+    /// Compact the storage, write only
+    StorageCompaction = 0x0A,
+}
+
+impl TryFrom<u8> for RmkCustomValue {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::from_repr(value).ok_or(value)
+    }
+}
+
+/// Values on the rgblight channel(`ViaChannelId::RgbLight`), same as QMK's `via_qmk_rgblight_value`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, FromRepr)]
+#[repr(u8)]
+pub enum ViaRgbLightValue {
+    Brightness = 0x01,
+    Effect = 0x02,
+    EffectSpeed = 0x03,
+    Color = 0x04,
+}
+
+impl TryFrom<u8> for ViaRgbLightValue {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::from_repr(value).ok_or(value)
+    }
+}
+
+/// Values on the RGB matrix channel(`ViaChannelId::RgbMatrix`), same as QMK's `via_qmk_rgb_matrix_value`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, FromRepr)]
+#[repr(u8)]
+pub enum ViaRgbMatrixValue {
+    Brightness = 0x01,
+    Effect = 0x02,
+    EffectSpeed = 0x03,
+    Color = 0x04,
+}
+
+impl TryFrom<u8> for ViaRgbMatrixValue {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::from_repr(value).ok_or(value)
+    }
+}
+
+/// Values on the backlight channel(`ViaChannelId::Backlight`), same as QMK's `via_qmk_backlight_value`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, FromRepr)]
+#[repr(u8)]
+pub enum ViaBacklightValue {
+    Brightness = 0x01,
+    Effect = 0x02,
+}
+
+impl TryFrom<u8> for ViaBacklightValue {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::from_repr(value).ok_or(value)
+    }
+}
+
 /// Vial communication commands.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, FromRepr)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
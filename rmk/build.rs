@@ -51,8 +51,13 @@ fn get_constants_str(constants: RmkConstantsConfig) -> String {
         const_declaration!(pub(crate) MOUSE_WHEEL_INTERVAL = constants.mouse_wheel_interval),
         const_declaration!(pub(crate) COMBO_MAX_NUM = constants.combo_max_num),
         const_declaration!(pub(crate) COMBO_MAX_LENGTH = constants.combo_max_length),
+        const_declaration!(pub(crate) LEADER_MAX_NUM = constants.leader_max_num),
+        const_declaration!(pub(crate) LEADER_MAX_LENGTH = constants.leader_max_length),
         const_declaration!(pub(crate) MACRO_SPACE_SIZE = constants.macro_space_size),
+        const_declaration!(pub(crate) NUM_MACRO = constants.macro_max_num),
+        const_declaration!(pub(crate) DYNAMIC_MACRO_MAX_LENGTH = constants.dynamic_macro_max_length),
         const_declaration!(pub(crate) FORK_MAX_NUM = constants.fork_max_num),
+        const_declaration!(pub(crate) KEY_OVERRIDE_MAX_NUM = constants.key_override_max_num),
         const_declaration!(pub(crate) DEBOUNCE_THRESHOLD = constants.debounce_time),
         const_declaration!(pub(crate) EVENT_CHANNEL_SIZE = constants.event_channel_size),
         const_declaration!(pub(crate) CONTROLLER_CHANNEL_SIZE = constants.controller_channel_size),
@@ -65,6 +70,7 @@ fn get_constants_str(constants: RmkConstantsConfig) -> String {
         const_declaration!(pub(crate) SPLIT_MESSAGE_CHANNEL_SIZE = constants.split_message_channel_size),
         const_declaration!(pub(crate) NUM_BLE_PROFILE = constants.ble_profiles_num),
         const_declaration!(pub(crate) SPLIT_CENTRAL_SLEEP_TIMEOUT_MINUTES = constants.split_central_sleep_timeout_minutes),
+        const_declaration!(pub(crate) SPLIT_HEARTBEAT_INTERVAL = constants.split_heartbeat_interval),
         const_declaration!(pub(crate) MORSE_MAX_NUM = constants.morse_max_num),
         const_declaration!(pub(crate) MAX_PATTERNS_PER_KEY = constants.max_patterns_per_key),
         format!("pub(crate) const BUILD_HASH: u32 = {build_hash:#010x};\n"),
@@ -112,6 +112,16 @@ impl<P: PacketPool> HidWriterTrait for BleHidServer<'_, '_, '_, P> {
                 })?;
                 Ok(n)
             }
+            Report::NkroKeyboardReport(nkro_report) => {
+                // NKRO isn't supported over BLE, fallback to 6KRO
+                let mut buf = [0u8; 8];
+                let n = serialize(&mut buf, &nkro_report.to_6kro()).map_err(|_| HidError::ReportSerializeError)?;
+                self.input_keyboard.notify(self.conn, &buf).await.map_err(|e| {
+                    error!("Failed to notify keyboard report: {:?}", e);
+                    HidError::BleError
+                })?;
+                Ok(n)
+            }
             Report::MouseReport(mouse_report) => {
                 let mut buf = [0u8; 5];
                 let n = serialize(&mut buf, &mouse_report).map_err(|_| HidError::ReportSerializeError)?;
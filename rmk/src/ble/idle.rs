@@ -0,0 +1,77 @@
+//! Idle mode of BLE keyboards
+//!
+//! After no key activity for `BleConfig::idle_timeout`, the keyboard enters the idle mode:
+//! the matrix is scanned at a slow rate and the advertising interval is increased.
+//! Any key event wakes the keyboard up.
+//!
+//! When USB is enabled, the keyboard is powered by the host, so it always stays awake.
+
+use core::sync::atomic::Ordering;
+
+use embassy_futures::select::{Either, select};
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+
+use crate::RawMutex;
+use crate::state::IDLE_STATE;
+
+/// Advertising interval used in the idle mode, if the configured interval is shorter
+pub(crate) const IDLE_ADV_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Signaled when there's key activity
+static KEY_ACTIVITY: Signal<RawMutex, ()> = Signal::new();
+/// Signaled when the idle state is changed
+pub(crate) static IDLE_STATE_CHANGED: Signal<RawMutex, bool> = Signal::new();
+
+/// Record a key activity, wakes the keyboard up if it's idle
+pub(crate) fn update_idle_activity() {
+    KEY_ACTIVITY.signal(());
+}
+
+/// Whether the keyboard is in the idle mode
+pub(crate) fn is_idle() -> bool {
+    IDLE_STATE.load(Ordering::Acquire)
+}
+
+fn set_idle(idle: bool) {
+    IDLE_STATE.store(idle, Ordering::Release);
+    IDLE_STATE_CHANGED.signal(idle);
+}
+
+/// Wait until the idle state is changed, returns the new state
+pub(crate) async fn wait_idle_state_changed() -> bool {
+    let current = is_idle();
+    loop {
+        if IDLE_STATE_CHANGED.wait().await != current {
+            return !current;
+        }
+    }
+}
+
+/// Enter the idle mode after `timeout` without key activity, `None` disables the idle mode
+pub(crate) async fn run_idle_manager(timeout: Option<Duration>) {
+    let Some(timeout) = timeout else {
+        core::future::pending::<()>().await;
+        return;
+    };
+
+    info!("Idle manager started with {}s timeout", timeout.as_secs());
+    loop {
+        match select(Timer::after(timeout), KEY_ACTIVITY.wait()).await {
+            Either::First(_) => {
+                // Keep awake when USB is enabled
+                #[cfg(not(feature = "_no_usb"))]
+                if crate::usb::USB_ENABLED.signaled() {
+                    continue;
+                }
+
+                info!("No key activity, entering idle mode");
+                set_idle(true);
+                KEY_ACTIVITY.wait().await;
+                info!("Key activity detected, leaving idle mode");
+                set_idle(false);
+            }
+            Either::Second(_) => (),
+        }
+    }
+}
@@ -1,11 +1,13 @@
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
 use bt_hci::cmd::le::{LeReadLocalSupportedFeatures, LeSetPhy};
 use bt_hci::controller::{ControllerCmdAsync, ControllerCmdSync};
-use embassy_futures::join::join;
-use embassy_futures::select::{Either3, select, select3};
-use embassy_time::{Duration, Timer, with_timeout};
+use embassy_futures::join::{join, join3};
+use embassy_futures::select::{Either, Either3, Either4, select, select3, select4};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{Duration, Instant, Timer, with_deadline, with_timeout};
 use rand_core::{CryptoRng, RngCore};
 use rmk_types::led_indicator::LedIndicator;
 use trouble_host::prelude::appearance::human_interface_device::KEYBOARD;
@@ -15,7 +17,6 @@ use trouble_host::prelude::*;
 use {
     crate::channel::{CONTROLLER_CHANNEL, send_controller_event},
     crate::event::ControllerEvent,
-    embassy_time::Instant,
 };
 #[cfg(not(feature = "_no_usb"))]
 use {
@@ -26,23 +27,24 @@ use {
     crate::usb::{USB_ENABLED, USB_REMOTE_WAKEUP, USB_SUSPENDED},
     crate::usb::{add_usb_reader_writer, add_usb_writer, new_usb_builder},
     crate::via::UsbVialReaderWriter,
-    embassy_futures::select::{Either, Either4, select4},
     embassy_usb::driver::Driver,
 };
 #[cfg(feature = "storage")]
-use {
-    crate::storage::{Storage, StorageData, StorageKeys},
-    crate::{read_storage, state::CONNECTION_TYPE},
-    embedded_storage_async::nor_flash::NorFlash as AsyncNorFlash,
-};
+use {crate::storage::Storage, embedded_storage_async::nor_flash::NorFlash as AsyncNorFlash};
 
 use crate::ble::battery_service::BleBatteryServer;
-use crate::ble::ble_server::{BleHidServer, BleViaServer, Server};
+use crate::ble::ble_server::{BleHidServer, BleViaServer, CCCD_TABLE_SIZE, Server};
 use crate::ble::device_info::{PnPID, VidSource};
+use crate::ble::idle::{IDLE_ADV_INTERVAL, is_idle, run_idle_manager, wait_idle_state_changed};
 use crate::ble::led::BleLedReader;
-use crate::ble::profile::{ProfileInfo, ProfileManager, UPDATED_CCCD_TABLE, UPDATED_PROFILE};
+use crate::ble::profile::{ProfileInfo, ProfileManager, UPDATED_CCCD_TABLE, UPDATED_PROFILE, is_bonded_peer};
+use crate::ble::rssi::{HOST_RSSI, RssiController, run_rssi_monitor};
 use crate::channel::{KEYBOARD_REPORT_CHANNEL, LED_SIGNAL, VIAL_READ_CHANNEL};
-use crate::config::RmkConfig;
+#[cfg(not(feature = "_no_usb"))]
+use crate::config::ConnectionPreference;
+use crate::config::{BleConfig, BleConnParams, RmkConfig};
+#[cfg(all(feature = "nkro", not(feature = "_no_usb")))]
+use crate::descriptor::NkroKeyboardReport;
 use crate::hid::{DummyWriter, RunnableHidWriter};
 use crate::keymap::KeyMap;
 #[cfg(feature = "split")]
@@ -55,8 +57,10 @@ use crate::{CONNECTION_STATE, run_keyboard};
 pub(crate) mod battery_service;
 pub(crate) mod ble_server;
 pub(crate) mod device_info;
+pub(crate) mod idle;
 pub(crate) mod led;
 pub(crate) mod profile;
+pub mod rssi;
 
 #[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -72,6 +76,37 @@ pub enum BleState {
 /// The number of the active profile
 pub static ACTIVE_PROFILE: AtomicU8 = AtomicU8::new(0);
 
+/// Deadline of the pairing mode, `None` if the keyboard isn't in pairing mode.
+///
+/// The pairing mode is entered by the `PairingMode` action. In pairing mode, the active profile advertises as pairable
+/// for [`PAIRING_MODE_TIMEOUT`] even if it has a bonded host, so that a new host can be paired with `bonded_only`
+/// enabled. The pairing mode ends when a host connects or after the timeout, then the keyboard continues advertising
+/// normally. The timeout counts from entering the pairing mode, so it also ends when the keyboard isn't advertising,
+/// for example, when it's connected via USB.
+static PAIRING_MODE: Mutex<CriticalSectionRawMutex, Cell<Option<Instant>>> = Mutex::new(Cell::new(None));
+
+/// Duration of the pairing mode
+const PAIRING_MODE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Enter the pairing mode at `now`, it ends after [`PAIRING_MODE_TIMEOUT`]
+pub(crate) fn enter_pairing_mode(now: Instant) {
+    PAIRING_MODE.lock(|deadline| deadline.set(Some(now + PAIRING_MODE_TIMEOUT)));
+}
+
+/// End the pairing mode
+fn exit_pairing_mode() {
+    PAIRING_MODE.lock(|deadline| deadline.set(None));
+}
+
+/// Deadline of the pairing mode, `None` if the keyboard isn't in pairing mode, or the pairing mode is timed out at `now`
+fn pairing_mode_deadline(now: Instant) -> Option<Instant> {
+    PAIRING_MODE.lock(|deadline| {
+        let active = deadline.get().filter(|d| now < *d);
+        deadline.set(active);
+        active
+    })
+}
+
 /// Global state of sleep management
 /// - `true`: Indicates central is sleeping
 /// - `false`: Indicates central is awake
@@ -105,7 +140,7 @@ pub async fn build_ble_stack<
 pub(crate) async fn run_ble<
     'a,
     'b,
-    C: Controller + ControllerCmdAsync<LeSetPhy> + ControllerCmdSync<LeReadLocalSupportedFeatures>,
+    C: Controller + ControllerCmdAsync<LeSetPhy> + ControllerCmdSync<LeReadLocalSupportedFeatures> + RssiController,
     #[cfg(feature = "storage")] F: AsyncNorFlash,
     #[cfg(not(feature = "_no_usb"))] D: Driver<'static>,
     const ROW: usize,
@@ -119,18 +154,23 @@ pub(crate) async fn run_ble<
     #[cfg(feature = "storage")] storage: &mut Storage<F, ROW, COL, NUM_LAYER, NUM_ENCODER>,
     mut rmk_config: RmkConfig<'static>,
 ) {
+    // Use the serial number generated from the device id, unless the serial number is set
     #[cfg(feature = "_nrf_ble")]
-    {
+    if rmk_config.usb_config.serial_number == crate::config::KeyboardUsbConfig::default().serial_number {
         rmk_config.usb_config.serial_number = crate::hid::get_serial_number();
     }
+    rmk_config.ble_config.validate();
+    let ble_config = rmk_config.ble_config;
 
     // Initialize usb device and usb hid reader/writer
     #[cfg(not(feature = "_no_usb"))]
+    let poll_ms = rmk_config.usb_config.poll_ms();
+    #[cfg(not(feature = "_no_usb"))]
     let (mut _usb_builder, mut keyboard_reader, mut keyboard_writer, mut other_writer, mut vial_reader_writer) = {
         let mut usb_builder: embassy_usb::Builder<'_, D> = new_usb_builder(usb_driver, rmk_config.usb_config);
-        let keyboard_reader_writer = add_usb_reader_writer!(&mut usb_builder, KeyboardReport, 1, 8);
-        let other_writer = add_usb_writer!(&mut usb_builder, CompositeReport, 9);
-        let vial_reader_writer = add_usb_reader_writer!(&mut usb_builder, ViaReport, 32, 32);
+        let keyboard_reader_writer = add_usb_reader_writer!(&mut usb_builder, KeyboardReport, 1, 8, poll_ms);
+        let other_writer = add_usb_writer!(&mut usb_builder, CompositeReport, 9, poll_ms);
+        let vial_reader_writer = add_usb_reader_writer!(&mut usb_builder, ViaReport, 32, 32, 1);
         let (keyboard_reader, keyboard_writer) = keyboard_reader_writer.split();
         (
             usb_builder,
@@ -140,6 +180,8 @@ pub(crate) async fn run_ble<
             vial_reader_writer,
         )
     };
+    #[cfg(all(feature = "nkro", not(feature = "_no_usb")))]
+    let mut nkro_writer = add_usb_writer!(&mut _usb_builder, NkroKeyboardReport, 29, poll_ms);
 
     // Optional usb logger initialization
     #[cfg(all(feature = "usb_log", not(feature = "_no_usb")))]
@@ -149,34 +191,12 @@ pub(crate) async fn run_ble<
     let mut usb_device = _usb_builder.build();
 
     #[cfg(feature = "controller")]
-    let mut controller_pub = unwrap!(CONTROLLER_CHANNEL.publisher());
-
-    // Load current connection type
-    #[cfg(feature = "storage")]
-    {
-        let mut buf: [u8; 16] = [0; 16];
-        if let Ok(Some(StorageData::ConnectionType(conn_type))) =
-            read_storage!(storage, &(StorageKeys::ConnectionType as u32), buf)
-        {
-            CONNECTION_TYPE.store(conn_type, Ordering::SeqCst);
-        } else {
-            // If no saved connection type, return default value
-            #[cfg(feature = "_no_usb")]
-            CONNECTION_TYPE.store(ConnectionType::Ble.into(), Ordering::SeqCst);
-            #[cfg(not(feature = "_no_usb"))]
-            CONNECTION_TYPE.store(ConnectionType::Usb.into(), Ordering::SeqCst);
-        }
-
-        #[cfg(feature = "controller")]
-        send_controller_event(
-            &mut controller_pub,
-            ControllerEvent::ConnectionType(CONNECTION_TYPE.load(Ordering::SeqCst)),
-        );
-    }
+    let controller_pub = unwrap!(CONTROLLER_CHANNEL.publisher());
 
     // Create profile manager
     let mut profile_manager = ProfileManager::new(
         &stack,
+        ble_config.connection_preference,
         #[cfg(feature = "controller")]
         controller_pub,
     );
@@ -186,6 +206,11 @@ pub(crate) async fn run_ble<
     profile_manager.load_bonded_devices(storage).await;
     // Update bonding information in the stack
     profile_manager.update_stack_bonds();
+    // Apply the connection type of the active profile
+    profile_manager.update_connection_type();
+    // Show the restored active profile
+    #[cfg(feature = "controller")]
+    profile_manager.publish_active_profile();
 
     // Build trouble host stack
     let Host {
@@ -193,8 +218,12 @@ pub(crate) async fn run_ble<
     } = stack.build();
 
     info!("Starting advertising and GATT service");
+    // The device name read by the host after connected is set once, it's the name of the profile restored at boot
     let server = Server::new_with_config(GapConfig::Peripheral(PeripheralConfig {
-        name: rmk_config.usb_config.product_name,
+        name: truncate_name(
+            profile_name(&ble_config, rmk_config.usb_config.product_name),
+            MAX_NAME_LEN,
+        ),
         appearance: &appearance::human_interface_device::KEYBOARD,
     }))
     .unwrap();
@@ -253,11 +282,31 @@ pub(crate) async fn run_ble<
     );
     #[cfg(feature = "_no_usb")]
     let background_task = ble_task(runner);
+    // The storage runs in the background all the time, so that it's not restarted when the connection is switched
+    #[cfg(feature = "storage")]
+    let background_task = join(background_task, storage.run());
 
     // Main loop
-    join(background_task, async {
+    join3(background_task, run_idle_manager(ble_config.idle_timeout), async {
         loop {
-            let adv_fut = advertise(rmk_config.usb_config.product_name, &mut peripheral, &server);
+            // The stack only holds the bonding information of the active profile
+            let bonded_peer = stack.get_bond_information().first().map(|bond| bond.identity);
+            let adv_fut = advertise(
+                profile_name(&ble_config, rmk_config.usb_config.product_name),
+                &ble_config,
+                bonded_peer,
+                &mut peripheral,
+                &server,
+            );
+            // The keyboard never advertises in USB only mode
+            #[cfg(not(feature = "_no_usb"))]
+            let adv_fut = async move {
+                if ble_config.connection_preference == ConnectionPreference::UsbOnly {
+                    core::future::pending().await
+                } else {
+                    adv_fut.await
+                }
+            };
             // USB + BLE dual mode
             #[cfg(not(feature = "_no_usb"))]
             {
@@ -267,9 +316,6 @@ pub(crate) async fn run_ble<
                         match select4(
                             USB_ENABLED.wait(),
                             adv_fut,
-                            #[cfg(feature = "storage")]
-                            run_dummy_keyboard(storage),
-                            #[cfg(not(feature = "storage"))]
                             run_dummy_keyboard(),
                             profile_manager.update_profile(),
                         )
@@ -285,12 +331,16 @@ pub(crate) async fn run_ble<
                                 USB_ENABLED.signal(());
                                 let usb_fut = run_keyboard(
                                     keymap,
-                                    #[cfg(feature = "storage")]
-                                    storage,
                                     USB_SUSPENDED.wait(),
                                     UsbLedReader::new(&mut keyboard_reader),
                                     UsbVialReaderWriter::new(&mut vial_reader_writer),
-                                    UsbKeyboardWriter::new(&mut keyboard_writer, &mut other_writer),
+                                    UsbKeyboardWriter::new(
+                                        &mut keyboard_writer,
+                                        &mut other_writer,
+                                        #[cfg(feature = "nkro")]
+                                        &mut nkro_writer,
+                                        rmk_config.composite_report_interval,
+                                    ),
                                     rmk_config.vial_config,
                                 );
                                 select(usb_fut, profile_manager.update_profile()).await;
@@ -306,8 +356,7 @@ pub(crate) async fn run_ble<
                                     &stack,
                                     keymap,
                                     &mut rmk_config,
-                                    #[cfg(feature = "storage")]
-                                    storage,
+                                    profile_manager.cccd_table(&conn.raw().peer_identity()),
                                 );
                                 select3(ble_fut, USB_SUSPENDED.wait(), profile_manager.update_profile()).await;
                                 continue;
@@ -318,19 +367,7 @@ pub(crate) async fn run_ble<
                                 if let Ok(mut publisher) = CONTROLLER_CHANNEL.publisher() {
                                     send_controller_event(&mut publisher, ControllerEvent::BleState(0, BleState::None));
                                 }
-                                // Set CONNECTION_STATE to true to keep receiving messages from the peripheral
-                                CONNECTION_STATE.store(ConnectionState::Connected.into(), Ordering::Release);
-
-                                // Enter sleep mode to reduce the power consumption
-                                #[cfg(feature = "split")]
-                                CENTRAL_SLEEP.signal(true);
-
-                                // Wait for the keyboard report for wake the keyboard
-                                let _ = KEYBOARD_REPORT_CHANNEL.receive().await;
-
-                                // Quit from sleep mode
-                                #[cfg(feature = "split")]
-                                CENTRAL_SLEEP.signal(false);
+                                sleep_until_key_pressed().await;
                                 continue;
                             }
                             _ => {}
@@ -340,27 +377,32 @@ pub(crate) async fn run_ble<
                         info!("BLE priority mode, running USB keyboard while advertising");
                         let usb_fut = run_keyboard(
                             keymap,
-                            #[cfg(feature = "storage")]
-                            storage,
                             core::future::pending::<()>(), // Run forever until BLE connected
                             UsbLedReader::new(&mut keyboard_reader),
                             UsbVialReaderWriter::new(&mut vial_reader_writer),
-                            UsbKeyboardWriter::new(&mut keyboard_writer, &mut other_writer),
+                            UsbKeyboardWriter::new(
+                                &mut keyboard_writer,
+                                &mut other_writer,
+                                #[cfg(feature = "nkro")]
+                                &mut nkro_writer,
+                                rmk_config.composite_report_interval,
+                            ),
                             rmk_config.vial_config,
                         );
+                        // USB only powers the keyboard in BLE only mode, the keys are monitored by the dummy keyboard
+                        let usb_fut = async move {
+                            if ble_config.connection_preference == ConnectionPreference::BleOnly {
+                                run_dummy_keyboard().await
+                            } else {
+                                usb_fut.await
+                            }
+                        };
                         match select3(adv_fut, usb_fut, profile_manager.update_profile()).await {
                             Either3::First(Ok(conn)) => {
                                 info!("BLE connected, running BLE keyboard");
+                                let cccd_table = profile_manager.cccd_table(&conn.raw().peer_identity());
                                 select(
-                                    run_ble_keyboard(
-                                        &server,
-                                        &conn,
-                                        &stack,
-                                        keymap,
-                                        &mut rmk_config,
-                                        #[cfg(feature = "storage")]
-                                        storage,
-                                    ),
+                                    run_ble_keyboard(&server, &conn, &stack, keymap, &mut rmk_config, cccd_table),
                                     profile_manager.update_profile(),
                                 )
                                 .await;
@@ -372,20 +414,7 @@ pub(crate) async fn run_ble<
                                 if let Ok(mut publisher) = CONTROLLER_CHANNEL.publisher() {
                                     send_controller_event(&mut publisher, ControllerEvent::BleState(0, BleState::None));
                                 }
-                                // Set CONNECTION_STATE to true to keep receiving messages from the peripheral
-                                CONNECTION_STATE.store(ConnectionState::Connected.into(), Ordering::Release);
-
-                                // Enter sleep mode to reduce the power consumption
-                                #[cfg(feature = "split")]
-                                CENTRAL_SLEEP.signal(true);
-
-                                // Wait for the keyboard report for wake the keyboard
-                                let _ = KEYBOARD_REPORT_CHANNEL.receive().await;
-
-                                // Quit from sleep mode
-                                #[cfg(feature = "split")]
-                                CENTRAL_SLEEP.signal(false);
-
+                                sleep_until_key_pressed().await;
                                 continue;
                             }
                             _ => {}
@@ -395,42 +424,23 @@ pub(crate) async fn run_ble<
             }
 
             #[cfg(feature = "_no_usb")]
-            match adv_fut.await {
-                Ok(conn) => {
+            match select(adv_fut, run_dummy_keyboard()).await {
+                Either::Second(_) => unreachable!("Dummy keyboard never ends"),
+                Either::First(Ok(conn)) => {
                     // BLE connected
+                    let cccd_table = profile_manager.cccd_table(&conn.raw().peer_identity());
                     select(
-                        run_ble_keyboard(
-                            &server,
-                            &conn,
-                            &stack,
-                            keymap,
-                            &mut rmk_config,
-                            #[cfg(feature = "storage")]
-                            storage,
-                        ),
+                        run_ble_keyboard(&server, &conn, &stack, keymap, &mut rmk_config, cccd_table),
                         profile_manager.update_profile(),
                     )
                     .await;
                 }
-                Err(BleHostError::BleHost(Error::Timeout)) => {
+                Either::First(Err(BleHostError::BleHost(Error::Timeout))) => {
                     warn!("Advertising timeout, sleep and wait for any key");
-
-                    // Set CONNECTION_STATE to true to keep receiving messages from the peripheral
-                    CONNECTION_STATE.store(ConnectionState::Connected.into(), Ordering::Release);
-
-                    // Enter sleep mode to reduce the power consumption
-                    #[cfg(feature = "split")]
-                    CENTRAL_SLEEP.signal(true);
-
-                    // Wait for the keyboard report for wake the keyboard
-                    let _ = KEYBOARD_REPORT_CHANNEL.receive().await;
-
-                    // Quit from sleep mode
-                    #[cfg(feature = "split")]
-                    CENTRAL_SLEEP.signal(false);
+                    sleep_until_key_pressed().await;
                     continue;
                 }
-                Err(e) => {
+                Either::First(Err(e)) => {
                     #[cfg(feature = "defmt")]
                     let e = defmt::Debug2Format(&e);
                     error!("Advertise error: {:?}", e);
@@ -634,20 +644,106 @@ async fn gatt_events_task(server: &Server<'_>, conn: &GattConnection<'_, '_, Def
     Ok(())
 }
 
+/// Timeout of the directed advertising, same as the max duration of high duty cycle directed advertising
+const DIRECTED_ADV_TIMEOUT: Duration = Duration::from_millis(1280);
+
+/// Get the address used for directed advertising to a bonded host.
+///
+/// The address type isn't saved in the bonding information,
+/// so a static random address(two most significant bits are `0b11`) is assumed to be random, others are public.
+fn directed_adv_peer(bd_addr: BdAddr) -> Address {
+    let kind = if bd_addr.raw()[5] & 0xC0 == 0xC0 {
+        AddrKind::RANDOM
+    } else {
+        AddrKind::PUBLIC
+    };
+    Address { kind, addr: bd_addr }
+}
+
+/// Advertise directly to the bonded host for a fast reconnection.
+///
+/// It fails if the host doesn't respond in time, e.g. the host uses a changed resolvable private address,
+/// or the host isn't in range, then the undirected advertising should be used.
+async fn advertise_directed<'a, 'b, C: Controller>(
+    peer: BdAddr,
+    ble_config: &BleConfig,
+    peripheral: &mut Peripheral<'a, C, DefaultPacketPool>,
+    server: &'b Server<'_>,
+) -> Result<GattConnection<'a, 'b, DefaultPacketPool>, BleHostError<C::Error>> {
+    let advertise_config = AdvertisementParameters {
+        tx_power: ble_config.tx_power(),
+        ..Default::default()
+    };
+
+    info!("[adv] directed advertising to {:?}", peer);
+    let advertiser = peripheral
+        .advertise(
+            &advertise_config,
+            Advertisement::ConnectableNonscannableDirectedHighDuty {
+                peer: directed_adv_peer(peer),
+            },
+        )
+        .await?;
+
+    // High duty cycle directed advertising lasts 1.28s at most
+    match with_timeout(DIRECTED_ADV_TIMEOUT, advertiser.accept()).await {
+        Ok(conn_res) => {
+            let conn = conn_res?.with_attribute_server(server)?;
+            info!("[adv] connection established by directed advertising");
+
+            Ok(conn)
+        }
+        Err(_) => Err(BleHostError::BleHost(Error::Timeout)),
+    }
+}
+
 /// Create an advertiser to use to connect to a BLE Central, and wait for it to connect.
+///
+/// If the active profile has a bonded host, directed advertising is tried first,
+/// then it falls back to the undirected advertising.
+///
+/// If `bonded_only` is set, connections from hosts other than the bonded one are rejected. It's done after connected
+/// instead of by the filter accept list of the controller, because the hosts usually connect with resolvable private
+/// addresses, which can only be resolved with the IRK in the bonding information.
+///
+/// In pairing mode, the directed advertising is skipped and any host is accepted until the pairing mode times out.
 async fn advertise<'a, 'b, C: Controller>(
     name: &'a str,
+    ble_config: &BleConfig,
+    bonded_peer: Option<Identity>,
     peripheral: &mut Peripheral<'a, C, DefaultPacketPool>,
     server: &'b Server<'_>,
 ) -> Result<GattConnection<'a, 'b, DefaultPacketPool>, BleHostError<C::Error>> {
-    // Wait for 10ms to ensure the USB is checked
-    embassy_time::Timer::after_millis(10).await;
+    let mut pairing_deadline = pairing_mode_deadline(Instant::now());
+    if pairing_deadline.is_none()
+        && let Some(peer) = bonded_peer
+    {
+        match advertise_directed(peer.bd_addr, ble_config, peripheral, server).await {
+            Ok(conn) => return Ok(conn),
+            Err(e) => {
+                #[cfg(feature = "defmt")]
+                let e = defmt::Debug2Format(&e);
+                info!(
+                    "[adv] directed advertising failed: {:?}, fallback to undirected advertising",
+                    e
+                );
+            }
+        }
+    }
+
+    // A long name doesn't fit in the advertising data, it's shortened there and sent completely in the scan response
+    let name = truncate_name(name, MAX_NAME_LEN);
+    let adv_name = if name.len() <= MAX_ADV_NAME_LEN {
+        AdStructure::CompleteLocalName(name.as_bytes())
+    } else {
+        AdStructure::ShortenedLocalName(truncate_name(name, MAX_ADV_NAME_LEN).as_bytes())
+    };
     let mut advertiser_data = [0; 31];
     AdStructure::encode_slice(
         &[
             AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
             AdStructure::ServiceUuids16(&[BATTERY.to_le_bytes(), HUMAN_INTERFACE_DEVICE.to_le_bytes()]),
-            AdStructure::CompleteLocalName(name.as_bytes()),
+            adv_name,
             AdStructure::Unknown {
                 ty: 0x19, // Appearance
                 data: &KEYBOARD.to_le_bytes(),
@@ -655,70 +751,149 @@ async fn advertise<'a, 'b, C: Controller>(
         ],
         &mut advertiser_data[..],
     )?;
+    let mut scan_data = [0; 31];
+    let scan_data_len =
+        AdStructure::encode_slice(&[AdStructure::CompleteLocalName(name.as_bytes())], &mut scan_data[..])?;
 
-    let advertise_config = AdvertisementParameters {
-        primary_phy: PhyKind::Le2M,
-        secondary_phy: PhyKind::Le2M,
-        tx_power: TxPower::Plus8dBm,
-        interval_min: Duration::from_millis(200),
-        interval_max: Duration::from_millis(200),
-        ..Default::default()
-    };
+    // Timeout for advertising is 300s
+    let deadline = Instant::now() + Duration::from_secs(300);
+    loop {
+        // Advertise at a slow rate in idle mode
+        let interval = if is_idle() {
+            ble_config.adv_interval.max(IDLE_ADV_INTERVAL)
+        } else {
+            ble_config.adv_interval
+        };
+        let advertise_config = AdvertisementParameters {
+            primary_phy: PhyKind::Le2M,
+            secondary_phy: PhyKind::Le2M,
+            tx_power: ble_config.tx_power(),
+            interval_min: interval,
+            interval_max: interval,
+            ..Default::default()
+        };
+
+        info!("[adv] advertising");
+        let advertiser = peripheral
+            .advertise(
+                &advertise_config,
+                Advertisement::ConnectableScannableUndirected {
+                    adv_data: &advertiser_data[..],
+                    scan_data: &scan_data[..scan_data_len],
+                },
+            )
+            .await?;
+
+        // Advertising state
+        #[cfg(feature = "controller")]
+        if let Ok(mut publisher) = CONTROLLER_CHANNEL.publisher() {
+            let profile = ACTIVE_PROFILE.load(Ordering::Relaxed);
+            send_controller_event(
+                &mut publisher,
+                ControllerEvent::BleState(profile, BleState::Advertising),
+            );
+        }
 
-    info!("[adv] advertising");
-    let advertiser = peripheral
-        .advertise(
-            &advertise_config,
-            Advertisement::ConnectableScannableUndirected {
-                adv_data: &advertiser_data[..],
-                scan_data: &[],
-            },
+        let accept_deadline = pairing_deadline.map_or(deadline, |d| d.min(deadline));
+        match select(
+            with_deadline(accept_deadline, advertiser.accept()),
+            wait_idle_state_changed(),
         )
-        .await?;
+        .await
+        {
+            Either::First(Ok(conn_res)) => {
+                let conn = conn_res?;
+                if pairing_deadline.is_none()
+                    && ble_config.bonded_only
+                    && let Some(peer) = bonded_peer
+                    && !is_bonded_peer(&peer, &conn.peer_identity())
+                {
+                    info!("[adv] reject connection from unknown host: {:?}", conn.peer_address());
+                    conn.disconnect();
+                    continue;
+                }
+                exit_pairing_mode();
+                let conn = conn.with_attribute_server(server)?;
+                info!("[adv] connection established");
 
-    // Advertising state
-    #[cfg(feature = "controller")]
-    if let Ok(mut publisher) = CONTROLLER_CHANNEL.publisher() {
-        let profile = ACTIVE_PROFILE.load(Ordering::Relaxed);
-        send_controller_event(
-            &mut publisher,
-            ControllerEvent::BleState(profile, BleState::Advertising),
-        );
+                return Ok(conn);
+            }
+            Either::First(Err(_)) if pairing_deadline.is_some() && Instant::now() < deadline => {
+                // No host is paired, only the bonded host can connect again
+                info!("[adv] pairing mode timeout");
+                exit_pairing_mode();
+                pairing_deadline = None;
+            }
+            Either::First(Err(_)) => {
+                exit_pairing_mode();
+                return Err(BleHostError::BleHost(Error::Timeout));
+            }
+            // Restart advertising with the new interval
+            Either::Second(_) => continue,
+        }
     }
+}
 
-    // Timeout for advertising is 300s
-    match with_timeout(Duration::from_secs(300), advertiser.accept()).await {
-        Ok(conn_res) => {
-            let conn = conn_res?.with_attribute_server(server)?;
-            info!("[adv] connection established");
+/// Max length of the name in the scan response, 31 bytes minus the length and type of the AD structure
+const MAX_NAME_LEN: usize = 29;
+/// Max length of the name in the advertising data, the flags, service uuids and appearance take 13 bytes
+const MAX_ADV_NAME_LEN: usize = 16;
 
-            Ok(conn)
-        }
-        Err(_) => Err(BleHostError::BleHost(Error::Timeout)),
+/// Name advertised by the active profile, fallback to the product name
+fn profile_name<'a>(ble_config: &BleConfig, product_name: &'a str) -> &'a str {
+    ble_config
+        .profile_name(ACTIVE_PROFILE.load(Ordering::SeqCst))
+        .unwrap_or(product_name)
+}
+
+/// Truncate the name to at most `max_len` bytes, without splitting a character
+fn truncate_name(name: &str, max_len: usize) -> &str {
+    let mut end = name.len().min(max_len);
+    while !name.is_char_boundary(end) {
+        end -= 1;
     }
+    &name[..end]
 }
 
 // Dummy keyboard service is used to monitoring keys when there's no actual connection.
 // It's useful for functions like switching active profiles when there's no connection.
-pub(crate) async fn run_dummy_keyboard<
-    #[cfg(feature = "storage")] F: AsyncNorFlash,
-    #[cfg(feature = "storage")] const ROW: usize,
-    #[cfg(feature = "storage")] const COL: usize,
-    #[cfg(feature = "storage")] const NUM_LAYER: usize,
-    #[cfg(feature = "storage")] const NUM_ENCODER: usize,
->(
-    #[cfg(feature = "storage")] storage: &mut Storage<F, ROW, COL, NUM_LAYER, NUM_ENCODER>,
-) {
+// All behaviors are still processed by the keyboard task, the key actions are logged with the `keylog` feature.
+// The storage runs in the background, so only the reporter sink is switched to the dummy writer.
+pub(crate) async fn run_dummy_keyboard() {
     CONNECTION_STATE.store(ConnectionState::Disconnected.into(), Ordering::Release);
-    #[cfg(feature = "storage")]
-    let storage_fut = storage.run();
     let mut dummy_writer = DummyWriter {};
-    #[cfg(feature = "storage")]
-    select(storage_fut, dummy_writer.run_writer()).await;
-    #[cfg(not(feature = "storage"))]
     dummy_writer.run_writer().await;
 }
 
+/// Enter the sleep mode after the advertising timeout, until a key is pressed.
+///
+/// The report of the key press is left in the report channel, it's handled by the next reporter sink.
+async fn sleep_until_key_pressed() {
+    // Set CONNECTION_STATE to true to keep receiving messages from the peripheral
+    CONNECTION_STATE.store(ConnectionState::Connected.into(), Ordering::Release);
+
+    // Enter sleep mode to reduce the power consumption
+    #[cfg(feature = "split")]
+    CENTRAL_SLEEP.signal(true);
+
+    // Wait for the keyboard report for wake the keyboard
+    KEYBOARD_REPORT_CHANNEL.ready_to_receive().await;
+
+    // Quit from sleep mode
+    #[cfg(feature = "split")]
+    CENTRAL_SLEEP.signal(false);
+}
+
+fn connect_params(params: &BleConnParams) -> ConnectParams {
+    ConnectParams {
+        min_connection_interval: params.min_interval,
+        max_connection_interval: params.max_interval,
+        max_latency: params.latency,
+        event_length: Duration::from_secs(0),
+        supervision_timeout: params.supervision_timeout,
+    }
+}
+
 pub(crate) async fn set_conn_params<
     'a,
     'b,
@@ -727,40 +902,18 @@ pub(crate) async fn set_conn_params<
 >(
     stack: &Stack<'_, C, P>,
     conn: &GattConnection<'a, 'b, P>,
+    ble_config: BleConfig,
 ) {
     // Wait for 5 seconds before setting connection parameters to avoid connection drop
     embassy_time::Timer::after_secs(5).await;
 
-    // For macOS/iOS(aka Apple devices), both interval should be set to 15ms
-    // Reference: https://developer.apple.com/accessories/Accessory-Design-Guidelines.pdf
-    update_conn_params(
-        stack,
-        conn.raw(),
-        &ConnectParams {
-            min_connection_interval: Duration::from_millis(15),
-            max_connection_interval: Duration::from_millis(15),
-            max_latency: 30,
-            event_length: Duration::from_secs(0),
-            supervision_timeout: Duration::from_secs(6),
-        },
-    )
-    .await;
+    update_conn_params(stack, conn.raw(), &connect_params(&ble_config.conn_params)).await;
 
-    embassy_time::Timer::after_secs(5).await;
+    if let Some(second_conn_params) = ble_config.second_conn_params {
+        embassy_time::Timer::after_secs(5).await;
 
-    // Setting the conn param the second time ensures that we have best performance on all platforms
-    update_conn_params(
-        stack,
-        conn.raw(),
-        &ConnectParams {
-            min_connection_interval: Duration::from_micros(7500),
-            max_connection_interval: Duration::from_micros(7500),
-            max_latency: 99,
-            event_length: Duration::from_secs(0),
-            supervision_timeout: Duration::from_secs(5),
-        },
-    )
-    .await;
+        update_conn_params(stack, conn.raw(), &connect_params(&second_conn_params)).await;
+    }
 
     // Wait forever. This is because we want the conn params setting can be interrupted when the connection is lost.
     // So this task shouldn't quit after setting the conn params.
@@ -773,8 +926,7 @@ async fn run_ble_keyboard<
     'b,
     'c,
     'd,
-    C: Controller + ControllerCmdAsync<LeSetPhy> + ControllerCmdSync<LeReadLocalSupportedFeatures>,
-    #[cfg(feature = "storage")] F: AsyncNorFlash,
+    C: Controller + ControllerCmdAsync<LeSetPhy> + ControllerCmdSync<LeReadLocalSupportedFeatures> + RssiController,
     const ROW: usize,
     const COL: usize,
     const NUM_LAYER: usize,
@@ -785,45 +937,38 @@ async fn run_ble_keyboard<
     stack: &Stack<'_, C, DefaultPacketPool>,
     keymap: &'c RefCell<KeyMap<'c, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
     rmk_config: &'d mut RmkConfig<'static>,
-    #[cfg(feature = "storage")] storage: &mut Storage<F, ROW, COL, NUM_LAYER, NUM_ENCODER>,
+    cccd_table: Option<CccdTable<CCCD_TABLE_SIZE>>,
 ) {
     let ble_hid_server = BleHidServer::new(&server, &conn);
     let ble_via_server = BleViaServer::new(&server, &conn);
     let ble_led_reader = BleLedReader {};
     let mut ble_battery_server = BleBatteryServer::new(&server, &conn);
 
-    // Load CCCD table from storage
-    #[cfg(feature = "storage")]
-    if let Ok(Some(bond_info)) = storage
-        .read_trouble_bond_info(ACTIVE_PROFILE.load(Ordering::SeqCst))
-        .await
-    {
-        if bond_info.info.identity.match_identity(&conn.raw().peer_identity()) {
-            info!("Loading CCCD table from storage: {:?}", bond_info.cccd_table);
-            server.set_cccd_table(conn.raw(), bond_info.cccd_table.clone());
-        }
+    // Load the saved CCCD table of the host
+    if let Some(cccd_table) = cccd_table {
+        info!("Loading saved CCCD table: {:?}", cccd_table);
+        server.set_cccd_table(conn.raw(), cccd_table);
     }
 
     // Use 2M Phy
     update_ble_phy(stack, conn.raw()).await;
 
     let communication_task = async {
-        match select3(
+        match select4(
             gatt_events_task(server, conn),
-            set_conn_params(stack, conn),
+            set_conn_params(stack, conn, rmk_config.ble_config),
             ble_battery_server.run(),
+            run_rssi_monitor(stack, conn.raw(), &HOST_RSSI),
         )
         .await
         {
-            Either3::First(e) => error!("[gatt_events_task] end: {:?}", e),
+            Either4::First(e) => error!("[gatt_events_task] end: {:?}", e),
             _ => {}
         }
     };
 
     run_keyboard(
         keymap,
-        #[cfg(feature = "storage")]
-        storage,
         communication_task,
         ble_led_reader,
         ble_via_server,
@@ -895,3 +1040,27 @@ pub(crate) async fn update_conn_params<
         break;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pairing_mode_timeout() {
+        let entered = Instant::from_secs(100);
+        enter_pairing_mode(entered);
+        assert_eq!(
+            pairing_mode_deadline(entered + Duration::from_secs(10)),
+            Some(entered + PAIRING_MODE_TIMEOUT)
+        );
+
+        // The pairing mode times out even if the keyboard doesn't advertise, e.g. it's connected via USB
+        assert_eq!(pairing_mode_deadline(entered + PAIRING_MODE_TIMEOUT), None);
+        // It's cleared, so it's not active again
+        assert_eq!(pairing_mode_deadline(entered), None);
+
+        enter_pairing_mode(entered);
+        exit_pairing_mode();
+        assert_eq!(pairing_mode_deadline(entered), None);
+    }
+}
@@ -6,6 +6,7 @@ use core::sync::atomic::Ordering;
 use bt_hci::{cmd::le::LeSetPhy, controller::ControllerCmdAsync};
 use embassy_futures::select::{Either3, select3};
 use embassy_sync::signal::Signal;
+use embassy_time::Instant;
 use trouble_host::prelude::*;
 use trouble_host::{BondInformation, LongTermKey};
 #[cfg(feature = "storage")]
@@ -21,13 +22,22 @@ use {
 
 use super::ble_server::CCCD_TABLE_SIZE;
 use crate::NUM_BLE_PROFILE;
-use crate::ble::ACTIVE_PROFILE;
+use crate::ble::{ACTIVE_PROFILE, enter_pairing_mode};
 use crate::channel::BLE_PROFILE_CHANNEL;
-use crate::state::CONNECTION_TYPE;
+use crate::config::ConnectionPreference;
+use crate::state::{CONNECTION_TYPE, ConnectionType};
 
 pub(crate) static UPDATED_PROFILE: Signal<crate::RawMutex, ProfileInfo> = Signal::new();
 pub(crate) static UPDATED_CCCD_TABLE: Signal<crate::RawMutex, CccdTable<CCCD_TABLE_SIZE>> = Signal::new();
 
+/// Check whether the connected `peer` is the `bonded` host.
+///
+/// The peer usually connects with a resolvable private address, which is resolved by the IRK saved in the bonding information,
+/// so the peer's identity must be matched against the bonded identity, not the other way around.
+pub(crate) fn is_bonded_peer(bonded: &Identity, peer: &Identity) -> bool {
+    peer.match_identity(bonded)
+}
+
 /// BLE profile info
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -61,7 +71,31 @@ pub(crate) enum BleProfileAction {
     PreviousProfile,
     NextProfile,
     ClearProfile,
+    /// Clear bonding information of the given profile
+    ClearBond(u8),
+    /// Clear bonding information of all profiles
+    ClearAllBonds,
     ToggleConnection,
+    /// Disconnect and advertise the active profile as pairable for a while, see [`enter_pairing_mode`]
+    EnterPairingMode,
+}
+
+/// Default connection type of a profile which doesn't have a saved connection type:
+/// BLE if the keyboard doesn't have USB, otherwise the type of the connection preference
+fn default_connection_type(preference: ConnectionPreference) -> u8 {
+    match preference {
+        _ if cfg!(feature = "_no_usb") => ConnectionType::Ble.into(),
+        ConnectionPreference::AutoUsbFirst | ConnectionPreference::UsbOnly => ConnectionType::Usb.into(),
+        ConnectionPreference::PreferBle | ConnectionPreference::BleOnly => ConnectionType::Ble.into(),
+    }
+}
+
+/// Whether the connection type is fixed by the preference, ignoring the saved and toggled connection type
+fn is_connection_type_fixed(preference: ConnectionPreference) -> bool {
+    matches!(
+        preference,
+        ConnectionPreference::UsbOnly | ConnectionPreference::BleOnly
+    )
 }
 
 /// Manage BLE profiles and bonding information
@@ -75,6 +109,10 @@ pub(crate) enum BleProfileAction {
 pub struct ProfileManager<'a, C: Controller + ControllerCmdAsync<LeSetPhy>, P: PacketPool> {
     /// List of bonded devices
     bonded_devices: heapless::Vec<ProfileInfo, NUM_BLE_PROFILE>,
+    /// Connection type of each profile
+    connection_types: [u8; NUM_BLE_PROFILE],
+    /// Connection preference, which decides the default connection type
+    connection_preference: ConnectionPreference,
     /// BLE stack
     stack: &'a Stack<'a, C, P>,
     /// Publisher for controller channel
@@ -85,9 +123,15 @@ pub struct ProfileManager<'a, C: Controller + ControllerCmdAsync<LeSetPhy>, P: P
 #[cfg(feature = "_ble")]
 impl<'a, C: Controller + ControllerCmdAsync<LeSetPhy>, P: PacketPool> ProfileManager<'a, C, P> {
     /// Create a new profile manager
-    pub fn new(stack: &'a Stack<'a, C, P>, #[cfg(feature = "controller")] controller_pub: ControllerPub) -> Self {
+    pub fn new(
+        stack: &'a Stack<'a, C, P>,
+        connection_preference: ConnectionPreference,
+        #[cfg(feature = "controller")] controller_pub: ControllerPub,
+    ) -> Self {
         Self {
             bonded_devices: heapless::Vec::new(),
+            connection_types: [default_connection_type(connection_preference); NUM_BLE_PROFILE],
+            connection_preference,
             stack,
             #[cfg(feature = "controller")]
             controller_pub,
@@ -129,17 +173,43 @@ impl<'a, C: Controller + ControllerCmdAsync<LeSetPhy>, P: PacketPool> ProfileMan
         {
             debug!("Loaded active profile: {}", profile);
             ACTIVE_PROFILE.store(profile, Ordering::SeqCst);
-
-            #[cfg(feature = "controller")]
-            send_controller_event(&mut self.controller_pub, ControllerEvent::BleProfile(profile));
         } else {
             // If no saved active profile, use 0 as default
             debug!("Loaded default active profile",);
             ACTIVE_PROFILE.store(0, Ordering::SeqCst);
+        };
 
-            #[cfg(feature = "controller")]
-            send_controller_event(&mut self.controller_pub, ControllerEvent::BleProfile(0));
+        // The connection type saved by the old firmware was shared by all profiles, use it as the default
+        if let Ok(Some(StorageData::ConnectionType(conn_type))) =
+            read_storage!(storage, &(StorageKeys::ConnectionType as u32), buf)
+        {
+            self.connection_types = [conn_type; NUM_BLE_PROFILE];
+        }
+        for profile in 0..NUM_BLE_PROFILE {
+            if let Ok(Some(conn_type)) = storage.read_profile_connection_type(profile as u8).await {
+                self.connection_types[profile] = conn_type;
+            }
+        }
+        debug!("Loaded connection types: {:?}", self.connection_types);
+    }
+
+    /// Apply the connection type of the active profile
+    pub fn update_connection_type(&mut self) {
+        let active_profile = ACTIVE_PROFILE.load(Ordering::SeqCst) as usize;
+        let conn_type = if is_connection_type_fixed(self.connection_preference) {
+            default_connection_type(self.connection_preference)
+        } else {
+            self.connection_types
+                .get(active_profile)
+                .copied()
+                .unwrap_or_else(|| default_connection_type(self.connection_preference))
         };
+        if CONNECTION_TYPE.swap(conn_type, Ordering::SeqCst) != conn_type {
+            info!("Connection type of profile {}: {}", active_profile, conn_type);
+        }
+
+        #[cfg(feature = "controller")]
+        send_controller_event(&mut self.controller_pub, ControllerEvent::ConnectionType(conn_type));
     }
 
     /// Update bonding information in the stack according to the current active profile
@@ -167,6 +237,21 @@ impl<'a, C: Controller + ControllerCmdAsync<LeSetPhy>, P: PacketPool> ProfileMan
         }
     }
 
+    /// Get the saved CCCD table of the active profile, if the host with `identity` is bonded to it.
+    ///
+    /// The bonding information is kept in memory, so the storage doesn't need to be read on every connection.
+    pub(crate) fn cccd_table(&self, identity: &Identity) -> Option<CccdTable<CCCD_TABLE_SIZE>> {
+        let active_profile = ACTIVE_PROFILE.load(Ordering::SeqCst);
+        self.bonded_devices
+            .iter()
+            .find(|bond_info| {
+                !bond_info.removed
+                    && bond_info.slot_num == active_profile
+                    && is_bonded_peer(&bond_info.info.identity, identity)
+            })
+            .map(|bond_info| bond_info.cccd_table.clone())
+    }
+
     /// Add/update bonding information
     pub async fn add_profile_info(&mut self, profile_info: ProfileInfo) {
         // Update profile information in memory
@@ -249,17 +334,36 @@ impl<'a, C: Controller + ControllerCmdAsync<LeSetPhy>, P: PacketPool> ProfileMan
             .await;
     }
 
+    /// Clear bonding information of all profiles, all profiles advertise as unpaired afterwards
+    pub async fn clear_all_bonds(&mut self) {
+        info!("Clearing bonding information on all profiles");
+
+        self.bonded_devices.clear();
+
+        // Remove all bonding information in the stack
+        self.update_stack_bonds();
+
+        #[cfg(feature = "storage")]
+        FLASH_CHANNEL.send(FlashOperationMessage::ClearAllSlots).await;
+    }
+
     /// Switch to the specified profile, return true if the profile is switched
     pub async fn switch_profile(&mut self, profile: u8) -> bool {
         let current = ACTIVE_PROFILE.load(core::sync::atomic::Ordering::SeqCst);
         if profile == current {
             return false;
         }
+        if profile as usize >= NUM_BLE_PROFILE {
+            warn!("Invalid BLE profile: {}, max is {}", profile, NUM_BLE_PROFILE - 1);
+            return false;
+        }
 
         ACTIVE_PROFILE.store(profile, core::sync::atomic::Ordering::SeqCst);
 
         // Update the active bonding information in the stack
         self.update_stack_bonds();
+        // Each profile has its own connection type
+        self.update_connection_type();
 
         #[cfg(feature = "storage")]
         FLASH_CHANNEL
@@ -269,11 +373,18 @@ impl<'a, C: Controller + ControllerCmdAsync<LeSetPhy>, P: PacketPool> ProfileMan
         info!("Switched to BLE profile: {}", profile);
 
         #[cfg(feature = "controller")]
-        send_controller_event(&mut self.controller_pub, ControllerEvent::BleProfile(profile));
+        self.publish_active_profile();
 
         true
     }
 
+    /// Publish the active profile to the controllers, e.g. to show it by LEDs
+    #[cfg(feature = "controller")]
+    pub fn publish_active_profile(&mut self) {
+        let profile = ACTIVE_PROFILE.load(Ordering::SeqCst);
+        send_controller_event(&mut self.controller_pub, ControllerEvent::BleProfile(profile));
+    }
+
     /// Wait for profile switch event and update active profile
     ///
     /// This function will wait for profile switch operation, then update the active profile
@@ -303,7 +414,11 @@ impl<'a, C: Controller + ControllerCmdAsync<LeSetPhy>, P: PacketPool> ProfileMan
                         }
                         BleProfileAction::PreviousProfile => {
                             let mut profile = ACTIVE_PROFILE.load(Ordering::SeqCst);
-                            profile = if profile == 0 { 7 } else { profile - 1 };
+                            profile = if profile == 0 {
+                                NUM_BLE_PROFILE as u8 - 1
+                            } else {
+                                profile - 1
+                            };
 
                             self.switch_profile(profile).await;
                         }
@@ -317,12 +432,45 @@ impl<'a, C: Controller + ControllerCmdAsync<LeSetPhy>, P: PacketPool> ProfileMan
                             let profile = ACTIVE_PROFILE.load(Ordering::SeqCst);
                             self.clear_bond(profile).await;
                         }
+                        BleProfileAction::ClearBond(profile) => {
+                            self.clear_bond(profile).await;
+                            if profile != ACTIVE_PROFILE.load(Ordering::SeqCst) {
+                                // The current connection uses another profile, keep it
+                                #[cfg(feature = "storage")]
+                                FLASH_OPERATION_FINISHED.wait().await;
+                                continue;
+                            }
+                        }
+                        BleProfileAction::EnterPairingMode => {
+                            // Breaking the loop disconnects the current connection or restarts the advertising
+                            info!(
+                                "Enter pairing mode on profile {}",
+                                ACTIVE_PROFILE.load(Ordering::SeqCst)
+                            );
+                            enter_pairing_mode(Instant::now());
+                            break;
+                        }
+                        BleProfileAction::ClearAllBonds => {
+                            // Breaking the loop disconnects the current connection
+                            self.clear_all_bonds().await;
+                        }
                         BleProfileAction::ToggleConnection => {
+                            if is_connection_type_fixed(self.connection_preference) {
+                                warn!(
+                                    "Connection type is fixed by the preference {:?}, ignore toggling",
+                                    self.connection_preference
+                                );
+                                continue;
+                            }
+                            let profile = ACTIVE_PROFILE.load(Ordering::SeqCst);
                             let current = CONNECTION_TYPE.load(Ordering::SeqCst);
                             let updated = 1 - current;
                             CONNECTION_TYPE.store(updated, Ordering::SeqCst);
+                            if let Some(conn_type) = self.connection_types.get_mut(profile as usize) {
+                                *conn_type = updated;
+                            }
 
-                            info!("Switching connection type to: {}", updated);
+                            info!("Switching connection type of profile {} to: {}", profile, updated);
 
                             #[cfg(feature = "controller")]
                             send_controller_event(
@@ -331,7 +479,9 @@ impl<'a, C: Controller + ControllerCmdAsync<LeSetPhy>, P: PacketPool> ProfileMan
                             );
 
                             #[cfg(feature = "storage")]
-                            FLASH_CHANNEL.send(FlashOperationMessage::ConnectionType(updated)).await;
+                            FLASH_CHANNEL
+                                .send(FlashOperationMessage::ProfileConnectionType(profile, updated))
+                                .await;
                         }
                     }
                     #[cfg(feature = "storage")]
@@ -349,3 +499,58 @@ impl<'a, C: Controller + ControllerCmdAsync<LeSetPhy>, P: PacketPool> ProfileMan
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use rand_core::{CryptoRng, RngCore};
+    use trouble_host::IdentityResolvingKey;
+
+    use super::*;
+
+    // A fixed rng, the prand of the generated address doesn't matter
+    struct FixedRng;
+
+    impl RngCore for FixedRng {
+        fn next_u32(&mut self) -> u32 {
+            0x1234_5678
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            0x1234_5678_9abc_def0
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            dest.iter_mut().enumerate().for_each(|(i, b)| *b = 0x5a ^ i as u8);
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for FixedRng {}
+
+    #[test]
+    fn test_rpa_of_bonded_host_is_accepted() {
+        let irk = IdentityResolvingKey::new(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+        let bonded = Identity {
+            bd_addr: BdAddr::new([0xc0, 0x11, 0x22, 0x33, 0x44, 0xd5]),
+            irk: Some(irk),
+        };
+        // The host reconnects with a resolvable private address, without IRK
+        let peer = Identity {
+            bd_addr: BdAddr::new(irk.generate_resolvable_address(&mut FixedRng)),
+            irk: None,
+        };
+        assert!(is_bonded_peer(&bonded, &peer));
+
+        // An address which isn't generated from the bonded IRK is rejected
+        let other_irk = IdentityResolvingKey::new(0xfedc_ba98_7654_3210_fedc_ba98_7654_3210);
+        let other = Identity {
+            bd_addr: BdAddr::new(other_irk.generate_resolvable_address(&mut FixedRng)),
+            irk: None,
+        };
+        assert!(!is_bonded_peer(&bonded, &other));
+    }
+}
@@ -0,0 +1,115 @@
+//! RSSI of the BLE connections, used for diagnosing range issues.
+//!
+//! While a connection is alive, its RSSI is read from the controller every [`RSSI_UPDATE_INTERVAL`]. When the
+//! connection is lost, the RSSI is reset to unknown, so a stale value is never reported.
+//!
+//! Reading the RSSI needs the `ReadRssi` command of the controller, so it's only enabled by the `ble_rssi` feature.
+//! Without it, the RSSI is always unknown.
+use core::sync::atomic::{AtomicI8, Ordering};
+
+#[cfg(feature = "ble_rssi")]
+use bt_hci::cmd::status::ReadRssi;
+#[cfg(feature = "ble_rssi")]
+use bt_hci::controller::ControllerCmdSync;
+use embassy_time::Duration;
+#[cfg(feature = "ble_rssi")]
+use embassy_time::Timer;
+use trouble_host::prelude::*;
+
+#[cfg(feature = "split")]
+use crate::SPLIT_PERIPHERALS_NUM;
+
+/// Interval of reading the RSSI of a connection
+pub const RSSI_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// RSSI reported when the connection is lost, or hasn't been read yet. It's the "not available" value of HCI
+pub const RSSI_UNKNOWN: i8 = 127;
+
+/// RSSI of the connection to the host
+pub(crate) static HOST_RSSI: AtomicI8 = AtomicI8::new(RSSI_UNKNOWN);
+
+/// RSSI of the connection to each BLE peripheral, cached in central
+#[cfg(feature = "split")]
+pub(crate) static PERIPHERAL_RSSI: [AtomicI8; SPLIT_PERIPHERALS_NUM] =
+    [const { AtomicI8::new(RSSI_UNKNOWN) }; SPLIT_PERIPHERALS_NUM];
+
+/// Latest RSSI of the host connection in dBm, `None` if the keyboard isn't connected via BLE
+pub fn get_host_rssi() -> Option<i8> {
+    known_rssi(HOST_RSSI.load(Ordering::Acquire))
+}
+
+/// Latest RSSI of the connection to the given peripheral in dBm, `None` if the peripheral isn't connected via BLE
+#[cfg(feature = "split")]
+pub fn get_peripheral_rssi(id: usize) -> Option<i8> {
+    known_rssi(PERIPHERAL_RSSI.get(id)?.load(Ordering::Acquire))
+}
+
+fn known_rssi(rssi: i8) -> Option<i8> {
+    (rssi != RSSI_UNKNOWN).then_some(rssi)
+}
+
+/// Controller which can be used by the RSSI monitor.
+///
+/// With the `ble_rssi` feature, the controller must support the `ReadRssi` command. Otherwise, it's implemented for
+/// all controllers, so the bound doesn't restrict the controllers which can be used by RMK.
+#[cfg(feature = "ble_rssi")]
+pub trait RssiController: ControllerCmdSync<ReadRssi> {}
+
+#[cfg(feature = "ble_rssi")]
+impl<C: ControllerCmdSync<ReadRssi>> RssiController for C {}
+
+/// Controller which can be used by the RSSI monitor.
+///
+/// With the `ble_rssi` feature, the controller must support the `ReadRssi` command. Otherwise, it's implemented for
+/// all controllers, so the bound doesn't restrict the controllers which can be used by RMK.
+#[cfg(not(feature = "ble_rssi"))]
+pub trait RssiController {}
+
+#[cfg(not(feature = "ble_rssi"))]
+impl<C> RssiController for C {}
+
+/// Read the RSSI of the connection periodically and save it to `rssi`, it never ends.
+///
+/// Run it along with the connection, `rssi` is reset to [`RSSI_UNKNOWN`] when it's dropped.
+/// Without the `ble_rssi` feature, it only waits forever.
+#[cfg(not(feature = "ble_rssi"))]
+pub(crate) async fn run_rssi_monitor<C: Controller + RssiController, P: PacketPool>(
+    _stack: &Stack<'_, C, P>,
+    _conn: &Connection<'_, P>,
+    _rssi: &AtomicI8,
+) {
+    core::future::pending::<()>().await;
+}
+
+/// Read the RSSI of the connection periodically and save it to `rssi`, it never ends.
+///
+/// Run it along with the connection, `rssi` is reset to [`RSSI_UNKNOWN`] when it's dropped.
+#[cfg(feature = "ble_rssi")]
+pub(crate) async fn run_rssi_monitor<C: Controller + RssiController, P: PacketPool>(
+    stack: &Stack<'_, C, P>,
+    conn: &Connection<'_, P>,
+    rssi: &AtomicI8,
+) {
+    struct ResetOnDrop<'a>(&'a AtomicI8);
+    impl Drop for ResetOnDrop<'_> {
+        fn drop(&mut self) {
+            self.0.store(RSSI_UNKNOWN, Ordering::Release);
+        }
+    }
+
+    let _reset = ResetOnDrop(rssi);
+    loop {
+        match conn.rssi(stack).await {
+            Ok(value) => {
+                trace!("RSSI: {}", value);
+                rssi.store(value, Ordering::Release);
+            }
+            Err(e) => {
+                #[cfg(feature = "defmt")]
+                let e = defmt::Debug2Format(&e);
+                debug!("Failed to read RSSI: {:?}", e);
+            }
+        }
+        Timer::after(RSSI_UPDATE_INTERVAL).await;
+    }
+}
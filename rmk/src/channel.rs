@@ -3,9 +3,10 @@
 use embassy_sync::channel::Channel;
 #[cfg(any(feature = "split", feature = "controller"))]
 use embassy_sync::pubsub::PubSubChannel;
+use embassy_sync::signal::Signal;
 pub use embassy_sync::{blocking_mutex, channel, pubsub, zerocopy_channel};
 #[cfg(feature = "_ble")]
-use {crate::ble::profile::BleProfileAction, embassy_sync::signal::Signal, rmk_types::led_indicator::LedIndicator};
+use {crate::ble::profile::BleProfileAction, rmk_types::led_indicator::LedIndicator};
 #[cfg(feature = "controller")]
 use {
     crate::event::ControllerEvent,
@@ -52,6 +53,15 @@ pub static KEY_EVENT_CHANNEL: Channel<RawMutex, KeyboardEvent, EVENT_CHANNEL_SIZ
 pub static EVENT_CHANNEL: Channel<RawMutex, Event, EVENT_CHANNEL_SIZE> = Channel::new();
 /// Channel for keyboard report from input processors to hid writer/reader
 pub static KEYBOARD_REPORT_CHANNEL: Channel<RawMutex, Report, REPORT_CHANNEL_SIZE> = Channel::new();
+/// Signal for switching pointing devices to the next CPI step, it's triggered by the `PointingCpiCycle` key
+pub static POINTING_CPI_CYCLE_SIGNAL: Signal<RawMutex, ()> = Signal::new();
+/// Signal for the change of the top activated layer with the new top layer index. Wait for it in your own task to
+/// drive a layer indicator, it doesn't need the `controller` feature
+pub static LAYER_CHANGE_SIGNAL: Signal<RawMutex, u8> = Signal::new();
+/// Signal for the low battery warning with the battery level, it's signaled once when the battery level drops below
+/// `low_battery_threshold`. Wait for it in your own task to warn the user, it doesn't need the `controller` feature
+#[cfg(feature = "_ble")]
+pub static LOW_BATTERY_SIGNAL: Signal<RawMutex, u8> = Signal::new();
 /// Channel for controller events
 #[cfg(feature = "controller")]
 pub static CONTROLLER_CHANNEL: PubSubChannel<
@@ -1,5 +1,7 @@
 #[cfg(feature = "_nrf_ble")]
 use embassy_nrf::gpio::{Input, Output};
+use embassy_time::Duration;
+use trouble_host::prelude::TxPower;
 
 pub struct BleBatteryConfig<'a> {
     #[cfg(feature = "_nrf_ble")]
@@ -51,3 +53,194 @@ impl<'a> BleBatteryConfig<'a> {
         }
     }
 }
+
+/// Default advertising interval, same as the interval used before it became configurable
+const DEFAULT_ADV_INTERVAL: Duration = Duration::from_millis(200);
+/// Default TX power of advertising, in dBm
+const DEFAULT_ADV_TX_POWER: i8 = 8;
+/// Minimum/maximum interval of legacy advertising allowed by the BLE spec(and the softdevice controller)
+const MIN_ADV_INTERVAL: Duration = Duration::from_millis(20);
+const MAX_ADV_INTERVAL: Duration = Duration::from_millis(10240);
+
+/// Connection parameters requested by the keyboard after connected
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BleConnParams {
+    /// Minimum connection interval, must be in 7.5ms..=4s
+    pub min_interval: Duration,
+    /// Maximum connection interval, must be in 7.5ms..=4s and not less than `min_interval`
+    pub max_interval: Duration,
+    /// Peripheral(slave) latency, in number of connection events, must be <= 499
+    pub latency: u16,
+    /// Supervision timeout, must be in 100ms..=32s
+    /// and larger than `(1 + latency) * max_interval * 2`
+    pub supervision_timeout: Duration,
+}
+
+impl BleConnParams {
+    /// Whether the parameters are allowed by the BLE spec
+    fn is_valid(&self) -> bool {
+        let min = Duration::from_micros(7500);
+        let max = Duration::from_secs(4);
+        self.min_interval >= min
+            && self.max_interval <= max
+            && self.min_interval <= self.max_interval
+            && self.latency <= 499
+            && self.supervision_timeout >= Duration::from_millis(100)
+            && self.supervision_timeout <= Duration::from_secs(32)
+            && self.supervision_timeout.as_micros() > (1 + self.latency as u64) * self.max_interval.as_micros() * 2
+    }
+}
+
+/// For macOS/iOS(aka Apple devices), both interval should be set to 15ms
+/// Reference: https://developer.apple.com/accessories/Accessory-Design-Guidelines.pdf
+const DEFAULT_CONN_PARAMS: BleConnParams = BleConnParams {
+    min_interval: Duration::from_millis(15),
+    max_interval: Duration::from_millis(15),
+    latency: 30,
+    supervision_timeout: Duration::from_secs(6),
+};
+
+/// Setting the conn param the second time ensures that we have best performance on all platforms
+const DEFAULT_SECOND_CONN_PARAMS: BleConnParams = BleConnParams {
+    min_interval: Duration::from_micros(7500),
+    max_interval: Duration::from_micros(7500),
+    latency: 99,
+    supervision_timeout: Duration::from_secs(5),
+};
+
+/// Which connection is used when both USB and BLE are available
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConnectionPreference {
+    /// USB is used when it's connected to a host, otherwise BLE.
+    /// The `ToggleConnection` key switches the connection type of the active profile, which is saved
+    #[default]
+    AutoUsbFirst,
+    /// BLE is used, USB is only used while BLE isn't connected, so that a charger doesn't take over BLE.
+    /// The `ToggleConnection` key switches the connection type of the active profile, which is saved
+    PreferBle,
+    /// Only USB is used, the keyboard never advertises. The `ToggleConnection` key is ignored
+    UsbOnly,
+    /// Only BLE is used, USB only powers the keyboard. The `ToggleConnection` key is ignored
+    BleOnly,
+}
+
+/// Config for BLE advertising and connection
+///
+/// A longer advertising interval and a lower TX power reduce the power consumption,
+/// at the cost of a slower (re)connection to the host.
+#[derive(Clone, Copy, Debug)]
+pub struct BleConfig {
+    /// Advertising interval, must be in 20ms..=10.24s
+    pub adv_interval: Duration,
+    /// TX power of advertising, in dBm.
+    /// If the value is not supported by the controller, the nearest lower supported level is used.
+    pub adv_tx_power: i8,
+    /// Connection parameters requested 5s after connected
+    pub conn_params: BleConnParams,
+    /// Connection parameters requested 5s after the first update, `None` to skip the second update.
+    ///
+    /// The default 7.5ms interval with latency 99 may cause disconnection on some hosts(e.g. Linux/BlueZ),
+    /// set it to `None` to keep using `conn_params`.
+    pub second_conn_params: Option<BleConnParams>,
+    /// Enter idle mode after no key activity for this duration, `None` to disable the idle mode.
+    ///
+    /// In idle mode, the matrix is scanned at a slow rate and the advertising interval is increased to at least 1s.
+    /// The keyboard never enters idle mode when USB is enabled.
+    pub idle_timeout: Option<Duration>,
+    /// Connection used on startup, when both USB and BLE are available.
+    ///
+    /// It's the default connection type of the profiles, a connection type switched by the `ToggleConnection` key
+    /// is saved per profile and overrides it, unless the preference is `UsbOnly` or `BleOnly`.
+    pub connection_preference: ConnectionPreference,
+    /// Name advertised by each BLE profile, the product name is used for the profiles without a name.
+    ///
+    /// Names longer than 29 bytes are truncated. The name in the advertising data is shortened to 16 bytes,
+    /// the complete name is sent in the scan response.
+    pub profile_names: &'static [&'static str],
+    /// Only accept the connection from the bonded host, if the active profile has one.
+    ///
+    /// Connections from other hosts are disconnected right after connected, so a new host can only be paired to a
+    /// profile without bonding information, e.g. after clearing the bond of the profile.
+    pub bonded_only: bool,
+}
+
+impl Default for BleConfig {
+    fn default() -> Self {
+        Self {
+            adv_interval: DEFAULT_ADV_INTERVAL,
+            adv_tx_power: DEFAULT_ADV_TX_POWER,
+            conn_params: DEFAULT_CONN_PARAMS,
+            second_conn_params: Some(DEFAULT_SECOND_CONN_PARAMS),
+            idle_timeout: None,
+            connection_preference: ConnectionPreference::AutoUsbFirst,
+            profile_names: &[],
+            bonded_only: false,
+        }
+    }
+}
+
+impl BleConfig {
+    /// Check the config, invalid values are replaced with the default ones
+    pub(crate) fn validate(&mut self) {
+        if self.adv_interval < MIN_ADV_INTERVAL || self.adv_interval > MAX_ADV_INTERVAL {
+            warn!(
+                "Invalid BLE advertising interval: {}ms, fallback to default {}ms",
+                self.adv_interval.as_millis(),
+                DEFAULT_ADV_INTERVAL.as_millis()
+            );
+            self.adv_interval = DEFAULT_ADV_INTERVAL;
+        }
+        if !self.conn_params.is_valid() {
+            warn!(
+                "Invalid BLE connection parameters: {:?}, fallback to default",
+                self.conn_params
+            );
+            self.conn_params = DEFAULT_CONN_PARAMS;
+        }
+        if let Some(params) = self.second_conn_params
+            && !params.is_valid()
+        {
+            warn!(
+                "Invalid second BLE connection parameters: {:?}, fallback to default",
+                params
+            );
+            self.second_conn_params = Some(DEFAULT_SECOND_CONN_PARAMS);
+        }
+    }
+
+    /// Get the name of the profile, `None` if the profile doesn't have a name
+    pub(crate) fn profile_name(&self, profile: u8) -> Option<&'static str> {
+        self.profile_names
+            .get(profile as usize)
+            .copied()
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Get the advertising TX power level
+    pub(crate) fn tx_power(&self) -> TxPower {
+        match self.adv_tx_power {
+            i8::MIN..=-40 => TxPower::Minus40dBm,
+            -39..=-20 => TxPower::Minus20dBm,
+            -19..=-16 => TxPower::Minus16dBm,
+            -15..=-12 => TxPower::Minus12dBm,
+            -11..=-8 => TxPower::Minus8dBm,
+            -7..=-4 => TxPower::Minus4dBm,
+            -3..=1 => TxPower::ZerodBm,
+            2 => TxPower::Plus2dBm,
+            3 => TxPower::Plus3dBm,
+            4 => TxPower::Plus4dBm,
+            5 => TxPower::Plus5dBm,
+            6 => TxPower::Plus6dBm,
+            7 => TxPower::Plus7dBm,
+            8..=9 => TxPower::Plus8dBm,
+            10..=11 => TxPower::Plus10dBm,
+            12..=13 => TxPower::Plus12dBm,
+            14..=15 => TxPower::Plus14dBm,
+            16..=17 => TxPower::Plus16dBm,
+            18..=19 => TxPower::Plus18dBm,
+            20..=i8::MAX => TxPower::Plus20dBm,
+        }
+    }
+}
@@ -3,39 +3,70 @@ mod ble_config;
 pub mod macro_config;
 
 #[cfg(feature = "_ble")]
-pub use ble_config::BleBatteryConfig;
+pub use ble_config::{BleBatteryConfig, BleConfig, BleConnParams, ConnectionPreference};
 use embassy_time::Duration;
-use heapless::Vec;
+use heapless::{String, Vec};
 use macro_config::KeyboardMacrosConfig;
+use rmk_types::keycode::KeyCode;
+use rmk_types::modifier::ModifierCombination;
+use static_cell::StaticCell;
 
 use crate::combo::Combo;
+use crate::event::{Hand, KeyboardEventPos};
 use crate::fork::Fork;
+use crate::key_override::KeyOverride;
+use crate::leader::Leader;
 use crate::morse::{Morse, MorseMode};
-use crate::{COMBO_MAX_NUM, FORK_MAX_NUM, MORSE_MAX_NUM};
+use crate::{COMBO_MAX_NUM, DEBOUNCE_THRESHOLD, FORK_MAX_NUM, KEY_OVERRIDE_MAX_NUM, LEADER_MAX_NUM, MORSE_MAX_NUM};
 
 /// Internal configurations for RMK keyboard.
 #[derive(Default)]
 pub struct RmkConfig<'a> {
     pub usb_config: KeyboardUsbConfig<'a>,
     pub vial_config: VialConfig<'a>,
+    /// Used by `ConfigurableDebouncer`, other debouncers ignore it
+    pub debounce_config: DebounceConfig,
+    /// Diode direction of the matrix, set it to the matrix by `with_diode_direction`
+    pub diode_direction: DiodeDirection,
+    /// Interval between two matrix scans, set it to the matrix by `with_scan_interval`.
+    /// 0 scans the matrix continuously
+    pub scan_interval: Duration,
+    /// Min interval between the mouse reports sent over USB, the movement within the interval is merged.
+    /// 0 disables the rate limiting
+    pub composite_report_interval: Duration,
     #[cfg(feature = "storage")]
     pub storage_config: StorageConfig,
     #[cfg(feature = "_ble")]
     pub ble_battery_config: BleBatteryConfig<'a>,
+    #[cfg(feature = "_ble")]
+    pub ble_config: BleConfig,
 }
 
 /// Config for configurable action behavior
 #[derive(Debug, Default)]
 pub struct BehaviorConfig {
-    pub tri_layer: Option<[u8; 3]>,
+    /// Tri-layer rules, `[upper, lower, adjust]`: the adjust layer is activated when both upper and lower layers are active
+    pub tri_layers: &'static [[u8; 3]],
     pub tap: TapConfig,
     pub tap_hold: TapHoldConfig,
     pub one_shot: OneShotConfig,
+    pub caps_word: CapsWordConfig,
+    pub auto_shift: AutoShiftConfig,
+    pub grave_escape: GraveEscapeConfig,
+    pub space_cadet: SpaceCadetConfig,
+    pub unicode: UnicodeConfig,
+    pub encoder_acceleration: EncoderAccelerationConfig,
+    pub media_key_repeat: MediaKeyRepeatConfig,
+    pub bypass: BypassConfig,
     pub combo: CombosConfig,
+    pub leader: LeadersConfig,
     pub fork: ForksConfig,
+    pub key_override: KeyOverridesConfig,
     pub morse: MorsesConfig,
     pub keyboard_macros: KeyboardMacrosConfig,
     pub mouse_key: MouseKeyConfig,
+    /// Whether the N-key rollover report is used at startup, it can be changed by `MagicNkroOn`/`MagicNkroOff`/`MagicToggleNkro` keys
+    pub nkro: bool,
 }
 
 /// Configurations for morse behavior
@@ -76,8 +107,25 @@ pub struct TapHoldConfig {
     pub timeout: Duration,
     /// Default mode
     pub mode: MorseMode,
+    /// Mode for layer-tap keys, overrides `mode` and the HRM heuristics when set
+    pub layer_tap_mode: Option<MorseMode>,
     /// If the previous key is on the same "hand", the current key will be determined as a tap
     pub unilateral_tap: bool,
+    /// Chordal hold: pressing a non-tap-hold key on the same hand resolves the held tap-hold key as tap.
+    /// Keys on the opposite hand, or without a hand in `hands`, are decided by the mode and timeout as usual
+    pub chordal_hold: bool,
+    /// Hand of each key, `hands[row][col]`, used by the chordal hold. `None` or keys out of the map have no hand
+    pub hands: &'static [&'static [Option<Hand>]],
+    /// Number of taps to toggle the layer of a `LayerTapToggle` key
+    pub tap_toggle_count: u8,
+    /// A tap-hold key pressed again within this time after its tap is released sends the tap action and keeps it
+    /// pressed, so the tap key can auto-repeat. 0 disables the quick tap
+    pub quick_tap_term: Duration,
+    /// Per-key timeout overrides.
+    ///
+    /// Tap-hold and morse keys at a position listed here use the given timeout.
+    /// Keys without an override fall back to the morse's own timeout, then to `timeout`.
+    pub per_key_timeouts: Vec<PerKeyTimeout, PER_KEY_TIMEOUT_MAX_NUM>,
 }
 
 impl Default for TapHoldConfig {
@@ -85,10 +133,63 @@ impl Default for TapHoldConfig {
         Self {
             enable_hrm: false,
             unilateral_tap: false,
+            chordal_hold: false,
+            hands: &[],
+            tap_toggle_count: 5,
+            quick_tap_term: Duration::from_millis(0),
             mode: MorseMode::Normal,
+            layer_tap_mode: None,
             prior_idle_time: Duration::from_millis(120),
             timeout: Duration::from_millis(250),
+            per_key_timeouts: Vec::new(),
+        }
+    }
+}
+
+impl TapHoldConfig {
+    /// Get the timeout override of the key at (row, col), if any
+    pub fn get_per_key_timeout(&self, row: u8, col: u8) -> Option<Duration> {
+        self.per_key_timeouts
+            .iter()
+            .find(|t| t.row == row && t.col == col)
+            .map(|t| t.timeout)
+    }
+
+    /// Get the hand of the key at (row, col), `None` if the key has no hand in `hands`
+    pub fn get_hand(&self, row: u8, col: u8) -> Option<Hand> {
+        *self.hands.get(row as usize)?.get(col as usize)?
+    }
+
+    /// Whether the held tap-hold key should be resolved as tap by the chordal hold when the other key is pressed
+    pub(crate) fn is_chordal_hold_tap(&self, held: KeyboardEventPos, pressed: KeyboardEventPos) -> bool {
+        if !self.chordal_hold {
+            return false;
         }
+        let (KeyboardEventPos::Key(held), KeyboardEventPos::Key(pressed)) = (held, pressed) else {
+            return false;
+        };
+        // Keys without a hand fall back to the timing
+        matches!(
+            (self.get_hand(held.row, held.col), self.get_hand(pressed.row, pressed.col)),
+            (Some(held_hand), Some(pressed_hand)) if held_hand == pressed_hand
+        )
+    }
+}
+
+/// Maximum number of per-key timeout overrides
+pub const PER_KEY_TIMEOUT_MAX_NUM: usize = 16;
+
+/// Timeout override of a single key
+#[derive(Clone, Copy, Debug)]
+pub struct PerKeyTimeout {
+    pub row: u8,
+    pub col: u8,
+    pub timeout: Duration,
+}
+
+impl PerKeyTimeout {
+    pub fn new(row: u8, col: u8, timeout: Duration) -> Self {
+        Self { row, col, timeout }
     }
 }
 
@@ -106,22 +207,227 @@ impl Default for OneShotConfig {
     }
 }
 
+/// Config for caps word behavior
+#[derive(Clone, Copy, Debug)]
+pub struct CapsWordConfig {
+    /// Caps word is turned off when no key is pressed within this timeout
+    pub timeout: Duration,
+}
+
+impl Default for CapsWordConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Config for the `GraveEscape` key
+#[derive(Clone, Copy, Debug)]
+pub struct GraveEscapeConfig {
+    /// `GraveEscape` sends grave if any of these modifiers is held when it's pressed, otherwise it sends escape
+    pub grave_modifiers: ModifierCombination,
+}
+
+impl Default for GraveEscapeConfig {
+    fn default() -> Self {
+        Self {
+            // All modifiers
+            grave_modifiers: ModifierCombination::from_bits(0xFF),
+        }
+    }
+}
+
+/// Config for the acceleration of rotary encoders
+#[derive(Clone, Copy, Debug)]
+pub struct EncoderAccelerationConfig {
+    /// A detent within this interval after the previous detent in the same direction is accelerated, 0 disables the acceleration
+    pub interval: Duration,
+    /// Number of times the action is triggered by an accelerated detent
+    pub multiplier: u8,
+    /// Accelerated detents use the encoder actions on this layer instead, if they're not transparent
+    pub accelerated_layer: Option<u8>,
+}
+
+impl Default for EncoderAccelerationConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(0),
+            multiplier: 1,
+            accelerated_layer: None,
+        }
+    }
+}
+
+/// Config for the repeat of held consumer and system control keys, such as volume keys
+#[derive(Clone, Copy, Debug)]
+pub struct MediaKeyRepeatConfig {
+    /// The delay before the first repeat
+    pub delay: Duration,
+    /// The interval between repeats, 0 disables the repeat and leaves it to the host
+    pub interval: Duration,
+}
+
+impl Default for MediaKeyRepeatConfig {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(500),
+            interval: Duration::from_millis(0),
+        }
+    }
+}
+
+/// Config for the bypass mode, which sends the keys on the default layer without combos, tap-hold, forks and macros
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BypassConfig {
+    /// Whether the bypass mode is on at startup, it can be changed by `BypassToggle` key
+    pub enable: bool,
+    /// Save the bypass mode to the storage when it's changed, so it's restored at boot
+    pub persist: bool,
+}
+
+/// Config for space cadet keys
+#[derive(Clone, Copy, Debug)]
+pub struct SpaceCadetConfig {
+    /// Space cadet keys are held after this timeout, use the tap-hold timeout if it's not set
+    pub timeout: Option<Duration>,
+    /// Key sent by tapping the left space cadet keys
+    pub left_tap_key: KeyCode,
+    /// Modifiers sent with `left_tap_key`
+    pub left_tap_modifiers: ModifierCombination,
+    /// Key sent by tapping the right space cadet keys, except `SpaceCadetRShiftEnter` which always sends enter
+    pub right_tap_key: KeyCode,
+    /// Modifiers sent with `right_tap_key`
+    pub right_tap_modifiers: ModifierCombination,
+}
+
+impl Default for SpaceCadetConfig {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            // `(`
+            left_tap_key: KeyCode::Kc9,
+            left_tap_modifiers: ModifierCombination::LSHIFT,
+            // `)`
+            right_tap_key: KeyCode::Kc0,
+            right_tap_modifiers: ModifierCombination::RSHIFT,
+        }
+    }
+}
+
+/// Input method of the host, which is used for typing unicode characters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UnicodeMode {
+    /// Linux with IBus: `Ctrl+Shift+U`, hex digits, then `Space`
+    #[default]
+    Linux,
+    /// macOS with the "Unicode Hex Input" input source: hex digits of UTF-16 typed while `Option` is held
+    MacOs,
+    /// Windows with `EnableHexNumpad` registry key set: hex digits typed after numpad `+` while `Alt` is held
+    Windows,
+    /// Windows with WinCompose: `RAlt`, `U`, hex digits, then `Enter`
+    WinCompose,
+}
+
+/// Config for typing unicode characters
+#[derive(Clone, Copy, Debug)]
+pub struct UnicodeConfig {
+    pub mode: UnicodeMode,
+    /// Delay between the key strokes of the input sequence, some hosts drop keys which are sent too fast
+    pub key_delay: Duration,
+}
+
+impl Default for UnicodeConfig {
+    fn default() -> Self {
+        Self {
+            mode: UnicodeMode::default(),
+            key_delay: Duration::from_millis(10),
+        }
+    }
+}
+
+/// Config for auto shift behavior
+#[derive(Clone, Debug)]
+pub struct AutoShiftConfig {
+    /// Whether auto shift is enabled at startup, it can be changed by `AutoShiftOn`/`AutoShiftOff`/`AutoShiftToggle` keys
+    pub enable: bool,
+    /// Keys held longer than this timeout are sent with shift
+    pub timeout: Duration,
+    /// Keys which are auto shifted besides letters, digits and symbols
+    pub allowlist: Vec<KeyCode, AUTO_SHIFT_LIST_MAX_NUM>,
+    /// Keys which are never auto shifted
+    pub denylist: Vec<KeyCode, AUTO_SHIFT_LIST_MAX_NUM>,
+}
+
+impl Default for AutoShiftConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            timeout: Duration::from_millis(175),
+            allowlist: Vec::new(),
+            denylist: Vec::new(),
+        }
+    }
+}
+
+impl AutoShiftConfig {
+    /// Check whether the key is auto shifted
+    pub fn is_auto_shift_key(&self, key: KeyCode) -> bool {
+        if self.denylist.contains(&key) {
+            return false;
+        }
+        key.is_letter()
+            || (KeyCode::Kc1 <= key && key <= KeyCode::Kc0)
+            || (KeyCode::Minus <= key && key <= KeyCode::Slash)
+            || self.allowlist.contains(&key)
+    }
+}
+
+/// Maximum number of keys in auto shift allowlist or denylist
+pub const AUTO_SHIFT_LIST_MAX_NUM: usize = 16;
+
 /// Config for combo behavior
 #[derive(Clone, Debug)]
 pub struct CombosConfig {
     pub combos: Vec<Combo, COMBO_MAX_NUM>,
+    /// Max time between the first and the last key press of a combo, judged by the time the keys are pressed
     pub timeout: Duration,
+    /// Extra time to wait for the delayed key events after the timeout, e.g. the events from a split peripheral.
+    ///
+    /// A key event which arrives within the allowance still joins the combo if the key is pressed within `timeout`.
+    /// It also delays the keys of an incomplete combo by at most the allowance. The default value is 20ms for split
+    /// keyboards, 0 for others.
+    pub latency_allowance: Duration,
 }
 
 impl Default for CombosConfig {
     fn default() -> Self {
         Self {
             timeout: Duration::from_millis(50),
+            latency_allowance: Duration::from_millis(if cfg!(feature = "split") { 20 } else { 0 }),
             combos: Vec::new(),
         }
     }
 }
 
+/// Config for leader key behavior
+#[derive(Clone, Debug)]
+pub struct LeadersConfig {
+    pub leaders: Vec<Leader, LEADER_MAX_NUM>,
+    /// The leader key sequence is aborted when no key is pressed within this timeout
+    pub timeout: Duration,
+}
+
+impl Default for LeadersConfig {
+    fn default() -> Self {
+        Self {
+            leaders: Vec::new(),
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
 /// Config for fork behavior
 #[derive(Clone, Debug)]
 pub struct ForksConfig {
@@ -134,6 +440,90 @@ impl Default for ForksConfig {
     }
 }
 
+/// Config for key overrides
+#[derive(Clone, Debug, Default)]
+pub struct KeyOverridesConfig {
+    pub overrides: Vec<KeyOverride, KEY_OVERRIDE_MAX_NUM>,
+}
+
+/// Diode direction of the key matrix.
+///
+/// The anode side of the diode is always connected to the output pins, which are driven by the matrix.
+/// The diode direction decides whether the output pins are columns or rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DiodeDirection {
+    /// Output pins are columns, input pins are rows
+    Col2Row,
+    /// Output pins are rows, input pins are columns
+    Row2Col,
+}
+
+impl Default for DiodeDirection {
+    /// The default direction is selected by the `col2row` feature
+    fn default() -> Self {
+        if cfg!(feature = "col2row") {
+            DiodeDirection::Col2Row
+        } else {
+            DiodeDirection::Row2Col
+        }
+    }
+}
+
+impl DiodeDirection {
+    /// Convert the matrix position `(in_idx, out_idx)` to `(row, col)`
+    pub(crate) fn to_row_col(self, in_idx: usize, out_idx: usize) -> (usize, usize) {
+        match self {
+            DiodeDirection::Col2Row => (in_idx, out_idx),
+            DiodeDirection::Row2Col => (out_idx, in_idx),
+        }
+    }
+}
+
+/// When a key change is reported by the debouncer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DebounceMode {
+    /// Report the change on the first edge, then ignore the key until the window has passed.
+    /// Lowest latency.
+    Eager,
+    /// Report the change after the key has been stable for the whole window.
+    /// Filters out chattering switches.
+    #[default]
+    Defer,
+}
+
+/// How the debounce window is tracked
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DebounceScope {
+    /// Each key has its own timer
+    #[default]
+    PerKey,
+    /// One timer is shared by the whole matrix
+    Global,
+}
+
+/// Config for `ConfigurableDebouncer`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DebounceConfig {
+    pub mode: DebounceMode,
+    pub scope: DebounceScope,
+    /// Debounce window, should be less than 65s and not shorter than the scan interval of the matrix.
+    /// It's in time rather than scans, so it doesn't change with the scan interval
+    pub window: Duration,
+}
+
+impl Default for DebounceConfig {
+    fn default() -> Self {
+        Self {
+            mode: DebounceMode::default(),
+            scope: DebounceScope::default(),
+            window: Duration::from_millis(DEBOUNCE_THRESHOLD as u64),
+        }
+    }
+}
+
 /// Config for storage
 #[derive(Clone, Copy, Debug)]
 pub struct StorageConfig {
@@ -160,11 +550,25 @@ impl Default for StorageConfig {
 /// Config for [vial](https://get.vial.today/).
 ///
 /// You can generate automatically using [`build.rs`](https://github.com/HaoboGu/rmk/blob/main/examples/use_rust/stm32h7/build.rs).
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug)]
 pub struct VialConfig<'a> {
     pub vial_keyboard_id: &'a [u8],
     pub vial_keyboard_def: &'a [u8],
     pub unlock_keys: &'a [(u8, u8)],
+    /// Reboot the keyboard after the storage is cleared by an EEPROM reset from the host,
+    /// so that the default keymap and configs are loaded
+    pub reboot_after_reset: bool,
+}
+
+impl Default for VialConfig<'_> {
+    fn default() -> Self {
+        Self {
+            vial_keyboard_id: &[],
+            vial_keyboard_def: &[],
+            unlock_keys: &[],
+            reboot_after_reset: true,
+        }
+    }
 }
 
 impl<'a> VialConfig<'a> {
@@ -173,6 +577,7 @@ impl<'a> VialConfig<'a> {
             vial_keyboard_id,
             vial_keyboard_def,
             unlock_keys,
+            reboot_after_reset: true,
         }
     }
 }
@@ -190,6 +595,49 @@ pub struct KeyboardUsbConfig<'a> {
     pub product_name: &'a str,
     /// Serial number
     pub serial_number: &'a str,
+    /// Poll interval(`bInterval`) of the HID endpoints in ms, 1ms means 1000Hz report rate.
+    /// Full-speed USB allows 1~255ms, 0 is treated as 1ms
+    pub poll_interval_ms: u8,
+    /// Wake up the host from the suspend when a key is pressed.
+    /// Some hosts don't work well with the remote wakeup, set it to `false` for them
+    pub remote_wakeup: bool,
+}
+
+/// Max length of the serial number set by [`KeyboardUsbConfig::with_serial_number`]
+pub const SERIAL_NUMBER_MAX_LEN: usize = 32;
+
+impl KeyboardUsbConfig<'static> {
+    /// Use a serial number which is generated at runtime, such as from the unique id of the chip.
+    ///
+    /// The serial number is copied into a static buffer, so it doesn't need to be `'static`.
+    /// It's truncated to [`SERIAL_NUMBER_MAX_LEN`] bytes. The static buffer can be filled only once, later calls
+    /// keep the current serial number and log an error.
+    pub fn with_serial_number(mut self, serial_number: &str) -> Self {
+        static SERIAL_NUMBER: StaticCell<String<SERIAL_NUMBER_MAX_LEN>> = StaticCell::new();
+        let Some(buf) = SERIAL_NUMBER.try_init(String::new()) else {
+            error!("Serial number is already set, `with_serial_number` can be called only once");
+            return self;
+        };
+        for c in serial_number.chars() {
+            if buf.push(c).is_err() {
+                warn!("Serial number is truncated to {} bytes", SERIAL_NUMBER_MAX_LEN);
+                break;
+            }
+        }
+        self.serial_number = buf.as_str();
+        self
+    }
+}
+
+impl KeyboardUsbConfig<'_> {
+    /// The validated poll interval of the HID endpoints in ms
+    pub(crate) fn poll_ms(&self) -> u8 {
+        if self.poll_interval_ms == 0 {
+            warn!("USB poll interval 0ms is invalid, use 1ms");
+            return 1;
+        }
+        self.poll_interval_ms
+    }
 }
 
 impl Default for KeyboardUsbConfig<'_> {
@@ -200,6 +648,8 @@ impl Default for KeyboardUsbConfig<'_> {
             manufacturer: "RMK",
             product_name: "RMK Keyboard",
             serial_number: "vial:f64c2b3c:000001",
+            poll_interval_ms: 1,
+            remote_wakeup: true,
         }
     }
 }
@@ -273,3 +723,17 @@ impl MouseKeyConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_serial_number_set_once() {
+        let config = KeyboardUsbConfig::default().with_serial_number("0123456789ABCDEF0123456789ABCDEF_truncated");
+        assert_eq!(config.serial_number, "0123456789ABCDEF0123456789ABCDEF");
+        // The second call doesn't panic, and keeps the serial number of the config
+        let config = KeyboardUsbConfig::default().with_serial_number("second");
+        assert_eq!(config.serial_number, KeyboardUsbConfig::default().serial_number);
+    }
+}
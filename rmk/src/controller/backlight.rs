@@ -0,0 +1,34 @@
+/// The controller for the backlight, the brightness is output by a [`BacklightDriver`] implemented by the board.
+use crate::channel::{CONTROLLER_CHANNEL, ControllerSub};
+use crate::controller::Controller;
+use crate::event::ControllerEvent;
+use crate::light::BacklightDriver;
+
+pub struct BacklightController<D: BacklightDriver> {
+    driver: D,
+    sub: ControllerSub,
+}
+
+impl<D: BacklightDriver> BacklightController<D> {
+    pub fn new(driver: D) -> Self {
+        Self {
+            driver,
+            sub: unwrap!(CONTROLLER_CHANNEL.subscriber()),
+        }
+    }
+}
+
+impl<D: BacklightDriver> Controller for BacklightController<D> {
+    type Event = ControllerEvent;
+
+    async fn process_event(&mut self, event: Self::Event) {
+        if let ControllerEvent::Backlight(backlight) = event {
+            debug!("Setting backlight: {:?}", backlight);
+            self.driver.set_brightness(backlight.output_brightness());
+        }
+    }
+
+    async fn next_message(&mut self) -> Self::Event {
+        self.sub.next_message_pure().await
+    }
+}
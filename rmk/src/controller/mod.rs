@@ -3,8 +3,12 @@
 //! This module defines the `Controller` trait and several macros for running output device controllers.
 //! The `Controller` trait provides the interface for individual output device controllers, and the macros facilitate their concurrent execution.
 
+pub mod backlight;
 pub mod battery_led;
 pub mod led_indicator;
+#[cfg(feature = "_ble")]
+pub mod profile_led;
+pub mod rgb_matrix;
 pub(crate) mod wpm;
 
 use embassy_futures::select::{Either, select};
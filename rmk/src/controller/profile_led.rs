@@ -0,0 +1,48 @@
+use embassy_time::{Duration, Timer};
+use embedded_hal::digital::StatefulOutputPin;
+
+use crate::channel::{CONTROLLER_CHANNEL, ControllerSub};
+use crate::controller::Controller;
+use crate::driver::gpio::OutputController;
+use crate::event::ControllerEvent;
+
+/// How long the LED is on in a blink
+const BLINK_ON: Duration = Duration::from_millis(150);
+/// How long the LED is off between two blinks
+const BLINK_OFF: Duration = Duration::from_millis(200);
+
+/// Blink an LED to show the active BLE profile, profile `n` blinks `n + 1` times.
+///
+/// It blinks when the profile is switched, and at boot with the restored profile.
+pub struct ProfileLedController<P: StatefulOutputPin> {
+    pin: OutputController<P>,
+    sub: ControllerSub,
+}
+
+impl<P: StatefulOutputPin> ProfileLedController<P> {
+    pub fn new(pin: P, low_active: bool) -> Self {
+        Self {
+            pin: OutputController::new(pin, low_active),
+            sub: unwrap!(CONTROLLER_CHANNEL.subscriber()),
+        }
+    }
+}
+
+impl<P: StatefulOutputPin> Controller for ProfileLedController<P> {
+    type Event = ControllerEvent;
+
+    async fn process_event(&mut self, event: Self::Event) {
+        if let ControllerEvent::BleProfile(profile) = event {
+            for _ in 0..=profile {
+                self.pin.activate();
+                Timer::after(BLINK_ON).await;
+                self.pin.deactivate();
+                Timer::after(BLINK_OFF).await;
+            }
+        }
+    }
+
+    async fn next_message(&mut self) -> Self::Event {
+        self.sub.next_message_pure().await
+    }
+}
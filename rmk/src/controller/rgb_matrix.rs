@@ -0,0 +1,66 @@
+//! The controller for the per-key RGB matrix, the colors are written by a [`RgbMatrixDriver`] implemented by the board.
+use crate::channel::{CONTROLLER_CHANNEL, ControllerSub};
+use crate::controller::{Controller, PollingController};
+use crate::event::{ControllerEvent, KeyboardEvent, KeyboardEventPos};
+use crate::light::{RgbMatrixConfig, RgbMatrixDriver, RgbMatrixFrame};
+
+/// Renders the effects of the RGB matrix with `LED_NUM` LEDs.
+///
+/// The state is set by Vial, or restored from the storage at startup. Key presses are received from the controller
+/// channel for the reactive effect.
+pub struct RgbMatrixController<D: RgbMatrixDriver, const LED_NUM: usize> {
+    driver: D,
+    sub: ControllerSub,
+    config: RgbMatrixConfig,
+    frame: RgbMatrixFrame<LED_NUM>,
+}
+
+impl<D: RgbMatrixDriver, const LED_NUM: usize> RgbMatrixController<D, LED_NUM> {
+    pub fn new(driver: D) -> Self {
+        Self {
+            driver,
+            sub: unwrap!(CONTROLLER_CHANNEL.subscriber()),
+            config: RgbMatrixConfig::default(),
+            frame: RgbMatrixFrame::new(),
+        }
+    }
+}
+
+impl<D: RgbMatrixDriver, const LED_NUM: usize> Controller for RgbMatrixController<D, LED_NUM> {
+    type Event = ControllerEvent;
+
+    async fn process_event(&mut self, event: Self::Event) {
+        match event {
+            ControllerEvent::RgbMatrix(config) => {
+                debug!("Setting RGB matrix: {:?}", config);
+                self.config = config;
+            }
+            ControllerEvent::Key(
+                KeyboardEvent {
+                    pressed: true,
+                    pos: KeyboardEventPos::Key(pos),
+                    ..
+                },
+                _,
+            ) => {
+                if let Some(led_index) = self.driver.led_index(pos.row, pos.col) {
+                    self.frame.light_up(led_index);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    async fn next_message(&mut self) -> Self::Event {
+        self.sub.next_message_pure().await
+    }
+}
+
+impl<D: RgbMatrixDriver, const LED_NUM: usize> PollingController for RgbMatrixController<D, LED_NUM> {
+    const INTERVAL: embassy_time::Duration = embassy_time::Duration::from_hz(60);
+
+    async fn update(&mut self) {
+        self.frame.render(&self.config);
+        self.driver.write(&self.frame.colors).await;
+    }
+}
@@ -0,0 +1,299 @@
+use embassy_time::{Duration, Instant};
+
+use super::{DebounceState, DebouncerTrait};
+use crate::config::{DebounceConfig, DebounceMode, DebounceScope};
+use crate::matrix::KeyState;
+
+/// Debouncer whose mode and window are selected at runtime by [`DebounceConfig`].
+///
+/// Timestamps are stored as wrapping `u16` milliseconds to keep the per-key state small.
+pub struct ConfigurableDebouncer<const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize> {
+    config: DebounceConfig,
+    /// Debounce window in ms
+    window_ms: u16,
+    /// Whether the key is in a debouncing process
+    debouncing: [[bool; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+    /// Start time of the debouncing process of each key, used in `DebounceScope::PerKey`
+    key_ms: [[u16; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+    /// Start time of the last debouncing process, used in `DebounceScope::Global`
+    global_ms: u16,
+}
+
+impl<const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize> Default
+    for ConfigurableDebouncer<INPUT_PIN_NUM, OUTPUT_PIN_NUM>
+{
+    fn default() -> Self {
+        Self::new(DebounceConfig::default())
+    }
+}
+
+impl<const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize> ConfigurableDebouncer<INPUT_PIN_NUM, OUTPUT_PIN_NUM> {
+    /// Create a debouncer with the given config
+    pub fn new(config: DebounceConfig) -> Self {
+        let window_ms = config.window.as_millis().min(u16::MAX as u64) as u16;
+        Self {
+            config,
+            window_ms,
+            debouncing: [[false; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+            key_ms: [[0; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+            global_ms: 0,
+        }
+    }
+
+    /// Record the start of a debouncing process
+    fn start(&mut self, in_idx: usize, out_idx: usize, now_ms: u16) {
+        self.debouncing[out_idx][in_idx] = true;
+        match self.config.scope {
+            DebounceScope::PerKey => self.key_ms[out_idx][in_idx] = now_ms,
+            DebounceScope::Global => self.global_ms = now_ms,
+        }
+    }
+
+    /// Whether the debounce window of the key has passed
+    fn window_passed(&self, in_idx: usize, out_idx: usize, now_ms: u16) -> bool {
+        let start_ms = match self.config.scope {
+            DebounceScope::PerKey => self.key_ms[out_idx][in_idx],
+            DebounceScope::Global => self.global_ms,
+        };
+        now_ms.wrapping_sub(start_ms) >= self.window_ms
+    }
+
+    fn detect_change_at(
+        &mut self,
+        in_idx: usize,
+        out_idx: usize,
+        pin_state: bool,
+        key_state: &KeyState,
+        now_ms: u16,
+    ) -> DebounceState {
+        match self.config.mode {
+            DebounceMode::Eager => {
+                if self.debouncing[out_idx][in_idx] {
+                    if !self.window_passed(in_idx, out_idx, now_ms) {
+                        // Locked out, all edges in the window are ignored
+                        return DebounceState::InProgress;
+                    }
+                    self.debouncing[out_idx][in_idx] = false;
+                }
+                if key_state.pressed != pin_state {
+                    // Report the first edge immediately and start the lockout
+                    self.start(in_idx, out_idx, now_ms);
+                    DebounceState::Debounced
+                } else {
+                    DebounceState::Ignored
+                }
+            }
+            DebounceMode::Defer => {
+                if key_state.pressed != pin_state {
+                    if !self.debouncing[out_idx][in_idx] {
+                        self.start(in_idx, out_idx, now_ms);
+                        DebounceState::InProgress
+                    } else if self.window_passed(in_idx, out_idx, now_ms) {
+                        // The new state has been stable for the whole window
+                        self.debouncing[out_idx][in_idx] = false;
+                        DebounceState::Debounced
+                    } else {
+                        DebounceState::InProgress
+                    }
+                } else if self.debouncing[out_idx][in_idx] {
+                    // Bounced back, the next edge starts a new window
+                    self.debouncing[out_idx][in_idx] = false;
+                    if self.config.scope == DebounceScope::Global {
+                        // Any activity restarts the global window
+                        self.global_ms = now_ms;
+                    }
+                    DebounceState::InProgress
+                } else {
+                    DebounceState::Ignored
+                }
+            }
+        }
+    }
+}
+
+impl<const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize> DebouncerTrait
+    for ConfigurableDebouncer<INPUT_PIN_NUM, OUTPUT_PIN_NUM>
+{
+    fn detect_change_with_debounce(
+        &mut self,
+        in_idx: usize,
+        out_idx: usize,
+        pin_state: bool,
+        key_state: &KeyState,
+    ) -> DebounceState {
+        let now_ms = Instant::now().as_millis() as u16;
+        self.detect_change_at(in_idx, out_idx, pin_state, key_state, now_ms)
+    }
+
+    fn debounce_window(&self) -> Option<Duration> {
+        Some(self.config.window)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use embassy_time::Duration;
+
+    use super::*;
+
+    fn debouncer(mode: DebounceMode, scope: DebounceScope) -> ConfigurableDebouncer<2, 2> {
+        ConfigurableDebouncer::new(DebounceConfig {
+            mode,
+            scope,
+            window: Duration::from_millis(8),
+        })
+    }
+
+    /// Feed `(time_ms, pin_state)` samples of key (0, 0) like a matrix scan, returns the time of each reported change
+    fn run(debouncer: &mut ConfigurableDebouncer<2, 2>, samples: &[(u16, bool)]) -> Vec<u16> {
+        let mut key_state = KeyState::new();
+        let mut changes = Vec::new();
+        for &(now_ms, pin_state) in samples {
+            if let DebounceState::Debounced = debouncer.detect_change_at(0, 0, pin_state, &key_state, now_ms) {
+                key_state.toggle_pressed();
+                changes.push(now_ms);
+            }
+        }
+        changes
+    }
+
+    // Press bounces in 1..=5ms, release bounces in 20..=22ms
+    const BOUNCING: [(u16, bool); 22] = [
+        (0, false),
+        (1, true),
+        (2, false),
+        (3, true),
+        (4, false),
+        (5, true),
+        (6, true),
+        (7, true),
+        (8, true),
+        (9, true),
+        (10, true),
+        (11, true),
+        (12, true),
+        (13, true),
+        (14, true),
+        (20, false),
+        (21, true),
+        (22, false),
+        (25, false),
+        (30, false),
+        (31, false),
+        (40, false),
+    ];
+
+    #[test]
+    fn test_eager_reports_first_edge() {
+        let mut debouncer = debouncer(DebounceMode::Eager, DebounceScope::PerKey);
+        // Press at the first edge, release at the first edge after the lockout
+        assert_eq!(run(&mut debouncer, &BOUNCING), [1, 20]);
+    }
+
+    #[test]
+    fn test_eager_lockout_ignores_bounces() {
+        let mut debouncer = debouncer(DebounceMode::Eager, DebounceScope::PerKey);
+        // Released during the lockout, the release is reported when the lockout ends
+        let samples = [(0, true), (1, false), (2, true), (3, false), (7, false), (8, false)];
+        assert_eq!(run(&mut debouncer, &samples), [0, 8]);
+    }
+
+    #[test]
+    fn test_defer_waits_for_stable() {
+        let mut debouncer = debouncer(DebounceMode::Defer, DebounceScope::PerKey);
+        // Press is stable from 5ms, release is stable from 22ms
+        assert_eq!(run(&mut debouncer, &BOUNCING), [13, 30]);
+    }
+
+    #[test]
+    fn test_defer_filters_chatter() {
+        let mut debouncer = debouncer(DebounceMode::Defer, DebounceScope::PerKey);
+        // Never stable for 8ms, nothing is reported
+        let samples: Vec<(u16, bool)> = (0..50).map(|t| (t, t % 6 < 3)).collect();
+        assert!(run(&mut debouncer, &samples).is_empty());
+    }
+
+    #[test]
+    fn test_per_key_windows_are_independent() {
+        let mut debouncer = debouncer(DebounceMode::Defer, DebounceScope::PerKey);
+        let released = KeyState::new();
+        assert!(matches!(
+            debouncer.detect_change_at(0, 0, true, &released, 0),
+            DebounceState::InProgress
+        ));
+        assert!(matches!(
+            debouncer.detect_change_at(1, 1, true, &released, 5),
+            DebounceState::InProgress
+        ));
+        assert!(matches!(
+            debouncer.detect_change_at(0, 0, true, &released, 8),
+            DebounceState::Debounced
+        ));
+        assert!(matches!(
+            debouncer.detect_change_at(1, 1, true, &released, 8),
+            DebounceState::InProgress
+        ));
+        assert!(matches!(
+            debouncer.detect_change_at(1, 1, true, &released, 13),
+            DebounceState::Debounced
+        ));
+    }
+
+    #[test]
+    fn test_defer_global_restarts_on_any_edge() {
+        let mut debouncer = debouncer(DebounceMode::Defer, DebounceScope::Global);
+        let released = KeyState::new();
+        assert!(matches!(
+            debouncer.detect_change_at(0, 0, true, &released, 0),
+            DebounceState::InProgress
+        ));
+        // Another key bounces, which restarts the shared window
+        assert!(matches!(
+            debouncer.detect_change_at(1, 1, true, &released, 5),
+            DebounceState::InProgress
+        ));
+        assert!(matches!(
+            debouncer.detect_change_at(1, 1, false, &released, 6),
+            DebounceState::InProgress
+        ));
+        assert!(matches!(
+            debouncer.detect_change_at(0, 0, true, &released, 8),
+            DebounceState::InProgress
+        ));
+        assert!(matches!(
+            debouncer.detect_change_at(0, 0, true, &released, 14),
+            DebounceState::Debounced
+        ));
+    }
+
+    #[test]
+    fn test_eager_global_shares_lockout() {
+        let mut debouncer = debouncer(DebounceMode::Eager, DebounceScope::Global);
+        let released = KeyState::new();
+        let pressed = KeyState { pressed: true };
+        assert!(matches!(
+            debouncer.detect_change_at(0, 0, true, &released, 0),
+            DebounceState::Debounced
+        ));
+        // Other keys still report immediately, and extend the shared lockout
+        assert!(matches!(
+            debouncer.detect_change_at(1, 1, true, &released, 6),
+            DebounceState::Debounced
+        ));
+        assert!(matches!(
+            debouncer.detect_change_at(0, 0, false, &pressed, 9),
+            DebounceState::InProgress
+        ));
+        assert!(matches!(
+            debouncer.detect_change_at(0, 0, false, &pressed, 14),
+            DebounceState::Debounced
+        ));
+    }
+
+    #[test]
+    fn test_timestamp_wrapping() {
+        let mut debouncer = debouncer(DebounceMode::Defer, DebounceScope::PerKey);
+        let samples = [(u16::MAX - 2, true), (u16::MAX, true), (3, true), (5, true)];
+        assert_eq!(run(&mut debouncer, &samples), [5]);
+    }
+}
@@ -1,4 +1,4 @@
-use embassy_time::Instant;
+use embassy_time::{Duration, Instant};
 
 use super::{DebounceState, DebouncerTrait};
 use crate::DEBOUNCE_THRESHOLD;
@@ -61,4 +61,8 @@ impl<const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize> DebouncerTrait
             DebounceState::Ignored
         }
     }
+
+    fn debounce_window(&self) -> Option<Duration> {
+        Some(Duration::from_millis(DEBOUNCE_THRESHOLD as u64))
+    }
 }
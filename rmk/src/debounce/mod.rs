@@ -1,7 +1,12 @@
+use embassy_time::Duration;
+
 use crate::matrix::KeyState;
 
+pub mod configurable_debouncer;
 pub mod default_debouncer;
 pub mod fast_debouncer;
+#[cfg(feature = "debounce_stats")]
+pub(crate) mod stats;
 
 pub trait DebouncerTrait {
     /// The `in_idx` `out_idx` can be used as two normal dimensions.
@@ -12,6 +17,11 @@ pub trait DebouncerTrait {
         pin_state: bool,
         key_state: &KeyState,
     ) -> DebounceState;
+
+    /// Debounce window, the matrix warns if it's shorter than the scan interval. `None` if the debouncer isn't timed
+    fn debounce_window(&self) -> Option<Duration> {
+        None
+    }
 }
 
 /// Debounce state
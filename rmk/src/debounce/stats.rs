@@ -0,0 +1,220 @@
+//! Debounce statistics, enabled by the `debounce_stats` feature.
+//!
+//! The counters are updated in the matrix scan and can be read and reset through Via custom values.
+//! They're kept in RAM only, so they're reset on reboot.
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+
+use super::DebounceState;
+
+/// Max number of keys whose counters are recorded
+pub(crate) const DEBOUNCE_STATS_MAX_NUM: usize = 16;
+
+/// Max number of keys which are tracked in a debouncing process at the same time
+const DEBOUNCING_MAX_NUM: usize = 8;
+
+/// A press of a key within this time after its release is counted as a chatter event
+const CHATTER_WINDOW: Duration = Duration::from_millis(30);
+
+pub(crate) static DEBOUNCE_STATS: Mutex<CriticalSectionRawMutex, RefCell<DebounceStats>> =
+    Mutex::new(RefCell::new(DebounceStats::new()));
+
+/// Record the debounce result of a scanned key, `pressed` is the reported key state before the change
+pub(crate) fn record_debounce(row: u8, col: u8, state: &DebounceState, pressed: bool) {
+    DEBOUNCE_STATS.lock(|s| s.borrow_mut().record(row, col, state, pressed, Instant::now()));
+}
+
+/// Counters of a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct DebounceStat {
+    pub(crate) row: u8,
+    pub(crate) col: u8,
+    /// Number of bounces which are discarded by the debouncer
+    pub(crate) rejections: u16,
+    /// Number of presses which are reported right after the release of the same key
+    pub(crate) chatters: u16,
+}
+
+/// A key in a debouncing process
+#[derive(Clone, Copy, Debug)]
+struct Debouncing {
+    row: u8,
+    col: u8,
+    /// Whether the change has been reported, the following `InProgress` is the lockout after the change
+    reported: bool,
+}
+
+pub(crate) struct DebounceStats {
+    stats: Vec<DebounceStat, DEBOUNCE_STATS_MAX_NUM>,
+    debouncing: Vec<Debouncing, DEBOUNCING_MAX_NUM>,
+    /// Position and time of the last reported release
+    last_release: Option<(u8, u8, Instant)>,
+}
+
+impl DebounceStats {
+    pub(crate) const fn new() -> Self {
+        Self {
+            stats: Vec::new(),
+            debouncing: Vec::new(),
+            last_release: None,
+        }
+    }
+
+    /// Recorded counters, in the order of the first abnormal event
+    pub(crate) fn stats(&self) -> &[DebounceStat] {
+        &self.stats
+    }
+
+    /// Clear all counters
+    pub(crate) fn reset(&mut self) {
+        self.stats.clear();
+    }
+
+    fn record(&mut self, row: u8, col: u8, state: &DebounceState, pressed: bool, now: Instant) {
+        let index = self.debouncing.iter().position(|d| d.row == row && d.col == col);
+        match state {
+            DebounceState::InProgress => {
+                if index.is_none() {
+                    let debouncing = Debouncing {
+                        row,
+                        col,
+                        reported: false,
+                    };
+                    if self.debouncing.push(debouncing).is_err() {
+                        debug!("Too many keys in debouncing, ({}, {}) is not tracked", row, col);
+                    }
+                }
+            }
+            DebounceState::Ignored => {
+                if let Some(i) = index {
+                    // The key is back to the reported state without a change, the bounce is discarded
+                    if !self.debouncing.swap_remove(i).reported {
+                        self.update(row, col, |s| s.rejections = s.rejections.saturating_add(1));
+                    }
+                }
+            }
+            DebounceState::Debounced => {
+                if pressed {
+                    self.last_release = Some((row, col, now));
+                } else if self.last_release.is_some_and(|(r, c, released_at)| {
+                    r == row && c == col && now.saturating_duration_since(released_at) < CHATTER_WINDOW
+                }) {
+                    self.update(row, col, |s| s.chatters = s.chatters.saturating_add(1));
+                }
+                match index {
+                    Some(i) => self.debouncing[i].reported = true,
+                    None => {
+                        let _ = self.debouncing.push(Debouncing {
+                            row,
+                            col,
+                            reported: true,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn update(&mut self, row: u8, col: u8, f: impl FnOnce(&mut DebounceStat)) {
+        let stat = match self.stats.iter().position(|s| s.row == row && s.col == col) {
+            Some(i) => &mut self.stats[i],
+            None => {
+                let stat = DebounceStat {
+                    row,
+                    col,
+                    rejections: 0,
+                    chatters: 0,
+                };
+                if self.stats.push(stat).is_err() {
+                    debug!("Debounce stats table is full, ({}, {}) is not recorded", row, col);
+                    return;
+                }
+                self.stats.last_mut().unwrap()
+            }
+        };
+        f(stat);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Feed `(time_ms, debounce_state)` of key (0, 1) like a matrix scan
+    fn run(stats: &mut DebounceStats, samples: &[(u64, DebounceState)]) {
+        let mut pressed = false;
+        for (ms, state) in samples {
+            stats.record(0, 1, state, pressed, Instant::from_millis(*ms));
+            if let DebounceState::Debounced = state {
+                pressed = !pressed;
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejection() {
+        let mut stats = DebounceStats::new();
+        run(
+            &mut stats,
+            &[
+                (0, DebounceState::InProgress),
+                (1, DebounceState::InProgress),
+                (2, DebounceState::Ignored),
+                (3, DebounceState::Ignored),
+            ],
+        );
+        assert_eq!(
+            stats.stats(),
+            [DebounceStat {
+                row: 0,
+                col: 1,
+                rejections: 1,
+                chatters: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_lockout_is_not_rejection() {
+        let mut stats = DebounceStats::new();
+        // A deferred change, and an eager change with lockout
+        run(
+            &mut stats,
+            &[
+                (0, DebounceState::InProgress),
+                (5, DebounceState::Debounced),
+                (6, DebounceState::Ignored),
+                (100, DebounceState::Debounced),
+                (101, DebounceState::InProgress),
+                (105, DebounceState::Ignored),
+            ],
+        );
+        assert!(stats.stats().is_empty());
+    }
+
+    #[test]
+    fn test_chatter() {
+        let mut stats = DebounceStats::new();
+        run(
+            &mut stats,
+            &[
+                // Press and release
+                (0, DebounceState::Debounced),
+                (100, DebounceState::Debounced),
+                // Pressed again shortly after the release
+                (110, DebounceState::Debounced),
+                (200, DebounceState::Debounced),
+                // A normal press
+                (300, DebounceState::Debounced),
+            ],
+        );
+        assert_eq!(stats.stats()[0].chatters, 1);
+        stats.reset();
+        assert!(stats.stats().is_empty());
+    }
+}
@@ -30,6 +30,75 @@ pub struct KeyboardReport {
     pub keycodes: [u8; 6],
 }
 
+/// Number of bytes of the keycode bitmap in [`NkroKeyboardReport`], which covers keycodes `0x00..=0xDF`
+pub(crate) const NKRO_KEYCODE_BYTES: usize = 28;
+
+/// Report descriptor of [`NkroKeyboardReport`].
+///
+/// It's written by hand because `gen_hid_descriptor` doesn't support the bitmap of keycodes.
+const NKRO_KEYBOARD_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x06, // Usage (Keyboard)
+    0xA1, 0x01, // Collection (Application)
+    0x05, 0x07, //   Usage Page (Keyboard)
+    0x19, 0xE0, //   Usage Minimum (Left Control)
+    0x29, 0xE7, //   Usage Maximum (Right GUI)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x08, //   Report Count (8)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x19, 0x00, //   Usage Minimum (0x00)
+    0x29, 0xDF, //   Usage Maximum (0xDF)
+    0x95, 0xE0, //   Report Count (224)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0xC0, // End Collection
+];
+
+/// N-key rollover keyboard report, every keycode has its own bit so that all pressed keys can be reported.
+///
+/// Some BIOS and KVM switches don't understand this report, [`KeyboardReport`] is used for them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NkroKeyboardReport {
+    pub modifier: u8, // ModifierCombination
+    pub keycodes: [u8; NKRO_KEYCODE_BYTES],
+}
+
+impl NkroKeyboardReport {
+    /// Set the bit of the keycode, keycodes out of the bitmap are ignored
+    pub(crate) fn set(&mut self, keycode: u8, pressed: bool) {
+        if let Some(byte) = self.keycodes.get_mut(keycode as usize / 8) {
+            if pressed {
+                *byte |= 1 << (keycode % 8);
+            } else {
+                *byte &= !(1 << (keycode % 8));
+            }
+        }
+    }
+
+    /// Convert to a 6KRO report, only the first 6 keycodes are kept
+    pub(crate) fn to_6kro(&self) -> KeyboardReport {
+        let mut report = KeyboardReport {
+            modifier: self.modifier,
+            ..Default::default()
+        };
+        let pressed = (0..NKRO_KEYCODE_BYTES * 8).filter(|&k| self.keycodes[k / 8] & (1 << (k % 8)) != 0);
+        for (slot, keycode) in report.keycodes.iter_mut().zip(pressed) {
+            *slot = keycode as u8;
+        }
+        report
+    }
+}
+
+impl SerializedDescriptor for NkroKeyboardReport {
+    fn desc() -> &'static [u8] {
+        NKRO_KEYBOARD_REPORT_DESCRIPTOR
+    }
+}
+
+impl AsInputReport for NkroKeyboardReport {}
+
 #[gen_hid_descriptor(
     (collection = APPLICATION, usage_page = 0xFF60, usage = 0x61) = {
         (usage = 0x62, logical_min = 0x0) = {
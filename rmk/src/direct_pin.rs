@@ -1,16 +1,20 @@
-use embassy_time::{Instant, Timer};
+use core::sync::atomic::Ordering;
+
+use embassy_time::{Duration, Instant, Timer};
 use embedded_hal;
 use embedded_hal::digital::InputPin;
 #[cfg(feature = "async_matrix")]
 use {embassy_futures::select::select_slice, embedded_hal_async::digital::Wait, heapless::Vec};
 
 use crate::MatrixTrait;
+use crate::config::DiodeDirection;
+use crate::debounce::DebouncerTrait;
 #[cfg(feature = "rapid_debouncer")]
 use crate::debounce::fast_debouncer::RapidDebouncer;
-use crate::debounce::{DebounceState, DebouncerTrait};
-use crate::event::{Event, KeyboardEvent};
+use crate::event::Event;
 use crate::input_device::InputDevice;
-use crate::matrix::KeyState;
+use crate::matrix::{DEFAULT_DIRECT_PIN_SCAN_INTERVAL, IDLE_SCAN_INTERVAL, MatrixScanner, wait_scan_interval};
+use crate::state::IDLE_STATE;
 
 /// DirectPinMartex only has input pins.
 pub struct DirectPinMatrix<
@@ -23,16 +27,16 @@ pub struct DirectPinMatrix<
 > {
     /// Input pins of the pcb matrix
     direct_pins: [[Option<In>; COL]; ROW],
-    /// Debouncer
-    debouncer: D,
-    /// Key state matrix
-    key_states: [[KeyState; COL]; ROW],
+    /// Debounces the pin states and produces the key events, the rows are scanned as the output pins
+    scanner: MatrixScanner<D, COL, ROW>,
     /// Start scanning
     scan_start: Option<Instant>,
     /// Pin active level
     low_active: bool,
     /// Current scan pos: (out_idx, in_idx)
     scan_pos: (usize, usize),
+    /// Interval between two scans when the keyboard isn't idle
+    scan_interval: Duration,
 }
 
 impl<
@@ -48,13 +52,22 @@ impl<
     pub fn new(direct_pins: [[Option<In>; COL]; ROW], debouncer: D, low_active: bool) -> Self {
         DirectPinMatrix {
             direct_pins,
-            debouncer,
-            key_states: [[KeyState::new(); COL]; ROW],
+            scanner: MatrixScanner::new(debouncer).with_diode_direction(DiodeDirection::Row2Col),
             scan_start: None,
             low_active,
             scan_pos: (0, 0),
+            scan_interval: DEFAULT_DIRECT_PIN_SCAN_INTERVAL,
         }
     }
+
+    /// Set the interval between two scans, the default is 100us. 0 scans the pins continuously.
+    ///
+    /// In idle mode, the pins are scanned every 20ms, or every `scan_interval` if it's longer.
+    pub fn with_scan_interval(mut self, scan_interval: Duration) -> Self {
+        self.scanner.check_scan_interval(scan_interval);
+        self.scan_interval = scan_interval;
+        self
+    }
 }
 
 impl<
@@ -76,6 +89,8 @@ impl<
             // Scan matrix and send report
             for row_idx in row_idx_start..self.direct_pins.len() {
                 let pins_row = self.direct_pins.get_mut(row_idx).unwrap();
+                // Only the row of the last reported key resumes from the middle
+                let col_idx_start = if row_idx == row_idx_start { col_idx_start } else { 0 };
                 for col_idx in col_idx_start..pins_row.len() {
                     let direct_pin = pins_row.get_mut(col_idx).unwrap();
                     // for (col_idx, direct_pin) in pins_row.iter_mut().enumerate() {
@@ -86,24 +101,14 @@ impl<
                             direct_pin.is_high().ok().unwrap_or_default()
                         };
 
-                        let debounce_state = self.debouncer.detect_change_with_debounce(
-                            col_idx,
-                            row_idx,
-                            pin_state,
-                            &self.key_states[row_idx][col_idx],
-                        );
-
-                        if let DebounceState::Debounced = debounce_state {
-                            self.key_states[row_idx][col_idx].toggle_pressed();
-                            let key_state = self.key_states[row_idx][col_idx];
-
+                        if let Some(event) = self.scanner.scan_key(col_idx, row_idx, pin_state) {
                             self.scan_pos = (row_idx, col_idx);
-                            return Event::Key(KeyboardEvent::key(row_idx as u8, col_idx as u8, key_state.pressed));
+                            return Event::Key(event);
                         }
 
                         // If there's key still pressed, always refresh the self.scan_start
                         #[cfg(feature = "async_matrix")]
-                        if self.key_states[row_idx][col_idx].pressed {
+                        if self.scanner.is_pressed(col_idx, row_idx) {
                             self.scan_start = Some(Instant::now());
                         }
                     }
@@ -112,7 +117,12 @@ impl<
 
             self.scan_pos = (0, 0);
 
-            Timer::after_micros(100).await;
+            if IDLE_STATE.load(Ordering::Acquire) {
+                // Scan at a slow rate in idle mode
+                Timer::after(IDLE_SCAN_INTERVAL.max(self.scan_interval)).await;
+            } else {
+                wait_scan_interval(self.scan_interval).await;
+            }
         }
     }
 }
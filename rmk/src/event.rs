@@ -1,5 +1,6 @@
+use embassy_time::Instant;
 use postcard::experimental::max_size::MaxSize;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 #[cfg(feature = "controller")]
 use {rmk_types::action::KeyAction, rmk_types::led_indicator::LedIndicator, rmk_types::modifier::ModifierCombination};
 
@@ -39,27 +40,71 @@ pub enum Event {
 /// `KeyboardEvent` is different from events from pointing devices,
 /// events from pointing devices are processed directly by the corresponding processors,
 /// while `KeyboardEvent` is processed by the keyboard with the keymap.
-#[derive(Serialize, Deserialize, Clone, Copy, Debug, MaxSize, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct KeyboardEvent {
     pub(crate) pressed: bool,
     pub(crate) pos: KeyboardEventPos,
+    /// Time when the key change is detected, the timing of tap-hold, combos, etc. is resolved against it
+    pub(crate) time: Instant,
 }
 
 impl KeyboardEvent {
+    /// Key change at (row, col), the time of the event is now
     pub fn key(row: u8, col: u8, pressed: bool) -> Self {
         Self {
             pressed,
             pos: KeyboardEventPos::Key(KeyPos { row, col }),
+            time: Instant::now(),
         }
     }
 
+    /// Rotary encoder event, the time of the event is now
     pub fn rotary_encoder(id: u8, direction: Direction, pressed: bool) -> Self {
         Self {
             pressed,
             pos: KeyboardEventPos::RotaryEncoder(RotaryEncoderPos { id, direction }),
+            time: Instant::now(),
         }
     }
+
+    /// Time when the key change is detected
+    pub fn time(&self) -> Instant {
+        self.time
+    }
+}
+
+/// Events are equal if they're the same change of the same key, the time isn't compared
+impl PartialEq for KeyboardEvent {
+    fn eq(&self, other: &Self) -> bool {
+        self.pressed == other.pressed && self.pos == other.pos
+    }
+}
+
+impl Eq for KeyboardEvent {}
+
+// The time is local to the device which detects the key change, so it's not serialized.
+// The deserialized event gets the current time, split peripherals send the age of the event along with it,
+// see `SplitMessage::Key`.
+impl Serialize for KeyboardEvent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.pressed, self.pos).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyboardEvent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (pressed, pos) = <(bool, KeyboardEventPos)>::deserialize(deserializer)?;
+        Ok(Self {
+            pressed,
+            pos,
+            time: Instant::now(),
+        })
+    }
+}
+
+impl MaxSize for KeyboardEvent {
+    const POSTCARD_MAX_SIZE: usize = bool::POSTCARD_MAX_SIZE + KeyboardEventPos::POSTCARD_MAX_SIZE;
 }
 
 /// The position of the keyboard event.
@@ -190,6 +235,8 @@ pub enum ControllerEvent {
     Battery(u8),
     /// Charging state changed, true means charging, false means not charging
     ChargingState(bool),
+    /// Battery percent dropped below the low battery threshold, sent once per crossing
+    LowBattery(u8),
     /// Layer changed
     Layer(u8),
     /// Modifier changed
@@ -200,6 +247,8 @@ pub enum ControllerEvent {
     ConnectionType(u8),
     /// Split peripheral connection
     SplitPeripheral(usize, bool),
+    /// Battery percent of the split peripheral changed
+    SplitPeripheralBattery(usize, u8),
     /// Split central connection
     SplitCentral(bool),
     /// Lock state led indicator
@@ -207,7 +256,13 @@ pub enum ControllerEvent {
     /// Ble state changed
     #[cfg(feature = "_ble")]
     BleState(u8, crate::ble::BleState),
-    /// Ble profile changed
+    /// Active BLE profile, sent when the profile is switched and at boot with the restored profile
     #[cfg(feature = "_ble")]
     BleProfile(u8),
+    /// RGB underglow state changed
+    RgbLight(crate::light::RgbLightConfig),
+    /// Backlight state changed
+    Backlight(crate::light::BacklightConfig),
+    /// RGB matrix state changed
+    RgbMatrix(crate::light::RgbMatrixConfig),
 }
@@ -1,6 +1,11 @@
 /// Traits and types for HID message reporting and listening.
+use core::cell::RefCell;
 use core::{future::Future, sync::atomic::Ordering};
 
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{Duration, Instant, Timer};
 use embassy_usb::class::hid::ReadError;
 use embassy_usb::driver::EndpointError;
 use serde::Serialize;
@@ -8,7 +13,7 @@ use usbd_hid::descriptor::{AsInputReport, MediaKeyboardReport, MouseReport, Syst
 
 use crate::CONNECTION_STATE;
 use crate::channel::KEYBOARD_REPORT_CHANNEL;
-use crate::descriptor::KeyboardReport;
+use crate::descriptor::{KeyboardReport, NkroKeyboardReport};
 use crate::state::ConnectionState;
 #[cfg(not(feature = "_no_usb"))]
 use crate::usb::USB_REMOTE_WAKEUP;
@@ -17,6 +22,8 @@ use crate::usb::USB_REMOTE_WAKEUP;
 pub enum Report {
     /// Normal keyboard hid report
     KeyboardReport(KeyboardReport),
+    /// N-key rollover keyboard hid report
+    NkroKeyboardReport(NkroKeyboardReport),
     /// Mouse hid report
     MouseReport(MouseReport),
     /// Media keyboard report
@@ -49,39 +56,191 @@ pub trait HidWriterTrait {
     fn write_report(&mut self, report: Self::ReportType) -> impl Future<Output = Result<usize, HidError>>;
 }
 
-/// Runnable writer
-pub trait RunnableHidWriter: HidWriterTrait {
+/// The report which is taken from `KEYBOARD_REPORT_CHANNEL` by the active writer, but not written yet.
+///
+/// The matrix, keyboard and storage tasks run all the time, only the writer(the reporter sink) is switched when
+/// the connection changes. There's at most one writer consuming the report channel at a time, the old writer is
+/// dropped at an await point before the new one runs. If the old writer is dropped while writing a report,
+/// the report is kept here and the new writer sends it first. So a report is always either in the channel, in this
+/// slot, or written by exactly one writer, and no key press is lost when switching between USB and BLE.
+static IN_FLIGHT_REPORT: Mutex<CriticalSectionRawMutex, RefCell<Option<Report>>> = Mutex::new(RefCell::new(None));
+
+/// The mouse report which is delayed by the rate limiting of the previous writer, see `MouseReportCoalescer`.
+///
+/// It's newer than the report in `IN_FLIGHT_REPORT`, so it's sent after that one.
+static PENDING_MOUSE_REPORT: Mutex<CriticalSectionRawMutex, RefCell<Option<MouseReport>>> =
+    Mutex::new(RefCell::new(None));
+
+/// Take the report which isn't written by the previous writer
+pub(crate) fn take_in_flight_report() -> Option<Report> {
+    IN_FLIGHT_REPORT
+        .lock(|r| r.borrow_mut().take())
+        .or_else(|| PENDING_MOUSE_REPORT.lock(|r| r.borrow_mut().take().map(Report::MouseReport)))
+}
+
+/// Runnable writer, which is the reporter sink of `KEYBOARD_REPORT_CHANNEL`
+pub trait RunnableHidWriter: HidWriterTrait<ReportType = Report> {
     /// Get the report to be sent to the host
     fn get_report(&mut self) -> impl Future<Output = Self::ReportType>;
 
     /// Run the writer task.
     fn run_writer(&mut self) -> impl Future<Output = ()> {
-        async {
-            loop {
-                // Get report to send
-                let report = self.get_report().await;
-                // Only send the report after the connection is established.
-                if CONNECTION_STATE.load(Ordering::Acquire)
-                    == <ConnectionState as Into<bool>>::into(ConnectionState::Connected)
-                {
-                    if let Err(e) = self.write_report(report.clone()).await {
-                        error!("Failed to send report: {:?}", e);
-                        #[cfg(not(feature = "_no_usb"))]
-                        // If the USB endpoint is disabled, try wakeup
-                        if let HidError::UsbEndpointError(EndpointError::Disabled) = e {
-                            USB_REMOTE_WAKEUP.signal(());
-                            // Wait 200ms for the wakeup, then send the report again
-                            // Ignore the error for the second send
-                            embassy_time::Timer::after_millis(200).await;
-                            if let Err(e) = self.write_report(report).await {
-                                error!("Failed to send report after wakeup: {:?}", e);
-                            }
+        write_reports(self)
+    }
+}
+
+/// Write the reports to the host after the connection is established, it never ends
+async fn write_reports<W: RunnableHidWriter + ?Sized>(writer: &mut W) {
+    loop {
+        // Send the report left by the previous writer first
+        let report = match take_in_flight_report() {
+            Some(report) => report,
+            None => writer.get_report().await,
+        };
+        // Only send the report after the connection is established.
+        if CONNECTION_STATE.load(Ordering::Acquire) == <ConnectionState as Into<bool>>::into(ConnectionState::Connected)
+        {
+            // Keep the report until it's written, in case that the writer is switched
+            IN_FLIGHT_REPORT.lock(|r| *r.borrow_mut() = Some(report.clone()));
+            if let Err(e) = writer.write_report(report.clone()).await {
+                error!("Failed to send report: {:?}", e);
+                #[cfg(not(feature = "_no_usb"))]
+                // If the USB endpoint is disabled, try wakeup
+                if let HidError::UsbEndpointError(EndpointError::Disabled) = e {
+                    USB_REMOTE_WAKEUP.signal(());
+                    // Wait 200ms for the wakeup, then send the report again
+                    // Ignore the error for the second send
+                    embassy_time::Timer::after_millis(200).await;
+                    if let Err(e) = writer.write_report(report).await {
+                        error!("Failed to send report after wakeup: {:?}", e);
+                    }
+                }
+            };
+            IN_FLIGHT_REPORT.lock(|r| r.borrow_mut().take());
+        }
+    }
+}
+
+#[cfg(not(feature = "_no_usb"))]
+/// Rate limiter of mouse reports, which merges the movement of the mouse reports within the interval.
+///
+/// The movement is summed up so no movement is lost. Reports with different buttons are never merged,
+/// and other reports are not delayed.
+pub(crate) struct MouseReportCoalescer {
+    /// Min interval between mouse reports, 0 disables the rate limiting
+    interval: Duration,
+    /// Merged mouse report which is waiting for the interval
+    pending: Option<MouseReport>,
+    /// The time when the next mouse report can be sent
+    next_send: Instant,
+}
+
+#[cfg(not(feature = "_no_usb"))]
+impl MouseReportCoalescer {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            pending: None,
+            next_send: Instant::MIN,
+        }
+    }
+
+    /// Receive the next report which should be sent from the report channel
+    pub(crate) async fn get_report(&mut self) -> Report {
+        loop {
+            let report = if self.pending.is_some() {
+                match select(Timer::at(self.next_send), KEYBOARD_REPORT_CHANNEL.receive()).await {
+                    Either::First(_) => {
+                        if let Some(report) = self.take_pending(Instant::now()) {
+                            return report;
                         }
-                    };
+                        continue;
+                    }
+                    Either::Second(report) => report,
                 }
+            } else {
+                KEYBOARD_REPORT_CHANNEL.receive().await
+            };
+            if let Some(report) = self.push(report, Instant::now()) {
+                return report;
             }
         }
     }
+
+    /// Process a received report, returns the report which should be sent now
+    fn push(&mut self, report: Report, now: Instant) -> Option<Report> {
+        let Report::MouseReport(mouse_report) = report else {
+            return Some(report);
+        };
+        if self.interval.as_ticks() == 0 {
+            return Some(report);
+        }
+        match self.pending.as_mut() {
+            Some(pending) => {
+                if merge_mouse_report(pending, &mouse_report) {
+                    None
+                } else {
+                    // The pending report is sent now, the new report waits for the next interval
+                    let sent = self.pending.replace(mouse_report).map(Report::MouseReport);
+                    self.next_send = now + self.interval;
+                    sent
+                }
+            }
+            None if now >= self.next_send => {
+                self.next_send = now + self.interval;
+                Some(report)
+            }
+            None => {
+                self.pending = Some(mouse_report);
+                None
+            }
+        }
+    }
+
+    /// Take the pending report if the interval has passed
+    fn take_pending(&mut self, now: Instant) -> Option<Report> {
+        if now < self.next_send {
+            return None;
+        }
+        let report = self.pending.take()?;
+        self.next_send = now + self.interval;
+        Some(Report::MouseReport(report))
+    }
+}
+
+#[cfg(not(feature = "_no_usb"))]
+impl Drop for MouseReportCoalescer {
+    fn drop(&mut self) {
+        // The writer is dropped when the connection is switched, the next writer sends the delayed movement
+        if let Some(report) = self.pending.take() {
+            PENDING_MOUSE_REPORT.lock(|r| *r.borrow_mut() = Some(report));
+        }
+    }
+}
+
+#[cfg(not(feature = "_no_usb"))]
+/// Merge the movement of `report` into `pending`, returns false if they can't be merged
+fn merge_mouse_report(pending: &mut MouseReport, report: &MouseReport) -> bool {
+    // -128 isn't in the logical range of the mouse report
+    let add = |a: i8, b: i8| a.checked_add(b).filter(|v| *v != i8::MIN);
+    if pending.buttons != report.buttons {
+        return false;
+    }
+    match (
+        add(pending.x, report.x),
+        add(pending.y, report.y),
+        add(pending.wheel, report.wheel),
+        add(pending.pan, report.pan),
+    ) {
+        (Some(x), Some(y), Some(wheel), Some(pan)) => {
+            pending.x = x;
+            pending.y = y;
+            pending.wheel = wheel;
+            pending.pan = pan;
+            true
+        }
+        _ => false,
+    }
 }
 
 /// HidReader trait is used for listening to HID messages from the host, via USB, BLE, etc.
@@ -111,7 +270,17 @@ impl RunnableHidWriter for DummyWriter {
         // Set CONNECTION_STATE to true to keep receiving messages from the peripheral
         CONNECTION_STATE.store(ConnectionState::Connected.into(), Ordering::Release);
         loop {
-            let _ = KEYBOARD_REPORT_CHANNEL.receive().await;
+            // The report left by the previous writer is dropped too, there's no host to send it to
+            let _report = match take_in_flight_report() {
+                Some(report) => report,
+                None => KEYBOARD_REPORT_CHANNEL.receive().await,
+            };
+            // The resolved key actions are already logged by the key event log, only note that the report is dropped
+            #[cfg(feature = "keylog")]
+            debug!(
+                "[keylog] {}us report dropped, no host connected",
+                Instant::now().as_micros()
+            );
         }
     }
 
@@ -120,6 +289,95 @@ impl RunnableHidWriter for DummyWriter {
     }
 }
 
+/// Writer which records the written reports instead of sending them to a host.
+///
+/// It's used for testing the behaviors such as tap-hold, combos and macros on the host: run it along with the
+/// keyboard, then check the exact sequence of reports in [`MockHidWriter::reports`]. At most `N` reports are
+/// recorded, writing more reports returns [`HidError::BufferOverflow`].
+#[cfg(any(test, feature = "std"))]
+pub struct MockHidWriter<const N: usize = 64> {
+    reports: heapless::Vec<Report, N>,
+    /// Number of reports after which the writes never complete
+    stall_after: Option<usize>,
+}
+
+#[cfg(any(test, feature = "std"))]
+impl<const N: usize> MockHidWriter<N> {
+    pub fn new() -> Self {
+        Self {
+            reports: heapless::Vec::new(),
+            stall_after: None,
+        }
+    }
+
+    /// Stop completing the writes after `count` reports are written, like a transport which is switched while
+    /// writing a report
+    pub fn with_stall_after(mut self, count: usize) -> Self {
+        self.stall_after = Some(count);
+        self
+    }
+
+    /// All written reports, in the order they're written
+    pub fn reports(&self) -> &[Report] {
+        &self.reports
+    }
+
+    /// The written keyboard reports, other reports are skipped
+    pub fn keyboard_reports(&self) -> impl Iterator<Item = &KeyboardReport> {
+        self.reports.iter().filter_map(|r| match r {
+            Report::KeyboardReport(report) => Some(report),
+            _ => None,
+        })
+    }
+
+    /// Clear the recorded reports
+    pub fn clear(&mut self) {
+        self.reports.clear();
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl<const N: usize> Default for MockHidWriter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl<const N: usize> HidWriterTrait for MockHidWriter<N> {
+    type ReportType = Report;
+
+    async fn write_report(&mut self, report: Self::ReportType) -> Result<usize, HidError> {
+        if self.stall_after.is_some_and(|count| self.reports.len() >= count) {
+            core::future::pending::<()>().await;
+        }
+        let mut buf = [0; 32];
+        let n = match &report {
+            Report::KeyboardReport(r) => ssmarshal::serialize(&mut buf, r),
+            Report::NkroKeyboardReport(r) => ssmarshal::serialize(&mut buf, r),
+            Report::MouseReport(r) => ssmarshal::serialize(&mut buf, r),
+            Report::MediaKeyboardReport(r) => ssmarshal::serialize(&mut buf, r),
+            Report::SystemControlReport(r) => ssmarshal::serialize(&mut buf, r),
+        }
+        .map_err(|_| HidError::ReportSerializeError)?;
+        self.reports.push(report).map_err(|_| HidError::BufferOverflow)?;
+        Ok(n)
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl<const N: usize> RunnableHidWriter for MockHidWriter<N> {
+    async fn run_writer(&mut self) {
+        // There's no real connection, the reports are always written
+        CONNECTION_STATE.store(ConnectionState::Connected.into(), Ordering::Release);
+        write_reports(self).await
+    }
+
+    async fn get_report(&mut self) -> Self::ReportType {
+        KEYBOARD_REPORT_CHANNEL.receive().await
+    }
+}
+
 #[cfg(feature = "_nrf_ble")]
 pub(crate) fn get_serial_number() -> &'static str {
     use heapless::String;
@@ -149,3 +407,165 @@ pub(crate) fn get_serial_number() -> &'static str {
 
     serial.as_str()
 }
+
+#[cfg(all(test, not(feature = "_no_usb")))]
+mod test {
+    use super::*;
+
+    fn mouse(buttons: u8, x: i8, y: i8) -> Report {
+        Report::MouseReport(MouseReport {
+            buttons,
+            x,
+            y,
+            wheel: 0,
+            pan: 0,
+        })
+    }
+
+    fn movement(report: Option<Report>) -> Option<(u8, i8, i8)> {
+        match report {
+            Some(Report::MouseReport(r)) => Some((r.buttons, r.x, r.y)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_mouse_report_coalescing() {
+        let mut coalescer = MouseReportCoalescer::new(Duration::from_millis(8));
+        // The first report is sent at once
+        assert_eq!(
+            movement(coalescer.push(mouse(0, 1, 2), Instant::from_millis(0))),
+            Some((0, 1, 2))
+        );
+        // Reports within the interval are merged
+        assert!(coalescer.push(mouse(0, 3, -1), Instant::from_millis(2)).is_none());
+        assert!(coalescer.push(mouse(0, 4, -1), Instant::from_millis(4)).is_none());
+        assert!(coalescer.take_pending(Instant::from_millis(6)).is_none());
+        assert_eq!(
+            movement(coalescer.take_pending(Instant::from_millis(8))),
+            Some((0, 7, -2))
+        );
+        assert!(coalescer.take_pending(Instant::from_millis(9)).is_none());
+    }
+
+    #[test]
+    fn test_mouse_report_not_merged() {
+        let mut coalescer = MouseReportCoalescer::new(Duration::from_millis(8));
+        assert!(coalescer.push(mouse(0, 1, 0), Instant::from_millis(0)).is_some());
+        assert!(coalescer.push(mouse(0, 100, 0), Instant::from_millis(1)).is_none());
+        // Overflow, the pending report is sent and the new one is kept
+        assert_eq!(
+            movement(coalescer.push(mouse(0, 100, 0), Instant::from_millis(2))),
+            Some((0, 100, 0))
+        );
+        // Button changes are never merged
+        assert_eq!(
+            movement(coalescer.push(mouse(1, 0, 0), Instant::from_millis(3))),
+            Some((0, 100, 0))
+        );
+        assert_eq!(
+            movement(coalescer.take_pending(Instant::from_millis(11))),
+            Some((1, 0, 0))
+        );
+        // Other reports are not delayed
+        let media = Report::MediaKeyboardReport(MediaKeyboardReport { usage_id: 0xE9 });
+        assert!(matches!(
+            coalescer.push(media, Instant::from_millis(12)),
+            Some(Report::MediaKeyboardReport(_))
+        ));
+    }
+
+    #[test]
+    fn test_pending_mouse_report_kept_when_dropped() {
+        let mut coalescer = MouseReportCoalescer::new(Duration::from_millis(8));
+        assert!(coalescer.push(mouse(0, 1, 0), Instant::from_millis(0)).is_some());
+        assert!(coalescer.push(mouse(0, 2, 3), Instant::from_millis(1)).is_none());
+        // The writer is switched before the interval passes, the next writer sends the pending report
+        drop(coalescer);
+        assert_eq!(movement(take_in_flight_report()), Some((0, 2, 3)));
+        assert!(take_in_flight_report().is_none());
+    }
+
+    #[test]
+    fn test_mouse_report_rate_limit_disabled() {
+        let mut coalescer = MouseReportCoalescer::new(Duration::from_millis(0));
+        assert!(coalescer.push(mouse(0, 1, 0), Instant::from_millis(0)).is_some());
+        assert!(coalescer.push(mouse(0, 1, 0), Instant::from_millis(0)).is_some());
+    }
+}
+
+#[cfg(test)]
+mod writer_test {
+    use embassy_futures::block_on;
+    use embassy_futures::select::select;
+    use embassy_futures::yield_now;
+    use rusty_fork::rusty_fork_test;
+
+    use super::*;
+
+    /// The first keycode of each written keyboard report
+    fn written(writer: &MockHidWriter) -> std::vec::Vec<u8> {
+        writer.keyboard_reports().map(|r| r.keycodes[0]).collect()
+    }
+
+    fn key_report(keycode: u8) -> Report {
+        Report::KeyboardReport(KeyboardReport {
+            modifier: 0,
+            reserved: 0,
+            leds: 0,
+            keycodes: [keycode, 0, 0, 0, 0, 0],
+        })
+    }
+
+    /// Run the writer until all pending tasks are stuck, then drop it like switching the transport
+    async fn run_until_switched(writer: &mut MockHidWriter) {
+        select(writer.run_writer(), async {
+            for _ in 0..10 {
+                yield_now().await;
+            }
+        })
+        .await;
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_no_report_lost_when_switching_writer() {
+            block_on(async {
+                CONNECTION_STATE.store(ConnectionState::Connected.into(), Ordering::Release);
+                for keycode in 4..8 {
+                    KEYBOARD_REPORT_CHANNEL.send(key_report(keycode)).await;
+                }
+
+                // The first writer is switched while writing the third report
+                let mut usb_writer = MockHidWriter::new().with_stall_after(2);
+                run_until_switched(&mut usb_writer).await;
+                assert_eq!(written(&usb_writer), [4, 5]);
+
+                // Reports sent during the switch are kept in the channel
+                KEYBOARD_REPORT_CHANNEL.send(key_report(8)).await;
+
+                // The new writer sends the interrupted report first, then the rest in order
+                let mut ble_writer = MockHidWriter::new();
+                run_until_switched(&mut ble_writer).await;
+                assert_eq!(written(&ble_writer), [6, 7, 8]);
+                assert!(take_in_flight_report().is_none());
+            });
+        }
+
+        #[test]
+        fn test_dummy_writer_drops_in_flight_report() {
+            block_on(async {
+                CONNECTION_STATE.store(ConnectionState::Connected.into(), Ordering::Release);
+                KEYBOARD_REPORT_CHANNEL.send(key_report(4)).await;
+                let mut usb_writer = MockHidWriter::new().with_stall_after(0);
+                run_until_switched(&mut usb_writer).await;
+                assert!(written(&usb_writer).is_empty());
+
+                // There's no host, the interrupted report isn't sent after reconnecting
+                select(DummyWriter {}.run_writer(), yield_now()).await;
+                assert!(take_in_flight_report().is_none());
+                assert!(KEYBOARD_REPORT_CHANNEL.is_empty());
+            });
+        }
+    }
+}
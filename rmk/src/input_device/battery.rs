@@ -7,6 +7,8 @@ use {crate::channel::send_controller_event, crate::event::ControllerEvent};
 
 use super::{InputDevice, InputProcessor};
 use crate::KeyMap;
+#[cfg(feature = "_ble")]
+use crate::channel::LOW_BATTERY_SIGNAL;
 #[cfg(feature = "controller")]
 use crate::channel::{CONTROLLER_CHANNEL, ControllerPub};
 use crate::event::Event;
@@ -77,10 +79,210 @@ impl<I: InputPin> InputDevice for ChargingStateReader<I> {
     }
 }
 
-pub struct BatteryProcessor<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize> {
-    keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
+/// Default discharge curve of a LiPo battery, `(millivolts, percent)` points ordered by voltage descending
+pub const DEFAULT_BATTERY_CURVE: [(u16, u8); 12] = [
+    (4200, 100),
+    (4060, 90),
+    (3980, 80),
+    (3920, 70),
+    (3870, 60),
+    (3820, 50),
+    (3790, 40),
+    (3770, 30),
+    (3740, 20),
+    (3680, 10),
+    (3450, 5),
+    (3000, 0),
+];
+
+/// A rising level is reported only if it's larger than the current level by more than this value,
+/// which prevents the reported level from oscillating
+#[cfg(feature = "_ble")]
+const BATTERY_LEVEL_HYSTERESIS: u8 = 2;
+
+/// The low battery warning is re-armed only after the battery level rises above the threshold by this value,
+/// so that a level oscillating around the threshold doesn't send the warning repeatedly
+#[cfg(any(feature = "_ble", test))]
+const LOW_BATTERY_HYSTERESIS: u8 = 5;
+
+/// Decides when the low battery warning is sent
+#[cfg(any(feature = "_ble", test))]
+#[derive(Clone, Copy, Debug)]
+struct LowBatteryWarning {
+    threshold: u8,
+    /// Whether the warning has been sent and not re-armed yet
+    warned: bool,
+}
+
+#[cfg(any(feature = "_ble", test))]
+impl LowBatteryWarning {
+    fn new(threshold: u8) -> Self {
+        Self {
+            threshold,
+            warned: false,
+        }
+    }
+
+    /// Update the battery level, returns true if the warning should be sent
+    fn update(&mut self, level: u8) -> bool {
+        if level < self.threshold {
+            let send = !self.warned;
+            self.warned = true;
+            send
+        } else {
+            if level >= self.threshold.saturating_add(LOW_BATTERY_HYSTERESIS) {
+                self.warned = false;
+            }
+            false
+        }
+    }
+}
+
+/// Config of converting battery voltage to battery level
+#[derive(Clone, Copy, Debug)]
+pub struct BatteryLevelConfig {
+    /// Discharge curve, `(millivolts, percent)` points ordered by voltage descending.
+    /// The battery level between two points is linearly interpolated.
+    ///
+    /// The voltages must be strictly descending and the levels must not increase, otherwise the default curve is used.
+    pub curve: &'static [(u16, u8)],
+    /// Window of the exponential moving average filter applied on the battery voltage, 0 or 1 disables the filter
+    pub smoothing_window: u8,
+    /// The low battery warning is sent once when the battery level drops below this value, 0 disables it.
+    ///
+    /// The warning is signaled by [`crate::channel::LOW_BATTERY_SIGNAL`] and `ControllerEvent::LowBattery`. It's sent
+    /// again only after the battery level rises above the threshold by 5%, e.g. after charging.
+    pub low_battery_threshold: u8,
+}
+
+impl Default for BatteryLevelConfig {
+    fn default() -> Self {
+        Self {
+            curve: &DEFAULT_BATTERY_CURVE,
+            smoothing_window: 8,
+            low_battery_threshold: 10,
+        }
+    }
+}
+
+impl BatteryLevelConfig {
+    /// Check the config, an invalid curve is replaced with the default one
+    pub(crate) fn validate(&mut self) {
+        if !is_valid_battery_curve(self.curve) {
+            warn!(
+                "Invalid battery curve: {:?}, the voltages must be descending and the levels must not increase, fallback to default",
+                self.curve
+            );
+            self.curve = &DEFAULT_BATTERY_CURVE;
+        }
+    }
+}
+
+/// Whether the curve isn't empty, the voltages are strictly descending and the levels don't increase
+fn is_valid_battery_curve(curve: &[(u16, u8)]) -> bool {
+    !curve.is_empty() && curve.windows(2).all(|p| p[0].0 > p[1].0 && p[0].1 >= p[1].1)
+}
+
+/// Convert the battery ADC value to the battery voltage in millivolts.
+fn battery_millivolts(val: u16, adc_divider_measured: u32, adc_divider_total: u32) -> u32 {
+    // According to nRF52840's datasheet, for single_ended saadc:
+    // val = v_adc * (gain / reference) * 2^(resolution)
+    //
+    // When using default setting, gain = 1/6, reference = 0.6v, resolution = 12bits, so:
+    // v_adc = val * 3.6v / 4096
+    //
+    // For example, rmk-ble-keyboard uses two resistors 820K and 2M adjusting the v_adc, then,
+    // v_adc = v_bat * measured / total => v_bat = val * 3.6v / 4096 * total / measured
+    let mut measured = adc_divider_measured.max(1);
+    let mut total = adc_divider_total;
+    if 500 < val && val < 1000 {
+        // Thing becomes different when using vddh as reference
+        // The adc value for vddh pin is actually vddh/5,
+        // so we use this rough range to detect vddh
+        measured = 1;
+        total = 5;
+    }
+    (val as u64 * 3600 * total as u64 / (4096 * measured as u64)) as u32
+}
+
+/// Get the battery level from the discharge curve, the curve must be valid
+fn battery_level_from_curve(millivolts: u32, curve: &[(u16, u8)]) -> u8 {
+    let (first_mv, first_level) = curve[0];
+    if millivolts >= first_mv as u32 {
+        return first_level;
+    }
+    for points in curve.windows(2) {
+        let (high_mv, high_level) = (points[0].0 as u32, points[0].1 as u32);
+        let (low_mv, low_level) = (points[1].0 as u32, points[1].1 as u32);
+        if millivolts >= low_mv {
+            let level = low_level + (millivolts - low_mv) * (high_level - low_level) / (high_mv - low_mv);
+            return level.min(100) as u8;
+        }
+    }
+    curve[curve.len() - 1].1
+}
+
+/// Converts the battery ADC values to battery levels, with the discharge curve and the moving average filter.
+///
+/// It's used by `BatteryProcessor`, and by split peripherals which send the battery level to the central.
+#[derive(Clone, Copy, Debug)]
+pub struct BatteryLevelConverter {
     adc_divider_measured: u32,
     adc_divider_total: u32,
+    /// Discharge curve and filter config
+    level_config: BatteryLevelConfig,
+    /// Filtered battery voltage, in 1/16 millivolts
+    filtered_voltage: Option<u32>,
+}
+
+impl BatteryLevelConverter {
+    /// Create the converter with `(adc_divider_measured, adc_divider_total)` of the battery ADC, an invalid curve in
+    /// `level_config` is replaced with the default one
+    pub fn new(adc_divider_measured: u32, adc_divider_total: u32, mut level_config: BatteryLevelConfig) -> Self {
+        level_config.validate();
+        Self {
+            adc_divider_measured,
+            adc_divider_total,
+            level_config,
+            filtered_voltage: None,
+        }
+    }
+
+    /// Convert the battery ADC value to the battery level
+    pub fn level(&mut self, val: u16) -> u8 {
+        self.level_from_millivolts(battery_millivolts(
+            val,
+            self.adc_divider_measured,
+            self.adc_divider_total,
+        ))
+    }
+
+    /// Restart the filter, e.g. when the voltage jumps after the charging state changes
+    pub fn reset(&mut self) {
+        self.filtered_voltage = None;
+    }
+
+    /// Filter the battery voltage, and get the battery level of the filtered voltage from the curve
+    fn level_from_millivolts(&mut self, millivolts: u32) -> u8 {
+        // Exponential moving average, the voltage is scaled by 16 to keep the precision
+        let sample = millivolts << 4;
+        let window = self.level_config.smoothing_window.max(1) as i64;
+        let filtered = match self.filtered_voltage {
+            Some(prev) => (prev as i64 + (sample as i64 - prev as i64) / window) as u32,
+            None => sample,
+        };
+        self.filtered_voltage = Some(filtered);
+        battery_level_from_curve(filtered >> 4, self.level_config.curve)
+    }
+}
+
+pub struct BatteryProcessor<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize> {
+    keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
+    /// Converter of the battery ADC values
+    converter: BatteryLevelConverter,
+    /// Low battery warning
+    #[cfg(feature = "_ble")]
+    low_battery: LowBatteryWarning,
     /// Current battery state
     battery_state: BatteryState,
     /// Publisher for controller channel
@@ -96,10 +298,26 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         adc_divider_total: u32,
         keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
     ) -> Self {
-        BatteryProcessor {
-            keymap,
+        Self::with_level_config(
             adc_divider_measured,
             adc_divider_total,
+            BatteryLevelConfig::default(),
+            keymap,
+        )
+    }
+
+    /// Create a battery processor with custom discharge curve and filter
+    pub fn with_level_config(
+        adc_divider_measured: u32,
+        adc_divider_total: u32,
+        level_config: BatteryLevelConfig,
+        keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
+    ) -> Self {
+        BatteryProcessor {
+            keymap,
+            converter: BatteryLevelConverter::new(adc_divider_measured, adc_divider_total, level_config),
+            #[cfg(feature = "_ble")]
+            low_battery: LowBatteryWarning::new(level_config.low_battery_threshold),
             battery_state: BatteryState::NotAvailable,
             #[cfg(feature = "controller")]
             controller_pub: unwrap!(CONTROLLER_CHANNEL.publisher()),
@@ -107,38 +325,23 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
     }
 
     #[cfg(feature = "_ble")]
-    fn get_battery_percent(&self, val: u16) -> u8 {
-        // Avoid overflow
-        let val = val as i32;
-
-        // According to nRF52840's datasheet, for single_ended saadc:
-        // val = v_adc * (gain / reference) * 2^(resolution)
-        //
-        // When using default setting, gain = 1/6, reference = 0.6v, resolution = 12bits, so:
-        // val = v_adc * 1137.8
-        //
-        // For example, rmk-ble-keyboard uses two resistors 820K and 2M adjusting the v_adc, then,
-        // v_adc = v_bat * measured / total => val = v_bat * 1137.8 * measured / total
-        //
-        // If the battery voltage range is 3.6v ~ 4.2v, the adc val range should be (4096 ~ 4755) * measured / total
-        let mut measured = self.adc_divider_measured as i32;
-        let mut total = self.adc_divider_total as i32;
-        if 500 < val && val < 1000 {
-            // Thing becomes different when using vddh as reference
-            // The adc value for vddh pin is actually vddh/5,
-            // so we use this rough range to detect vddh
-            measured = 1;
-            total = 5;
+    fn get_battery_percent(&mut self, val: u16) -> u8 {
+        let level = self.converter.level(val);
+        match self.battery_state {
+            // Ignore small rising to avoid oscillation
+            BatteryState::Normal(current) if level > current && level - current <= BATTERY_LEVEL_HYSTERESIS => current,
+            _ => level,
         }
-        if val > 4755_i32 * measured / total {
-            // 4755 ~= 4.2v * 1137.8
-            100_u8
-        } else if val < 4055_i32 * measured / total {
-            // 4096 ~= 3.6v * 1137.8
-            // To simplify the calculation, we use 4055 here
-            0_u8
-        } else {
-            ((val * total / measured - 4055) / 7) as u8
+    }
+
+    /// Send the low battery warning when the battery level drops below the threshold
+    #[cfg(feature = "_ble")]
+    fn check_low_battery(&mut self, level: u8) {
+        if self.low_battery.update(level) {
+            warn!("Low battery: {}%", level);
+            LOW_BATTERY_SIGNAL.signal(level);
+            #[cfg(feature = "controller")]
+            send_controller_event(&mut self.controller_pub, ControllerEvent::LowBattery(level));
         }
     }
 }
@@ -155,6 +358,7 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                 {
                     if matches!(self.battery_state, BatteryState::Normal(_) | BatteryState::NotAvailable) {
                         let battery_percent = self.get_battery_percent(val);
+                        self.check_low_battery(battery_percent);
 
                         #[cfg(feature = "controller")]
                         send_controller_event(&mut self.controller_pub, ControllerEvent::Battery(battery_percent));
@@ -177,6 +381,8 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                     #[cfg(feature = "controller")]
                     send_controller_event(&mut self.controller_pub, ControllerEvent::ChargingState(charging));
 
+                    // The voltage jumps when the charging state changes, restart the filter
+                    self.converter.reset();
                     if charging {
                         self.battery_state = BatteryState::Charging;
                     } else {
@@ -197,3 +403,100 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         self.keymap
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_battery_curve_validation() {
+        assert!(is_valid_battery_curve(&DEFAULT_BATTERY_CURVE));
+        assert!(is_valid_battery_curve(&[(4200, 100)]));
+        assert!(!is_valid_battery_curve(&[]));
+        // Voltages not descending
+        assert!(!is_valid_battery_curve(&[(3000, 0), (4200, 100)]));
+        assert!(!is_valid_battery_curve(&[(4200, 100), (4200, 50), (3000, 0)]));
+        // Level increases with a lower voltage
+        assert!(!is_valid_battery_curve(&[(4200, 50), (3800, 60), (3000, 0)]));
+
+        let mut config = BatteryLevelConfig {
+            curve: &[(3000, 0), (4200, 100)],
+            ..Default::default()
+        };
+        config.validate();
+        assert_eq!(config.curve, &DEFAULT_BATTERY_CURVE);
+    }
+
+    #[test]
+    fn test_battery_curve_interpolation() {
+        let curve = [(4200, 100), (3800, 50), (3400, 10), (3000, 0)];
+        // Out of the curve
+        assert_eq!(battery_level_from_curve(4300, &curve), 100);
+        assert_eq!(battery_level_from_curve(2900, &curve), 0);
+        // On the points
+        assert_eq!(battery_level_from_curve(4200, &curve), 100);
+        assert_eq!(battery_level_from_curve(3800, &curve), 50);
+        assert_eq!(battery_level_from_curve(3000, &curve), 0);
+        // Between the points
+        assert_eq!(battery_level_from_curve(4000, &curve), 75);
+        assert_eq!(battery_level_from_curve(3600, &curve), 30);
+        assert_eq!(battery_level_from_curve(3200, &curve), 5);
+    }
+
+    #[test]
+    fn test_battery_smoothing() {
+        let config = BatteryLevelConfig {
+            curve: &[(4200, 100), (3200, 0)],
+            smoothing_window: 4,
+            ..Default::default()
+        };
+        let mut converter = BatteryLevelConverter::new(1, 1, config);
+        // The first sample is taken as-is
+        assert_eq!(converter.level_from_millivolts(4200), 100);
+        // A single low sample only moves the level by 1/4 of the difference
+        assert_eq!(converter.level_from_millivolts(3800), 90);
+        assert_eq!(converter.level_from_millivolts(3800), 82);
+        // The level converges to the new voltage
+        for _ in 0..50 {
+            converter.level_from_millivolts(3800);
+        }
+        assert_eq!(converter.level_from_millivolts(3800), 60);
+
+        // The filter is restarted after reset
+        converter.reset();
+        assert_eq!(converter.level_from_millivolts(4200), 100);
+
+        // Window 0 disables the filter
+        let mut converter = BatteryLevelConverter::new(
+            1,
+            1,
+            BatteryLevelConfig {
+                smoothing_window: 0,
+                ..config
+            },
+        );
+        assert_eq!(converter.level_from_millivolts(4200), 100);
+        assert_eq!(converter.level_from_millivolts(3700), 50);
+    }
+
+    #[test]
+    fn test_low_battery_warning_fires_once() {
+        let mut warning = LowBatteryWarning::new(10);
+        assert!(!warning.update(50));
+        assert!(!warning.update(10));
+        // Sent once when the level drops below the threshold
+        assert!(warning.update(9));
+        assert!(!warning.update(8));
+        // Oscillating around the threshold doesn't send it again
+        assert!(!warning.update(10));
+        assert!(!warning.update(12));
+        assert!(!warning.update(9));
+        // Re-armed after the level rises above the threshold by the hysteresis
+        assert!(!warning.update(15));
+        assert!(warning.update(9));
+
+        // Threshold 0 disables the warning
+        let mut warning = LowBatteryWarning::new(0);
+        assert!(!warning.update(0));
+    }
+}
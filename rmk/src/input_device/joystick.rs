@@ -8,6 +8,62 @@ use crate::hid::Report;
 use crate::input_device::{InputProcessor, ProcessResult};
 use crate::keymap::KeyMap;
 
+/// Response curve of the joystick, applied to each axis after the deadzone
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum JoystickCurve {
+    /// The output is proportional to the deflection
+    #[default]
+    Linear,
+    /// The output grows with the square of the deflection, small deflections are more precise
+    Quadratic,
+}
+
+/// How the transformed axes of the joystick are turned into mouse movement
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct JoystickResponse {
+    /// Axis values within the deadzone are treated as 0, values outside are shifted towards 0 by the deadzone
+    pub deadzone: u16,
+    /// Response curve
+    pub curve: JoystickCurve,
+    /// Sensitivity in percent, 100 keeps the speed unchanged
+    pub sensitivity: u16,
+    /// Invert the x axis
+    pub invert_x: bool,
+    /// Invert the y axis
+    pub invert_y: bool,
+}
+
+impl Default for JoystickResponse {
+    fn default() -> Self {
+        Self {
+            deadzone: 0,
+            curve: JoystickCurve::Linear,
+            sensitivity: 100,
+            invert_x: false,
+            invert_y: false,
+        }
+    }
+}
+
+impl JoystickResponse {
+    /// Apply the deadzone, curve, sensitivity and inversion to an axis value
+    fn apply(&self, value: i16, invert: bool) -> i8 {
+        // Computed in i64, the curve and the sensitivity can't overflow at full scale
+        let magnitude = (value.unsigned_abs() as i64 - self.deadzone as i64).max(0);
+        let max = i8::MAX as i64;
+        let magnitude = match self.curve {
+            JoystickCurve::Linear => magnitude,
+            // Values at full speed(`i8::MAX`) are kept
+            JoystickCurve::Quadratic => magnitude * magnitude / max,
+        };
+        let magnitude = (magnitude * self.sensitivity as i64 / 100).min(max);
+        let value = if (value < 0) != invert { -magnitude } else { magnitude };
+        value as i8
+    }
+}
+
 pub struct JoystickProcessor<
     'a,
     const ROW: usize,
@@ -21,6 +77,7 @@ pub struct JoystickProcessor<
     keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
     record: [i16; N],
     resolution: u16,
+    response: JoystickResponse,
 }
 
 impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize, const N: usize>
@@ -38,8 +95,16 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
             resolution,
             keymap,
             record: [0; N],
+            response: JoystickResponse::default(),
         }
     }
+
+    /// Set the deadzone, response curve, sensitivity and axis inversion
+    pub fn with_response(mut self, response: JoystickResponse) -> Self {
+        self.response = response;
+        self
+    }
+
     async fn generate_report(&mut self) {
         let mut report = [0i16; N];
 
@@ -63,8 +128,8 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         // map to mouse
         let mouse_report = MouseReport {
             buttons: 0,
-            x: (report[0].clamp(i8::MIN as i16, i8::MAX as i16)) as i8,
-            y: (report[1].clamp(i8::MIN as i16, i8::MAX as i16)) as i8,
+            x: self.response.apply(report[0], self.response.invert_x),
+            y: self.response.apply(report[1], self.response.invert_y),
             wheel: 0,
             pan: 0,
         };
@@ -101,3 +166,63 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         self.keymap
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_response() {
+        let response = JoystickResponse::default();
+        assert_eq!(response.apply(10, false), 10);
+        assert_eq!(response.apply(-10, false), -10);
+        // Clamped to the range of mouse report
+        assert_eq!(response.apply(300, false), 127);
+        assert_eq!(response.apply(-300, false), -127);
+    }
+
+    #[test]
+    fn test_deadzone_and_invert() {
+        let response = JoystickResponse {
+            deadzone: 5,
+            ..Default::default()
+        };
+        assert_eq!(response.apply(5, false), 0);
+        assert_eq!(response.apply(-3, false), 0);
+        // Shifted by the deadzone, so the movement starts from 1
+        assert_eq!(response.apply(6, false), 1);
+        assert_eq!(response.apply(-15, false), -10);
+        assert_eq!(response.apply(-15, true), 10);
+    }
+
+    #[test]
+    fn test_curve_and_sensitivity() {
+        let response = JoystickResponse {
+            curve: JoystickCurve::Quadratic,
+            sensitivity: 50,
+            ..Default::default()
+        };
+        assert_eq!(response.apply(127, false), 63);
+        assert_eq!(response.apply(-40, false), -6);
+        assert_eq!(response.apply(8, false), 0);
+    }
+
+    #[test]
+    fn test_full_scale_response() {
+        let response = JoystickResponse {
+            curve: JoystickCurve::Quadratic,
+            sensitivity: u16::MAX,
+            ..Default::default()
+        };
+        assert_eq!(response.apply(i16::MAX, false), 127);
+        assert_eq!(response.apply(i16::MIN, false), -127);
+        assert_eq!(response.apply(i16::MIN, true), 127);
+
+        let response = JoystickResponse {
+            sensitivity: u16::MAX,
+            ..Default::default()
+        };
+        assert_eq!(response.apply(i16::MAX, false), 127);
+        assert_eq!(response.apply(i16::MIN, false), -127);
+    }
+}
@@ -12,6 +12,9 @@ use crate::keymap::KeyMap;
 pub mod adc;
 pub mod battery;
 pub mod joystick;
+#[cfg(feature = "pmw3360")]
+pub mod pmw3360;
+pub mod pointing;
 pub mod rotary_encoder;
 
 /// The trait for runnable input devices and processors.
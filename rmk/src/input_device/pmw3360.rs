@@ -0,0 +1,274 @@
+//! Driver of the PMW3360 optical sensor, which is commonly used in trackballs.
+//!
+//! The sensor is connected over SPI. The motion is read in bursts and sent as relative [`Event::AxisEventStream`] events,
+//! which are converted to the mouse report by [`crate::input_device::pointing::PointingProcessor`].
+//!
+//! The driver is async: the delays between the SPI transfers are done with timers, so other tasks keep running while
+//! the sensor is being initialized or read. The SROM firmware, if it's given in [`Pmw3360Config::srom`], is uploaded
+//! in the power-up sequence.
+use embassy_futures::select::{Either, select};
+use embassy_time::{Duration, Timer};
+use embedded_hal_async::spi::{Error, Operation, SpiDevice};
+use heapless::Deque;
+
+use crate::channel::POINTING_CPI_CYCLE_SIGNAL;
+use crate::event::{Axis, AxisEvent, AxisValType, Event};
+use crate::input_device::InputDevice;
+
+const REG_PRODUCT_ID: u8 = 0x00;
+const REG_MOTION: u8 = 0x02;
+const REG_DELTA_Y_H: u8 = 0x06;
+const REG_CONFIG1: u8 = 0x0F;
+const REG_CONFIG2: u8 = 0x10;
+const REG_SROM_ENABLE: u8 = 0x13;
+const REG_SROM_ID: u8 = 0x2A;
+const REG_POWER_UP_RESET: u8 = 0x3A;
+const REG_MOTION_BURST: u8 = 0x50;
+const REG_SROM_LOAD_BURST: u8 = 0x62;
+
+const PRODUCT_ID: u8 = 0x42;
+const MIN_CPI: u16 = 100;
+const MAX_CPI: u16 = 12000;
+
+/// Configuration of the PMW3360 sensor
+#[derive(Clone, Copy, Debug)]
+pub struct Pmw3360Config {
+    /// CPI steps, the first one is used after the power-up. `PointingCpiCycle` key switches to the next step
+    pub cpi: &'static [u16],
+    /// Interval of reading the motion
+    pub poll_interval: Duration,
+    /// Invert the x axis
+    pub invert_x: bool,
+    /// Invert the y axis
+    pub invert_y: bool,
+    /// SROM firmware provided by the sensor vendor, which is uploaded in the power-up sequence.
+    ///
+    /// The sensor tracks unreliably with its built-in firmware, so uploading the SROM is recommended. The bytes are
+    /// sent in one SPI transaction, and the sensor needs at least 15us between two bytes, so the SPI clock should be
+    /// 500kHz or lower. `None` keeps the built-in firmware.
+    pub srom: Option<&'static [u8]>,
+}
+
+impl Default for Pmw3360Config {
+    fn default() -> Self {
+        Self {
+            cpi: &[1600],
+            poll_interval: Duration::from_millis(1),
+            invert_x: false,
+            invert_y: false,
+            srom: None,
+        }
+    }
+}
+
+pub struct Pmw3360<S: SpiDevice> {
+    spi: S,
+    config: Pmw3360Config,
+    /// Index of the current CPI in `config.cpi`
+    cpi_index: usize,
+    initialized: bool,
+    /// Events of the last motion which are not sent yet
+    pending: Deque<Event, 3>,
+}
+
+impl<S: SpiDevice> Pmw3360<S> {
+    pub fn new(spi: S, config: Pmw3360Config) -> Self {
+        Self {
+            spi,
+            config,
+            cpi_index: 0,
+            initialized: false,
+            pending: Deque::new(),
+        }
+    }
+
+    /// Power-up sequence of the sensor, from the datasheet
+    async fn init(&mut self) -> Result<(), S::Error> {
+        self.write_register(REG_POWER_UP_RESET, 0x5A).await?;
+        Timer::after_millis(50).await;
+        // Read the motion registers once to clear them
+        for reg in REG_MOTION..=REG_DELTA_Y_H {
+            self.read_register(reg).await?;
+        }
+
+        if let Some(srom) = self.config.srom {
+            self.upload_srom(srom).await?;
+        }
+
+        // Wired mode, the rest mode is disabled
+        self.write_register(REG_CONFIG2, 0x00).await?;
+        let cpi = self.current_cpi();
+        self.set_cpi(cpi).await?;
+
+        let product_id = self.read_register(REG_PRODUCT_ID).await?;
+        if product_id != PRODUCT_ID {
+            warn!("Unexpected PMW3360 product id: {:#x}", product_id);
+        }
+        info!("PMW3360 initialized, CPI: {}", cpi);
+        Ok(())
+    }
+
+    /// SROM download sequence, from the datasheet
+    async fn upload_srom(&mut self, srom: &[u8]) -> Result<(), S::Error> {
+        // The rest mode must be disabled during the download
+        self.write_register(REG_CONFIG2, 0x00).await?;
+        self.write_register(REG_SROM_ENABLE, 0x1D).await?;
+        Timer::after_millis(10).await;
+        self.write_register(REG_SROM_ENABLE, 0x18).await?;
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[REG_SROM_LOAD_BURST | 0x80]),
+                Operation::DelayNs(15_000),
+                Operation::Write(srom),
+            ])
+            .await?;
+        Timer::after_micros(200).await;
+
+        // The SROM id is 0 if the firmware isn't running
+        let srom_id = self.read_register(REG_SROM_ID).await?;
+        if srom_id == 0 {
+            warn!("PMW3360 SROM upload failed, the built-in firmware is used");
+        } else {
+            info!("PMW3360 SROM uploaded, SROM id: {:#x}", srom_id);
+        }
+        Ok(())
+    }
+
+    fn current_cpi(&self) -> u16 {
+        self.config.cpi.get(self.cpi_index).copied().unwrap_or(1600)
+    }
+
+    async fn set_cpi(&mut self, cpi: u16) -> Result<(), S::Error> {
+        self.write_register(REG_CONFIG1, cpi_to_register(cpi)).await
+    }
+
+    /// Switch to the next CPI step
+    async fn cycle_cpi(&mut self) {
+        if self.config.cpi.is_empty() {
+            return;
+        }
+        self.cpi_index = (self.cpi_index + 1) % self.config.cpi.len();
+        let cpi = self.current_cpi();
+        match self.set_cpi(cpi).await {
+            Ok(()) => info!("PMW3360 CPI: {}", cpi),
+            Err(e) => error!("Set PMW3360 CPI error: {:?}", e.kind()),
+        }
+    }
+
+    /// Read the motion with a motion burst, returns `None` if there's no motion or the sensor is lifted
+    async fn read_motion(&mut self) -> Result<Option<(i16, i16)>, S::Error> {
+        self.write_register(REG_MOTION_BURST, 0x00).await?;
+        let mut buf = [0u8; 6];
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[REG_MOTION_BURST]),
+                Operation::DelayNs(35_000),
+                Operation::Read(&mut buf),
+            ])
+            .await?;
+        // Motion bit and lift bit of the motion register
+        if buf[0] & 0x80 == 0 || buf[0] & 0x08 != 0 {
+            return Ok(None);
+        }
+        let x = i16::from_le_bytes([buf[2], buf[3]]);
+        let y = i16::from_le_bytes([buf[4], buf[5]]);
+        Ok(Some((x, y)))
+    }
+
+    async fn write_register(&mut self, reg: u8, value: u8) -> Result<(), S::Error> {
+        self.spi
+            .transaction(&mut [Operation::Write(&[reg | 0x80, value]), Operation::DelayNs(35_000)])
+            .await?;
+        // tSWW/tSWR
+        Timer::after_micros(180).await;
+        Ok(())
+    }
+
+    async fn read_register(&mut self, reg: u8) -> Result<u8, S::Error> {
+        let mut buf = [0u8; 1];
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[reg & 0x7F]),
+                Operation::DelayNs(160_000),
+                Operation::Read(&mut buf),
+            ])
+            .await?;
+        // tSRW/tSRR
+        Timer::after_micros(20).await;
+        Ok(buf[0])
+    }
+
+    fn push_motion(&mut self, x: i16, y: i16) {
+        let x = if self.config.invert_x { x.saturating_neg() } else { x };
+        let y = if self.config.invert_y { y.saturating_neg() } else { y };
+        let axis_event = |axis, value| {
+            Event::AxisEventStream(AxisEvent {
+                typ: AxisValType::Rel,
+                axis,
+                value,
+            })
+        };
+        self.pending.clear();
+        let _ = self.pending.push_back(axis_event(Axis::X, x));
+        let _ = self.pending.push_back(axis_event(Axis::Y, y));
+        let _ = self.pending.push_back(Event::Eos);
+    }
+}
+
+impl<S: SpiDevice> InputDevice for Pmw3360<S> {
+    async fn read_event(&mut self) -> Event {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return event;
+            }
+
+            if !self.initialized {
+                match self.init().await {
+                    Ok(()) => self.initialized = true,
+                    Err(e) => {
+                        error!("PMW3360 init error: {:?}", e.kind());
+                        Timer::after_secs(1).await;
+                        continue;
+                    }
+                }
+            }
+
+            if let Either::Second(_) = select(
+                Timer::after(self.config.poll_interval),
+                POINTING_CPI_CYCLE_SIGNAL.wait(),
+            )
+            .await
+            {
+                self.cycle_cpi().await;
+                continue;
+            }
+
+            match self.read_motion().await {
+                Ok(Some((x, y))) => self.push_motion(x, y),
+                Ok(None) => (),
+                Err(e) => error!("Read PMW3360 motion error: {:?}", e.kind()),
+            }
+        }
+    }
+}
+
+/// Convert the CPI to the value of the `Config1` register, the CPI is set in steps of 100
+fn cpi_to_register(cpi: u16) -> u8 {
+    (cpi.clamp(MIN_CPI, MAX_CPI) / 100 - 1) as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cpi_to_register() {
+        assert_eq!(cpi_to_register(100), 0);
+        assert_eq!(cpi_to_register(1600), 15);
+        assert_eq!(cpi_to_register(1650), 15);
+        assert_eq!(cpi_to_register(12000), 119);
+        // Out of range
+        assert_eq!(cpi_to_register(0), 0);
+        assert_eq!(cpi_to_register(20000), 119);
+    }
+}
@@ -0,0 +1,198 @@
+use core::cell::RefCell;
+use core::sync::atomic::Ordering;
+
+use usbd_hid::descriptor::MouseReport;
+
+use crate::event::{Axis, AxisValType, Event};
+use crate::hid::Report;
+use crate::input_device::{InputProcessor, ProcessResult};
+use crate::keyboard::{MOUSE_BUTTON_STATES, POINTING_SCROLL_KEY_HELD};
+use crate::keymap::KeyMap;
+
+/// Scroll mode of pointing devices, in which the motion is sent as scroll wheel instead of cursor movement
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScrollConfig {
+    /// The scroll mode is active while this layer is active, besides holding the `PointingScrollMode` key
+    pub layer: Option<u8>,
+    /// Motion counts per scroll step, larger values scroll slower
+    pub divisor: u16,
+    /// Invert the horizontal scroll
+    pub invert_x: bool,
+    /// Invert the vertical scroll
+    pub invert_y: bool,
+}
+
+impl Default for ScrollConfig {
+    fn default() -> Self {
+        Self {
+            layer: None,
+            divisor: 8,
+            invert_x: false,
+            invert_y: false,
+        }
+    }
+}
+
+/// Processor of pointing devices, such as trackballs and trackpads.
+///
+/// It accumulates the relative motion in the [`Event::AxisEventStream`] and sends it as mouse movement at [`Event::Eos`].
+/// The mouse buttons held by mouse keys are kept in the report, so dragging with a mouse key works.
+///
+/// In the scroll mode, the x/y motion is converted to pan/wheel. The motion which is less than a scroll step is kept
+/// to the next motion, and it's dropped when the scroll mode is switched, so the switch never causes a jump.
+pub struct PointingProcessor<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize> {
+    keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
+    x: i16,
+    y: i16,
+    wheel: i16,
+    pan: i16,
+    scroll: ScrollConfig,
+    /// Whether the last motion was in the scroll mode
+    scrolling: bool,
+    /// Motion which is not enough for a scroll step yet
+    scroll_remainder: (i16, i16),
+}
+
+impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    PointingProcessor<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    pub fn new(keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>>) -> Self {
+        Self {
+            keymap,
+            x: 0,
+            y: 0,
+            wheel: 0,
+            pan: 0,
+            scroll: ScrollConfig::default(),
+            scrolling: false,
+            scroll_remainder: (0, 0),
+        }
+    }
+
+    /// Set the scroll mode config
+    pub fn with_scroll(mut self, scroll: ScrollConfig) -> Self {
+        self.scroll = scroll;
+        self
+    }
+
+    fn is_scroll_mode(&self) -> bool {
+        POINTING_SCROLL_KEY_HELD.load(Ordering::Relaxed)
+            || self
+                .scroll
+                .layer
+                .is_some_and(|layer| self.keymap.borrow().is_layer_active(layer))
+    }
+
+    /// Convert the accumulated x/y motion to pan/wheel
+    fn convert_to_scroll(&mut self) {
+        let divisor = self.scroll.divisor.max(1) as i16;
+        let pan = scroll_steps(&mut self.scroll_remainder.0, self.x, divisor);
+        // Moving up scrolls up, the y axis of the mouse points down while the wheel points up
+        let wheel = -scroll_steps(&mut self.scroll_remainder.1, self.y, divisor);
+        let pan = if self.scroll.invert_x { -pan } else { pan };
+        let wheel = if self.scroll.invert_y { -wheel } else { wheel };
+        self.pan = self.pan.saturating_add(pan);
+        self.wheel = self.wheel.saturating_add(wheel);
+        self.x = 0;
+        self.y = 0;
+    }
+
+    /// Send the accumulated motion, motion out of the range of the mouse report is split into multiple reports
+    async fn generate_report(&mut self) {
+        let scrolling = self.is_scroll_mode();
+        if scrolling != self.scrolling {
+            debug!("Pointing scroll mode: {}", scrolling);
+            self.scrolling = scrolling;
+            self.scroll_remainder = (0, 0);
+        }
+        if scrolling {
+            self.convert_to_scroll();
+        }
+        while self.x != 0 || self.y != 0 || self.wheel != 0 || self.pan != 0 {
+            let mouse_report = MouseReport {
+                buttons: MOUSE_BUTTON_STATES.load(Ordering::Relaxed),
+                x: take_i8(&mut self.x),
+                y: take_i8(&mut self.y),
+                wheel: take_i8(&mut self.wheel),
+                pan: take_i8(&mut self.pan),
+            };
+            self.send_report(Report::MouseReport(mouse_report)).await;
+        }
+    }
+}
+
+/// Add the motion to the remainder, and take the whole scroll steps from it
+fn scroll_steps(remainder: &mut i16, motion: i16, divisor: i16) -> i16 {
+    *remainder = remainder.saturating_add(motion);
+    let steps = *remainder / divisor;
+    *remainder -= steps * divisor;
+    steps
+}
+
+/// Take the part of the value which fits in `i8`, the rest is left in the value
+fn take_i8(value: &mut i16) -> i8 {
+    let taken = (*value).clamp(-(i8::MAX as i16), i8::MAX as i16);
+    *value -= taken;
+    taken as i8
+}
+
+impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    InputProcessor<'a, ROW, COL, NUM_LAYER, NUM_ENCODER> for PointingProcessor<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    async fn process(&mut self, event: Event) -> ProcessResult {
+        match event {
+            Event::AxisEventStream(axis_event) if matches!(axis_event.typ, AxisValType::Rel) => {
+                let value = match axis_event.axis {
+                    Axis::X => &mut self.x,
+                    Axis::Y => &mut self.y,
+                    Axis::V => &mut self.wheel,
+                    Axis::H => &mut self.pan,
+                    _ => return ProcessResult::Stop,
+                };
+                *value = value.saturating_add(axis_event.value);
+                ProcessResult::Stop
+            }
+            Event::Eos => {
+                self.generate_report().await;
+                ProcessResult::Stop
+            }
+            _ => ProcessResult::Continue(event),
+        }
+    }
+
+    /// Get the current keymap
+    fn get_keymap(&self) -> &RefCell<KeyMap<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>> {
+        self.keymap
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_take_i8() {
+        let mut value = 10;
+        assert_eq!(take_i8(&mut value), 10);
+        assert_eq!(value, 0);
+
+        // Large motion is split
+        let mut value = -300;
+        assert_eq!(take_i8(&mut value), -127);
+        assert_eq!(take_i8(&mut value), -127);
+        assert_eq!(take_i8(&mut value), -46);
+        assert_eq!(value, 0);
+    }
+
+    #[test]
+    fn test_scroll_steps() {
+        let mut remainder = 0;
+        assert_eq!(scroll_steps(&mut remainder, 5, 8), 0);
+        // The remainder is kept to the next motion
+        assert_eq!(scroll_steps(&mut remainder, 5, 8), 1);
+        assert_eq!(remainder, 2);
+        assert_eq!(scroll_steps(&mut remainder, -20, 8), -2);
+        assert_eq!(remainder, -2);
+    }
+}
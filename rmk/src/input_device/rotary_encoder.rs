@@ -23,6 +23,10 @@ pub struct RotaryEncoder<A, B, P> {
     /// The last action of the rotary encoder.
     /// When it's not `None`, the rotary encoder needs to emit a release event.
     last_action: Option<Direction>,
+    /// Number of pulses from the phase in one physical detent
+    pulses_per_detent: u8,
+    /// Pulses counted in the current detent, positive for clockwise
+    pulses: i8,
 }
 
 /// The encoder direction is either `Clockwise`, `CounterClockwise`, or `None`
@@ -128,6 +132,8 @@ where
             phase: DefaultPhase,
             id,
             last_action: None,
+            pulses_per_detent: 1,
+            pulses: 0,
         }
     }
 }
@@ -147,6 +153,8 @@ where
             phase: ResolutionPhase::new(resolution, reverse),
             id,
             last_action: None,
+            pulses_per_detent: 1,
+            pulses: 0,
         }
     }
 }
@@ -161,9 +169,19 @@ impl<A: InputPin, B: InputPin, P: Phase> RotaryEncoder<A, B, P> {
             phase,
             id,
             last_action: None,
+            pulses_per_detent: 1,
+            pulses: 0,
         }
     }
 
+    /// Set the number of pulses from the phase in one physical detent, 1 by default.
+    ///
+    /// Many encoders emit 2 or 4 pulses per detent, the pulses are divided so that one detent triggers one action.
+    pub fn with_pulses_per_detent(mut self, pulses_per_detent: u8) -> Self {
+        self.pulses_per_detent = pulses_per_detent.clamp(1, i8::MAX as u8);
+        self
+    }
+
     /// Call `update` to evaluate the next state of the encoder, propagates errors from `InputPin` read
     pub fn update(&mut self) -> Direction {
         // use mask to get previous state value
@@ -187,7 +205,28 @@ impl<A: InputPin, B: InputPin, P: Phase> RotaryEncoder<A, B, P> {
         self.state = s >> 2;
 
         // Use the phase implementation
-        self.phase.direction(s)
+        let direction = self.phase.direction(s);
+        self.count_pulse(direction)
+    }
+
+    /// Count a pulse from the phase, the direction is returned once a whole detent is turned
+    fn count_pulse(&mut self, direction: Direction) -> Direction {
+        let step = match direction {
+            Direction::Clockwise => 1,
+            Direction::CounterClockwise => -1,
+            Direction::None => return Direction::None,
+        };
+        // Turning back restarts the detent
+        if self.pulses.signum() == -step {
+            self.pulses = 0;
+        }
+        self.pulses += step;
+        if self.pulses.unsigned_abs() >= self.pulses_per_detent {
+            self.pulses = 0;
+            direction
+        } else {
+            Direction::None
+        }
     }
 
     /// Returns a reference to the first pin. Can be used to clear interrupt.
@@ -294,4 +333,43 @@ mod test {
             assert_eq!(d, d2);
         }
     }
+
+    #[test]
+    fn test_pulses_per_detent() {
+        struct NoPin;
+        impl embedded_hal::digital::ErrorType for NoPin {
+            type Error = core::convert::Infallible;
+        }
+        impl InputPin for NoPin {
+            fn is_high(&mut self) -> Result<bool, Self::Error> {
+                Ok(true)
+            }
+            fn is_low(&mut self) -> Result<bool, Self::Error> {
+                Ok(false)
+            }
+        }
+
+        let mut encoder = RotaryEncoder::new(NoPin, NoPin, 0).with_pulses_per_detent(4);
+        for _ in 0..3 {
+            assert_eq!(encoder.count_pulse(Direction::Clockwise), Direction::None);
+        }
+        assert_eq!(encoder.count_pulse(Direction::Clockwise), Direction::Clockwise);
+        assert_eq!(encoder.count_pulse(Direction::None), Direction::None);
+
+        // Turning back before the detent is completed restarts the count
+        for _ in 0..2 {
+            assert_eq!(encoder.count_pulse(Direction::Clockwise), Direction::None);
+        }
+        for _ in 0..3 {
+            assert_eq!(encoder.count_pulse(Direction::CounterClockwise), Direction::None);
+        }
+        assert_eq!(
+            encoder.count_pulse(Direction::CounterClockwise),
+            Direction::CounterClockwise
+        );
+
+        // Every pulse is a detent by default
+        let mut encoder = RotaryEncoder::new(NoPin, NoPin, 0);
+        assert_eq!(encoder.count_pulse(Direction::Clockwise), Direction::Clockwise);
+    }
 }
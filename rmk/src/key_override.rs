@@ -0,0 +1,121 @@
+use rmk_types::keycode::KeyCode;
+use rmk_types::modifier::ModifierCombination;
+
+/// A key override, which sends `replacement` instead of `trigger` when `trigger_modifiers` are pressed,
+/// e.g. `Shift + Backspace` sends `Delete`.
+///
+/// The pressed trigger modifiers are released while the replacement key is held.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyOverride {
+    /// The key which triggers the override
+    pub(crate) trigger: KeyCode,
+    /// Modifiers which must be pressed with the trigger key.
+    /// If both left and right side of a modifier are set, either side is accepted.
+    pub(crate) trigger_modifiers: ModifierCombination,
+    /// The override isn't triggered if any of these modifiers is pressed
+    pub(crate) negative_modifiers: ModifierCombination,
+    /// The key which is sent instead of the trigger key
+    pub(crate) replacement: KeyCode,
+    /// Modifiers which are sent with the replacement key
+    pub(crate) replacement_modifiers: ModifierCombination,
+}
+
+impl KeyOverride {
+    pub fn new(
+        trigger: KeyCode,
+        trigger_modifiers: ModifierCombination,
+        negative_modifiers: ModifierCombination,
+        replacement: KeyCode,
+        replacement_modifiers: ModifierCombination,
+    ) -> Self {
+        Self {
+            trigger,
+            trigger_modifiers,
+            negative_modifiers,
+            replacement,
+            replacement_modifiers,
+        }
+    }
+
+    /// Check whether the override is triggered by the key with the pressed modifiers.
+    ///
+    /// Returns the pressed trigger modifiers, which are released while the replacement key is sent.
+    pub(crate) fn check(&self, key: KeyCode, modifiers: ModifierCombination) -> Option<ModifierCombination> {
+        if key != self.trigger || (modifiers & self.negative_modifiers).into_bits() != 0 {
+            return None;
+        }
+        let required = self.trigger_modifiers.into_bits();
+        let pressed = modifiers.into_bits();
+        // Bits of ctrl, shift, alt and gui, the right side is 4 bits higher than the left side
+        let matched = (0..4).all(|i| {
+            let both_sides = (1 << i) | (1 << (i + 4));
+            match required & both_sides {
+                0 => true,
+                r if r == both_sides => pressed & both_sides != 0,
+                r => pressed & r == r,
+            }
+        });
+        matched.then(|| modifiers & self.trigger_modifiers)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SHIFT: ModifierCombination = ModifierCombination::new().with_left_shift(true).with_right_shift(true);
+
+    #[test]
+    fn test_either_side_modifier() {
+        let o = KeyOverride::new(
+            KeyCode::Backspace,
+            SHIFT,
+            ModifierCombination::new(),
+            KeyCode::Delete,
+            ModifierCombination::new(),
+        );
+        assert_eq!(
+            o.check(KeyCode::Backspace, ModifierCombination::RSHIFT),
+            Some(ModifierCombination::RSHIFT)
+        );
+        assert_eq!(
+            o.check(
+                KeyCode::Backspace,
+                ModifierCombination::LSHIFT | ModifierCombination::LCTRL
+            ),
+            Some(ModifierCombination::LSHIFT)
+        );
+        assert_eq!(o.check(KeyCode::Backspace, ModifierCombination::LCTRL), None);
+        assert_eq!(o.check(KeyCode::A, ModifierCombination::LSHIFT), None);
+    }
+
+    #[test]
+    fn test_all_modifiers_required() {
+        let o = KeyOverride::new(
+            KeyCode::Dot,
+            ModifierCombination::LCTRL | SHIFT,
+            ModifierCombination::LALT,
+            KeyCode::Comma,
+            ModifierCombination::new(),
+        );
+        assert_eq!(o.check(KeyCode::Dot, ModifierCombination::LSHIFT), None);
+        // The right ctrl doesn't match the left ctrl
+        assert_eq!(
+            o.check(KeyCode::Dot, ModifierCombination::RCTRL | ModifierCombination::LSHIFT),
+            None
+        );
+        assert_eq!(
+            o.check(KeyCode::Dot, ModifierCombination::LCTRL | ModifierCombination::LSHIFT),
+            Some(ModifierCombination::LCTRL | ModifierCombination::LSHIFT)
+        );
+        // Negative modifiers
+        assert_eq!(
+            o.check(
+                KeyCode::Dot,
+                ModifierCombination::LCTRL | ModifierCombination::LSHIFT | ModifierCombination::LALT
+            ),
+            None
+        );
+    }
+}
@@ -21,28 +21,59 @@ use {
 
 use crate::channel::{KEY_EVENT_CHANNEL, KEYBOARD_REPORT_CHANNEL};
 use crate::combo::Combo;
-use crate::descriptor::KeyboardReport;
+use crate::descriptor::{KeyboardReport, NkroKeyboardReport};
 use crate::event::{KeyboardEvent, KeyboardEventPos};
 use crate::fork::{ActiveFork, StateBits};
 use crate::hid::Report;
 use crate::input_device::Runnable;
 use crate::input_device::rotary_encoder::Direction;
+use crate::keyboard::auto_shift::PendingAutoShift;
+use crate::keyboard::bypass::BypassState;
+use crate::keyboard::dynamic_macro::DynamicMacroState;
 use crate::keyboard::held_buffer::{HeldBuffer, HeldKey, KeyState};
+use crate::keyboard::key_override::ActiveKeyOverride;
+#[cfg(feature = "keylog")]
+use crate::keyboard::keylog::{TapHoldDecision, log_key_action, log_key_event, log_tap_hold_decision};
+use crate::keyboard::layer_tap_toggle::LayerTapToggleState;
+use crate::keyboard::leader::LeaderState;
+use crate::keyboard::media_key_repeat::MediaKeyRepeat;
+use crate::keyboard::repeat::RepeatState;
 use crate::keyboard_macros::MacroOperation;
 use crate::keymap::KeyMap;
 use crate::morse::{MorseMode, MorsePattern, TAP};
 #[cfg(all(feature = "split", feature = "_ble"))]
 use crate::split::ble::central::update_activity_time;
-use crate::{FORK_MAX_NUM, boot};
+use crate::{FORK_MAX_NUM, KEY_OVERRIDE_MAX_NUM, boot};
 
+pub(crate) mod auto_shift;
+pub(crate) mod bypass;
 pub(crate) mod combo;
+pub(crate) mod dynamic_macro;
+pub(crate) mod encoder_acceleration;
 pub(crate) mod held_buffer;
+pub(crate) mod key_override;
+#[cfg(feature = "keylog")]
+pub(crate) mod keylog;
+pub(crate) mod layer_tap_toggle;
+pub(crate) mod leader;
+pub(crate) mod media_key_repeat;
 pub(crate) mod morse;
 pub(crate) mod mouse;
 pub(crate) mod oneshot;
+pub(crate) mod repeat;
+pub(crate) mod space_cadet;
+pub(crate) mod unicode;
 
 const HOLD_BUFFER_SIZE: usize = 16;
 
+// Brightness step of backlight keycodes, 5 steps from off to the max brightness
+const BACKLIGHT_STEP: u8 = 51;
+
+// The backlight state is saved after it's unchanged for this delay, so that pressing the backlight keys repeatedly
+// doesn't write the flash on every press
+#[cfg(feature = "storage")]
+const BACKLIGHT_SAVE_DELAY: Duration = Duration::from_secs(3);
+
 // Timestamp of the last key action, the value is the number of seconds since the boot
 #[cfg(feature = "_ble")]
 pub(crate) static LAST_KEY_TIMESTAMP: Signal<crate::RawMutex, u32> = Signal::new();
@@ -51,6 +82,12 @@ pub(crate) static LAST_KEY_TIMESTAMP: Signal<crate::RawMutex, u32> = Signal::new
 /// LedIndicator type would be nicer, but that does not have const expr constructor
 pub(crate) static LOCK_LED_STATES: core::sync::atomic::AtomicU8 = core::sync::atomic::AtomicU8::new(0u8);
 
+/// Mouse buttons held by mouse keys, pointing devices send them with the motion so that dragging works
+pub(crate) static MOUSE_BUTTON_STATES: core::sync::atomic::AtomicU8 = core::sync::atomic::AtomicU8::new(0u8);
+
+/// Whether the `PointingScrollMode` key is held, pointing devices scroll instead of moving the cursor while it's held
+pub(crate) static POINTING_SCROLL_KEY_HELD: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
 #[derive(Debug)]
 enum LoopState {
     /// Default state, fire and forget current key event
@@ -72,6 +109,8 @@ enum OneShotState<T> {
     Single(T),
     /// Another key was pressed before one shot key was released, treat as a normal modifier/layer
     Held(T),
+    /// One shot key was tapped twice, keep it active until it's tapped again
+    Locked(T),
     /// One shot inactive
     #[default]
     None,
@@ -81,7 +120,9 @@ impl<T> OneShotState<T> {
     /// Get the current one shot value if any
     pub fn value(&self) -> Option<&T> {
         match self {
-            OneShotState::Initial(v) | OneShotState::Single(v) | OneShotState::Held(v) => Some(v),
+            OneShotState::Initial(v) | OneShotState::Single(v) | OneShotState::Held(v) | OneShotState::Locked(v) => {
+                Some(v)
+            }
             OneShotState::None => None,
         }
     }
@@ -96,14 +137,27 @@ impl<const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCOD
         loop {
             let result = match self.next_buffered_key() {
                 Some(key) => self.process_buffered_key(key).await,
-                None => {
-                    // No buffered tap-hold event, wait for new key
-                    let event = KEY_EVENT_CHANNEL.receive().await;
-                    // Process the key event
-                    self.process_inner(event).await
-                }
+                None => match self.next_timeout_time() {
+                    // An auto shift key or the leader key is waiting, or a media key is repeating
+                    Some(timeout_time) => match with_deadline(timeout_time, KEY_EVENT_CHANNEL.receive()).await {
+                        Ok(event) => self.process_inner(event).await,
+                        Err(_timeout) => {
+                            self.process_timeouts().await;
+                            LoopState::OK
+                        }
+                    },
+                    None => {
+                        // No buffered tap-hold event, wait for new key
+                        let event = KEY_EVENT_CHANNEL.receive().await;
+                        // Process the key event
+                        self.process_inner(event).await
+                    }
+                },
             };
 
+            // The dynamic macro is played after the play key is processed
+            self.play_pending_dynamic_macro().await;
+
             match result {
                 LoopState::Queue => {
                     // keep unprocessed key events
@@ -149,14 +203,34 @@ pub struct Keyboard<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usi
     /// Timer which records the timestamp of rotary encoder changes
     pub(crate) rotary_encoder_timer: [[Option<Instant>; 2]; NUM_ENCODER],
 
+    /// Actions of the accelerated detents of rotary encoders, which are released with the detents
+    accelerated_encoders: [Option<KeyAction>; NUM_ENCODER],
+
     /// Record the timestamp of last **simple key** press.
     /// It's used in tap-hold prior-idle-time check.
     last_press_time: Instant,
 
-    /// stores the last KeyCode executed, to be repeated if the repeat key os pressed
-    /// Used in repeat-key
+    /// Position and release time of the last tapped tap-hold key, used in the quick tap check
+    last_tap: Option<(KeyboardEventPos, Instant)>,
+
+    /// The last pressed KeyCode, used in caps word
     last_key_code: KeyCode,
 
+    /// Repeat key state
+    repeat: RepeatState,
+
+    /// The layer locked by `TO`, it's not deactivated by releasing momentary layer keys
+    to_layer: Option<u8>,
+
+    /// Layers locked by `LayerLock`, they're not deactivated by releasing momentary layer keys
+    locked_layers: [bool; NUM_LAYER],
+
+    /// Tap counting state of `LayerTapToggle` keys
+    layer_tap_toggle: Option<LayerTapToggleState>,
+
+    /// The held space cadet key and its press time, it's cleared when another key is pressed
+    space_cadet: Option<(KeyboardEventPos, Instant)>,
+
     /// One shot layer state
     osl_state: OneShotState<u8>,
 
@@ -168,6 +242,29 @@ pub struct Keyboard<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usi
     /// Caps word idle timer - tracks when caps word should timeout
     caps_word_timer: Option<Instant>,
 
+    /// Whether auto shift is currently enabled
+    auto_shift_on: bool,
+    /// The auto shift key which is waiting for the auto shift decision
+    pending_auto_shift: Option<PendingAutoShift>,
+    /// Position of the key which is sent with auto shift
+    auto_shifted: Option<KeyboardEventPos>,
+
+    /// The key sent by `GraveEscape`, which is decided when it's pressed
+    grave_escape_key: KeyCode,
+
+    /// Leader key state
+    leader: LeaderState,
+
+    /// Dynamic macro recording and playing state
+    dynamic_macro: DynamicMacroState,
+
+    /// The held consumer or system control key which is repeated
+    media_key_repeat: Option<MediaKeyRepeat>,
+
+    /// The time to save the changed backlight state to the storage
+    #[cfg(feature = "storage")]
+    backlight_save_time: Option<Instant>,
+
     /// The modifiers coming from (last) Action::KeyWithModifier
     with_modifiers: ModifierCombination,
 
@@ -179,6 +276,9 @@ pub struct Keyboard<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usi
     fork_states: [Option<ActiveFork>; FORK_MAX_NUM], // chosen replacement key of the currently triggered forks and the related modifier suppression
     fork_keep_mask: ModifierCombination, // aggregate here the explicit modifiers pressed since the last fork activations
 
+    /// The triggered key overrides, the index is same as the key override config
+    active_key_overrides: [Option<ActiveKeyOverride>; KEY_OVERRIDE_MAX_NUM],
+
     /// The held modifiers for the keyboard hid report
     held_modifiers: ModifierCombination,
 
@@ -189,6 +289,13 @@ pub struct Keyboard<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usi
     /// This is still needed besides `held_keycodes` because multiple keys with same keycode can be registered.
     registered_keys: [Option<KeyboardEvent>; 6],
 
+    /// Whether the N-key rollover report is used instead of the 6KRO report
+    nkro_on: bool,
+
+    /// Internal NKRO report buf, which holds all pressed keys besides `held_keycodes`.
+    /// The modifiers are filled when the report is sent.
+    nkro_report: NkroKeyboardReport,
+
     /// Internal mouse report buf
     mouse_report: MouseReport,
 
@@ -206,6 +313,9 @@ pub struct Keyboard<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usi
     /// Used for temporarily disabling combos
     combo_on: bool,
 
+    /// Bypass mode state, the keys on the default layer are sent without any processing in the bypass mode
+    bypass: BypassState,
+
     /// Publisher for controller channel
     #[cfg(feature = "controller")]
     controller_pub: ControllerPub,
@@ -219,21 +329,35 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
             keymap,
             timer: [[None; ROW]; COL],
             rotary_encoder_timer: [[None; 2]; NUM_ENCODER],
+            accelerated_encoders: [None; NUM_ENCODER],
             last_press_time: Instant::now(),
+            last_tap: None,
             osl_state: OneShotState::default(),
             osm_state: OneShotState::default(),
             caps_word_active: false,
             caps_word_timer: None,
+            auto_shift_on: keymap.borrow().behavior.auto_shift.enable,
+            pending_auto_shift: None,
+            auto_shifted: None,
+            grave_escape_key: KeyCode::Escape,
+            leader: LeaderState::new(),
+            dynamic_macro: DynamicMacroState::new(),
+            media_key_repeat: None,
+            #[cfg(feature = "storage")]
+            backlight_save_time: None,
             with_modifiers: ModifierCombination::default(),
             macro_texting: false,
             macro_caps: false,
             fork_states: [None; FORK_MAX_NUM],
             fork_keep_mask: ModifierCombination::default(),
+            active_key_overrides: [None; KEY_OVERRIDE_MAX_NUM],
             unprocessed_events: Vec::new(),
             held_buffer: HeldBuffer::new(),
             registered_keys: [None; 6],
             held_modifiers: ModifierCombination::default(),
             held_keycodes: [KeyCode::No; 6],
+            nkro_on: keymap.borrow().behavior.nkro,
+            nkro_report: NkroKeyboardReport::default(),
             mouse_report: MouseReport {
                 buttons: 0,
                 x: 0,
@@ -244,10 +368,16 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
             media_report: MediaKeyboardReport { usage_id: 0 },
             system_control_report: SystemControlReport { usage_id: 0 },
             last_key_code: KeyCode::No,
+            repeat: RepeatState::default(),
+            to_layer: None,
+            locked_layers: [false; NUM_LAYER],
+            layer_tap_toggle: None,
+            space_cadet: None,
             mouse_accel: 0,
             mouse_repeat: 0,
             mouse_wheel_repeat: 0,
             combo_on: true,
+            bypass: BypassState::new(keymap.borrow().behavior.bypass.enable),
             #[cfg(feature = "controller")]
             controller_pub: unwrap!(CONTROLLER_CHANNEL.publisher()),
         }
@@ -333,21 +463,81 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         LoopState::OK
     }
 
+    /// The earliest time when the auto shift key or the leader key times out, the held media key is repeated, or
+    /// the backlight state is saved
+    fn next_timeout_time(&self) -> Option<Instant> {
+        [
+            self.pending_auto_shift.map(|p| p.timeout_time()),
+            self.leader.timeout_time(),
+            self.media_key_repeat.map(|r| r.next_repeat()),
+            #[cfg(feature = "storage")]
+            self.backlight_save_time,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Process the auto shift timeout, the leader key timeout, the media key repeat and the backlight saving whose
+    /// time is reached
+    async fn process_timeouts(&mut self) {
+        let now = Instant::now();
+        if self.pending_auto_shift.is_some_and(|p| p.timeout_time() <= now) {
+            self.process_auto_shift_timeout().await;
+        }
+        if self.leader.timeout_time().is_some_and(|t| t <= now) {
+            self.process_leader_timeout().await;
+        }
+        if self.media_key_repeat.is_some_and(|r| r.next_repeat() <= now) {
+            self.process_media_key_repeat().await;
+        }
+        #[cfg(feature = "storage")]
+        if self.backlight_save_time.is_some_and(|t| t <= now) {
+            self.save_backlight().await;
+        }
+    }
+
     /// Process key changes at (row, col)
     async fn process_inner(&mut self, event: KeyboardEvent) -> LoopState {
         #[cfg(feature = "matrix_tester")]
         self.keymap.borrow_mut().matrix_state.update(&event);
+        #[cfg(feature = "keylog")]
+        log_key_event(event);
 
+        // Time of the previous detent of the rotary encoder, used in encoder acceleration
+        let last_detent = match event.pos {
+            KeyboardEventPos::RotaryEncoder(_) if event.pressed => self.get_timer_value(event),
+            _ => None,
+        };
         // Matrix should process key pressed event first, record the timestamp of key changes
         if event.pressed {
-            self.set_timer_value(event, Some(Instant::now()));
+            self.set_timer_value(event, Some(event.time));
         }
+        self.interrupt_auto_shift(event).await;
+        self.interrupt_space_cadet(event);
+        self.interrupt_layer_tap_toggle(event);
         // Update activity time for BLE split central sleep management
         #[cfg(all(feature = "split", feature = "_ble"))]
         update_activity_time();
+        // Wake the keyboard up from idle mode
+        #[cfg(feature = "_ble")]
+        crate::ble::idle::update_idle_activity();
+
+        if self.is_bypassed(event) {
+            self.process_bypass(event).await;
+            return LoopState::OK;
+        }
 
         // Process key
-        let key_action = &self.keymap.borrow_mut().get_action_with_layer_cache(event);
+        let key_action = self.keymap.borrow_mut().get_action_with_layer_cache(event);
+
+        // An in-progress morse (e.g. a tap dance waiting for the next tap) keeps the action resolved at its first press.
+        // The active layer might be changed between taps, following taps and releases should not go to the new layer.
+        let key_action = match self.held_buffer.find_pos(event.pos) {
+            Some(k) if k.action.is_morse() => k.action,
+            _ => key_action,
+        };
+        let key_action = &self.accelerate_encoder(key_action, event, last_detent).await;
 
         if self.combo_on {
             if let (Some(key_action), is_combo) = self.process_combo(key_action, event).await {
@@ -361,28 +551,44 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
     }
 
     async fn process_key_action(&mut self, key_action: &KeyAction, event: KeyboardEvent, is_combo: bool) -> LoopState {
-        // When pressing a morse key, check flow tap first.
-        if event.pressed
-            && self.keymap.borrow().behavior.tap_hold.enable_hrm
-            && key_action.is_morse()
-            && self.last_press_time.elapsed() < self.keymap.borrow().behavior.tap_hold.prior_idle_time
+        // Any key press ends the quick tap of the last tapped key
+        let last_tap = if event.pressed { self.last_tap.take() } else { None };
+        let quick_tap = self.is_quick_tap(key_action, event, last_tap);
+
+        // When pressing a morse key, check quick tap and flow tap first.
+        if quick_tap
+            || (event.pressed
+                && self.keymap.borrow().behavior.tap_hold.enable_hrm
+                && key_action.is_morse()
+                && event.time.saturating_duration_since(self.last_press_time)
+                    < self.keymap.borrow().behavior.tap_hold.prior_idle_time)
         {
-            // It's in key streak, trigger the first tap action
-            debug!("Flow tap detected, trigger tap action for current morse key");
+            // It's pressed again right after a tap, or in key streak, trigger the first tap action
+            debug!("Quick tap or flow tap detected, trigger tap action for current morse key");
 
             // TODO: Check whether current morse key is in the buffer, if so, remove it from buffer and use the buffered morse pattern?
             // TODO: Check only morse with modifier?
 
             let action = Self::action_from_pattern(&self.keymap.borrow().behavior, key_action, TAP); //tap action
+            #[cfg(feature = "keylog")]
+            log_tap_hold_decision(
+                event,
+                TAP,
+                action,
+                if quick_tap {
+                    TapHoldDecision::QuickTap
+                } else {
+                    TapHoldDecision::FlowTap
+                },
+            );
             self.process_key_action_normal(action, event).await;
             // Push back after triggered press
-            let now = Instant::now();
-            let time_out = now + Self::morse_timeout(&self.keymap.borrow().behavior, key_action);
+            let time_out = event.time + Self::morse_timeout(&self.keymap.borrow().behavior, key_action, event.pos);
             self.held_buffer.push(HeldKey::new(
                 event,
                 *key_action,
                 KeyState::ProcessedButReleaseNotReportedYet(action),
-                now,
+                event.time,
                 time_out,
             ));
             return LoopState::OK;
@@ -409,9 +615,9 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
             }
             KeyBehaviorDecision::Buffer => {
                 debug!("Current key is buffered, return LoopState::Queue");
-                let press_time = Instant::now();
+                let press_time = event.time;
                 let timeout_time = if key_action.is_morse() {
-                    press_time + Self::morse_timeout(&self.keymap.borrow().behavior, key_action)
+                    press_time + Self::morse_timeout(&self.keymap.borrow().behavior, key_action, event.pos)
                 } else {
                     press_time
                 };
@@ -476,6 +682,13 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                                         &held_key.action,
                                         pattern,
                                     );
+                                    #[cfg(feature = "keylog")]
+                                    log_tap_hold_decision(
+                                        held_key.event,
+                                        pattern,
+                                        action,
+                                        TapHoldDecision::UnilateralTap,
+                                    );
                                     self.process_key_action_normal(action, held_key.event).await;
                                     held_key.state = KeyState::ProcessedButReleaseNotReportedYet(action);
                                     // Push back after triggered tap
@@ -490,6 +703,13 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                                         &held_key.action,
                                         pattern,
                                     );
+                                    #[cfg(feature = "keylog")]
+                                    log_tap_hold_decision(
+                                        held_key.event,
+                                        pattern,
+                                        action,
+                                        TapHoldDecision::UnilateralTap,
+                                    );
                                     held_key.event.pressed = true;
                                     self.process_key_action_tap(action, held_key.event).await;
                                     // The tap is fully fired, don't push it back to buffer again
@@ -521,6 +741,13 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                                     debug!("pattern after permissive hold: {:?}", pattern);
                                     let action =
                                         Self::action_from_pattern(&self.keymap.borrow().behavior, &action, pattern);
+                                    #[cfg(feature = "keylog")]
+                                    log_tap_hold_decision(
+                                        held_key.event,
+                                        pattern,
+                                        action,
+                                        TapHoldDecision::PermissiveHold,
+                                    );
                                     self.process_key_action_normal(action, held_key.event).await;
                                     held_key.state = KeyState::ProcessedButReleaseNotReportedYet(action);
                                     // Push back after triggered hold
@@ -530,6 +757,13 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                                     debug!("pattern after released, permissive hold: {:?}", pattern);
                                     let action =
                                         Self::action_from_pattern(&self.keymap.borrow().behavior, &action, pattern);
+                                    #[cfg(feature = "keylog")]
+                                    log_tap_hold_decision(
+                                        held_key.event,
+                                        pattern,
+                                        action,
+                                        TapHoldDecision::PermissiveHold,
+                                    );
                                     held_key.event.pressed = true;
                                     self.process_key_action_tap(action, held_key.event).await;
                                     // The tap is fully fired, don't push it back to buffer again
@@ -557,6 +791,9 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                                 KeyAction::Tap(action) => {
                                     self.process_key_action_tap(action, held_key.event).await;
                                 }
+                                KeyAction::LayerTapToggle(layer) => {
+                                    self.process_layer_tap_toggle(layer, held_key.event);
+                                }
                                 _ => unreachable!(),
                             }
                         } else {
@@ -579,6 +816,13 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                                     );
                                     if let Some(action) = final_action {
                                         debug!("tap prediction {:?} -> {:?}", pattern, action);
+                                        #[cfg(feature = "keylog")]
+                                        log_tap_hold_decision(
+                                            held_key.event,
+                                            pattern,
+                                            action,
+                                            TapHoldDecision::Release,
+                                        );
                                         self.process_key_action_normal(action, held_key.event).await;
                                         held_key.state = KeyState::ProcessedButReleaseNotReportedYet(action);
                                     }
@@ -678,6 +922,22 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                     if event.pressed {
                         // The current key is being pressed
 
+                        // Check chordal hold first, a normal key on the same hand resolves the held key as tapping
+                        if !key_action.is_morse()
+                            && matches!(held_key.state, KeyState::Pressed(_))
+                            && self
+                                .keymap
+                                .borrow()
+                                .behavior
+                                .tap_hold
+                                .is_chordal_hold_tap(held_key.event.pos, event.pos)
+                        {
+                            debug!("Chordal hold: same hand key is pressed, resolve morse key as tapping");
+                            let _ = decisions.push((held_key.event.pos, HeldKeyDecision::UnilateralTap));
+                            decision_for_current_key = KeyBehaviorDecision::CleanBuffer;
+                            continue;
+                        }
+
                         // Check morse key mode
                         match tap_hold_mode {
                             MorseMode::PermissiveHold => {
@@ -738,15 +998,26 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
 
         #[cfg(feature = "controller")]
         send_controller_event(&mut self.controller_pub, ControllerEvent::Key(event, key_action));
+        #[cfg(feature = "keylog")]
+        log_key_action(event, key_action);
 
         if !key_action.is_morse() {
             match key_action {
                 KeyAction::No | KeyAction::Transparent => (),
                 KeyAction::Single(action) => {
                     debug!("Process Single key action: {:?}, {:?}", action, event);
-                    self.process_key_action_normal(action, event).await;
+                    match action {
+                        Action::Key(key) if event.pressed && self.is_auto_shift_key(key) => {
+                            self.process_auto_shift_press(key, event)
+                        }
+                        Action::Key(key) if !event.pressed && self.auto_shifted == Some(event.pos) => {
+                            self.process_auto_shift_release(key, event).await
+                        }
+                        _ => self.process_key_action_normal(action, event).await,
+                    }
                 }
                 KeyAction::Tap(action) => self.process_key_action_tap(action, event).await,
+                KeyAction::LayerTapToggle(layer) => self.process_layer_tap_toggle(layer, event),
                 _ => unreachable!(),
             }
         } else {
@@ -876,6 +1147,20 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
     ///
     /// This function returns key action after processing combo, and a boolean indicates that if current returned key action is a combo output
     async fn process_combo(&mut self, key_action: &KeyAction, event: KeyboardEvent) -> (Option<KeyAction>, bool) {
+        // The combo window is judged by the time the keys are pressed rather than when the events arrive, so a delayed
+        // event from the split peripheral still joins the combo. A key pressed after the window ends the waiting keys
+        let timeout = self.keymap.borrow().behavior.combo.timeout;
+        if event.pressed
+            && self
+                .held_buffer
+                .keys
+                .iter()
+                .any(|k| k.state == KeyState::WaitingCombo && event.time > k.press_time + timeout)
+        {
+            debug!("[Combo] {:?} is pressed after the combo timeout, dispatch combo", event);
+            self.dispatch_combos().await;
+        }
+
         let mut is_combo_action = false;
         let current_layer = self.keymap.borrow().get_activated_layer();
         for combo in self.keymap.borrow_mut().behavior.combo.combos.iter_mut() {
@@ -884,12 +1169,14 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
 
         if event.pressed && is_combo_action {
             let pressed_time = self.get_timer_value(event).unwrap_or(Instant::now());
+            // Wait a bit longer than the timeout for the delayed events
+            let latency_allowance = self.keymap.borrow().behavior.combo.latency_allowance;
             self.held_buffer.push(HeldKey::new(
                 event,
                 *key_action,
                 KeyState::WaitingCombo,
                 pressed_time,
-                pressed_time + self.keymap.borrow().behavior.combo.timeout,
+                pressed_time + timeout + latency_allowance,
             ));
 
             // FIXME: last combo is not checked
@@ -971,6 +1258,8 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
     }
 
     async fn process_key_action_normal(&mut self, action: Action, event: KeyboardEvent) {
+        self.record_dynamic_macro(action, event);
+
         match action {
             Action::No | Action::Transparent => {}
             Action::Key(key) => self.process_action_key(key, event).await,
@@ -986,25 +1275,23 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                 // Toggle a layer when the key is release
                 if !event.pressed {
                     self.keymap.borrow_mut().toggle_layer(layer_num);
+                    // Toggling a locked layer unlocks it
+                    self.unlock_layer(layer_num);
                 }
             }
             Action::LayerToggleOnly(layer_num) => {
-                // Activate a layer and deactivate all other layers(except default layer)
                 if event.pressed {
-                    // Disable all layers except the default layer
-                    let default_layer = self.keymap.borrow().get_default_layer();
-                    for i in 0..NUM_LAYER as u8 {
-                        if i != default_layer {
-                            self.keymap.borrow_mut().deactivate_layer(i);
-                        }
-                    }
-                    // Activate the target layer
-                    self.keymap.borrow_mut().activate_layer(layer_num);
+                    self.process_action_to_layer(layer_num);
                 }
             }
             Action::DefaultLayer(layer_num) => {
-                // Set the default layer
-                self.keymap.borrow_mut().set_default_layer(layer_num);
+                // Set the default layer when the key is pressed, activated layers are kept except the layer locked by `TO`
+                if event.pressed {
+                    if let Some(l) = self.to_layer.take() {
+                        self.keymap.borrow_mut().deactivate_layer(l);
+                    }
+                    self.set_default_layer(layer_num).await;
+                }
             }
             Action::Modifier(modifiers) => {
                 if event.pressed {
@@ -1052,9 +1339,42 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                 self.update_osl(event);
             }
             Action::OneShotKey(_k) => warn!("One-shot key is not supported: {:?}", action),
+            Action::Unicode(code_point) => {
+                if event.pressed {
+                    self.send_unicode(code_point).await;
+                }
+            }
+            Action::ProfileSelect(_) | Action::ProfileNext | Action::ProfilePrevious | Action::PairingMode => {
+                self.process_action_profile(action, event).await;
+            }
         }
     }
 
+    /// Switch the BLE profile or enter the pairing mode when the key is released.
+    ///
+    /// The current connection is disconnected by the profile manager, then the keyboard advertises for the selected
+    /// profile: directed to the bonded host if there's one, otherwise as pairable.
+    async fn process_action_profile(&mut self, action: Action, event: KeyboardEvent) {
+        if event.pressed {
+            return;
+        }
+        #[cfg(feature = "_ble")]
+        {
+            use crate::ble::profile::BleProfileAction;
+            use crate::channel::BLE_PROFILE_CHANNEL;
+            let profile_action = match action {
+                Action::ProfileSelect(profile) => BleProfileAction::SwitchProfile(profile),
+                Action::ProfileNext => BleProfileAction::NextProfile,
+                Action::ProfilePrevious => BleProfileAction::PreviousProfile,
+                Action::PairingMode => BleProfileAction::EnterPairingMode,
+                _ => return,
+            };
+            BLE_PROFILE_CHANNEL.send(profile_action).await;
+        }
+        #[cfg(not(feature = "_ble"))]
+        warn!("BLE profile action is ignored without BLE: {:?}", action);
+    }
+
     /// Tap action, send a key when the key is pressed, then release the key.
     async fn process_key_action_tap(&mut self, action: Action, mut event: KeyboardEvent) {
         debug!("TAP action: {:?}, {:?}", action, event);
@@ -1085,11 +1405,21 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
             self.osm_state = match self.osm_state {
                 OneShotState::None => OneShotState::Initial(modifiers),
                 OneShotState::Initial(m) => OneShotState::Initial(m | modifiers),
+                // Tapping an armed one shot modifier again locks it
+                OneShotState::Single(m) if m & modifiers == modifiers => OneShotState::Locked(m),
                 OneShotState::Single(m) => OneShotState::Single(m | modifiers),
                 OneShotState::Held(m) => OneShotState::Held(m | modifiers),
+                // Tapping a locked one shot modifier again unlocks it
+                OneShotState::Locked(m) if m & modifiers == modifiers => OneShotState::None,
+                OneShotState::Locked(m) => OneShotState::Locked(m | modifiers),
             };
 
             self.update_osl(event);
+
+            // Locking and unlocking changes the reported modifiers immediately
+            if matches!(self.osm_state, OneShotState::Locked(_) | OneShotState::None) {
+                self.send_keyboard_report_with_resolved_modifiers(event.pressed).await;
+            }
         } else {
             match self.osm_state {
                 OneShotState::Initial(m) | OneShotState::Single(m) => {
@@ -1139,6 +1469,7 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                 OneShotState::Initial(_) => OneShotState::Initial(layer_num),
                 OneShotState::Single(_) => OneShotState::Single(layer_num),
                 OneShotState::Held(_) => OneShotState::Held(layer_num),
+                OneShotState::Locked(_) => OneShotState::Locked(layer_num),
             };
 
             // Activate new layer
@@ -1184,11 +1515,12 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
             if let Some(osm) = self.osm_state.value() {
                 result |= *osm;
             }
-        } else if let OneShotState::Held(osm) = self.osm_state {
+        } else if let OneShotState::Held(osm) | OneShotState::Locked(osm) = self.osm_state {
             // One shot modifiers usually "released" together with the key release,
             // except when one-shoot is in "held mode" (to allow Alt+Tab like use cases)
             // In this later case Held -> None state change will report
-            // the "modifier released" change in a separate hid report
+            // the "modifier released" change in a separate hid report.
+            // Locked one shot modifiers are kept until they're unlocked.
             result |= osm;
         };
 
@@ -1229,16 +1561,20 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         // Execute the remaining suppressions
         result &= !fork_suppress;
 
+        // The triggered key overrides release their trigger modifiers
+        result &= !self.key_override_suppress();
+
         // Apply the modifiers from Action::KeyWithModifiers
         // the suppression effect of forks should not apply on these
         result |= self.with_modifiers;
 
-        // Apply caps word shift if active and appropriate
+        // Apply caps word shift if active and appropriate.
+        // Only letters and minus are shifted, digits and backspace keep their original meaning
         if self.caps_word_active
             && let Some(timer) = self.caps_word_timer
-            && timer.elapsed() < Duration::from_secs(5)
+            && timer.elapsed() < self.keymap.borrow().behavior.caps_word.timeout
         {
-            if pressed {
+            if pressed && self.last_key_code.is_caps_word_shifted_key() {
                 result |= ModifierCombination::new().with_left_shift(true);
             }
         } else {
@@ -1262,13 +1598,21 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
 
     // Process action key
     async fn process_action_key(&mut self, key: KeyCode, event: KeyboardEvent) {
+        let is_repeat = matches!(key, KeyCode::Again | KeyCode::RepeatKey | KeyCode::AltRepeatKey);
         let key = match key {
             KeyCode::GraveEscape => {
-                if self.held_modifiers.into_bits() == 0 {
-                    KeyCode::Escape
-                } else {
-                    KeyCode::Grave
+                // Decide the key when it's pressed, the release should unregister the same key
+                // even if the modifiers are changed
+                if event.pressed {
+                    let grave_modifiers = self.keymap.borrow().behavior.grave_escape.grave_modifiers;
+                    self.grave_escape_key =
+                        if (self.resolve_explicit_modifiers(true) & grave_modifiers).into_bits() == 0 {
+                            KeyCode::Escape
+                        } else {
+                            KeyCode::Grave
+                        };
                 }
+                self.grave_escape_key
             }
             KeyCode::CapsWordToggle => {
                 // Handle caps word keycode by triggering the action
@@ -1284,32 +1628,103 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                 };
                 return;
             }
-            KeyCode::Again => {
-                debug!("Repeat last key code: {:?} , {:?}", self.last_key_code, event);
-                self.last_key_code
+            KeyCode::AutoShiftOn | KeyCode::AutoShiftOff | KeyCode::AutoShiftToggle => {
+                if event.pressed {
+                    self.auto_shift_on = match key {
+                        KeyCode::AutoShiftOn => true,
+                        KeyCode::AutoShiftOff => false,
+                        _ => !self.auto_shift_on,
+                    };
+                }
+                return;
+            }
+            KeyCode::MagicNkroOn | KeyCode::MagicNkroOff | KeyCode::MagicToggleNkro => {
+                if event.pressed {
+                    let nkro_on = match key {
+                        KeyCode::MagicNkroOn => true,
+                        KeyCode::MagicNkroOff => false,
+                        _ => !self.nkro_on,
+                    };
+                    self.set_nkro(nkro_on).await;
+                }
+                return;
             }
+            KeyCode::Leader => {
+                if event.pressed {
+                    self.start_leader(event);
+                }
+                return;
+            }
+            KeyCode::LayerLock => {
+                if event.pressed {
+                    self.process_layer_lock();
+                }
+                return;
+            }
+            KeyCode::PointingCpiCycle => {
+                if event.pressed {
+                    crate::channel::POINTING_CPI_CYCLE_SIGNAL.signal(());
+                }
+                return;
+            }
+            KeyCode::PointingScrollMode => {
+                POINTING_SCROLL_KEY_HELD.store(event.pressed, core::sync::atomic::Ordering::Relaxed);
+                return;
+            }
+            KeyCode::BypassToggle => {
+                if event.pressed {
+                    self.toggle_bypass().await;
+                }
+                return;
+            }
+            KeyCode::DynamicMacroRecordStart1
+            | KeyCode::DynamicMacroRecordStart2
+            | KeyCode::DynamicMacroRecordStop
+            | KeyCode::DynamicMacroPlay1
+            | KeyCode::DynamicMacroPlay2 => {
+                self.process_dynamic_macro_key(key, event);
+                return;
+            }
+            KeyCode::SpaceCadetLCtrlParenthesisOpen
+            | KeyCode::SpaceCadetRCtrlParenthesisClose
+            | KeyCode::SpaceCadetLShiftParenthesisOpen
+            | KeyCode::SpaceCadetRShiftParenthesisClose
+            | KeyCode::SpaceCadetLAltParenthesisOpen
+            | KeyCode::SpaceCadetRAltParenthesisClose
+            | KeyCode::SpaceCadetRShiftEnter => {
+                self.process_space_cadet(key, event).await;
+                return;
+            }
+            KeyCode::Again | KeyCode::RepeatKey | KeyCode::AltRepeatKey => match self.resolve_repeat_key(key, event) {
+                Some(repeat_key) => repeat_key,
+                None => return,
+            },
             _ => key,
         };
 
+        // Keys typed after the leader key are captured as the leader key sequence
+        if self.process_leader_key(key, event).await {
+            return;
+        }
+
+        let key = self.resolve_key_override(key, event);
+
         if event.pressed {
             // Record last press time
             if key.is_simple_key() {
                 // Records only the simple key
                 self.last_press_time = Instant::now();
             }
-            // Check repeat key
-            if key != KeyCode::Again {
-                debug!(
-                    "Last key code changed from {:?} to {:?}(pressed: {:?})",
-                    self.last_key_code, key, event.pressed
-                );
-                self.last_key_code = key;
+            self.last_key_code = key;
+            // The repeated key doesn't change the key to repeat
+            if !is_repeat {
+                self.record_repeat_key(key);
             }
             // Check caps word
             if self.caps_word_active {
                 if key.is_caps_word_continue_key()
                     && let Some(timer) = self.caps_word_timer
-                    && timer.elapsed() < Duration::from_secs(5)
+                    && timer.elapsed() < self.keymap.borrow().behavior.caps_word.timeout
                 {
                     self.caps_word_timer = Some(Instant::now());
                 } else {
@@ -1336,10 +1751,13 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         } else if key.is_combo() {
             self.process_action_combo(key, event).await;
         } else if key.is_boot() {
-            self.process_boot(key, event);
+            self.process_boot(key, event).await;
+        } else if key.is_backlight() {
+            self.process_backlight(key, event).await;
         } else {
             warn!("Unsupported key: {:?}", key);
         }
+        self.finish_key_override(event).await;
         self.update_osm(event);
         self.update_osl(event);
     }
@@ -1350,10 +1768,71 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         if event.pressed {
             self.keymap.borrow_mut().activate_layer(layer_num);
         } else {
+            self.deactivate_momentary_layer(layer_num);
+        }
+    }
+
+    /// Deactivate the layer of a released momentary layer key, the layer locked by `TO` or `LayerLock` is kept
+    fn deactivate_momentary_layer(&mut self, layer_num: u8) {
+        let locked = self.locked_layers.get(layer_num as usize).copied().unwrap_or(false);
+        if self.to_layer != Some(layer_num) && !locked {
             self.keymap.borrow_mut().deactivate_layer(layer_num);
         }
     }
 
+    /// Unlock the layer locked by `TO` or `LayerLock`, the layer state isn't changed
+    fn unlock_layer(&mut self, layer_num: u8) {
+        self.to_layer.take_if(|l| *l == layer_num);
+        if let Some(locked) = self.locked_layers.get_mut(layer_num as usize) {
+            *locked = false;
+        }
+    }
+
+    /// Lock the top activated layer, or unlock and deactivate it if it's already locked(`LayerLock`).
+    ///
+    /// A locked layer stays active after the momentary layer key which activates it is released.
+    fn process_layer_lock(&mut self) {
+        let layer = self.keymap.borrow().get_activated_layer();
+        if layer == self.keymap.borrow().get_default_layer() {
+            debug!("Layer lock: no activated layer above the default layer");
+            return;
+        }
+        if self.locked_layers[layer as usize] {
+            debug!("Layer lock: unlock layer {}", layer);
+            self.locked_layers[layer as usize] = false;
+            self.keymap.borrow_mut().deactivate_layer(layer);
+        } else {
+            debug!("Layer lock: lock layer {}", layer);
+            self.locked_layers[layer as usize] = true;
+        }
+    }
+
+    /// Activate a layer and deactivate all other layers except the default layer(`TO`).
+    ///
+    /// The layer stays active until another `TO` or `DF` is pressed.
+    /// Layers of held momentary layer keys are deactivated immediately, releasing those keys later doesn't change the layer state.
+    fn process_action_to_layer(&mut self, layer_num: u8) {
+        if layer_num as usize >= NUM_LAYER {
+            warn!(
+                "Not a valid layer {}, keyboard supports only {} layers",
+                layer_num, NUM_LAYER
+            );
+            return;
+        }
+        let default_layer = self.keymap.borrow().get_default_layer();
+        for i in 0..NUM_LAYER as u8 {
+            if i != default_layer && i != layer_num {
+                self.keymap.borrow_mut().deactivate_layer(i);
+            }
+        }
+        self.keymap.borrow_mut().activate_layer(layer_num);
+        self.to_layer = Some(layer_num);
+        self.locked_layers = [false; NUM_LAYER];
+        // Pending tap toggles and one-shot layers are cancelled
+        self.layer_tap_toggle = None;
+        self.osl_state = OneShotState::None;
+    }
+
     /// Process combo action.
     async fn process_action_combo(&mut self, key: KeyCode, event: KeyboardEvent) {
         if event.pressed {
@@ -1376,6 +1855,7 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
             };
 
             self.send_media_report().await;
+            self.update_media_key_repeat(key, event);
         }
     }
 
@@ -1391,6 +1871,7 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                 self.system_control_report.usage_id = 0;
                 self.send_system_control_report().await;
             }
+            self.update_media_key_repeat(key, event);
         }
     }
 
@@ -1546,16 +2027,28 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                 }
             }
 
-            // Apply diagonal compensation for movement
+            MOUSE_BUTTON_STATES.store(self.mouse_report.buttons, core::sync::atomic::Ordering::Relaxed);
+
+            // Apply diagonal compensation for movement.
+            // Both axes are reset to the current unit first, otherwise the already compensated axis
+            // would be compensated again every time the other direction key repeats.
             if self.mouse_report.x != 0 && self.mouse_report.y != 0 {
-                let (x, y) = self.apply_diagonal_compensation(self.mouse_report.x, self.mouse_report.y);
+                let unit = self.calculate_mouse_move_unit();
+                let (x, y) = self.apply_diagonal_compensation(
+                    unit * self.mouse_report.x.signum(),
+                    unit * self.mouse_report.y.signum(),
+                );
                 self.mouse_report.x = x;
                 self.mouse_report.y = y;
             }
 
             // Apply diagonal compensation for wheel
             if self.mouse_report.wheel != 0 && self.mouse_report.pan != 0 {
-                let (wheel, pan) = self.apply_diagonal_compensation(self.mouse_report.wheel, self.mouse_report.pan);
+                let unit = self.calculate_mouse_wheel_unit();
+                let (wheel, pan) = self.apply_diagonal_compensation(
+                    unit * self.mouse_report.wheel.signum(),
+                    unit * self.mouse_report.pan.signum(),
+                );
                 self.mouse_report.wheel = wheel;
                 self.mouse_report.pan = pan;
             }
@@ -1673,14 +2166,23 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                 } else if id == NUM_BLE_PROFILE as u8 + 3 {
                     // User11:
                     BLE_PROFILE_CHANNEL.send(BleProfileAction::ToggleConnection).await;
+                } else if id == NUM_BLE_PROFILE as u8 + 5 {
+                    // User13: Clear bonding information of all profiles
+                    BLE_PROFILE_CHANNEL.send(BleProfileAction::ClearAllBonds).await;
                 }
             }
         }
     }
 
-    fn process_boot(&mut self, key: KeyCode, event: KeyboardEvent) {
+    async fn process_boot(&mut self, key: KeyCode, event: KeyboardEvent) {
         // When releasing the key, process the boot action
         if !event.pressed {
+            // Make sure that pending keymap/config changes are saved before the reset
+            #[cfg(feature = "storage")]
+            {
+                self.save_backlight().await;
+                crate::storage::flush_flash_operations().await;
+            }
             match key {
                 KeyCode::Bootloader => {
                     boot::jump_to_bootloader();
@@ -1693,6 +2195,45 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         }
     }
 
+    async fn process_backlight(&mut self, key: KeyCode, event: KeyboardEvent) {
+        if !event.pressed {
+            return;
+        }
+        let mut backlight = self.keymap.borrow().get_backlight();
+        match key {
+            KeyCode::BacklightOn => backlight.enabled = true,
+            KeyCode::BacklightOff => backlight.enabled = false,
+            KeyCode::BacklightToggle => backlight.enabled = !backlight.enabled,
+            KeyCode::BacklightUp => backlight.brightness = backlight.brightness.saturating_add(BACKLIGHT_STEP),
+            KeyCode::BacklightDown => backlight.brightness = backlight.brightness.saturating_sub(BACKLIGHT_STEP),
+            // Cycle through the brightness steps, wrap to 0 after the max brightness
+            KeyCode::BacklightStep => {
+                backlight.brightness = backlight.brightness.checked_add(BACKLIGHT_STEP).unwrap_or(0)
+            }
+            _ => {
+                warn!("Unsupported backlight key: {:?}", key);
+                return;
+            }
+        }
+        self.keymap.borrow_mut().set_backlight(backlight);
+        // Save it later, the timer restarts if it's changed again before saving
+        #[cfg(feature = "storage")]
+        {
+            self.backlight_save_time = Some(Instant::now() + BACKLIGHT_SAVE_DELAY);
+        }
+    }
+
+    /// Save the changed backlight state to the storage, if there is one
+    #[cfg(feature = "storage")]
+    async fn save_backlight(&mut self) {
+        if self.backlight_save_time.take().is_some() {
+            let backlight = self.keymap.borrow().get_backlight();
+            crate::channel::FLASH_CHANNEL
+                .send(crate::storage::FlashOperationMessage::BacklightConfig(backlight))
+                .await;
+        }
+    }
+
     async fn process_action_macro(&mut self, key: KeyCode, event: KeyboardEvent) {
         // Get macro index
         if let Some(macro_idx) = key.as_macro_index() {
@@ -1779,18 +2320,62 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         // all modifier related effects are combined here to be sent with the hid report:
         let modifiers = self.resolve_modifiers(pressed);
         info!("Sending keyboard report, pressed: {}", pressed);
-        self.send_report(Report::KeyboardReport(KeyboardReport {
-            modifier: modifiers.into_bits(),
-            reserved: 0,
-            leds: LOCK_LED_STATES.load(core::sync::atomic::Ordering::Relaxed),
-            keycodes: self.held_keycodes.map(|k| k as u8),
-        }))
-        .await;
+        let report = if self.nkro_on {
+            Report::NkroKeyboardReport(NkroKeyboardReport {
+                modifier: modifiers.into_bits(),
+                ..self.nkro_report
+            })
+        } else {
+            Report::KeyboardReport(KeyboardReport {
+                modifier: modifiers.into_bits(),
+                reserved: 0,
+                leds: LOCK_LED_STATES.load(core::sync::atomic::Ordering::Relaxed),
+                keycodes: self.held_keycodes.map(|k| k as u8),
+            })
+        };
+        self.send_report(report).await;
 
         // Yield once after sending the report to channel
         yield_now().await;
     }
 
+    /// Switch between the NKRO report and the 6KRO report.
+    ///
+    /// All keys are released by the report of the current mode first, then the held keys are sent by the report of the new mode,
+    /// so that no key gets stuck on the host.
+    async fn set_nkro(&mut self, nkro_on: bool) {
+        if nkro_on == self.nkro_on {
+            return;
+        }
+        info!("Switch NKRO: {}", nkro_on);
+        let release_all = if self.nkro_on {
+            Report::NkroKeyboardReport(NkroKeyboardReport::default())
+        } else {
+            Report::KeyboardReport(KeyboardReport::default())
+        };
+        self.send_report(release_all).await;
+        self.nkro_on = nkro_on;
+        // Not a new key press, re-send the held keys only
+        self.send_keyboard_report_with_resolved_modifiers(false).await;
+        #[cfg(feature = "storage")]
+        crate::channel::FLASH_CHANNEL
+            .send(crate::storage::FlashOperationMessage::Nkro(nkro_on))
+            .await;
+    }
+
+    /// Switch the default layer and save it to the storage, so it's restored at boot
+    async fn set_default_layer(&mut self, layer_num: u8) {
+        if layer_num as usize >= NUM_LAYER || layer_num == self.keymap.borrow().get_default_layer() {
+            return;
+        }
+        info!("Switch default layer: {}", layer_num);
+        self.keymap.borrow_mut().set_default_layer(layer_num);
+        #[cfg(feature = "storage")]
+        crate::channel::FLASH_CHANNEL
+            .send(crate::storage::FlashOperationMessage::DefaultLayer(layer_num))
+            .await;
+    }
+
     /// Send system control report if needed
     pub(crate) async fn send_system_control_report(&mut self) {
         self.send_report(Report::SystemControlReport(self.system_control_report))
@@ -1891,6 +2476,11 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
 
     /// Register a key to be sent in hid report.
     fn register_keycode(&mut self, key: KeyCode, event: KeyboardEvent) {
+        // The NKRO report isn't limited by the slots
+        if key.is_basic() {
+            self.nkro_report.set(key as u8, true);
+        }
+
         // First, find the key event slot according to the position
         let slot = self.registered_keys.iter().enumerate().find_map(|(i, k)| {
             if let Some(e) = k
@@ -1937,6 +2527,11 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                 self.registered_keys[index] = None;
             }
         }
+
+        // Keep the key in the NKRO report if it's still held by another position
+        if key.is_basic() && !self.held_keycodes.contains(&key) {
+            self.nkro_report.set(key as u8, false);
+        }
     }
 
     /// Register a modifier to be sent in hid report.
@@ -2124,15 +2719,19 @@ mod test {
 
     use embassy_futures::block_on;
     use embassy_time::{Duration, Timer};
-    use rmk_types::action::KeyAction;
+    use rmk_types::action::{EncoderAction, KeyAction};
     use rmk_types::modifier::ModifierCombination;
     use rusty_fork::rusty_fork_test;
 
     use super::*;
-    use crate::config::{BehaviorConfig, CombosConfig, ForksConfig};
+    use crate::config::{
+        BehaviorConfig, CombosConfig, EncoderAccelerationConfig, ForksConfig, GraveEscapeConfig, MorsesConfig,
+    };
     use crate::event::{KeyPos, KeyboardEvent, KeyboardEventPos};
     use crate::fork::Fork;
-    use crate::{a, k, layer, mo, th};
+    use crate::input_device::rotary_encoder::Direction;
+    use crate::morse::Morse;
+    use crate::{a, k, layer, mo, td, th};
 
     // Init logger for tests
     #[ctor::ctor]
@@ -2188,6 +2787,7 @@ mod test {
                 ),
             ]),
             timeout: Duration::from_millis(100),
+            latency_allowance: Duration::from_millis(0),
         }
     }
 
@@ -2206,6 +2806,23 @@ mod test {
         Keyboard::new(keymap_ref)
     }
 
+    fn create_test_keyboard_with_encoder(config: BehaviorConfig) -> Keyboard<'static, 5, 14, 2, 1> {
+        static BEHAVIOR_CONFIG: static_cell::StaticCell<BehaviorConfig> = static_cell::StaticCell::new();
+        let behavior_config = BEHAVIOR_CONFIG.init(config);
+
+        // Box::leak is acceptable in tests
+        let leaked_keymap = Box::leak(Box::new(get_keymap()));
+        let leaked_encoder_map = Box::leak(Box::new([
+            [EncoderAction::new(k!(Up), k!(Down))],
+            [EncoderAction::new(k!(PageUp), k!(PageDown))],
+        ]));
+
+        let keymap = block_on(KeyMap::new(leaked_keymap, Some(leaked_encoder_map), behavior_config));
+        let keymap_ref = Box::leak(Box::new(RefCell::new(keymap)));
+
+        Keyboard::new(keymap_ref)
+    }
+
     fn create_test_keyboard() -> Keyboard<'static, 5, 14, 2> {
         create_test_keyboard_with_config(BehaviorConfig::default())
     }
@@ -2229,6 +2846,16 @@ mod test {
         KeyboardEvent::key(row, col, pressed)
     }
 
+    fn received_keyboard_reports() -> Vec<KeyboardReport> {
+        let mut reports = Vec::<_, 16>::new();
+        while let Ok(report) = KEYBOARD_REPORT_CHANNEL.try_receive() {
+            if let Report::KeyboardReport(report) = report {
+                let _ = reports.push(report);
+            }
+        }
+        reports
+    }
+
     rusty_fork_test! {
         #[test]
         fn test_register_key() {
@@ -2294,6 +2921,92 @@ mod test {
             block_on(main);
         }
 
+        #[test]
+        fn test_nkro_more_than_6_keys() {
+            let main = async {
+                let mut keyboard = create_test_keyboard();
+                let keys = [KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G];
+                for (col, key) in keys.iter().enumerate() {
+                    keyboard.register_key(*key, KeyboardEvent::key(2, col as u8, true));
+                }
+                let pressed = |report: &NkroKeyboardReport, key: KeyCode| {
+                    report.keycodes[key as usize / 8] & (1 << (key as u8 % 8)) != 0
+                };
+
+                // The 7th key only fits in the NKRO report
+                assert!(!keyboard.held_keycodes.contains(&KeyCode::G));
+                assert!(keys.iter().all(|&k| pressed(&keyboard.nkro_report, k)));
+                // The 6KRO fallback keeps the first 6 keys
+                assert_eq!(keyboard.nkro_report.to_6kro().keycodes, [0x04, 0x05, 0x06, 0x07, 0x08, 0x09]);
+
+                keyboard.unregister_key(KeyCode::G, KeyboardEvent::key(2, 6, false));
+                keyboard.unregister_key(KeyCode::A, KeyboardEvent::key(2, 0, false));
+                assert!(!pressed(&keyboard.nkro_report, KeyCode::G));
+                assert!(!pressed(&keyboard.nkro_report, KeyCode::A));
+                assert!(pressed(&keyboard.nkro_report, KeyCode::B));
+            };
+
+            block_on(main);
+        }
+
+        #[test]
+        fn test_grave_escape() {
+            let main = async {
+                let mut keyboard = create_test_keyboard();
+                keyboard.keymap.borrow_mut().set_action_at(
+                    KeyboardEventPos::Key(KeyPos { row: 0, col: 0 }),
+                    0,
+                    KeyAction::Single(Action::Key(KeyCode::GraveEscape)),
+                );
+
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::Escape);
+                keyboard.process_inner(KeyboardEvent::key(0, 0, false)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::No);
+
+                // Shift is held when pressing, the key is released after shift
+                keyboard.register_key(KeyCode::LShift, KeyboardEvent::key(3, 0, true));
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::Grave);
+                keyboard.unregister_key(KeyCode::LShift, KeyboardEvent::key(3, 0, false));
+                keyboard.process_inner(KeyboardEvent::key(0, 0, false)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::No);
+                assert_eq!(keyboard.nkro_report.keycodes, [0; 28]);
+            };
+
+            block_on(main);
+        }
+
+        #[test]
+        fn test_grave_escape_modifiers() {
+            let main = async {
+                let mut keyboard = create_test_keyboard_with_config(BehaviorConfig {
+                    grave_escape: GraveEscapeConfig {
+                        grave_modifiers: ModifierCombination::LSHIFT | ModifierCombination::RSHIFT,
+                    },
+                    ..BehaviorConfig::default()
+                });
+                keyboard.keymap.borrow_mut().set_action_at(
+                    KeyboardEventPos::Key(KeyPos { row: 0, col: 0 }),
+                    0,
+                    KeyAction::Single(Action::Key(KeyCode::GraveEscape)),
+                );
+
+                // Ctrl isn't in the grave modifiers
+                keyboard.register_key(KeyCode::LCtrl, KeyboardEvent::key(4, 0, true));
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::Escape);
+                keyboard.process_inner(KeyboardEvent::key(0, 0, false)).await;
+                keyboard.unregister_key(KeyCode::LCtrl, KeyboardEvent::key(4, 0, false));
+
+                keyboard.register_key(KeyCode::RShift, KeyboardEvent::key(3, 13, true));
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::Grave);
+            };
+
+            block_on(main);
+        }
+
         #[test]
         fn test_repeat_key_single() {
             let main = async {
@@ -2401,6 +3114,179 @@ mod test {
             block_on(main);
         }
 
+        #[test]
+        fn test_repeat_key_modifiers() {
+            let main = async {
+                let mut keyboard = create_test_keyboard();
+                keyboard.keymap.borrow_mut().set_action_at(
+                    KeyboardEventPos::Key(KeyPos { row: 0, col: 0 }),
+                    0,
+                    KeyAction::Single(Action::Key(KeyCode::Again)),
+                );
+
+                // Ctrl + W
+                keyboard.process_inner(KeyboardEvent::key(4, 0, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(1, 2, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(1, 2, false)).await;
+                keyboard.process_inner(KeyboardEvent::key(4, 0, false)).await;
+
+                // Modifier only press is not repeatable
+                keyboard.process_inner(KeyboardEvent::key(3, 0, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(3, 0, false)).await;
+
+                // Ctrl + W is repeated
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::W);
+                assert_eq!(keyboard.resolve_modifiers(true), ModifierCombination::LCTRL);
+
+                keyboard.process_inner(KeyboardEvent::key(0, 0, false)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::No);
+                assert_eq!(keyboard.resolve_modifiers(false), ModifierCombination::new());
+            };
+            block_on(main);
+        }
+
+        #[test]
+        fn test_alt_repeat_key_after_layer_change() {
+            let main = async {
+                let mut keyboard = create_test_keyboard();
+                keyboard.keymap.borrow_mut().set_action_at(
+                    KeyboardEventPos::Key(KeyPos { row: 0, col: 0 }),
+                    0,
+                    KeyAction::Single(Action::Key(KeyCode::Again)),
+                );
+                keyboard.keymap.borrow_mut().set_action_at(
+                    KeyboardEventPos::Key(KeyPos { row: 0, col: 13 }),
+                    0,
+                    KeyAction::Single(Action::Key(KeyCode::AltRepeatKey)),
+                );
+
+                // Nothing to reverse
+                keyboard.process_inner(KeyboardEvent::key(1, 2, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(1, 2, false)).await;
+                keyboard.process_inner(KeyboardEvent::key(0, 13, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::No);
+                keyboard.process_inner(KeyboardEvent::key(0, 13, false)).await;
+
+                // Press Left on layer 1
+                keyboard.process_inner(KeyboardEvent::key(4, 9, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(4, 10, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(4, 10, false)).await;
+                keyboard.process_inner(KeyboardEvent::key(4, 9, false)).await;
+
+                // The repeat keys on layer 0 send the keys of the last pressed key
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::Left);
+                keyboard.process_inner(KeyboardEvent::key(0, 0, false)).await;
+                keyboard.process_inner(KeyboardEvent::key(0, 13, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::Right);
+                keyboard.process_inner(KeyboardEvent::key(0, 13, false)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::No);
+
+                // The reversed key doesn't change the key to repeat
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::Left);
+            };
+            block_on(main);
+        }
+
+        #[test]
+        fn test_default_layer() {
+            let main = async {
+                let mut keyboard = create_test_keyboard();
+                keyboard.keymap.borrow_mut().set_action_at(
+                    KeyboardEventPos::Key(KeyPos { row: 0, col: 13 }),
+                    0,
+                    KeyAction::Single(Action::DefaultLayer(1)),
+                );
+                keyboard.keymap.borrow_mut().set_action_at(
+                    KeyboardEventPos::Key(KeyPos { row: 0, col: 0 }),
+                    1,
+                    KeyAction::Single(Action::DefaultLayer(0)),
+                );
+
+                // Switch the default layer to 1
+                keyboard.process_inner(KeyboardEvent::key(0, 13, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(0, 13, false)).await;
+                assert_eq!(keyboard.keymap.borrow().get_default_layer(), 1);
+                keyboard.process_inner(KeyboardEvent::key(4, 10, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::Left);
+                keyboard.process_inner(KeyboardEvent::key(4, 10, false)).await;
+
+                // Invalid layer is ignored
+                keyboard.process_key_action_normal(Action::DefaultLayer(5), KeyboardEvent::key(0, 1, true)).await;
+                assert_eq!(keyboard.keymap.borrow().get_default_layer(), 1);
+
+                // Switch back to layer 0
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(0, 0, false)).await;
+                assert_eq!(keyboard.keymap.borrow().get_default_layer(), 0);
+            };
+            block_on(main);
+        }
+
+        #[test]
+        fn test_to_layer_with_held_momentary_layer() {
+            let main = async {
+                let mut keyboard = create_test_keyboard();
+                keyboard.keymap.borrow_mut().set_action_at(
+                    KeyboardEventPos::Key(KeyPos { row: 0, col: 13 }),
+                    1,
+                    KeyAction::Single(Action::LayerToggleOnly(1)),
+                );
+                keyboard.keymap.borrow_mut().set_action_at(
+                    KeyboardEventPos::Key(KeyPos { row: 0, col: 0 }),
+                    1,
+                    KeyAction::Single(Action::DefaultLayer(0)),
+                );
+
+                // Press TO(1) while MO(1) is held
+                keyboard.process_inner(KeyboardEvent::key(4, 9, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(0, 13, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(0, 13, false)).await;
+                // Releasing MO(1) doesn't deactivate the layer locked by TO(1)
+                keyboard.process_inner(KeyboardEvent::key(4, 9, false)).await;
+                assert_eq!(keyboard.keymap.borrow().get_activated_layer(), 1);
+                keyboard.process_inner(KeyboardEvent::key(4, 10, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::Left);
+                keyboard.process_inner(KeyboardEvent::key(4, 10, false)).await;
+
+                // DF unlocks the layer
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(0, 0, false)).await;
+                assert_eq!(keyboard.keymap.borrow().get_activated_layer(), 0);
+            };
+            block_on(main);
+        }
+
+        #[test]
+        fn test_layer_lock() {
+            let main = async {
+                let mut keyboard = create_test_keyboard();
+                keyboard.keymap.borrow_mut().set_action_at(
+                    KeyboardEventPos::Key(KeyPos { row: 0, col: 13 }),
+                    1,
+                    KeyAction::Single(Action::Key(KeyCode::LayerLock)),
+                );
+
+                // Lock layer 1 while MO(1) is held
+                keyboard.process_inner(KeyboardEvent::key(4, 9, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(0, 13, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(0, 13, false)).await;
+                keyboard.process_inner(KeyboardEvent::key(4, 9, false)).await;
+                assert_eq!(keyboard.keymap.borrow().get_activated_layer(), 1);
+                keyboard.process_inner(KeyboardEvent::key(4, 10, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::Left);
+                keyboard.process_inner(KeyboardEvent::key(4, 10, false)).await;
+
+                // Pressing the layer lock key again unlocks the layer
+                keyboard.process_inner(KeyboardEvent::key(0, 13, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(0, 13, false)).await;
+                assert_eq!(keyboard.keymap.borrow().get_activated_layer(), 0);
+            };
+            block_on(main);
+        }
+
         #[test]
         fn test_key_action_transparent() {
             let main = async {
@@ -2420,6 +3306,147 @@ mod test {
             block_on(main);
         }
 
+        fn create_transparent_test_keyboard() -> Keyboard<'static, 1, 3, 4> {
+            static BEHAVIOR_CONFIG: static_cell::StaticCell<BehaviorConfig> = static_cell::StaticCell::new();
+            let behavior_config = BEHAVIOR_CONFIG.init(BehaviorConfig::default());
+            let keymap = Box::leak(Box::new([
+                [[k!(A), k!(B), k!(C)]],
+                [[k!(D), a!(Transparent), k!(E)]],
+                [[k!(F), k!(G), a!(Transparent)]],
+                [[a!(Transparent), a!(Transparent), a!(Transparent)]],
+            ]));
+            let keymap = block_on(KeyMap::new(keymap, None, behavior_config));
+            Keyboard::new(Box::leak(Box::new(RefCell::new(keymap))))
+        }
+
+        #[test]
+        fn test_key_action_transparent_multi_level() {
+            let main = async {
+                let mut keyboard = create_transparent_test_keyboard();
+                keyboard.keymap.borrow_mut().activate_layer(1);
+                keyboard.keymap.borrow_mut().activate_layer(2);
+                keyboard.keymap.borrow_mut().activate_layer(3);
+
+                // Transparent on layer 3 hits layer 2, not the default layer
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::F);
+                keyboard.process_inner(KeyboardEvent::key(0, 0, false)).await;
+
+                // Transparent on layer 3 and 2 hits layer 1
+                keyboard.process_inner(KeyboardEvent::key(0, 2, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::E);
+                keyboard.process_inner(KeyboardEvent::key(0, 2, false)).await;
+
+                // Layer 2 is not active, transparent on layer 3 hits layer 1 and then the default layer
+                keyboard.keymap.borrow_mut().deactivate_layer(2);
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::D);
+                keyboard.process_inner(KeyboardEvent::key(0, 0, false)).await;
+                keyboard.process_inner(KeyboardEvent::key(0, 1, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::B);
+                keyboard.process_inner(KeyboardEvent::key(0, 1, false)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::No);
+            };
+            block_on(main);
+        }
+
+        #[test]
+        fn test_effective_action() {
+            let keyboard = create_transparent_test_keyboard();
+            keyboard.keymap.borrow_mut().activate_layer(2);
+            keyboard.keymap.borrow_mut().activate_layer(3);
+            let keymap = keyboard.keymap.borrow();
+            assert_eq!(
+                keymap.get_effective_action(KeyboardEventPos::key_pos(0, 0)),
+                Some((k!(F), 2))
+            );
+            assert_eq!(
+                keymap.get_effective_action(KeyboardEventPos::key_pos(2, 0)),
+                Some((k!(C), 0))
+            );
+        }
+
+        #[test]
+        fn test_key_action_transparent_stops_at_default_layer() {
+            let main = async {
+                let mut keyboard = create_transparent_test_keyboard();
+                keyboard.keymap.borrow_mut().set_default_layer(3);
+                keyboard.keymap.borrow_mut().activate_layer(2);
+
+                // Layers below the default layer are not checked, even if they're active
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::No);
+                keyboard.process_inner(KeyboardEvent::key(0, 0, false)).await;
+            };
+            block_on(main);
+        }
+
+        #[test]
+        fn test_tap_dance_keeps_layer_between_taps() {
+            let main = async {
+                static BEHAVIOR_CONFIG: static_cell::StaticCell<BehaviorConfig> = static_cell::StaticCell::new();
+                let behavior_config = BEHAVIOR_CONFIG.init(BehaviorConfig {
+                    morse: MorsesConfig {
+                        morses: Vec::from_slice(&[Morse::new_from_vial(
+                            Action::Key(KeyCode::A),
+                            Action::Key(KeyCode::B),
+                            Action::Key(KeyCode::C),
+                            Action::Key(KeyCode::D),
+                            250,
+                        )])
+                        .unwrap(),
+                    },
+                    ..BehaviorConfig::default()
+                });
+                let keymap = Box::leak(Box::new([[[td!(0)]], [[k!(X)]]]));
+                let keymap = block_on(KeyMap::new(keymap, None, behavior_config));
+                let mut keyboard: Keyboard<'static, 1, 1, 2> = Keyboard::new(Box::leak(Box::new(RefCell::new(keymap))));
+
+                // Tap the tap dance key once, it waits for a possible second tap
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                keyboard.process_inner(KeyboardEvent::key(0, 0, false)).await;
+                assert!(keyboard.held_buffer.find_pos(KeyboardEventPos::key_pos(0, 0)).is_some());
+
+                // Layer 1 is activated before the second tap
+                keyboard.keymap.borrow_mut().activate_layer(1);
+
+                // The second press still belongs to the tap dance, `X` on layer 1 is not triggered
+                keyboard.process_inner(KeyboardEvent::key(0, 0, true)).await;
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::No);
+                assert!(keyboard.held_buffer.find_pos(KeyboardEventPos::key_pos(0, 0)).is_some());
+
+                // Releasing finishes the double tap
+                keyboard.process_inner(KeyboardEvent::key(0, 0, false)).await;
+                assert!(keyboard.held_buffer.is_empty());
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::No);
+            };
+            block_on(main);
+        }
+
+        #[test]
+        fn test_mouse_diagonal_movement_normalized() {
+            let main = async {
+                let mut keyboard = create_test_keyboard();
+                let up = KeyboardEvent::key(0, 0, true);
+                let right = KeyboardEvent::key(0, 1, true);
+
+                keyboard.process_action_mouse(KeyCode::MouseUp, up).await;
+                assert_eq!(keyboard.mouse_report.x, 0);
+                assert!(keyboard.mouse_report.y < 0);
+
+                // Holding two direction keys moves the cursor diagonally with the same speed on both axes
+                keyboard.process_action_mouse(KeyCode::MouseRight, right).await;
+                assert!(keyboard.mouse_report.x > 0);
+                assert_eq!(keyboard.mouse_report.x, -keyboard.mouse_report.y);
+
+                // Repeating one of the direction keys doesn't shrink the other axis
+                keyboard.process_action_mouse(KeyCode::MouseUp, up).await;
+                assert!(keyboard.mouse_report.x > 0);
+                assert_eq!(keyboard.mouse_report.x, -keyboard.mouse_report.y);
+            };
+            block_on(main);
+        }
+
         #[test]
         fn test_key_action_no() {
             let main = async {
@@ -2665,5 +3692,210 @@ mod test {
 
             block_on(main);
         }
+
+        #[test]
+        fn test_combo_with_delayed_peripheral_event() {
+            let main = async {
+                let mut keyboard = create_test_keyboard_with_config(BehaviorConfig {
+                    combo: CombosConfig {
+                        latency_allowance: Duration::from_millis(50),
+                        ..get_combos_config()
+                    },
+                    ..BehaviorConfig::default()
+                });
+                // Make sure that the simulated press time isn't before the start of the clock
+                Timer::after(Duration::from_millis(200)).await;
+                let now = Instant::now();
+
+                // V is pressed on the central, the keyboard waits for the timeout plus the latency allowance
+                let mut v = event(3, 4, true);
+                v.time = now - Duration::from_millis(130);
+                keyboard.process_inner(v).await;
+                let waiting = keyboard.next_buffered_key().unwrap();
+                assert_eq!(waiting.timeout_time, v.time + Duration::from_millis(150));
+
+                // B is pressed on the peripheral 80ms after V, its event arrives after the 100ms timeout
+                let mut b = event(3, 5, true);
+                b.time = now - Duration::from_millis(50);
+                keyboard.process_inner(b).await;
+                assert_eq!(keyboard.held_modifiers, ModifierCombination::new().with_left_shift(true));
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::No);
+                assert!(keyboard.held_buffer.is_empty());
+            };
+            block_on(main);
+        }
+
+        #[test]
+        fn test_combo_with_delayed_event_out_of_window() {
+            let main = async {
+                let mut keyboard = create_test_keyboard_with_config(BehaviorConfig {
+                    combo: CombosConfig {
+                        latency_allowance: Duration::from_millis(50),
+                        ..get_combos_config()
+                    },
+                    ..BehaviorConfig::default()
+                });
+                Timer::after(Duration::from_millis(200)).await;
+                let now = Instant::now();
+
+                let mut v = event(3, 4, true);
+                v.time = now - Duration::from_millis(130);
+                keyboard.process_inner(v).await;
+
+                // B is pressed 120ms after V, it arrives within the allowance but it's pressed after the timeout
+                let mut b = event(3, 5, true);
+                b.time = now - Duration::from_millis(10);
+                keyboard.process_inner(b).await;
+                assert_eq!(keyboard.held_modifiers, ModifierCombination::new());
+                assert_eq!(keyboard.held_keycodes[0], KeyCode::V);
+                // B might start another combo, so it's waiting
+                assert_eq!(keyboard.next_buffered_key().unwrap().event, b);
+            };
+            block_on(main);
+        }
+
+        #[test]
+        fn test_tap_hold_with_delayed_release_event() {
+            let main = async {
+                let mut keyboard = create_test_keyboard();
+                Timer::after(Duration::from_millis(500)).await;
+                let now = Instant::now();
+
+                let mut press = event(2, 1, true);
+                press.time = now - Duration::from_millis(400);
+                keyboard.process_inner(press).await;
+
+                // th!(A, LShift) is released 100ms after the press, the release arrives after the 250ms timeout
+                let mut release = event(2, 1, false);
+                release.time = now - Duration::from_millis(300);
+                keyboard.process_inner(release).await;
+
+                let reports = received_keyboard_reports();
+                assert!(reports.iter().any(|r| r.keycodes[0] == KeyCode::A as u8));
+                assert!(reports.iter().all(|r| r.modifier == 0));
+                assert!(keyboard.held_buffer.is_empty());
+            };
+            block_on(main);
+        }
+
+        #[test]
+        fn test_space_cadet_with_delayed_release_event() {
+            let main = async {
+                let mut keyboard = create_test_keyboard();
+                keyboard.keymap.borrow_mut().set_action_at(
+                    KeyboardEventPos::key_pos(0, 0),
+                    0,
+                    k!(SpaceCadetLShiftParenthesisOpen),
+                );
+                Timer::after(Duration::from_millis(500)).await;
+                let now = Instant::now();
+
+                let mut press = event(0, 0, true);
+                press.time = now - Duration::from_millis(400);
+                keyboard.process_inner(press).await;
+
+                // Released 100ms after the press, the release arrives after the timeout
+                let mut release = event(0, 0, false);
+                release.time = now - Duration::from_millis(300);
+                keyboard.process_inner(release).await;
+
+                // Tapped, `(` is sent
+                let reports = received_keyboard_reports();
+                assert!(reports.iter().any(|r| r.keycodes[0] == KeyCode::Kc9 as u8));
+            };
+            block_on(main);
+        }
+
+        #[test]
+        fn test_encoder_acceleration_slow_detents() {
+            let main = async {
+                let mut keyboard = create_test_keyboard_with_encoder(BehaviorConfig {
+                    encoder_acceleration: EncoderAccelerationConfig {
+                        interval: Duration::from_millis(50),
+                        multiplier: 3,
+                        accelerated_layer: None,
+                    },
+                    ..BehaviorConfig::default()
+                });
+                Timer::after(Duration::from_millis(500)).await;
+                let now = Instant::now();
+
+                // Two detents 100ms apart, the second one isn't accelerated
+                for ago in [300, 200] {
+                    let mut press = KeyboardEvent::rotary_encoder(0, Direction::Clockwise, true);
+                    press.time = now - Duration::from_millis(ago);
+                    keyboard.process_inner(press).await;
+                    let mut release = KeyboardEvent::rotary_encoder(0, Direction::Clockwise, false);
+                    release.time = press.time;
+                    keyboard.process_inner(release).await;
+                }
+
+                let reports = received_keyboard_reports();
+                assert_eq!(reports.iter().filter(|r| r.keycodes[0] == KeyCode::Up as u8).count(), 2);
+            };
+            block_on(main);
+        }
+
+        #[test]
+        fn test_encoder_acceleration_fast_detents() {
+            let main = async {
+                let mut keyboard = create_test_keyboard_with_encoder(BehaviorConfig {
+                    encoder_acceleration: EncoderAccelerationConfig {
+                        interval: Duration::from_millis(50),
+                        multiplier: 3,
+                        accelerated_layer: None,
+                    },
+                    ..BehaviorConfig::default()
+                });
+                Timer::after(Duration::from_millis(500)).await;
+                let now = Instant::now();
+
+                // Two detents 20ms apart, the second one is sent 3 times
+                for ago in [300, 280] {
+                    let mut press = KeyboardEvent::rotary_encoder(0, Direction::Clockwise, true);
+                    press.time = now - Duration::from_millis(ago);
+                    keyboard.process_inner(press).await;
+                    let mut release = KeyboardEvent::rotary_encoder(0, Direction::Clockwise, false);
+                    release.time = press.time;
+                    keyboard.process_inner(release).await;
+                }
+
+                let reports = received_keyboard_reports();
+                assert_eq!(reports.iter().filter(|r| r.keycodes[0] == KeyCode::Up as u8).count(), 4);
+                assert!(reports.iter().all(|r| r.keycodes[0] != KeyCode::PageUp as u8));
+            };
+            block_on(main);
+        }
+
+        #[test]
+        fn test_encoder_acceleration_on_accelerated_layer() {
+            let main = async {
+                let mut keyboard = create_test_keyboard_with_encoder(BehaviorConfig {
+                    encoder_acceleration: EncoderAccelerationConfig {
+                        interval: Duration::from_millis(50),
+                        multiplier: 2,
+                        accelerated_layer: Some(1),
+                    },
+                    ..BehaviorConfig::default()
+                });
+                Timer::after(Duration::from_millis(500)).await;
+                let now = Instant::now();
+
+                for ago in [300, 280] {
+                    let mut press = KeyboardEvent::rotary_encoder(0, Direction::Clockwise, true);
+                    press.time = now - Duration::from_millis(ago);
+                    keyboard.process_inner(press).await;
+                    let mut release = KeyboardEvent::rotary_encoder(0, Direction::Clockwise, false);
+                    release.time = press.time;
+                    keyboard.process_inner(release).await;
+                }
+
+                // The first detent sends `Up`, the accelerated one sends `PageUp` twice
+                let reports = received_keyboard_reports();
+                assert_eq!(reports.iter().filter(|r| r.keycodes[0] == KeyCode::Up as u8).count(), 1);
+                assert_eq!(reports.iter().filter(|r| r.keycodes[0] == KeyCode::PageUp as u8).count(), 2);
+            };
+            block_on(main);
+        }
     }
 }
@@ -0,0 +1,81 @@
+use embassy_time::Instant;
+use rmk_types::action::Action;
+use rmk_types::keycode::KeyCode;
+use rmk_types::modifier::ModifierCombination;
+
+use crate::event::KeyboardEvent;
+use crate::keyboard::Keyboard;
+
+/// An auto shift key which is pressed, but not decided whether it's shifted yet
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct PendingAutoShift {
+    key: KeyCode,
+    event: KeyboardEvent,
+    /// The key is sent with shift if it's still held at this time
+    timeout_time: Instant,
+}
+
+impl PendingAutoShift {
+    pub(crate) fn timeout_time(&self) -> Instant {
+        self.timeout_time
+    }
+}
+
+impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    Keyboard<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    /// Check whether the pressed key should wait for the auto shift decision
+    pub(crate) fn is_auto_shift_key(&self, key: KeyCode) -> bool {
+        // Keys pressed together with other modifiers are not auto shifted
+        self.auto_shift_on
+            && self.held_modifiers.into_bits() == 0
+            && self.keymap.borrow().behavior.auto_shift.is_auto_shift_key(key)
+    }
+
+    /// Process an auto shift key press.
+    ///
+    /// Nothing is sent until the key is released, another key event arrives or the auto shift timeout is reached,
+    /// so the host never autorepeats the unshifted key while the decision is pending. The timeout is processed in
+    /// the main loop, see `process_timeouts`.
+    pub(crate) fn process_auto_shift_press(&mut self, key: KeyCode, event: KeyboardEvent) {
+        let timeout_time = event.time + self.keymap.borrow().behavior.auto_shift.timeout;
+        self.pending_auto_shift = Some(PendingAutoShift {
+            key,
+            event,
+            timeout_time,
+        });
+    }
+
+    /// Send the pending auto shift key without shift, because it's released or interrupted by another key event
+    /// before the timeout.
+    ///
+    /// It's called before the new key event is processed, so the events are still sent in order.
+    pub(crate) async fn interrupt_auto_shift(&mut self, event: KeyboardEvent) {
+        if let Some(pending) = self.pending_auto_shift.take() {
+            debug!("Auto shift interrupted by {:?}, send {:?}", event, pending.key);
+            self.process_key_action_normal(Action::Key(pending.key), pending.event)
+                .await;
+        }
+    }
+
+    /// Send the pending auto shift key with shift, because it's held longer than the timeout
+    pub(crate) async fn process_auto_shift_timeout(&mut self) {
+        if let Some(pending) = self.pending_auto_shift.take() {
+            debug!("Auto shift timeout, send shifted {:?}", pending.key);
+            self.auto_shifted = Some(pending.event.pos);
+            self.process_key_action_normal(
+                Action::KeyWithModifier(pending.key, ModifierCombination::LSHIFT),
+                pending.event,
+            )
+            .await;
+        }
+    }
+
+    /// Process the release of a key which is sent with auto shift
+    pub(crate) async fn process_auto_shift_release(&mut self, key: KeyCode, event: KeyboardEvent) {
+        self.auto_shifted = None;
+        self.process_key_action_normal(Action::KeyWithModifier(key, ModifierCombination::LSHIFT), event)
+            .await;
+    }
+}
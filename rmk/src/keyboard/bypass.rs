@@ -0,0 +1,111 @@
+use heapless::Vec;
+use rmk_types::action::{Action, KeyAction};
+use rmk_types::keycode::KeyCode;
+use rmk_types::modifier::ModifierCombination;
+
+use crate::event::{KeyboardEvent, KeyboardEventPos};
+use crate::keyboard::Keyboard;
+use crate::morse::TAP;
+#[cfg(feature = "_ble")]
+use {crate::keyboard::LAST_KEY_TIMESTAMP, embassy_time::Instant};
+
+/// Max number of keys which are held in the bypass mode at the same time
+const BYPASS_KEYS_MAX_NUM: usize = 16;
+
+/// State of the bypass mode
+pub(crate) struct BypassState {
+    /// Whether the bypass mode is on
+    on: bool,
+    /// Keys pressed in the bypass mode, their releases are processed in the bypass mode too
+    keys: Vec<KeyboardEventPos, BYPASS_KEYS_MAX_NUM>,
+}
+
+impl BypassState {
+    pub(crate) fn new(on: bool) -> Self {
+        Self { on, keys: Vec::new() }
+    }
+}
+
+impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    Keyboard<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    /// Whether the key event should be processed in the bypass mode.
+    ///
+    /// A key is released in the same mode as it's pressed, so the keys held when the bypass mode is toggled,
+    /// including undecided tap-hold keys, are still resolved by the normal rules.
+    pub(crate) fn is_bypassed(&mut self, event: KeyboardEvent) -> bool {
+        if event.pressed {
+            if !self.bypass.on {
+                return false;
+            }
+            if self.bypass.keys.push(event.pos).is_err() {
+                warn!("Too many bypassed keys, {:?} is processed normally", event.pos);
+                return false;
+            }
+            true
+        } else {
+            match self.bypass.keys.iter().position(|pos| *pos == event.pos) {
+                Some(i) => {
+                    self.bypass.keys.swap_remove(i);
+                    true
+                }
+                None => false,
+            }
+        }
+    }
+
+    /// Process a key in the bypass mode, combos, tap-hold, forks and macros are skipped.
+    ///
+    /// The key on the default layer is sent, tap-hold and morse keys send their tap action.
+    /// `BypassToggle` is still resolved on the active layers, so the bypass mode can be turned off.
+    pub(crate) async fn process_bypass(&mut self, event: KeyboardEvent) {
+        let key_action = self.keymap.borrow_mut().get_action_with_layer_cache(event);
+        let key_action = if key_action == KeyAction::Single(Action::Key(KeyCode::BypassToggle)) {
+            key_action
+        } else {
+            let default_layer = self.keymap.borrow().get_default_layer() as usize;
+            self.keymap.borrow().get_action_at(event.pos, default_layer)
+        };
+        debug!("Bypass key action: {:?}, {:?}", key_action, event);
+
+        // Clear with_modifier if a new key is pressed
+        if self.with_modifiers.into_bits() != 0 && event.pressed {
+            self.with_modifiers = ModifierCombination::new();
+        }
+
+        #[cfg(feature = "_ble")]
+        LAST_KEY_TIMESTAMP.signal(Instant::now().as_secs() as u32);
+
+        let action = match key_action {
+            KeyAction::No | KeyAction::Transparent => return,
+            KeyAction::Single(action) => action,
+            KeyAction::Tap(action) => {
+                if event.pressed {
+                    self.process_key_action_tap(action, event).await;
+                }
+                return;
+            }
+            KeyAction::LayerTapToggle(layer) => Action::LayerOn(layer),
+            KeyAction::TapHold(_, _) | KeyAction::Morse(_) => {
+                Self::action_from_pattern(&self.keymap.borrow().behavior, &key_action, TAP)
+            }
+        };
+        match action {
+            Action::TriggerMacro(_) => (),
+            Action::Key(key) if key.is_macro() => (),
+            _ => self.process_key_action_normal(action, event).await,
+        }
+    }
+
+    /// Switch the bypass mode, it's saved to the storage if `persist` is set
+    pub(crate) async fn toggle_bypass(&mut self) {
+        self.bypass.on = !self.bypass.on;
+        info!("Bypass mode: {}", self.bypass.on);
+        #[cfg(feature = "storage")]
+        if self.keymap.borrow().behavior.bypass.persist {
+            crate::channel::FLASH_CHANNEL
+                .send(crate::storage::FlashOperationMessage::Bypass(self.bypass.on))
+                .await;
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use embassy_time::Timer;
+use heapless::Vec;
+use rmk_types::action::Action;
+use rmk_types::keycode::KeyCode;
+
+use crate::DYNAMIC_MACRO_MAX_LENGTH;
+use crate::event::KeyboardEvent;
+use crate::keyboard::Keyboard;
+
+/// Number of the dynamic macro slots
+const NUM_DYNAMIC_MACRO: usize = 2;
+
+/// State of the dynamic macros, which are recorded in RAM and lost after reboot
+pub(crate) struct DynamicMacroState {
+    /// Recorded actions and the key events which triggered them
+    macros: [Vec<(Action, KeyboardEvent), DYNAMIC_MACRO_MAX_LENGTH>; NUM_DYNAMIC_MACRO],
+    /// The slot which is being recorded
+    recording: Option<usize>,
+    /// The slot which will be played after the current key event is processed
+    pending_play: Option<usize>,
+}
+
+impl DynamicMacroState {
+    pub(crate) fn new() -> Self {
+        Self {
+            macros: [Vec::new(), Vec::new()],
+            recording: None,
+            pending_play: None,
+        }
+    }
+}
+
+impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    Keyboard<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    /// Process the dynamic macro keycodes, they take effect when pressed
+    pub(crate) fn process_dynamic_macro_key(&mut self, key: KeyCode, event: KeyboardEvent) {
+        if !event.pressed {
+            return;
+        }
+        match key {
+            KeyCode::DynamicMacroRecordStart1 | KeyCode::DynamicMacroRecordStart2 => {
+                // Pressing a record key again stops the current recording
+                if self.dynamic_macro.recording.is_some() {
+                    self.stop_dynamic_macro_recording();
+                    return;
+                }
+                let slot = if key == KeyCode::DynamicMacroRecordStart1 { 0 } else { 1 };
+                debug!("Start recording dynamic macro {}", slot);
+                self.dynamic_macro.macros[slot].clear();
+                self.dynamic_macro.recording = Some(slot);
+            }
+            KeyCode::DynamicMacroRecordStop => self.stop_dynamic_macro_recording(),
+            KeyCode::DynamicMacroPlay1 | KeyCode::DynamicMacroPlay2 => {
+                if self.dynamic_macro.recording.is_some() {
+                    warn!("Dynamic macro cannot be played while recording");
+                    return;
+                }
+                let slot = if key == KeyCode::DynamicMacroPlay1 { 0 } else { 1 };
+                self.dynamic_macro.pending_play = Some(slot);
+            }
+            _ => (),
+        }
+    }
+
+    /// Record the action into the dynamic macro which is being recorded.
+    ///
+    /// If the buffer is full, the recording is stopped and the recorded part is kept.
+    pub(crate) fn record_dynamic_macro(&mut self, action: Action, event: KeyboardEvent) {
+        let Some(slot) = self.dynamic_macro.recording else {
+            return;
+        };
+        if let Action::Key(key) = action
+            && key.is_dynamic_macro()
+        {
+            return;
+        }
+        if self.dynamic_macro.macros[slot].push((action, event)).is_err() {
+            warn!("Dynamic macro {} is full, stop recording", slot);
+            self.stop_dynamic_macro_recording();
+        }
+    }
+
+    /// Stop recording, only the complete presses and releases are kept.
+    ///
+    /// The keys which are still held, e.g. the layer key used to access the stop key,
+    /// and the keys pressed before the recording started are dropped,
+    /// so that no key is left pressed after playing the macro.
+    fn stop_dynamic_macro_recording(&mut self) {
+        let Some(slot) = self.dynamic_macro.recording.take() else {
+            return;
+        };
+        let recorded = &mut self.dynamic_macro.macros[slot];
+        let mut i = 0;
+        while i < recorded.len() {
+            let (_, event) = recorded[i];
+            let paired = if event.pressed {
+                recorded[i + 1..].iter().any(|(_, e)| e.pos == event.pos && !e.pressed)
+            } else {
+                recorded[..i].iter().any(|(_, e)| e.pos == event.pos && e.pressed)
+            };
+            if paired {
+                i += 1;
+            } else {
+                recorded.remove(i);
+            }
+        }
+        debug!("Dynamic macro {} recorded, length: {}", slot, recorded.len());
+    }
+
+    /// Play the dynamic macro requested by the processed key event, if any
+    pub(crate) async fn play_pending_dynamic_macro(&mut self) {
+        let Some(slot) = self.dynamic_macro.pending_play.take() else {
+            return;
+        };
+        debug!("Play dynamic macro {}", slot);
+        for i in 0..self.dynamic_macro.macros[slot].len() {
+            let (action, event) = self.dynamic_macro.macros[slot][i];
+            self.process_key_action_normal(action, event).await;
+            Timer::after_millis(2).await;
+        }
+    }
+}
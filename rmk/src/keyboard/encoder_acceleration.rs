@@ -0,0 +1,58 @@
+use embassy_time::Instant;
+use rmk_types::action::KeyAction;
+
+use crate::event::{KeyboardEvent, KeyboardEventPos};
+use crate::keyboard::Keyboard;
+
+impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    Keyboard<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    /// Accelerate fast turns of rotary encoders.
+    ///
+    /// A detent is accelerated if it's within `interval` after the previous detent in the same direction.
+    /// An accelerated detent uses the encoder action on `accelerated_layer` if it's set,
+    /// and the action is triggered `multiplier` times, the extra triggers are sent as taps before the returned action.
+    /// The release of the detent always releases the same action as the press.
+    pub(crate) async fn accelerate_encoder(
+        &mut self,
+        key_action: KeyAction,
+        event: KeyboardEvent,
+        last_detent: Option<Instant>,
+    ) -> KeyAction {
+        let KeyboardEventPos::RotaryEncoder(encoder_pos) = event.pos else {
+            return key_action;
+        };
+        let Some(accelerated) = self.accelerated_encoders.get_mut(encoder_pos.id as usize) else {
+            return key_action;
+        };
+        if !event.pressed {
+            return accelerated.take().unwrap_or(key_action);
+        }
+
+        let config = self.keymap.borrow().behavior.encoder_acceleration;
+        if config.interval.as_ticks() == 0
+            || !last_detent.is_some_and(|t| event.time.saturating_duration_since(t) < config.interval)
+        {
+            return key_action;
+        }
+
+        let action = match config.accelerated_layer {
+            Some(layer) if (layer as usize) < NUM_LAYER => {
+                match self.keymap.borrow().get_action_at(event.pos, layer as usize) {
+                    KeyAction::No | KeyAction::Transparent => key_action,
+                    action => action,
+                }
+            }
+            _ => key_action,
+        };
+        debug!("Accelerated encoder {}: {:?}", encoder_pos.id, action);
+        *accelerated = Some(action);
+
+        if let KeyAction::Single(a) = action {
+            for _ in 1..config.multiplier {
+                self.process_key_action_tap(a, event).await;
+            }
+        }
+        action
+    }
+}
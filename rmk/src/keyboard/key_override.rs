@@ -0,0 +1,93 @@
+use rmk_types::keycode::KeyCode;
+use rmk_types::modifier::ModifierCombination;
+
+use crate::event::{KeyboardEvent, KeyboardEventPos};
+use crate::keyboard::Keyboard;
+
+/// A triggered key override
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ActiveKeyOverride {
+    /// Position of the trigger key
+    pos: KeyboardEventPos,
+    /// Trigger modifiers which are released while the replacement key is held
+    suppress: ModifierCombination,
+}
+
+impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    Keyboard<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    /// Replace the key if a key override is triggered.
+    ///
+    /// The decision is made when the key is pressed, the release of the key releases the same replacement key.
+    /// The replacement key is not checked against the key overrides again, so key overrides never loop.
+    pub(crate) fn resolve_key_override(&mut self, key: KeyCode, event: KeyboardEvent) -> KeyCode {
+        if !event.pressed {
+            for (i, active) in self.active_key_overrides.iter().enumerate() {
+                if let Some(active) = active
+                    && active.pos == event.pos
+                {
+                    let key_override = self.keymap.borrow().behavior.key_override.overrides[i];
+                    self.with_modifiers &= !key_override.replacement_modifiers;
+                    return key_override.replacement;
+                }
+            }
+            return key;
+        }
+
+        let modifiers = self.resolve_explicit_modifiers(true);
+        let triggered = self
+            .keymap
+            .borrow()
+            .behavior
+            .key_override
+            .overrides
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.active_key_overrides[*i].is_none())
+            .find_map(|(i, o)| o.check(key, modifiers).map(|suppress| (i, *o, suppress)));
+
+        match triggered {
+            Some((i, key_override, suppress)) => {
+                debug!("Key override triggered: {:?}", key_override);
+                // The replacement modifiers are sent in the same report with the key, like `Action::KeyWithModifier`
+                self.with_modifiers |= key_override.replacement_modifiers;
+                self.active_key_overrides[i] = Some(ActiveKeyOverride {
+                    pos: event.pos,
+                    suppress,
+                });
+                key_override.replacement
+            }
+            None => key,
+        }
+    }
+
+    /// Deactivate the key override after its release report is sent.
+    ///
+    /// If the suppressed modifiers are still held, they're sent to the host again.
+    pub(crate) async fn finish_key_override(&mut self, event: KeyboardEvent) {
+        if event.pressed {
+            return;
+        }
+        let Some(active) = self
+            .active_key_overrides
+            .iter_mut()
+            .find(|a| a.is_some_and(|a| a.pos == event.pos))
+            .and_then(|a| a.take())
+        else {
+            return;
+        };
+        if (self.resolve_explicit_modifiers(false) & active.suppress).into_bits() != 0 {
+            self.send_keyboard_report_with_resolved_modifiers(false).await;
+        }
+    }
+
+    /// Modifiers which are released by the active key overrides
+    pub(crate) fn key_override_suppress(&self) -> ModifierCombination {
+        self.active_key_overrides
+            .iter()
+            .flatten()
+            .fold(ModifierCombination::new(), |suppress, active| {
+                suppress | active.suppress
+            })
+    }
+}
@@ -0,0 +1,63 @@
+//! Key event log for debugging, enabled by the `keylog` feature.
+//!
+//! Every raw key event, the resolved key action and the decision of tap-hold keys are logged with a timestamp,
+//! through the same logger as other logs(defmt or log). The log is only formatted and queued by the logger,
+//! the key processing and HID reports are not blocked.
+use embassy_time::Instant;
+use rmk_types::action::{Action, KeyAction};
+
+use crate::event::KeyboardEvent;
+use crate::morse::MorsePattern;
+
+/// Why a tap-hold(morse) key is resolved
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum TapHoldDecision {
+    /// The key is held longer than the timeout
+    HoldTimeout,
+    /// The key isn't pressed again within the timeout after release
+    IdleTimeout,
+    /// The key is released and there's no longer pattern
+    Release,
+    /// The key is pressed right after another key
+    FlowTap,
+    /// The key is pressed again right after it's tapped
+    QuickTap,
+    /// A key on the same hand is pressed
+    UnilateralTap,
+    /// Another key is pressed and released, or pressed, while the key is held
+    PermissiveHold,
+}
+
+/// Log the raw key event from the matrix
+pub(crate) fn log_key_event(event: KeyboardEvent) {
+    info!("[keylog] {}us event: {:?}", Instant::now().as_micros(), event);
+}
+
+/// Log the key action resolved from the keymap
+pub(crate) fn log_key_action(event: KeyboardEvent, action: KeyAction) {
+    info!(
+        "[keylog] {}us action: {:?}, pos: {:?}, pressed: {}",
+        Instant::now().as_micros(),
+        action,
+        event.pos,
+        event.pressed
+    );
+}
+
+/// Log the decision of a tap-hold key
+pub(crate) fn log_tap_hold_decision(
+    event: KeyboardEvent,
+    pattern: MorsePattern,
+    action: Action,
+    decision: TapHoldDecision,
+) {
+    info!(
+        "[keylog] {}us decision: {:?}, pos: {:?}, pattern: {:?}, action: {:?}",
+        Instant::now().as_micros(),
+        decision,
+        event.pos,
+        pattern,
+        action
+    );
+}
@@ -0,0 +1,101 @@
+use embassy_time::Instant;
+
+use crate::event::{KeyboardEvent, KeyboardEventPos};
+use crate::keyboard::Keyboard;
+
+/// State of the last pressed `LayerTapToggle` key, used for counting taps
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LayerTapToggleState {
+    pos: KeyboardEventPos,
+    layer: u8,
+    /// Number of taps in the current tap sequence, including the held one
+    taps: u8,
+    pressed_at: Instant,
+    released_at: Option<Instant>,
+    /// Whether the layer was active when the tap sequence started
+    was_active: bool,
+    /// Another key is pressed while the key is held, the key is a hold
+    interrupted: bool,
+}
+
+impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    Keyboard<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    /// Process `LayerTapToggle`, which activates the layer while held,
+    /// and toggles the layer when it's tapped `tap_toggle_count` times within the tapping term.
+    ///
+    /// A layer which is already active when the key is pressed, e.g. it's turned on by `TG` or a previous toggle,
+    /// is kept active after the key is released. Only toggling turns it off.
+    pub(crate) fn process_layer_tap_toggle(&mut self, layer: u8, event: KeyboardEvent) {
+        let tapping_term = self.keymap.borrow().behavior.tap_hold.timeout;
+        if event.pressed {
+            let now = event.time;
+            self.layer_tap_toggle = Some(match self.layer_tap_toggle {
+                // Continue the tap sequence
+                Some(state)
+                    if state.layer == layer
+                        && !state.interrupted
+                        && state
+                            .released_at
+                            .is_some_and(|t| now.saturating_duration_since(t) < tapping_term) =>
+                {
+                    LayerTapToggleState {
+                        pos: event.pos,
+                        taps: state.taps.saturating_add(1),
+                        pressed_at: now,
+                        released_at: None,
+                        ..state
+                    }
+                }
+                _ => LayerTapToggleState {
+                    pos: event.pos,
+                    layer,
+                    taps: 1,
+                    pressed_at: now,
+                    released_at: None,
+                    was_active: self.keymap.borrow().is_layer_active(layer),
+                    interrupted: false,
+                },
+            });
+            self.keymap.borrow_mut().activate_layer(layer);
+            return;
+        }
+
+        let Some(mut state) = self.layer_tap_toggle.filter(|s| s.pos == event.pos && s.layer == layer) else {
+            // The state is taken by another `LayerTapToggle` key or cleared by `TO`, release it like a momentary layer
+            self.deactivate_momentary_layer(layer);
+            return;
+        };
+        let is_tap = !state.interrupted && event.time.saturating_duration_since(state.pressed_at) < tapping_term;
+        if is_tap && state.taps >= self.keymap.borrow().behavior.tap_hold.tap_toggle_count {
+            debug!("Layer tap toggle: toggle layer {}", layer);
+            if state.was_active {
+                self.keymap.borrow_mut().deactivate_layer(layer);
+                self.unlock_layer(layer);
+            }
+            // The layer is kept active after release if it was inactive
+            self.layer_tap_toggle = None;
+            return;
+        }
+
+        if !state.was_active {
+            self.deactivate_momentary_layer(layer);
+        }
+        if is_tap {
+            state.released_at = Some(event.time);
+            self.layer_tap_toggle = Some(state);
+        } else {
+            self.layer_tap_toggle = None;
+        }
+    }
+
+    /// Pressing another key breaks the tap sequence of `LayerTapToggle`
+    pub(crate) fn interrupt_layer_tap_toggle(&mut self, event: KeyboardEvent) {
+        if event.pressed
+            && let Some(state) = self.layer_tap_toggle.as_mut()
+            && state.pos != event.pos
+        {
+            state.interrupted = true;
+        }
+    }
+}
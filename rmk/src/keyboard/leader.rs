@@ -0,0 +1,129 @@
+use embassy_time::Instant;
+use heapless::Vec;
+use rmk_types::action::KeyAction;
+use rmk_types::keycode::KeyCode;
+
+use crate::LEADER_MAX_LENGTH;
+use crate::event::{KeyboardEvent, KeyboardEventPos};
+use crate::keyboard::Keyboard;
+use crate::leader::{LeaderMatch, match_leaders};
+
+/// State of the leader key
+pub(crate) struct LeaderState {
+    /// Keys typed after the leader key, `None` if the leader key is not active
+    sequence: Option<Vec<KeyCode, LEADER_MAX_LENGTH>>,
+    /// The leader key sequence is aborted at this time
+    timeout_time: Instant,
+    /// The last event of the leader key sequence, used for triggering the output after timeout
+    last_event: Option<KeyboardEvent>,
+    /// Positions of the captured key presses, their releases are not sent to the host either
+    captured: Vec<KeyboardEventPos, LEADER_MAX_LENGTH>,
+}
+
+impl LeaderState {
+    pub(crate) fn new() -> Self {
+        Self {
+            sequence: None,
+            timeout_time: Instant::MIN,
+            last_event: None,
+            captured: Vec::new(),
+        }
+    }
+
+    /// The time when the active leader key sequence is aborted, `None` if the leader key is not active
+    pub(crate) fn timeout_time(&self) -> Option<Instant> {
+        self.sequence.as_ref().map(|_| self.timeout_time)
+    }
+}
+
+impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    Keyboard<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    /// Start capturing the leader key sequence
+    pub(crate) fn start_leader(&mut self, event: KeyboardEvent) {
+        debug!("Leader key activated");
+        self.leader.sequence = Some(Vec::new());
+        self.leader.timeout_time = Instant::now() + self.keymap.borrow().behavior.leader.timeout;
+        self.leader.last_event = Some(event);
+    }
+
+    /// Capture the key as a part of the leader key sequence.
+    ///
+    /// Returns true if the key is consumed by the leader key and should not be processed further.
+    pub(crate) async fn process_leader_key(&mut self, key: KeyCode, event: KeyboardEvent) -> bool {
+        if !event.pressed {
+            // The release of a captured key is consumed as well
+            if let Some(i) = self.leader.captured.iter().position(|pos| *pos == event.pos) {
+                self.leader.captured.swap_remove(i);
+                return true;
+            }
+            return false;
+        }
+
+        // Modifiers are not a part of the sequence
+        if self.leader.sequence.is_none() || key.is_modifier() {
+            return false;
+        }
+
+        if Instant::now() >= self.leader.timeout_time {
+            // The sequence has timed out before the current key, process the current key normally
+            self.process_leader_timeout().await;
+            return false;
+        }
+
+        let Some(sequence) = self.leader.sequence.as_mut() else {
+            return false;
+        };
+        let result = if sequence.push(key).is_ok() {
+            match_leaders(&self.keymap.borrow().behavior.leader.leaders, sequence)
+        } else {
+            LeaderMatch::None
+        };
+        let _ = self.leader.captured.push(event.pos);
+        self.leader.last_event = Some(event);
+
+        match result {
+            LeaderMatch::Partial(_) => {
+                // Wait for more keys, the timeout is reset after every key
+                self.leader.timeout_time = Instant::now() + self.keymap.borrow().behavior.leader.timeout;
+            }
+            LeaderMatch::Complete(output) => {
+                debug!("Leader sequence matched, trigger {:?}", output);
+                self.leader.sequence = None;
+                self.trigger_leader_output(output, event).await;
+            }
+            LeaderMatch::None => {
+                debug!("No leader sequence matched, abort");
+                self.leader.sequence = None;
+            }
+        }
+        true
+    }
+
+    /// The leader key times out, trigger the output if the typed keys are a complete sequence
+    pub(crate) async fn process_leader_timeout(&mut self) {
+        let Some(sequence) = self.leader.sequence.take() else {
+            return;
+        };
+        let result = match_leaders(&self.keymap.borrow().behavior.leader.leaders, &sequence);
+        if let LeaderMatch::Partial(Some(output)) = result
+            && let Some(event) = self.leader.last_event
+        {
+            debug!("Leader key timeout, trigger {:?}", output);
+            self.trigger_leader_output(output, event).await;
+        } else {
+            debug!("Leader key timeout, abort");
+        }
+    }
+
+    async fn trigger_leader_output(&mut self, output: KeyAction, mut event: KeyboardEvent) {
+        event.pressed = true;
+        // The output is tapped at the position of the last captured key, don't consume its release
+        let captured = core::mem::take(&mut self.leader.captured);
+        match output {
+            KeyAction::Single(action) | KeyAction::Tap(action) => self.process_key_action_tap(action, event).await,
+            _ => warn!("Unsupported leader output: {:?}", output),
+        }
+        self.leader.captured = captured;
+    }
+}
@@ -0,0 +1,67 @@
+use embassy_time::Instant;
+use rmk_types::keycode::KeyCode;
+
+use crate::event::{KeyboardEvent, KeyboardEventPos};
+use crate::keyboard::Keyboard;
+
+/// A held consumer or system control key which is repeated by the keyboard
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MediaKeyRepeat {
+    pos: KeyboardEventPos,
+    key: KeyCode,
+    /// The time of the next repeat
+    next_repeat: Instant,
+}
+
+impl MediaKeyRepeat {
+    pub(crate) fn next_repeat(&self) -> Instant {
+        self.next_repeat
+    }
+}
+
+impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    Keyboard<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    /// Start repeating a pressed consumer or system control key, or stop repeating it when it's released.
+    ///
+    /// Only the last pressed key is repeated, like the consumer and system control reports which hold only one key.
+    pub(crate) fn update_media_key_repeat(&mut self, key: KeyCode, event: KeyboardEvent) {
+        if event.pressed {
+            let config = self.keymap.borrow().behavior.media_key_repeat;
+            if config.interval.as_ticks() == 0 {
+                return;
+            }
+            self.media_key_repeat = Some(MediaKeyRepeat {
+                pos: event.pos,
+                key,
+                next_repeat: Instant::now() + config.delay,
+            });
+        } else if self.media_key_repeat.is_some_and(|r| r.pos == event.pos) {
+            self.media_key_repeat = None;
+        }
+    }
+
+    /// Repeat the held key, by releasing it and pressing it again.
+    ///
+    /// Hosts ignore a report which is the same as the previous one, so the key is released first.
+    pub(crate) async fn process_media_key_repeat(&mut self) {
+        let interval = self.keymap.borrow().behavior.media_key_repeat.interval;
+        let Some(repeat) = self.media_key_repeat.as_mut() else {
+            return;
+        };
+        repeat.next_repeat = Instant::now() + interval;
+        let key = repeat.key;
+        debug!("Repeat media key: {:?}", key);
+        if key.is_consumer() {
+            self.media_report.usage_id = 0;
+            self.send_media_report().await;
+            self.media_report.usage_id = key.as_consumer_control_usage_id() as u16;
+            self.send_media_report().await;
+        } else if let Some(system_key) = key.as_system_control_usage_id() {
+            self.system_control_report.usage_id = 0;
+            self.send_system_control_report().await;
+            self.system_control_report.usage_id = system_key as u8;
+            self.send_system_control_report().await;
+        }
+    }
+}
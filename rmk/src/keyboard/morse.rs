@@ -2,9 +2,11 @@ use embassy_time::{Duration, Instant};
 use rmk_types::action::{Action, KeyAction};
 
 use crate::config::BehaviorConfig;
-use crate::event::KeyboardEvent;
+use crate::event::{KeyboardEvent, KeyboardEventPos};
 use crate::keyboard::Keyboard;
 use crate::keyboard::held_buffer::{HeldKey, KeyState};
+#[cfg(feature = "keylog")]
+use crate::keyboard::keylog::{TapHoldDecision, log_tap_hold_decision};
 use crate::morse::{HOLD, MorseMode, MorsePattern, TAP};
 
 // 'morse' is an alias for the superset of tap dance and tap hold keys, since their handling have many similarities
@@ -24,6 +26,8 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                 let final_action = Self::try_predict_final_action(&self.keymap.borrow().behavior, &key.action, pattern);
                 if let Some(action) = final_action {
                     debug!("hold prediction {:?} -> {:?}", pattern, action);
+                    #[cfg(feature = "keylog")]
+                    log_tap_hold_decision(key.event, pattern, action, TapHoldDecision::HoldTimeout);
                     self.process_key_action_normal(action, key.event).await;
                     if let Some(k) = self.held_buffer.find_pos_mut(key.event.pos) {
                         k.state = KeyState::ProcessedButReleaseNotReportedYet(action);
@@ -39,6 +43,8 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
             KeyState::Released(pattern) => {
                 // The time since the key release is longer than the timeout, trigger the action
                 let action = Self::action_from_pattern(&self.keymap.borrow().behavior, &key.action, pattern);
+                #[cfg(feature = "keylog")]
+                log_tap_hold_decision(key.event, pattern, action, TapHoldDecision::IdleTimeout);
                 self.process_key_action_tap(action, key.event).await;
                 let _ = self.held_buffer.remove(key.event.pos); // Removing from the held buffer is like setting to an idle state
             }
@@ -67,7 +73,8 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         if event.pressed {
             // Pressed, check the held buffer, update the tap state
             let pressed_time = self.get_timer_value(event).unwrap_or(Instant::now());
-            let timeout_time = pressed_time + Self::morse_timeout(&self.keymap.borrow().behavior, key_action);
+            let timeout_time =
+                pressed_time + Self::morse_timeout(&self.keymap.borrow().behavior, key_action, event.pos);
             match self.held_buffer.find_pos_mut(event.pos) {
                 Some(k) => {
                     // The current key is already in the buffer, update its state
@@ -96,7 +103,7 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                 debug!("Releasing morse key: {:?}", k);
                 match k.state {
                     KeyState::Pressed(pattern) => {
-                        let released_time = Instant::now(); // TODO? It would be better if the event would carry the real timestamp of the release event!
+                        let released_time = event.time;
 
                         let hold = released_time >= k.timeout_time;
 
@@ -112,7 +119,10 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                             Self::try_predict_final_action(&self.keymap.borrow().behavior, &k.action, pattern);
                         if let Some(action) = final_action {
                             debug!("released prediction {:?} -> {:?}", pattern, action);
+                            #[cfg(feature = "keylog")]
+                            log_tap_hold_decision(event, pattern, action, TapHoldDecision::Release);
                             // Reached the longest configured morse pattern, trigger the corresponding action immediately
+                            let key_action = k.action;
                             self.held_buffer.remove(event.pos); // Remove the key from the held buffer, is like setting to an idle state
 
                             debug!(
@@ -125,31 +135,35 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                             press_event.pressed = true;
                             self.process_key_action_tap(action, press_event).await;
                             self.held_buffer.remove(event.pos); // Remove the key from the held buffer, is like setting to an idle state
+                            self.record_tap(&key_action, action, event);
                         } else {
                             // Expect a possible longer morse pattern (or idle timeout), update the state
                             k.state = KeyState::Released(pattern);
                             // Use current release time for `IdleAfterTap` state
                             k.press_time = released_time; // Use release time as the "press_time"
-                            k.timeout_time =
-                                k.press_time + Self::morse_timeout(&self.keymap.borrow().behavior, &k.action);
+                            k.timeout_time = k.press_time
+                                + Self::morse_timeout(&self.keymap.borrow().behavior, &k.action, event.pos);
                         }
                     }
                     KeyState::Holding(pattern) => {
                         // The try_predict_final_action => None is already decided, when we entered in Holding mode
                         // So, just expect a possible longer morse pattern (or idle timeout), update the state
-                        let released_time = Instant::now(); // TODO? It would be better if the event would carry the real timestamp of the release event!                        
+                        let released_time = event.time;
                         k.state = KeyState::Released(pattern);
                         // Use current release time for `IdleAfterTap` state
                         k.press_time = released_time; // Use release time as the "press_time"
-                        k.timeout_time = k.press_time + Self::morse_timeout(&self.keymap.borrow().behavior, &k.action);
+                        k.timeout_time =
+                            k.press_time + Self::morse_timeout(&self.keymap.borrow().behavior, &k.action, event.pos);
                     }
                     KeyState::ProcessedButReleaseNotReportedYet(action) => {
                         // Releasing a tap-hold action whose pressed HID report is already sent
                         info!("Releasing a morse action whose pressed action is already triggered");
+                        let key_action = k.action;
                         let _ = self.held_buffer.remove(event.pos);
                         // Process the release action
                         debug!("[morse] Releasing morse key: {:?}", event);
                         self.process_key_action_normal(action, event).await;
+                        self.record_tap(&key_action, action, event);
                         // Clear timer
                         self.set_timer_value(event, None);
                     }
@@ -159,6 +173,29 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         }
     }
 
+    /// Record the release of a tap-hold key which is resolved as tap, for the quick tap check
+    fn record_tap(&mut self, key_action: &KeyAction, action: Action, event: KeyboardEvent) {
+        if let KeyAction::TapHold(tap_action, _) = key_action
+            && *tap_action == action
+        {
+            self.last_tap = Some((event.pos, event.time));
+        }
+    }
+
+    /// Whether the tap-hold key is pressed again within the quick tap term after its tap is released
+    pub(crate) fn is_quick_tap(
+        &self,
+        key_action: &KeyAction,
+        event: KeyboardEvent,
+        last_tap: Option<(KeyboardEventPos, Instant)>,
+    ) -> bool {
+        let quick_tap_term = self.keymap.borrow().behavior.tap_hold.quick_tap_term;
+        matches!(key_action, KeyAction::TapHold(_, _))
+            && last_tap.is_some_and(|(pos, released_time)| {
+                pos == event.pos && event.time.saturating_duration_since(released_time) < quick_tap_term
+            })
+    }
+
     pub(crate) async fn fire_held_non_morse_keys(&mut self) {
         self.held_buffer.keys.sort_unstable_by_key(|k| k.press_time);
 
@@ -169,6 +206,7 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
             match action {
                 KeyAction::Single(action) => self.process_key_action_normal(action, key.event).await,
                 KeyAction::Tap(action) => self.process_key_action_tap(action, key.event).await,
+                KeyAction::LayerTapToggle(layer) => self.process_layer_tap_toggle(layer, key.event),
                 _ => (),
             }
         }
@@ -197,7 +235,14 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         }
     }
 
-    pub fn morse_timeout(behavior_config: &BehaviorConfig, keyAction: &KeyAction) -> Duration {
+    pub fn morse_timeout(behavior_config: &BehaviorConfig, keyAction: &KeyAction, pos: KeyboardEventPos) -> Duration {
+        // Per-key override has the highest priority
+        if let KeyboardEventPos::Key(key_pos) = pos
+            && let Some(timeout) = behavior_config.tap_hold.get_per_key_timeout(key_pos.row, key_pos.col)
+        {
+            return timeout;
+        }
+
         match keyAction {
             KeyAction::Morse(idx) => behavior_config
                 .morse
@@ -217,6 +262,10 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                 .morses
                 .get(*idx as usize)
                 .map(|td| (td.mode, td.unilateral_tap)),
+            KeyAction::TapHold(_, Action::LayerOn(_)) => behavior_config
+                .tap_hold
+                .layer_tap_mode
+                .map(|mode| (mode, behavior_config.tap_hold.unilateral_tap)),
             _ => None,
         }
         .unwrap_or_else(|| {
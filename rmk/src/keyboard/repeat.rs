@@ -0,0 +1,84 @@
+use rmk_types::keycode::KeyCode;
+use rmk_types::modifier::ModifierCombination;
+
+use crate::event::KeyboardEvent;
+use crate::keyboard::Keyboard;
+
+/// State of the repeat key
+#[derive(Default)]
+pub(crate) struct RepeatState {
+    /// The last repeatable key and the modifiers which were pressed with it
+    last: Option<(KeyCode, ModifierCombination)>,
+    /// The key and modifiers sent by the held repeat key, they are released with the repeat key
+    active: Option<(KeyCode, ModifierCombination)>,
+}
+
+impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    Keyboard<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    /// Resolve the key sent by the repeat key(`Again`/`RepeatKey`) or the reverse repeat key(`AltRepeatKey`).
+    ///
+    /// The key and the modifiers are decided when the repeat key is pressed, the release releases the same key.
+    /// Returns `None` if there's nothing to repeat.
+    pub(crate) fn resolve_repeat_key(&mut self, key: KeyCode, event: KeyboardEvent) -> Option<KeyCode> {
+        if event.pressed {
+            let (last_key, modifiers) = self.repeat.last?;
+            let repeat_key = if key == KeyCode::AltRepeatKey {
+                reverse_key(last_key)?
+            } else {
+                last_key
+            };
+            debug!("Repeat key: {:?}, modifiers: {:?}", repeat_key, modifiers);
+            // The modifiers are sent in the same report with the key, like `Action::KeyWithModifier`
+            self.with_modifiers |= modifiers;
+            self.repeat.active = Some((repeat_key, modifiers));
+            Some(repeat_key)
+        } else {
+            let (repeat_key, modifiers) = self.repeat.active.take()?;
+            self.with_modifiers &= !modifiers;
+            Some(repeat_key)
+        }
+    }
+
+    /// Record the pressed key and current modifiers for the repeat key.
+    ///
+    /// The key is the resolved keycode, so the repeat key sends the same key after the active layer is changed.
+    /// Modifier keys are not repeatable, pressing them alone doesn't change the last key.
+    pub(crate) fn record_repeat_key(&mut self, key: KeyCode) {
+        if key.is_modifier() || key == KeyCode::No {
+            return;
+        }
+        // The modifiers released by key overrides are not repeated
+        let modifiers = (self.resolve_explicit_modifiers(true) & !self.key_override_suppress()) | self.with_modifiers;
+        self.repeat.last = Some((key, modifiers));
+    }
+}
+
+/// The key in the opposite direction, which is sent by `AltRepeatKey`
+fn reverse_key(key: KeyCode) -> Option<KeyCode> {
+    let reversed = match key {
+        KeyCode::Left => KeyCode::Right,
+        KeyCode::Right => KeyCode::Left,
+        KeyCode::Up => KeyCode::Down,
+        KeyCode::Down => KeyCode::Up,
+        KeyCode::Home => KeyCode::End,
+        KeyCode::End => KeyCode::Home,
+        KeyCode::PageUp => KeyCode::PageDown,
+        KeyCode::PageDown => KeyCode::PageUp,
+        KeyCode::Backspace => KeyCode::Delete,
+        KeyCode::Delete => KeyCode::Backspace,
+        KeyCode::MouseUp => KeyCode::MouseDown,
+        KeyCode::MouseDown => KeyCode::MouseUp,
+        KeyCode::MouseLeft => KeyCode::MouseRight,
+        KeyCode::MouseRight => KeyCode::MouseLeft,
+        KeyCode::MouseWheelUp => KeyCode::MouseWheelDown,
+        KeyCode::MouseWheelDown => KeyCode::MouseWheelUp,
+        KeyCode::MouseWheelLeft => KeyCode::MouseWheelRight,
+        KeyCode::MouseWheelRight => KeyCode::MouseWheelLeft,
+        _ => {
+            debug!("No reverse key for {:?}", key);
+            return None;
+        }
+    };
+    Some(reversed)
+}
@@ -0,0 +1,69 @@
+use embassy_time::Timer;
+use rmk_types::keycode::KeyCode;
+use rmk_types::modifier::ModifierCombination;
+
+use crate::event::KeyboardEvent;
+use crate::keyboard::Keyboard;
+
+impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    Keyboard<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    /// Process space cadet keys, which act as a modifier when held and send a symbol when tapped.
+    ///
+    /// The modifier is registered as soon as the key is pressed, so the key can be used with other keys immediately.
+    /// The key is a tap only if no other key is pressed while it's held and it's released within the timeout.
+    pub(crate) async fn process_space_cadet(&mut self, key: KeyCode, event: KeyboardEvent) {
+        let hold_key = match key {
+            KeyCode::SpaceCadetLCtrlParenthesisOpen => KeyCode::LCtrl,
+            KeyCode::SpaceCadetRCtrlParenthesisClose => KeyCode::RCtrl,
+            KeyCode::SpaceCadetLShiftParenthesisOpen => KeyCode::LShift,
+            KeyCode::SpaceCadetRShiftParenthesisClose | KeyCode::SpaceCadetRShiftEnter => KeyCode::RShift,
+            KeyCode::SpaceCadetLAltParenthesisOpen => KeyCode::LAlt,
+            KeyCode::SpaceCadetRAltParenthesisClose => KeyCode::RAlt,
+            _ => return,
+        };
+
+        if event.pressed {
+            self.space_cadet = Some((event.pos, event.time));
+            self.register_key(hold_key, event);
+            self.send_keyboard_report_with_resolved_modifiers(true).await;
+            return;
+        }
+
+        self.unregister_key(hold_key, event);
+        let config = self.keymap.borrow().behavior.space_cadet;
+        let timeout = config.timeout.unwrap_or(self.keymap.borrow().behavior.tap_hold.timeout);
+        let is_tap = self
+            .space_cadet
+            .take_if(|(pos, _)| *pos == event.pos)
+            .is_some_and(|(_, pressed_at)| event.time.saturating_duration_since(pressed_at) < timeout);
+        if !is_tap {
+            self.send_keyboard_report_with_resolved_modifiers(false).await;
+            return;
+        }
+
+        let (tap_key, tap_modifiers) = match key {
+            KeyCode::SpaceCadetRShiftEnter => (KeyCode::Enter, ModifierCombination::new()),
+            KeyCode::SpaceCadetLCtrlParenthesisOpen
+            | KeyCode::SpaceCadetLShiftParenthesisOpen
+            | KeyCode::SpaceCadetLAltParenthesisOpen => (config.left_tap_key, config.left_tap_modifiers),
+            _ => (config.right_tap_key, config.right_tap_modifiers),
+        };
+        debug!("Space cadet tapped: {:?}, modifiers: {:?}", tap_key, tap_modifiers);
+        // The tap modifiers are sent in the same report with the key, like `Action::KeyWithModifier`
+        self.with_modifiers |= tap_modifiers;
+        self.register_key(tap_key, event);
+        self.send_keyboard_report_with_resolved_modifiers(true).await;
+        Timer::after_millis(10).await;
+        self.unregister_key(tap_key, event);
+        self.with_modifiers &= !tap_modifiers;
+        self.send_keyboard_report_with_resolved_modifiers(false).await;
+    }
+
+    /// Pressing any other key while a space cadet key is held makes the space cadet key a hold
+    pub(crate) fn interrupt_space_cadet(&mut self, event: KeyboardEvent) {
+        if event.pressed && self.space_cadet.is_some_and(|(pos, _)| pos != event.pos) {
+            self.space_cadet = None;
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use embassy_time::{Duration, Timer};
+use rmk_types::keycode::KeyCode;
+use rmk_types::modifier::ModifierCombination;
+
+use crate::config::UnicodeMode;
+use crate::descriptor::{KeyboardReport, NkroKeyboardReport};
+use crate::hid::Report;
+use crate::keyboard::{Keyboard, LOCK_LED_STATES};
+
+impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    Keyboard<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    /// Type the unicode code point with the input sequence of `UnicodeMode`.
+    ///
+    /// The held keys are released during the input sequence, and sent again after it.
+    pub(crate) async fn send_unicode(&mut self, code_point: u32) {
+        let Some(c) = char::from_u32(code_point) else {
+            warn!("Invalid unicode code point: {:#x}", code_point);
+            return;
+        };
+        let config = self.keymap.borrow().behavior.unicode;
+        let delay = config.key_delay;
+        let no_modifier = ModifierCombination::new();
+        let mut utf16 = [0u16; 2];
+        match config.mode {
+            UnicodeMode::Linux => {
+                self.tap_raw(
+                    ModifierCombination::LCTRL | ModifierCombination::LSHIFT,
+                    KeyCode::U,
+                    delay,
+                )
+                .await;
+                self.send_raw_report(no_modifier, KeyCode::No, delay).await;
+                self.tap_hex(code_point, 1, no_modifier, false, delay).await;
+                self.tap_raw(no_modifier, KeyCode::Space, delay).await;
+            }
+            UnicodeMode::MacOs => {
+                // Code points above U+FFFF are typed as the surrogate pair, 4 digits for each
+                self.send_raw_report(ModifierCombination::LALT, KeyCode::No, delay)
+                    .await;
+                for unit in c.encode_utf16(&mut utf16) {
+                    self.tap_hex(*unit as u32, 4, ModifierCombination::LALT, false, delay)
+                        .await;
+                }
+                self.send_raw_report(no_modifier, KeyCode::No, delay).await;
+            }
+            UnicodeMode::Windows => {
+                // The digits must be typed on the numpad, the surrogates are typed one by one
+                for unit in c.encode_utf16(&mut utf16) {
+                    self.send_raw_report(ModifierCombination::LALT, KeyCode::No, delay)
+                        .await;
+                    self.tap_raw(ModifierCombination::LALT, KeyCode::KpPlus, delay).await;
+                    self.tap_hex(*unit as u32, 1, ModifierCombination::LALT, true, delay)
+                        .await;
+                    self.send_raw_report(no_modifier, KeyCode::No, delay).await;
+                }
+            }
+            UnicodeMode::WinCompose => {
+                // Tap the compose key
+                self.send_raw_report(ModifierCombination::RALT, KeyCode::No, delay)
+                    .await;
+                self.send_raw_report(no_modifier, KeyCode::No, delay).await;
+                self.tap_raw(no_modifier, KeyCode::U, delay).await;
+                self.tap_hex(code_point, 1, no_modifier, false, delay).await;
+                self.tap_raw(no_modifier, KeyCode::Enter, delay).await;
+            }
+        }
+
+        // Restore the held keys
+        self.send_keyboard_report_with_resolved_modifiers(false).await;
+    }
+
+    /// Tap the hex digits of the value, leading zeros are typed to fill `min_digits`
+    async fn tap_hex(
+        &mut self,
+        value: u32,
+        min_digits: u32,
+        modifiers: ModifierCombination,
+        numpad: bool,
+        delay: Duration,
+    ) {
+        let digits = (value.max(1).ilog2() / 4 + 1).max(min_digits);
+        for i in (0..digits).rev() {
+            let nibble = ((value >> (i * 4)) & 0xF) as u16;
+            self.tap_raw(modifiers, hex_keycode(nibble, numpad), delay).await;
+        }
+    }
+
+    /// Press and release the key, the modifiers are kept pressed
+    async fn tap_raw(&mut self, modifiers: ModifierCombination, key: KeyCode, delay: Duration) {
+        self.send_raw_report(modifiers, key, delay).await;
+        self.send_raw_report(modifiers, KeyCode::No, delay).await;
+    }
+
+    /// Send a keyboard report which contains only the given modifiers and key, then wait for `delay`
+    async fn send_raw_report(&mut self, modifiers: ModifierCombination, key: KeyCode, delay: Duration) {
+        let report = if self.nkro_on {
+            let mut report = NkroKeyboardReport {
+                modifier: modifiers.into_bits(),
+                ..Default::default()
+            };
+            if key != KeyCode::No {
+                report.set(key as u8, true);
+            }
+            Report::NkroKeyboardReport(report)
+        } else {
+            Report::KeyboardReport(KeyboardReport {
+                modifier: modifiers.into_bits(),
+                reserved: 0,
+                leds: LOCK_LED_STATES.load(core::sync::atomic::Ordering::Relaxed),
+                keycodes: [key as u8, 0, 0, 0, 0, 0],
+            })
+        };
+        self.send_report(report).await;
+        Timer::after(delay).await;
+    }
+}
+
+/// Keycode of the hex digit
+fn hex_keycode(nibble: u16, numpad: bool) -> KeyCode {
+    match nibble {
+        0 if numpad => KeyCode::Kp0,
+        0 => KeyCode::Kc0,
+        1..=9 if numpad => KeyCode::from(KeyCode::Kp1 as u16 + nibble - 1),
+        1..=9 => KeyCode::from(KeyCode::Kc1 as u16 + nibble - 1),
+        _ => KeyCode::from(KeyCode::A as u16 + nibble - 10),
+    }
+}
@@ -1,6 +1,6 @@
 #[cfg(feature = "storage")]
 use embedded_storage_async::nor_flash::NorFlash;
-use rmk_types::action::{EncoderAction, KeyAction};
+use rmk_types::action::{Action, EncoderAction, KeyAction};
 #[cfg(feature = "controller")]
 use {
     crate::channel::{CONTROLLER_CHANNEL, ControllerPub, send_controller_event},
@@ -8,11 +8,13 @@ use {
 };
 
 use crate::COMBO_MAX_NUM;
+use crate::channel::LAYER_CHANGE_SIGNAL;
 use crate::combo::Combo;
 use crate::config::BehaviorConfig;
 use crate::event::{KeyboardEvent, KeyboardEventPos};
 use crate::input_device::rotary_encoder::Direction;
 use crate::keyboard_macros::MacroOperation;
+use crate::light::{BacklightConfig, RgbLightConfig, RgbMatrixConfig};
 #[cfg(feature = "matrix_tester")]
 use crate::matrix::MatrixState;
 #[cfg(feature = "storage")]
@@ -31,6 +33,14 @@ pub struct KeyMap<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize
     layer_state: [bool; NUM_LAYER],
     /// Default layer number, max: 32
     default_layer: u8,
+    /// Layout options, set by Vial
+    layout_option: u32,
+    /// State of the RGB underglow, set by Vial
+    rgb_light: RgbLightConfig,
+    /// State of the backlight
+    backlight: BacklightConfig,
+    /// State of the per-key RGB matrix, set by Vial
+    rgb_matrix: RgbMatrixConfig,
     /// Layer cache
     layer_cache: [[u8; COL]; ROW],
     /// Rotary encoder cache
@@ -40,6 +50,8 @@ pub struct KeyMap<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize
     /// Publisher for controller channel
     #[cfg(feature = "controller")]
     controller_pub: ControllerPub,
+    /// The top activated layer which is notified last time
+    notified_layer: u8,
     /// Matrix state
     #[cfg(feature = "matrix_tester")]
     pub(crate) matrix_state: MatrixState<ROW, COL>,
@@ -80,11 +92,16 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
             encoders: encoder_map,
             layer_state: [false; NUM_LAYER],
             default_layer: 0,
+            layout_option: 0,
+            rgb_light: RgbLightConfig::default(),
+            backlight: BacklightConfig::default(),
+            rgb_matrix: RgbMatrixConfig::default(),
             layer_cache: [[0; COL]; ROW],
             encoder_layer_cache: [[0; 2]; NUM_ENCODER],
             behavior,
             #[cfg(feature = "controller")]
             controller_pub: unwrap!(CONTROLLER_CHANNEL.publisher()),
+            notified_layer: 0,
             #[cfg(feature = "matrix_tester")]
             matrix_state: MatrixState::new(),
         }
@@ -101,6 +118,11 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         fill_vec(&mut behavior.fork.forks); // Is this needed? (has no Vial support)
         fill_vec(&mut behavior.morse.morses);
 
+        let mut layout_option = 0;
+        let mut default_layer = 0;
+        let mut rgb_light = RgbLightConfig::default();
+        let mut backlight = BacklightConfig::default();
+        let mut rgb_matrix = RgbMatrixConfig::default();
         if let Some(storage) = storage {
             if {
                 Ok(())
@@ -108,6 +130,14 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
                     .and(storage.read_keymap(action_map, &mut encoder_map).await)
                     // Read behavior config
                     .and(storage.read_behavior_config(behavior).await)
+                    // Read layout options and the default layer
+                    .and(storage.read_layout_config(&mut layout_option, &mut default_layer).await)
+                    // Read RGB underglow state
+                    .and(storage.read_rgb_light_config(&mut rgb_light).await)
+                    // Read backlight state
+                    .and(storage.read_backlight_config(&mut backlight).await)
+                    // Read RGB matrix state
+                    .and(storage.read_rgb_matrix_config(&mut rgb_matrix).await)
                     // Read macro cache
                     .and(
                         storage
@@ -136,12 +166,17 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
             layers: action_map,
             encoders: encoder_map,
             layer_state: [false; NUM_LAYER],
-            default_layer: 0,
+            default_layer,
+            layout_option,
+            rgb_light,
+            backlight,
+            rgb_matrix,
             layer_cache: [[0; COL]; ROW],
             encoder_layer_cache: [[0; 2]; NUM_ENCODER],
             behavior,
             #[cfg(feature = "controller")]
             controller_pub: unwrap!(CONTROLLER_CHANNEL.publisher()),
+            notified_layer: default_layer,
             #[cfg(feature = "matrix_tester")]
             matrix_state: MatrixState::new(),
         }
@@ -156,9 +191,82 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         self.default_layer
     }
 
-    /// Set the default layer number
+    /// Set the default layer number.
+    ///
+    /// Activated layers above the default layer are still applied on top of it,
+    /// layers below the default layer are not checked until the default layer is changed back.
     pub(crate) fn set_default_layer(&mut self, layer_num: u8) {
+        if layer_num as usize >= NUM_LAYER {
+            warn!(
+                "Not a valid layer {}, keyboard supports only {} layers",
+                layer_num, NUM_LAYER
+            );
+            return;
+        }
         self.default_layer = layer_num;
+
+        self.notify_layer_change();
+    }
+
+    /// Get the layout options
+    pub(crate) fn get_layout_option(&self) -> u32 {
+        self.layout_option
+    }
+
+    /// Set the layout options
+    pub(crate) fn set_layout_option(&mut self, layout_option: u32) {
+        self.layout_option = layout_option;
+    }
+
+    pub(crate) fn get_rgb_light(&self) -> RgbLightConfig {
+        self.rgb_light
+    }
+
+    /// Set the state of the RGB underglow, and notify the controllers and split peripherals
+    pub(crate) fn set_rgb_light(&mut self, rgb_light: RgbLightConfig) {
+        self.rgb_light = rgb_light;
+        #[cfg(feature = "controller")]
+        send_controller_event(&mut self.controller_pub, ControllerEvent::RgbLight(rgb_light));
+        // Sync the lighting state to peripherals
+        #[cfg(feature = "split")]
+        if let Ok(publisher) = crate::channel::SPLIT_MESSAGE_PUBLISHER.publisher() {
+            publisher.publish_immediate(crate::split::SplitMessage::Lighting {
+                hue: rgb_light.hue,
+                sat: rgb_light.saturation,
+                val: rgb_light.brightness,
+                effect: rgb_light.effect,
+            });
+        }
+    }
+
+    pub(crate) fn get_backlight(&self) -> BacklightConfig {
+        self.backlight
+    }
+
+    /// Set the state of the backlight, and notify the controllers
+    pub(crate) fn set_backlight(&mut self, backlight: BacklightConfig) {
+        self.backlight = backlight;
+        #[cfg(feature = "controller")]
+        send_controller_event(&mut self.controller_pub, ControllerEvent::Backlight(backlight));
+    }
+
+    pub(crate) fn get_rgb_matrix(&self) -> RgbMatrixConfig {
+        self.rgb_matrix
+    }
+
+    /// Set the state of the RGB matrix, and notify the controllers
+    pub(crate) fn set_rgb_matrix(&mut self, rgb_matrix: RgbMatrixConfig) {
+        self.rgb_matrix = rgb_matrix;
+        #[cfg(feature = "controller")]
+        send_controller_event(&mut self.controller_pub, ControllerEvent::RgbMatrix(rgb_matrix));
+    }
+
+    /// Send the current lighting states to the controllers, so that the restored states are applied at startup
+    #[cfg(feature = "controller")]
+    pub(crate) fn publish_light_states(&mut self) {
+        send_controller_event(&mut self.controller_pub, ControllerEvent::RgbLight(self.rgb_light));
+        send_controller_event(&mut self.controller_pub, ControllerEvent::Backlight(self.backlight));
+        send_controller_event(&mut self.controller_pub, ControllerEvent::RgbMatrix(self.rgb_matrix));
     }
 
     pub(crate) fn get_next_macro_operation(&self, macro_start_idx: usize, offset: usize) -> (MacroOperation, usize) {
@@ -176,16 +284,28 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         )
     }
 
+    /// Set the action in keymap, a position or layer which is out of the keymap is ignored
     pub(crate) fn set_action_at(&mut self, pos: KeyboardEventPos, layer_num: usize, action: KeyAction) {
         match pos {
             KeyboardEventPos::Key(key_pos) => {
                 let row = key_pos.row as usize;
                 let col = key_pos.col as usize;
-                self.layers[layer_num][row][col] = action;
+                match self
+                    .layers
+                    .get_mut(layer_num)
+                    .and_then(|l| l.get_mut(row))
+                    .and_then(|r| r.get_mut(col))
+                {
+                    Some(a) => *a = action,
+                    None => warn!("Set action out of keymap: ({}, {}), layer {}", row, col, layer_num),
+                }
             }
             KeyboardEventPos::RotaryEncoder(encoder_pos) => {
                 if let Some(encoders) = &mut self.encoders {
-                    if let Some(encoder_action) = encoders[layer_num].get_mut(encoder_pos.id as usize) {
+                    if let Some(encoder_action) = encoders
+                        .get_mut(layer_num)
+                        .and_then(|e| e.get_mut(encoder_pos.id as usize))
+                    {
                         match encoder_pos.direction {
                             Direction::Clockwise => encoder_action.set_clockwise(action),
                             Direction::CounterClockwise => encoder_action.set_counter_clockwise(action),
@@ -197,18 +317,23 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         }
     }
 
-    /// Fetch the action in keymap, with layer cache
+    /// Fetch the action in keymap, `KeyAction::No` if the position or layer is out of the keymap
     pub(crate) fn get_action_at(&self, pos: KeyboardEventPos, layer_num: usize) -> KeyAction {
         match pos {
             KeyboardEventPos::Key(key_pos) => {
                 let row = key_pos.row as usize;
                 let col = key_pos.col as usize;
-                self.layers[layer_num][row][col]
+                self.layers
+                    .get(layer_num)
+                    .and_then(|l| l.get(row))
+                    .and_then(|r| r.get(col))
+                    .copied()
+                    .unwrap_or(KeyAction::No)
             }
             KeyboardEventPos::RotaryEncoder(encoder_pos) => {
                 // Get the action from the keymap
                 if let Some(encoders) = &self.encoders {
-                    if let Some(encoder_action) = encoders[layer_num].get(encoder_pos.id as usize) {
+                    if let Some(encoder_action) = encoders.get(layer_num).and_then(|e| e.get(encoder_pos.id as usize)) {
                         if encoder_pos.direction != Direction::None {
                             return match encoder_pos.direction {
                                 Direction::Clockwise => encoder_action.clockwise(),
@@ -232,60 +357,36 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
             return action;
         }
 
-        // Iterate from higher layer to lower layer, the lowest checked layer is the default layer
-        match event.pos {
-            KeyboardEventPos::Key(key_pos) => {
-                let row = key_pos.row as usize;
-                let col = key_pos.col as usize;
-                for (layer_idx, layer) in self.layers.iter().enumerate().rev() {
-                    if self.layer_state[layer_idx] || layer_idx as u8 == self.default_layer {
-                        // This layer is activated
-                        let action = layer[row][col];
-                        if action == KeyAction::Transparent {
-                            continue;
-                        }
-
-                        // Found a valid action in the layer, cache it
-                        self.save_layer_cache(event.pos, layer_idx as u8);
-
-                        return action;
-                    }
+        match self.get_effective_action(event.pos) {
+            Some((action, layer)) => {
+                // Found a valid action in the layer, cache it
+                self.save_layer_cache(event.pos, layer);
+                action
+            }
+            None => KeyAction::No,
+        }
+    }
 
-                    if layer_idx as u8 == self.default_layer {
-                        // No action
-                        break;
-                    }
-                }
+    /// Resolve the action which is triggered if the key is pressed now, and the layer of the action.
+    ///
+    /// Iterate from higher layer to lower layer, a transparent key falls through to the next lower active layer.
+    /// The lowest checked layer is the default layer, layers below it are never checked.
+    /// Returns `None` if the key is transparent on all checked layers.
+    pub(crate) fn get_effective_action(&self, pos: KeyboardEventPos) -> Option<(KeyAction, u8)> {
+        for layer_idx in (0..NUM_LAYER).rev() {
+            if !self.layer_state[layer_idx] && layer_idx as u8 != self.default_layer {
+                continue;
             }
-            KeyboardEventPos::RotaryEncoder(encoder_pos) => {
-                if let Some(encoders) = &self.encoders {
-                    for (layer_idx, layer) in encoders.iter().enumerate().rev() {
-                        // Get the KeyAction for rotary_encoder_event from self.encoders
-                        if self.layer_state[layer_idx] || layer_idx as u8 == self.default_layer {
-                            // This layer is activated
-                            if let Some(encoder_action) = layer.get(encoder_pos.id as usize) {
-                                let action = match encoder_pos.direction {
-                                    Direction::Clockwise => encoder_action.clockwise(),
-                                    Direction::CounterClockwise => encoder_action.counter_clockwise(),
-                                    Direction::None => KeyAction::No,
-                                };
-                                if action == KeyAction::Transparent {
-                                    continue;
-                                }
-                                self.save_layer_cache(event.pos, layer_idx as u8);
-                                return action;
-                            }
-                        }
-                        if layer_idx as u8 == self.default_layer {
-                            // No action
-                            break;
-                        }
-                    }
-                }
+            // This layer is activated
+            let action = self.get_action_at(pos, layer_idx);
+            if !is_transparent(&action) {
+                return Some((action, layer_idx as u8));
+            }
+            if layer_idx as u8 == self.default_layer {
+                break;
             }
         }
-
-        KeyAction::No
+        None
     }
 
     pub(crate) fn get_activated_layer(&self) -> u8 {
@@ -341,16 +442,31 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         }
     }
 
-    /// Update Tri Layer state
+    /// Update Tri Layer state.
+    ///
+    /// The rules are evaluated in order, so the adjust layer of a rule can be the upper or lower layer of a later rule.
+    /// When several rules share the same adjust layer, it's active if any of them is satisfied.
     fn update_tri_layer(&mut self) {
-        if let Some(ref tri_layer) = self.behavior.tri_layer {
-            self.layer_state[tri_layer[2] as usize] =
-                self.layer_state[tri_layer[0] as usize] && self.layer_state[tri_layer[1] as usize];
+        let tri_layers = self.behavior.tri_layers;
+        for [_, _, adjust] in tri_layers {
+            self.layer_state[*adjust as usize] = false;
+        }
+        for [upper, lower, adjust] in tri_layers {
+            if self.layer_state[*upper as usize] && self.layer_state[*lower as usize] {
+                self.layer_state[*adjust as usize] = true;
+            }
         }
 
-        #[cfg(feature = "controller")]
-        {
-            let layer = self.get_activated_layer();
+        self.notify_layer_change();
+    }
+
+    /// Signal the top activated layer and send it to controllers if it's changed
+    fn notify_layer_change(&mut self) {
+        let layer = self.get_activated_layer();
+        if layer != self.notified_layer {
+            self.notified_layer = layer;
+            LAYER_CHANGE_SIGNAL.signal(layer);
+            #[cfg(feature = "controller")]
             send_controller_event(&mut self.controller_pub, ControllerEvent::Layer(layer));
         }
     }
@@ -381,6 +497,11 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
         self.update_tri_layer();
     }
 
+    /// Whether the given layer is activated, the default layer is not included
+    pub(crate) fn is_layer_active(&self, layer_num: u8) -> bool {
+        self.layer_state.get(layer_num as usize).copied().unwrap_or(false)
+    }
+
     /// Toggle given layer
     pub(crate) fn toggle_layer(&mut self, layer_num: u8) {
         if layer_num as usize >= NUM_LAYER {
@@ -393,11 +514,7 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
 
         self.layer_state[layer_num as usize] = !self.layer_state[layer_num as usize];
 
-        #[cfg(feature = "controller")]
-        {
-            let layer = self.get_activated_layer();
-            send_controller_event(&mut self.controller_pub, ControllerEvent::Layer(layer));
-        }
+        self.notify_layer_change();
     }
 
     //order combos by their actions length
@@ -406,17 +523,98 @@ impl<'a, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_E
     }
 }
 
+/// Whether the action falls through to the next lower active layer
+fn is_transparent(action: &KeyAction) -> bool {
+    matches!(action, KeyAction::Transparent | KeyAction::Single(Action::Transparent))
+}
+
 #[cfg(test)]
 mod test {
     use rmk_types::action::{Action, KeyAction};
     use rmk_types::keycode::KeyCode;
     use rmk_types::modifier::ModifierCombination;
+    use rusty_fork::rusty_fork_test;
 
-    use super::{_reorder_combos, Combo};
+    use super::{_reorder_combos, Combo, KeyMap};
+    use crate::channel::LAYER_CHANGE_SIGNAL;
+    use crate::config::BehaviorConfig;
+    use crate::event::KeyboardEventPos;
     use crate::fork::{Fork, StateBits};
     use crate::keymap::fill_vec;
     use crate::{COMBO_MAX_NUM, FORK_MAX_NUM, k};
 
+    rusty_fork_test! {
+        #[test]
+        fn test_layer_change_signal() {
+            let layers = Box::leak(Box::new([[[k!(A)]], [[k!(B)]], [[k!(C)]]]));
+            let behavior_config = Box::leak(Box::new(BehaviorConfig::default()));
+            let mut keymap: KeyMap<1, 1, 3> = embassy_futures::block_on(KeyMap::new(layers, None, behavior_config));
+
+            keymap.activate_layer(1);
+            assert_eq!(LAYER_CHANGE_SIGNAL.try_take(), Some(1));
+
+            // The top layer isn't changed, nothing is signaled
+            keymap.activate_layer(1);
+            assert_eq!(LAYER_CHANGE_SIGNAL.try_take(), None);
+
+            // Any layer action changing the top layer is signaled, only the latest top layer is kept
+            keymap.toggle_layer(2);
+            keymap.deactivate_layer(2);
+            assert_eq!(LAYER_CHANGE_SIGNAL.try_take(), Some(1));
+            keymap.deactivate_layer(1);
+            assert_eq!(LAYER_CHANGE_SIGNAL.try_take(), Some(0));
+        }
+    }
+
+    #[test]
+    fn test_action_at_out_of_keymap() {
+        let layers = Box::leak(Box::new([[[k!(A), k!(B)]], [[k!(C), k!(D)]]]));
+        let behavior_config = Box::leak(Box::new(BehaviorConfig::default()));
+        let mut keymap: KeyMap<1, 2, 2> = embassy_futures::block_on(KeyMap::new(layers, None, behavior_config));
+
+        // Out of range positions and layers are ignored
+        keymap.set_action_at(KeyboardEventPos::key_pos(2, 0), 0, k!(E));
+        keymap.set_action_at(KeyboardEventPos::key_pos(0, 1), 0, k!(E));
+        keymap.set_action_at(KeyboardEventPos::key_pos(0, 0), 2, k!(E));
+        assert_eq!(*keymap.layers, [[[k!(A), k!(B)]], [[k!(C), k!(D)]]]);
+        assert_eq!(keymap.get_action_at(KeyboardEventPos::key_pos(2, 0), 0), KeyAction::No);
+        assert_eq!(keymap.get_action_at(KeyboardEventPos::key_pos(0, 0), 2), KeyAction::No);
+
+        keymap.set_action_at(KeyboardEventPos::key_pos(1, 0), 1, k!(E));
+        assert_eq!(keymap.get_action_at(KeyboardEventPos::key_pos(1, 0), 1), k!(E));
+    }
+
+    #[test]
+    fn test_tri_layers() {
+        let layers = Box::leak(Box::new([
+            [[k!(A)]],
+            [[k!(B)]],
+            [[k!(C)]],
+            [[k!(D)]],
+            [[k!(E)]],
+            [[k!(F)]],
+        ]));
+        let behavior_config = Box::leak(Box::new(BehaviorConfig {
+            tri_layers: &[[1, 2, 3], [1, 4, 3], [3, 4, 5]],
+            ..BehaviorConfig::default()
+        }));
+        let mut keymap: KeyMap<1, 1, 6> = embassy_futures::block_on(KeyMap::new(layers, None, behavior_config));
+
+        keymap.activate_layer(1);
+        assert!(!keymap.is_layer_active(3));
+        keymap.activate_layer(2);
+        assert!(keymap.is_layer_active(3));
+
+        // Layer 3 is kept by the second rule, and it activates layer 5 by the third rule
+        keymap.activate_layer(4);
+        keymap.deactivate_layer(2);
+        assert!(keymap.is_layer_active(3));
+        assert!(keymap.is_layer_active(5));
+        keymap.deactivate_layer(1);
+        assert!(!keymap.is_layer_active(3));
+        assert!(!keymap.is_layer_active(5));
+    }
+
     #[test]
     fn test_fill_vec() {
         let mut combos: heapless::Vec<_, COMBO_MAX_NUM> = heapless::Vec::from_slice(&[
@@ -123,10 +123,7 @@ macro_rules! tg {
 #[macro_export]
 macro_rules! tt {
     ($x: literal) => {
-        $crate::types::action::KeyAction::TapHold(
-            $crate::types::action::Action::LayerToggle($x),
-            $crate::types::action::Action::LayerOn($x),
-        )
+        $crate::types::action::KeyAction::LayerTapToggle($x)
     };
 }
 
@@ -181,6 +178,46 @@ macro_rules! morse {
     };
 }
 
+/// Create an action which types the unicode code point
+#[macro_export]
+macro_rules! uc {
+    ($code_point: expr) => {
+        $crate::types::action::KeyAction::Single($crate::types::action::Action::Unicode($code_point))
+    };
+}
+
+/// Create an action which selects the BLE profile `n`
+#[macro_export]
+macro_rules! bt {
+    ($n: expr) => {
+        $crate::types::action::KeyAction::Single($crate::types::action::Action::ProfileSelect($n))
+    };
+}
+
+/// Create an action which switches to the next BLE profile
+#[macro_export]
+macro_rules! bt_next {
+    () => {
+        $crate::types::action::KeyAction::Single($crate::types::action::Action::ProfileNext)
+    };
+}
+
+/// Create an action which switches to the previous BLE profile
+#[macro_export]
+macro_rules! bt_prev {
+    () => {
+        $crate::types::action::KeyAction::Single($crate::types::action::Action::ProfilePrevious)
+    };
+}
+
+/// Create an action which enters the BLE pairing mode
+#[macro_export]
+macro_rules! bt_pair {
+    () => {
+        $crate::types::action::KeyAction::Single($crate::types::action::Action::PairingMode)
+    };
+}
+
 // Create a macro trigger action
 // Use `macros` because `macro` is a key word in Rust
 #[macro_export]
@@ -0,0 +1,84 @@
+use heapless::Vec;
+use rmk_types::action::KeyAction;
+use rmk_types::keycode::KeyCode;
+
+use crate::LEADER_MAX_LENGTH;
+
+/// A leader key sequence.
+///
+/// After the leader key is pressed, typing all keys of `sequence` in order triggers `output`.
+#[derive(Clone, Debug)]
+pub struct Leader {
+    pub(crate) sequence: Vec<KeyCode, LEADER_MAX_LENGTH>,
+    pub(crate) output: KeyAction,
+}
+
+impl Leader {
+    /// Create a leader key sequence.
+    ///
+    /// A sequence longer than `LEADER_MAX_LENGTH` is skipped: it's stored as an empty sequence, which never matches.
+    pub fn new<I: IntoIterator<Item = KeyCode>>(sequence: I, output: KeyAction) -> Self {
+        let mut keys = Vec::new();
+        for key in sequence {
+            if keys.push(key).is_err() {
+                warn!(
+                    "Leader sequence is longer than LEADER_MAX_LENGTH({}), skipped",
+                    LEADER_MAX_LENGTH
+                );
+                keys.clear();
+                break;
+            }
+        }
+        Self { sequence: keys, output }
+    }
+
+    /// Check whether the typed keys are exactly this sequence
+    fn is_match(&self, keys: &[KeyCode]) -> bool {
+        !self.sequence.is_empty() && self.sequence.as_slice() == keys
+    }
+
+    /// Check whether the typed keys are the beginning of this sequence, and more keys are needed
+    fn is_prefix(&self, keys: &[KeyCode]) -> bool {
+        self.sequence.len() > keys.len() && self.sequence.starts_with(keys)
+    }
+}
+
+/// Result of matching the typed keys against all leader sequences
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LeaderMatch {
+    /// The typed keys are the beginning of a longer sequence, wait for more keys.
+    /// If the typed keys are also a complete sequence, its output is triggered when the leader key times out.
+    Partial(Option<KeyAction>),
+    /// The typed keys are a complete sequence and no longer sequence starts with them
+    Complete(KeyAction),
+    /// No sequence starts with the typed keys
+    None,
+}
+
+/// Match the typed keys against all leader sequences
+pub(crate) fn match_leaders(leaders: &[Leader], keys: &[KeyCode]) -> LeaderMatch {
+    let exact = leaders.iter().find(|l| l.is_match(keys)).map(|l| l.output);
+    if leaders.iter().any(|l| l.is_prefix(keys)) {
+        LeaderMatch::Partial(exact)
+    } else if let Some(output) = exact {
+        LeaderMatch::Complete(output)
+    } else {
+        LeaderMatch::None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rmk_types::action::Action;
+
+    use super::*;
+
+    #[test]
+    fn test_too_long_sequence_is_skipped() {
+        let output = KeyAction::Single(Action::Key(KeyCode::C));
+        let keys = [KeyCode::W; LEADER_MAX_LENGTH + 1];
+        let leaders = [Leader::new(keys, output)];
+        assert!(leaders[0].sequence.is_empty());
+        assert_eq!(match_leaders(&leaders, &keys[..1]), LeaderMatch::None);
+    }
+}
@@ -22,6 +22,8 @@ use core::cell::RefCell;
 use core::future::Future;
 use core::sync::atomic::Ordering;
 
+#[cfg(feature = "_ble")]
+use ble::rssi::RssiController;
 #[cfg(feature = "_ble")]
 use bt_hci::{
     cmd::le::{LeReadLocalSupportedFeatures, LeSetPhy},
@@ -30,7 +32,11 @@ use bt_hci::{
 use config::{RmkConfig, VialConfig};
 #[cfg(feature = "controller")]
 use controller::{PollingController, wpm::WpmController};
+#[cfg(all(feature = "nkro", not(feature = "_no_usb"), not(feature = "_ble")))]
+use descriptor::NkroKeyboardReport;
 use descriptor::ViaReport;
+#[cfg(feature = "controller")]
+use embassy_futures::select::select;
 use embassy_futures::select::{Either4, select4};
 #[cfg(not(any(cortex_m)))]
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex as RawMutex;
@@ -61,7 +67,7 @@ use {
 };
 pub use {embassy_futures, futures, heapless, rmk_macro as macros, rmk_types as types};
 #[cfg(feature = "storage")]
-use {embassy_futures::select::select, embedded_storage_async::nor_flash::NorFlash as AsyncNorFlash, storage::Storage};
+use {embedded_storage_async::nor_flash::NorFlash as AsyncNorFlash, storage::Storage};
 
 use crate::keyboard::LOCK_LED_STATES;
 use crate::state::ConnectionState;
@@ -82,18 +88,23 @@ pub mod event;
 pub mod fork;
 pub mod hid;
 pub mod input_device;
+pub mod key_override;
 pub mod keyboard;
 pub mod keyboard_macros;
 pub mod keymap;
 pub mod layout_macro;
+pub mod leader;
 pub mod light;
 pub mod matrix;
 pub mod morse;
+pub mod shift_register;
 #[cfg(feature = "split")]
 pub mod split;
 pub mod state;
 #[cfg(feature = "storage")]
 pub mod storage;
+#[cfg(feature = "std")]
+pub mod time_driver;
 #[cfg(not(feature = "_no_usb"))]
 pub mod usb;
 pub mod via;
@@ -185,7 +196,7 @@ pub async fn initialize_keymap_and_storage<
 pub async fn run_rmk<
     'a,
     #[cfg(feature = "_ble")] 'b,
-    #[cfg(feature = "_ble")] C: Controller + ControllerCmdAsync<LeSetPhy> + ControllerCmdSync<LeReadLocalSupportedFeatures>,
+    #[cfg(feature = "_ble")] C: Controller + ControllerCmdAsync<LeSetPhy> + ControllerCmdSync<LeReadLocalSupportedFeatures> + RssiController,
     #[cfg(feature = "storage")] F: AsyncNorFlash,
     #[cfg(not(feature = "_no_usb"))] D: Driver<'static>,
     const ROW: usize,
@@ -199,6 +210,10 @@ pub async fn run_rmk<
     #[cfg(feature = "storage")] storage: &mut Storage<F, ROW, COL, NUM_LAYER, NUM_ENCODER>,
     rmk_config: RmkConfig<'static>,
 ) -> ! {
+    // Apply the lighting states restored from storage
+    #[cfg(feature = "controller")]
+    keymap.borrow_mut().publish_light_states();
+
     // Dispatch the keyboard runner
     #[cfg(feature = "_ble")]
     crate::ble::run_ble(
@@ -216,10 +231,13 @@ pub async fn run_rmk<
     // USB keyboard
     #[cfg(all(not(feature = "_no_usb"), not(feature = "_ble")))]
     {
+        let poll_ms = rmk_config.usb_config.poll_ms();
         let mut usb_builder: embassy_usb::Builder<'_, D> = new_usb_builder(usb_driver, rmk_config.usb_config);
-        let keyboard_reader_writer = add_usb_reader_writer!(&mut usb_builder, KeyboardReport, 1, 8);
-        let mut other_writer = add_usb_writer!(&mut usb_builder, CompositeReport, 9);
-        let mut vial_reader_writer = add_usb_reader_writer!(&mut usb_builder, ViaReport, 32, 32);
+        let keyboard_reader_writer = add_usb_reader_writer!(&mut usb_builder, KeyboardReport, 1, 8, poll_ms);
+        let mut other_writer = add_usb_writer!(&mut usb_builder, CompositeReport, 9, poll_ms);
+        #[cfg(feature = "nkro")]
+        let mut nkro_writer = add_usb_writer!(&mut usb_builder, NkroKeyboardReport, 29, poll_ms);
+        let mut vial_reader_writer = add_usb_reader_writer!(&mut usb_builder, ViaReport, 32, 32, 1);
         let (mut keyboard_reader, mut keyboard_writer) = keyboard_reader_writer.split();
 
         #[cfg(feature = "usb_log")]
@@ -231,8 +249,14 @@ pub async fn run_rmk<
         let logger_fut = async {};
         let mut usb_device = usb_builder.build();
 
+        // The storage runs in the background, it's not restarted with the keyboard tasks
+        #[cfg(feature = "storage")]
+        let storage_fut = storage.run();
+        #[cfg(not(feature = "storage"))]
+        let storage_fut = async {};
+
         // Run all tasks, if one of them fails, wait 1 second and then restart
-        embassy_futures::join::join(logger_fut, async {
+        embassy_futures::join::join3(logger_fut, storage_fut, async {
             loop {
                 let usb_task = async {
                     loop {
@@ -247,6 +271,9 @@ pub async fn run_rmk<
                             Either::First(_) => continue,
                             Either::Second(_) => {
                                 info!("USB wakeup remote");
+                                if let Err(e) = usb_device.remote_wakeup().await {
+                                    info!("USB wakeup remote error: {:?}", e)
+                                }
                             }
                         }
                     }
@@ -254,12 +281,16 @@ pub async fn run_rmk<
 
                 run_keyboard(
                     keymap,
-                    #[cfg(feature = "storage")]
-                    storage,
                     usb_task,
                     UsbLedReader::new(&mut keyboard_reader),
                     UsbVialReaderWriter::new(&mut vial_reader_writer),
-                    UsbKeyboardWriter::new(&mut keyboard_writer, &mut other_writer),
+                    UsbKeyboardWriter::new(
+                        &mut keyboard_writer,
+                        &mut other_writer,
+                        #[cfg(feature = "nkro")]
+                        &mut nkro_writer,
+                        rmk_config.composite_report_interval,
+                    ),
                     rmk_config.vial_config,
                 )
                 .await;
@@ -278,14 +309,12 @@ pub(crate) async fn run_keyboard<
     R: HidReaderTrait<ReportType = LedIndicator>,
     W: RunnableHidWriter,
     Fu: Future<Output = ()>,
-    #[cfg(feature = "storage")] F: AsyncNorFlash,
     const ROW: usize,
     const COL: usize,
     const NUM_LAYER: usize,
     const NUM_ENCODER: usize,
 >(
     keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
-    #[cfg(feature = "storage")] storage: &mut Storage<F, ROW, COL, NUM_LAYER, NUM_ENCODER>,
     communication_task: Fu,
     mut led_reader: R,
     vial_reader_writer: Rw,
@@ -304,7 +333,11 @@ pub(crate) async fn run_keyboard<
             match led_reader.read_report().await {
                 Ok(led_indicator) => {
                     info!("Got led indicator");
-                    LOCK_LED_STATES.store(led_indicator.into_bits(), core::sync::atomic::Ordering::Relaxed);
+                    let last = LOCK_LED_STATES.swap(led_indicator.into_bits(), core::sync::atomic::Ordering::Relaxed);
+                    // The host may send the same state again, for example after reconnecting
+                    if last == led_indicator.into_bits() {
+                        continue;
+                    }
                     #[cfg(feature = "controller")]
                     {
                         info!("Publishing led indicator");
@@ -325,17 +358,11 @@ pub(crate) async fn run_keyboard<
 
     let via_fut = vial_service.run();
 
-    #[cfg(feature = "storage")]
-    let storage_fut = storage.run();
-
     #[cfg(feature = "controller")]
     let mut wpm_controller = WpmController::new();
 
     match select4(
         communication_task,
-        #[cfg(feature = "storage")]
-        select(storage_fut, via_fut),
-        #[cfg(not(feature = "storage"))]
         via_fut,
         #[cfg(feature = "controller")]
         select(wpm_controller.polling_loop(), led_fut),
@@ -346,7 +373,7 @@ pub(crate) async fn run_keyboard<
     .await
     {
         Either4::First(_) => error!("Communication task has ended"),
-        Either4::Second(_) => error!("Storage or vial task has ended"),
+        Either4::Second(_) => error!("Vial task has ended"),
         Either4::Third(_) => error!("Controller or led task has ended"),
         Either4::Fourth(_) => error!("Keyboard writer task has ended"),
     }
@@ -1,3 +1,5 @@
+pub mod color;
+
 use embassy_usb::class::hid::HidReader;
 use embassy_usb::driver::Driver;
 use rmk_types::led_indicator::LedIndicator;
@@ -24,3 +26,298 @@ impl<'d, D: Driver<'d>> HidReaderTrait for UsbLedReader<'_, 'd, D> {
         Ok(LedIndicator::from_bits(buf[0]))
     }
 }
+
+/// State of the RGB underglow, which can be changed by Vial's "Lighting" tab.
+///
+/// RMK only maintains and persists the state, the LEDs are driven by a [`Controller`](crate::controller::Controller)
+/// which receives `ControllerEvent::RgbLight` when the state changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RgbLightConfig {
+    /// Brightness, 0~255
+    pub brightness: u8,
+    /// Index of the lighting effect, 0 means off
+    pub effect: u8,
+    /// Speed of the lighting effect, 0~255
+    pub effect_speed: u8,
+    /// Hue, 0~255
+    pub hue: u8,
+    /// Saturation, 0~255
+    pub saturation: u8,
+}
+
+impl Default for RgbLightConfig {
+    fn default() -> Self {
+        Self {
+            brightness: 255,
+            effect: 1,
+            effect_speed: 0,
+            hue: 0,
+            saturation: 255,
+        }
+    }
+}
+
+/// State of the per-key RGB matrix, which can be changed by Vial's "Lighting" tab.
+///
+/// The LEDs are driven by [`RgbMatrixController`](crate::controller::rgb_matrix::RgbMatrixController), which
+/// receives `ControllerEvent::RgbMatrix` when the state changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RgbMatrixConfig {
+    /// Brightness, 0~255
+    pub brightness: u8,
+    /// Index of the lighting effect, see [`RgbMatrixEffect`]
+    pub effect: u8,
+    /// Speed of the lighting effect, 0~255
+    pub effect_speed: u8,
+    /// Hue, 0~255
+    pub hue: u8,
+    /// Saturation, 0~255
+    pub saturation: u8,
+}
+
+impl Default for RgbMatrixConfig {
+    fn default() -> Self {
+        Self {
+            brightness: 255,
+            effect: RgbMatrixEffect::Solid as u8,
+            effect_speed: 128,
+            hue: 0,
+            saturation: 255,
+        }
+    }
+}
+
+/// Effects of the RGB matrix
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum RgbMatrixEffect {
+    /// All LEDs are off
+    Off = 0,
+    /// All LEDs have the same color
+    Solid = 1,
+    /// The hue changes along the LEDs and moves with the effect speed
+    Gradient = 2,
+    /// The LED under a pressed key lights up and fades out, the effect speed sets how fast it fades
+    Reactive = 3,
+}
+
+impl RgbMatrixEffect {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(RgbMatrixEffect::Off),
+            1 => Some(RgbMatrixEffect::Solid),
+            2 => Some(RgbMatrixEffect::Gradient),
+            3 => Some(RgbMatrixEffect::Reactive),
+            _ => None,
+        }
+    }
+}
+
+/// Color of an LED
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// The trait for driving the RGB matrix, boards implement it for their LED chain, such as WS2812.
+///
+/// Use it with [`RgbMatrixController`](crate::controller::rgb_matrix::RgbMatrixController).
+pub trait RgbMatrixDriver {
+    /// Index of the LED under the key, `None` if the key has no LED
+    fn led_index(&self, row: u8, col: u8) -> Option<usize>;
+
+    /// Write the colors of all LEDs, ordered by the LED index.
+    ///
+    /// The colors are already gamma corrected by [`color::gamma_correct`].
+    async fn write(&mut self, colors: &[Rgb]);
+}
+
+/// Renders the frames of the RGB matrix effects with `LED_NUM` LEDs
+#[cfg(any(feature = "controller", test))]
+pub(crate) struct RgbMatrixFrame<const LED_NUM: usize> {
+    /// Brightness of the reactive effect of each LED, it's set to max when the key is pressed and fades out
+    heat: [u8; LED_NUM],
+    /// Hue offset of the gradient effect, it moves with the effect speed
+    gradient_offset: u8,
+    /// Gamma corrected colors of the last rendered frame
+    pub(crate) colors: [Rgb; LED_NUM],
+}
+
+#[cfg(any(feature = "controller", test))]
+impl<const LED_NUM: usize> RgbMatrixFrame<LED_NUM> {
+    pub(crate) fn new() -> Self {
+        Self {
+            heat: [0; LED_NUM],
+            gradient_offset: 0,
+            colors: [Rgb::default(); LED_NUM],
+        }
+    }
+
+    /// Light up the LED of a pressed key for the reactive effect
+    pub(crate) fn light_up(&mut self, led_index: usize) {
+        if let Some(heat) = self.heat.get_mut(led_index) {
+            *heat = u8::MAX;
+        }
+    }
+
+    /// Render the next frame into `colors`
+    pub(crate) fn render(&mut self, config: &RgbMatrixConfig) {
+        let RgbMatrixConfig {
+            brightness,
+            effect,
+            effect_speed,
+            hue,
+            saturation,
+        } = *config;
+        match RgbMatrixEffect::from_u8(effect) {
+            Some(RgbMatrixEffect::Solid) => self.colors.fill(color::hsv_to_rgb(hue, saturation, brightness)),
+            Some(RgbMatrixEffect::Gradient) => {
+                for (i, c) in self.colors.iter_mut().enumerate() {
+                    let offset = (i * 256 / LED_NUM) as u8;
+                    *c = color::hsv_to_rgb(
+                        hue.wrapping_add(offset).wrapping_add(self.gradient_offset),
+                        saturation,
+                        brightness,
+                    );
+                }
+                self.gradient_offset = self.gradient_offset.wrapping_add(1 + effect_speed / 32);
+            }
+            Some(RgbMatrixEffect::Reactive) => {
+                // It takes about 1s to fade out at the min speed, and about 0.1s at the max speed
+                let fade = 4 + effect_speed / 8;
+                for (c, heat) in self.colors.iter_mut().zip(self.heat.iter_mut()) {
+                    *c = color::hsv_to_rgb(hue, saturation, (*heat as u16 * brightness as u16 / 255) as u8);
+                    *heat = heat.saturating_sub(fade);
+                }
+            }
+            Some(RgbMatrixEffect::Off) | None => self.colors.fill(Rgb::default()),
+        }
+        for c in self.colors.iter_mut() {
+            *c = color::gamma_correct(*c);
+        }
+    }
+}
+
+/// State of the backlight, which can be changed by Vial's "Lighting" tab and backlight keycodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BacklightConfig {
+    /// Whether the backlight is on
+    pub enabled: bool,
+    /// Brightness, 0~255
+    pub brightness: u8,
+}
+
+impl Default for BacklightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            brightness: 255,
+        }
+    }
+}
+
+impl BacklightConfig {
+    /// The brightness that should be output, 0 if the backlight is off
+    pub fn output_brightness(&self) -> u8 {
+        if self.enabled { self.brightness } else { 0 }
+    }
+}
+
+/// The trait for driving the backlight, boards implement it for their PWM pin.
+///
+/// Use it with [`BacklightController`](crate::controller::backlight::BacklightController).
+pub trait BacklightDriver {
+    /// Set the output brightness, 0 means off and 255 means the max brightness
+    fn set_brightness(&mut self, brightness: u8);
+}
+
+#[cfg(test)]
+mod test {
+    use super::color::{gamma_correct, hsv_to_rgb};
+    use super::*;
+
+    fn config(effect: RgbMatrixEffect, effect_speed: u8) -> RgbMatrixConfig {
+        RgbMatrixConfig {
+            brightness: 200,
+            effect: effect as u8,
+            effect_speed,
+            hue: 85,
+            saturation: 255,
+        }
+    }
+
+    #[test]
+    fn test_render_solid() {
+        let mut frame = RgbMatrixFrame::<4>::new();
+        frame.render(&config(RgbMatrixEffect::Solid, 0));
+        assert_eq!(frame.colors, [gamma_correct(hsv_to_rgb(85, 255, 200)); 4]);
+
+        // Off and unknown effects turn all LEDs off
+        frame.render(&config(RgbMatrixEffect::Off, 0));
+        assert_eq!(frame.colors, [Rgb::default(); 4]);
+        frame.render(&RgbMatrixConfig {
+            effect: 0xFF,
+            ..config(RgbMatrixEffect::Solid, 0)
+        });
+        assert_eq!(frame.colors, [Rgb::default(); 4]);
+    }
+
+    #[test]
+    fn test_render_gradient() {
+        let mut frame = RgbMatrixFrame::<4>::new();
+        frame.render(&config(RgbMatrixEffect::Gradient, 64));
+        // The hue is spread over the LEDs
+        for (i, c) in frame.colors.iter().enumerate() {
+            assert_eq!(*c, gamma_correct(hsv_to_rgb(85u8.wrapping_add(64 * i as u8), 255, 200)));
+        }
+
+        // The gradient moves by `1 + effect_speed / 32` per frame
+        frame.render(&config(RgbMatrixEffect::Gradient, 64));
+        assert_eq!(frame.colors[0], gamma_correct(hsv_to_rgb(85 + 3, 255, 200)));
+        frame.render(&config(RgbMatrixEffect::Gradient, 255));
+        assert_eq!(frame.colors[0], gamma_correct(hsv_to_rgb(85 + 6, 255, 200)));
+    }
+
+    #[test]
+    fn test_render_reactive() {
+        let mut frame = RgbMatrixFrame::<3>::new();
+        frame.light_up(1);
+        // Out of range LEDs are ignored
+        frame.light_up(3);
+
+        frame.render(&config(RgbMatrixEffect::Reactive, 0));
+        assert_eq!(frame.colors[0], Rgb::default());
+        assert_eq!(frame.colors[1], gamma_correct(hsv_to_rgb(85, 255, 200)));
+        assert_eq!(frame.colors[2], Rgb::default());
+
+        // The LED fades out by `4 + effect_speed / 8` per frame: 251 * 200 / 255, then 247 * 200 / 255
+        frame.render(&config(RgbMatrixEffect::Reactive, 0));
+        assert_eq!(frame.colors[1], gamma_correct(hsv_to_rgb(85, 255, 196)));
+        frame.render(&config(RgbMatrixEffect::Reactive, 255));
+        assert_eq!(frame.colors[1], gamma_correct(hsv_to_rgb(85, 255, 193)));
+
+        // A faster effect fades out in fewer frames
+        let frames_to_fade_out = |speed| {
+            let mut frame = RgbMatrixFrame::<1>::new();
+            frame.light_up(0);
+            let mut n = 0;
+            loop {
+                frame.render(&config(RgbMatrixEffect::Reactive, speed));
+                if frame.colors[0] == Rgb::default() {
+                    return n;
+                }
+                n += 1;
+            }
+        };
+        assert!(frames_to_fade_out(255) < frames_to_fade_out(0));
+        // About 1s at the 60Hz frame rate of the controller
+        assert!(frames_to_fade_out(0) <= 64);
+    }
+}
@@ -0,0 +1,101 @@
+//! Color math for the lighting, shared by the RGB underglow and the RGB matrix.
+//!
+//! All functions are `no_std` and allocation free, so they can also be used in the controllers of the board.
+use super::Rgb;
+
+/// Gamma correction table, `round(255 * (i / 255) ^ 2.2)`.
+///
+/// The perceived brightness of an LED isn't linear to its PWM duty, without the correction, low brightness levels
+/// look too bright and the colors look washed out.
+#[rustfmt::skip]
+pub const GAMMA_TABLE: [u8; 256] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2,
+    3, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 6, 6, 6,
+    6, 7, 7, 7, 8, 8, 8, 9, 9, 9, 10, 10, 11, 11, 11, 12,
+    12, 13, 13, 13, 14, 14, 15, 15, 16, 16, 17, 17, 18, 18, 19, 19,
+    20, 20, 21, 22, 22, 23, 23, 24, 25, 25, 26, 26, 27, 28, 28, 29,
+    30, 30, 31, 32, 33, 33, 34, 35, 35, 36, 37, 38, 39, 39, 40, 41,
+    42, 43, 43, 44, 45, 46, 47, 48, 49, 49, 50, 51, 52, 53, 54, 55,
+    56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71,
+    73, 74, 75, 76, 77, 78, 79, 81, 82, 83, 84, 85, 87, 88, 89, 90,
+    91, 93, 94, 95, 97, 98, 99, 100, 102, 103, 105, 106, 107, 109, 110, 111,
+    113, 114, 116, 117, 119, 120, 121, 123, 124, 126, 127, 129, 130, 132, 133, 135,
+    137, 138, 140, 141, 143, 145, 146, 148, 149, 151, 153, 154, 156, 158, 159, 161,
+    163, 165, 166, 168, 170, 172, 173, 175, 177, 179, 181, 182, 184, 186, 188, 190,
+    192, 194, 196, 197, 199, 201, 203, 205, 207, 209, 211, 213, 215, 217, 219, 221,
+    223, 225, 227, 229, 231, 234, 236, 238, 240, 242, 244, 246, 248, 251, 253, 255,
+];
+
+/// Convert HSV to RGB, all components are 0~255, the hue wraps around at 255 like QMK
+pub const fn hsv_to_rgb(hue: u8, saturation: u8, value: u8) -> Rgb {
+    if saturation == 0 {
+        return Rgb {
+            r: value,
+            g: value,
+            b: value,
+        };
+    }
+    let region = hue / 43;
+    let remainder = (hue - region * 43) as u16 * 6;
+    let (s, v) = (saturation as u16, value as u16);
+    let p = ((v * (255 - s)) >> 8) as u8;
+    let q = ((v * (255 - ((s * remainder) >> 8))) >> 8) as u8;
+    let t = ((v * (255 - ((s * (255 - remainder)) >> 8))) >> 8) as u8;
+    let (r, g, b) = match region {
+        0 => (value, t, p),
+        1 => (q, value, p),
+        2 => (p, value, t),
+        3 => (p, q, value),
+        4 => (t, p, value),
+        _ => (value, p, q),
+    };
+    Rgb { r, g, b }
+}
+
+/// Apply the gamma correction to each component of the color
+pub const fn gamma_correct(color: Rgb) -> Rgb {
+    Rgb {
+        r: GAMMA_TABLE[color.r as usize],
+        g: GAMMA_TABLE[color.g as usize],
+        b: GAMMA_TABLE[color.b as usize],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rgb(r: u8, g: u8, b: u8) -> Rgb {
+        Rgb { r, g, b }
+    }
+
+    #[test]
+    fn test_hsv_to_rgb() {
+        // Primary colors
+        assert_eq!(hsv_to_rgb(0, 255, 255), rgb(255, 0, 0));
+        assert_eq!(hsv_to_rgb(85, 255, 255), rgb(3, 255, 0));
+        assert_eq!(hsv_to_rgb(170, 255, 255), rgb(0, 9, 255));
+        // Secondary colors
+        assert_eq!(hsv_to_rgb(43, 255, 255), rgb(254, 255, 0));
+        assert_eq!(hsv_to_rgb(128, 255, 255), rgb(0, 255, 252));
+        assert_eq!(hsv_to_rgb(213, 255, 255), rgb(246, 0, 255));
+        // The value scales the color
+        assert_eq!(hsv_to_rgb(0, 255, 128), rgb(128, 0, 0));
+        assert_eq!(hsv_to_rgb(0, 255, 0), rgb(0, 0, 0));
+        // No saturation is gray
+        assert_eq!(hsv_to_rgb(100, 0, 77), rgb(77, 77, 77));
+    }
+
+    #[test]
+    fn test_gamma_table() {
+        assert_eq!(GAMMA_TABLE[0], 0);
+        assert_eq!(GAMMA_TABLE[255], 255);
+        // Monotonic, and never brighter than the linear output
+        for i in 1..256 {
+            assert!(GAMMA_TABLE[i] >= GAMMA_TABLE[i - 1]);
+            assert!(GAMMA_TABLE[i] as usize <= i);
+        }
+        assert_eq!(gamma_correct(rgb(255, 128, 0)), rgb(255, 56, 0));
+    }
+}
@@ -1,16 +1,39 @@
 use core::future::Future;
 use core::sync::atomic::Ordering;
 
-use embassy_time::{Instant, Timer};
+use embassy_time::{Duration, Instant, Timer};
 use embedded_hal::digital::{InputPin, OutputPin};
 #[cfg(feature = "async_matrix")]
 use {embassy_futures::select::select_slice, embedded_hal_async::digital::Wait, heapless::Vec};
 
 use crate::CONNECTION_STATE;
+use crate::config::DiodeDirection;
+#[cfg(feature = "debounce_stats")]
+use crate::debounce::stats::record_debounce;
 use crate::debounce::{DebounceState, DebouncerTrait};
 use crate::event::{Event, KeyboardEvent};
 use crate::input_device::InputDevice;
-use crate::state::ConnectionState;
+use crate::state::{ConnectionState, IDLE_STATE};
+
+/// Interval between two matrix scans in idle mode
+pub(crate) const IDLE_SCAN_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Default interval between two scans of the matrices, which scan continuously
+pub(crate) const DEFAULT_SCAN_INTERVAL: Duration = Duration::MIN;
+
+/// Default interval between two scans of the direct pin matrices
+pub(crate) const DEFAULT_DIRECT_PIN_SCAN_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Wait for the next scan of the matrix.
+///
+/// A zero interval doesn't wait, but still yields to other tasks, so that the scanning never starves them.
+pub(crate) async fn wait_scan_interval(scan_interval: Duration) {
+    if scan_interval.as_ticks() == 0 {
+        embassy_futures::yield_now().await;
+    } else {
+        Timer::after(scan_interval).await;
+    }
+}
 
 /// Recording the matrix pressed state
 #[cfg(feature = "matrix_tester")]
@@ -22,7 +45,8 @@ pub struct MatrixState<const ROW: usize, const COL: usize> {
 
 #[cfg(feature = "matrix_tester")]
 impl<const ROW: usize, const COL: usize> MatrixState<ROW, COL> {
-    const ROW_LEN: usize = (COL + 8) / 8;
+    // Number of bytes of each row, same as QMK's `matrix_row_t`
+    const ROW_LEN: usize = COL.div_ceil(8);
     const OUT_OF_BOUNDARY: () = if ROW * Self::ROW_LEN > 30 {
         panic!(
             "Cannot use matrix tester because your keyboard has too many keys. \
@@ -30,6 +54,8 @@ impl<const ROW: usize, const COL: usize> MatrixState<ROW, COL> {
         )
     };
     pub fn new() -> Self {
+        // Associated consts are evaluated only when used, check the size at compile time here
+        let () = Self::OUT_OF_BOUNDARY;
         Self { state: [0; 30] }
     }
     pub fn update(&mut self, event: &crate::event::KeyboardEvent) {
@@ -128,6 +154,109 @@ impl KeyState {
     }
 }
 
+/// Core of the matrix scanning: debounces the raw pin states and produces the key events.
+///
+/// It doesn't touch any pin, the matrices read the pins and pass their states to it. So the scanning logic can be
+/// tested on the host with any raw pin states, see also `MockMatrix`.
+pub struct MatrixScanner<D: DebouncerTrait, const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize> {
+    /// Debouncer
+    debouncer: D,
+    /// Key state matrix
+    key_states: [[KeyState; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+    /// Diode direction, decides whether the output pins are columns or rows
+    diode_direction: DiodeDirection,
+    /// Offset added to the (row, col) of the key events, used by the split central
+    offset: (usize, usize),
+}
+
+impl<D: DebouncerTrait, const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize>
+    MatrixScanner<D, INPUT_PIN_NUM, OUTPUT_PIN_NUM>
+{
+    /// Create a scanner with all keys released
+    pub fn new(debouncer: D) -> Self {
+        Self {
+            debouncer,
+            key_states: [[KeyState::new(); INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+            diode_direction: DiodeDirection::default(),
+            offset: (0, 0),
+        }
+    }
+
+    /// Set the diode direction, the default direction is selected by the `col2row` feature
+    pub fn with_diode_direction(mut self, diode_direction: DiodeDirection) -> Self {
+        self.diode_direction = diode_direction;
+        self
+    }
+
+    /// Set the offset added to the (row, col) of the key events
+    pub fn with_offset(mut self, row_offset: usize, col_offset: usize) -> Self {
+        self.offset = (row_offset, col_offset);
+        self
+    }
+
+    /// Check the debounce window against the scan interval of the matrix, warn and return false if it's shorter.
+    ///
+    /// A key state change is only seen once per scan, so a shorter window is effectively one scan interval
+    pub(crate) fn check_scan_interval(&self, scan_interval: Duration) -> bool {
+        match self.debouncer.debounce_window() {
+            Some(window) if window < scan_interval => {
+                warn!(
+                    "Debounce window {}ms is shorter than the scan interval {}ms, the actual debounce time is one scan interval",
+                    window.as_millis(),
+                    scan_interval.as_millis()
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Debounce the raw state of the key at (in_idx, out_idx), returns the key event if the key is pressed or released
+    pub fn scan_key(&mut self, in_idx: usize, out_idx: usize, pin_state: bool) -> Option<KeyboardEvent> {
+        let (row, col) = self.row_col(in_idx, out_idx);
+        let key_state = &mut self.key_states[out_idx][in_idx];
+        let debounce_state = self
+            .debouncer
+            .detect_change_with_debounce(in_idx, out_idx, pin_state, key_state);
+        #[cfg(feature = "debounce_stats")]
+        record_debounce(row, col, &debounce_state, key_state.pressed);
+
+        if let DebounceState::Debounced = debounce_state {
+            key_state.toggle_pressed();
+            Some(KeyboardEvent::key(row, col, key_state.pressed))
+        } else {
+            None
+        }
+    }
+
+    /// Scan the raw states of all keys, `pin_states[out_idx][in_idx]`, and pass the key events to `on_event` in the
+    /// scanning order
+    pub fn scan(
+        &mut self,
+        pin_states: &[[bool; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+        mut on_event: impl FnMut(KeyboardEvent),
+    ) {
+        for (out_idx, states) in pin_states.iter().enumerate() {
+            for (in_idx, pin_state) in states.iter().enumerate() {
+                if let Some(event) = self.scan_key(in_idx, out_idx, *pin_state) {
+                    on_event(event);
+                }
+            }
+        }
+    }
+
+    /// Whether the key at (in_idx, out_idx) is pressed after debouncing
+    pub fn is_pressed(&self, in_idx: usize, out_idx: usize) -> bool {
+        self.key_states[out_idx][in_idx].pressed
+    }
+
+    /// (row, col) of the key event at (in_idx, out_idx), with the offset
+    fn row_col(&self, in_idx: usize, out_idx: usize) -> (u8, u8) {
+        let (row, col) = self.diode_direction.to_row_col(in_idx, out_idx);
+        ((row + self.offset.0) as u8, (col + self.offset.1) as u8)
+    }
+}
+
 /// Matrix is the physical pcb layout of the keyboard matrix.
 pub struct Matrix<
     #[cfg(feature = "async_matrix")] In: Wait + InputPin,
@@ -141,14 +270,14 @@ pub struct Matrix<
     input_pins: [In; INPUT_PIN_NUM],
     /// Output pins of the pcb matrix
     output_pins: [Out; OUTPUT_PIN_NUM],
-    /// Debouncer
-    debouncer: D,
-    /// Key state matrix
-    key_states: [[KeyState; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+    /// Debounces the pin states and produces the key events
+    scanner: MatrixScanner<D, INPUT_PIN_NUM, OUTPUT_PIN_NUM>,
     /// Start scanning
     scan_start: Option<Instant>,
     /// Current scan pos: (out_idx, in_idx)
     scan_pos: (usize, usize),
+    /// Interval between two scans when the keyboard isn't idle
+    scan_interval: Duration,
 }
 
 impl<
@@ -165,12 +294,30 @@ impl<
         Matrix {
             input_pins,
             output_pins,
-            debouncer,
-            key_states: [[KeyState::new(); INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+            scanner: MatrixScanner::new(debouncer),
             scan_start: None,
             scan_pos: (0, 0),
+            scan_interval: DEFAULT_SCAN_INTERVAL,
         }
     }
+
+    /// Set the diode direction, the default direction is selected by the `col2row` feature.
+    ///
+    /// The output pins are always driven, so for a `Row2Col` matrix, pass the row pins as output pins.
+    /// Note that `MatrixTrait::ROW` and `MatrixTrait::COL` always follow the `col2row` feature.
+    pub fn with_diode_direction(mut self, diode_direction: DiodeDirection) -> Self {
+        self.scanner = self.scanner.with_diode_direction(diode_direction);
+        self
+    }
+
+    /// Set the interval between two scans, the default is 0, which scans the matrix continuously.
+    ///
+    /// In idle mode, the matrix is scanned every 20ms, or every `scan_interval` if it's longer.
+    pub fn with_scan_interval(mut self, scan_interval: Duration) -> Self {
+        self.scanner.check_scan_interval(scan_interval);
+        self.scan_interval = scan_interval;
+        self
+    }
 }
 
 impl<
@@ -198,27 +345,15 @@ impl<
                 for in_idx in in_idx_start..self.input_pins.len() {
                     let in_pin = self.input_pins.get_mut(in_idx).unwrap();
                     // Check input pins and debounce
-                    let debounce_state = self.debouncer.detect_change_with_debounce(
-                        in_idx,
-                        out_idx,
-                        in_pin.is_high().ok().unwrap_or_default(),
-                        &self.key_states[out_idx][in_idx],
-                    );
-
-                    if let DebounceState::Debounced = debounce_state {
-                        self.key_states[out_idx][in_idx].toggle_pressed();
-                        #[cfg(feature = "col2row")]
-                        let (row, col, key_state) = (in_idx, out_idx, self.key_states[out_idx][in_idx]);
-                        #[cfg(not(feature = "col2row"))]
-                        let (row, col, key_state) = (out_idx, in_idx, self.key_states[out_idx][in_idx]);
-
+                    let pin_state = in_pin.is_high().ok().unwrap_or_default();
+                    if let Some(event) = self.scanner.scan_key(in_idx, out_idx, pin_state) {
                         self.scan_pos = (out_idx, in_idx);
-                        return Event::Key(KeyboardEvent::key(row as u8, col as u8, key_state.pressed));
+                        return Event::Key(event);
                     }
 
                     // If there's key still pressed, always refresh the self.scan_start
                     #[cfg(feature = "async_matrix")]
-                    if self.key_states[out_idx][in_idx].pressed {
+                    if self.scanner.is_pressed(in_idx, out_idx) {
                         self.scan_start = Some(Instant::now());
                     }
                 }
@@ -229,6 +364,13 @@ impl<
                 }
             }
             self.scan_pos = (0, 0);
+
+            // Scan at a slow rate in idle mode
+            if IDLE_STATE.load(Ordering::Acquire) {
+                Timer::after(IDLE_SCAN_INTERVAL.max(self.scan_interval)).await;
+            } else {
+                wait_scan_interval(self.scan_interval).await;
+            }
         }
     }
 }
@@ -320,3 +462,274 @@ impl<const ROW: usize, const COL: usize> InputDevice for TestMatrix<ROW, COL> {
         Event::Key(KeyboardEvent::key(0, 0, self.last))
     }
 }
+
+/// Matrix with simulated pins, used to test the scanning logic on the host without hardware.
+///
+/// Set the raw state of the keys by `set_key`, `read_event` scans them every 1ms like a real matrix.
+#[cfg(any(test, feature = "std"))]
+pub struct MockMatrix<D: DebouncerTrait, const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize> {
+    scanner: MatrixScanner<D, INPUT_PIN_NUM, OUTPUT_PIN_NUM>,
+    /// Raw state of the pins, `pin_states[out_idx][in_idx]`
+    pin_states: [[bool; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+}
+
+#[cfg(any(test, feature = "std"))]
+impl<D: DebouncerTrait, const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize>
+    MockMatrix<D, INPUT_PIN_NUM, OUTPUT_PIN_NUM>
+{
+    /// Create a mock matrix with all keys released
+    pub fn new(scanner: MatrixScanner<D, INPUT_PIN_NUM, OUTPUT_PIN_NUM>) -> Self {
+        Self {
+            scanner,
+            pin_states: [[false; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+        }
+    }
+
+    /// Set the raw state of the key at (row, col), without the offset of the scanner
+    pub fn set_key(&mut self, row: usize, col: usize, pressed: bool) {
+        let (in_idx, out_idx) = match self.scanner.diode_direction {
+            DiodeDirection::Col2Row => (row, col),
+            DiodeDirection::Row2Col => (col, row),
+        };
+        self.pin_states[out_idx][in_idx] = pressed;
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl<D: DebouncerTrait, const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize> MatrixTrait
+    for MockMatrix<D, INPUT_PIN_NUM, OUTPUT_PIN_NUM>
+{
+    #[cfg(feature = "col2row")]
+    const ROW: usize = INPUT_PIN_NUM;
+    #[cfg(feature = "col2row")]
+    const COL: usize = OUTPUT_PIN_NUM;
+    #[cfg(not(feature = "col2row"))]
+    const ROW: usize = OUTPUT_PIN_NUM;
+    #[cfg(not(feature = "col2row"))]
+    const COL: usize = INPUT_PIN_NUM;
+
+    #[cfg(feature = "async_matrix")]
+    fn wait_for_key(&mut self) -> impl Future<Output = ()> {
+        async {}
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+impl<D: DebouncerTrait, const INPUT_PIN_NUM: usize, const OUTPUT_PIN_NUM: usize> InputDevice
+    for MockMatrix<D, INPUT_PIN_NUM, OUTPUT_PIN_NUM>
+{
+    async fn read_event(&mut self) -> Event {
+        loop {
+            for out_idx in 0..OUTPUT_PIN_NUM {
+                for in_idx in 0..INPUT_PIN_NUM {
+                    let pin_state = self.pin_states[out_idx][in_idx];
+                    if let Some(event) = self.scanner.scan_key(in_idx, out_idx, pin_state) {
+                        return Event::Key(event);
+                    }
+                }
+            }
+            Timer::after_millis(1).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use embassy_futures::block_on;
+    use embassy_time::Duration;
+
+    #[cfg(feature = "matrix_tester")]
+    use super::MatrixState;
+    use super::*;
+    use crate::config::{DebounceConfig, DebounceMode};
+    use crate::debounce::configurable_debouncer::ConfigurableDebouncer;
+    use crate::event::{KeyPos, KeyboardEventPos};
+
+    /// Reports every change of the pin state immediately
+    struct NoDebouncer;
+
+    impl DebouncerTrait for NoDebouncer {
+        fn detect_change_with_debounce(
+            &mut self,
+            _in_idx: usize,
+            _out_idx: usize,
+            pin_state: bool,
+            key_state: &KeyState,
+        ) -> DebounceState {
+            if pin_state != key_state.pressed {
+                DebounceState::Debounced
+            } else {
+                DebounceState::Ignored
+            }
+        }
+    }
+
+    /// A pin which is always low
+    struct LowPin;
+
+    impl embedded_hal::digital::ErrorType for LowPin {
+        type Error = core::convert::Infallible;
+    }
+
+    impl InputPin for LowPin {
+        fn is_high(&mut self) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+    }
+
+    impl OutputPin for LowPin {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    /// (row, col, pressed) of the key events of a scan
+    fn scan<D: DebouncerTrait, const IN: usize, const OUT: usize>(
+        scanner: &mut MatrixScanner<D, IN, OUT>,
+        pin_states: &[[bool; IN]; OUT],
+    ) -> std::vec::Vec<(u8, u8, bool)> {
+        let mut events = std::vec::Vec::new();
+        scanner.scan(pin_states, |e| {
+            let KeyboardEventPos::Key(KeyPos { row, col }) = e.pos else {
+                panic!("Unexpected event: {:?}", e);
+            };
+            events.push((row, col, e.pressed));
+        });
+        events
+    }
+
+    #[cfg(not(feature = "async_matrix"))]
+    #[test]
+    fn test_matrix_scan_interval() {
+        // The matrix scans continuously by default
+        let matrix = Matrix::<_, _, _, 2, 1>::new([LowPin, LowPin], [LowPin], NoDebouncer);
+        assert_eq!(matrix.scan_interval, Duration::MIN);
+        let matrix = matrix.with_scan_interval(Duration::from_millis(1));
+        assert_eq!(matrix.scan_interval, Duration::from_millis(1));
+    }
+
+    #[test]
+    fn test_scanner_check_scan_interval() {
+        let debouncer = ConfigurableDebouncer::<1, 1>::new(DebounceConfig {
+            window: Duration::from_millis(5),
+            ..Default::default()
+        });
+        let scanner = MatrixScanner::<_, 1, 1>::new(debouncer);
+        assert!(scanner.check_scan_interval(Duration::MIN));
+        assert!(scanner.check_scan_interval(Duration::from_millis(5)));
+        assert!(!scanner.check_scan_interval(Duration::from_millis(10)));
+
+        // Debouncers without a window accept any interval
+        let scanner = MatrixScanner::<_, 1, 1>::new(NoDebouncer);
+        assert!(scanner.check_scan_interval(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_scanner_diode_direction() {
+        // 2 input pins, 3 output pins
+        let mut pin_states = [[false; 2]; 3];
+        pin_states[2][1] = true;
+
+        let mut scanner = MatrixScanner::<_, 2, 3>::new(NoDebouncer).with_diode_direction(DiodeDirection::Col2Row);
+        assert_eq!(scan(&mut scanner, &pin_states), [(1, 2, true)]);
+        let mut scanner = MatrixScanner::<_, 2, 3>::new(NoDebouncer).with_diode_direction(DiodeDirection::Row2Col);
+        assert_eq!(scan(&mut scanner, &pin_states), [(2, 1, true)]);
+
+        // Offset of the split central
+        let mut scanner = MatrixScanner::<_, 2, 3>::new(NoDebouncer)
+            .with_diode_direction(DiodeDirection::Col2Row)
+            .with_offset(4, 5);
+        assert_eq!(scan(&mut scanner, &pin_states), [(5, 7, true)]);
+    }
+
+    #[test]
+    fn test_scanner_events() {
+        let mut scanner = MatrixScanner::<_, 2, 2>::new(NoDebouncer).with_diode_direction(DiodeDirection::Col2Row);
+        // Events are produced in the scanning order, only for the changed keys
+        assert_eq!(
+            scan(&mut scanner, &[[true, true], [false, true]]),
+            [(0, 0, true), (1, 0, true), (1, 1, true)]
+        );
+        assert!(scanner.is_pressed(1, 1));
+        assert!(scan(&mut scanner, &[[true, true], [false, true]]).is_empty());
+        assert_eq!(scan(&mut scanner, &[[true, false], [false, true]]), [(1, 0, false)]);
+        assert!(!scanner.is_pressed(1, 0));
+    }
+
+    #[test]
+    fn test_scanner_eager_debounce() {
+        let debouncer = ConfigurableDebouncer::<1, 1>::new(DebounceConfig {
+            mode: DebounceMode::Eager,
+            window: Duration::from_secs(10),
+            ..Default::default()
+        });
+        let mut scanner = MatrixScanner::new(debouncer).with_diode_direction(DiodeDirection::Col2Row);
+        // The first edge is reported, the bounces in the window are ignored
+        assert_eq!(scan(&mut scanner, &[[true]]), [(0, 0, true)]);
+        assert!(scan(&mut scanner, &[[false]]).is_empty());
+        assert!(scan(&mut scanner, &[[true]]).is_empty());
+        assert!(scanner.is_pressed(0, 0));
+    }
+
+    #[test]
+    fn test_mock_matrix() {
+        let scanner = MatrixScanner::<_, 2, 3>::new(NoDebouncer).with_diode_direction(DiodeDirection::Row2Col);
+        let mut matrix = MockMatrix::new(scanner);
+        block_on(async {
+            matrix.set_key(2, 1, true);
+            let Event::Key(event) = matrix.read_event().await else {
+                panic!("Unexpected event");
+            };
+            assert_eq!(event.pos, KeyboardEventPos::Key(KeyPos { row: 2, col: 1 }));
+            assert!(event.pressed);
+
+            matrix.set_key(2, 1, false);
+            let Event::Key(event) = matrix.read_event().await else {
+                panic!("Unexpected event");
+            };
+            assert_eq!(event.pos, KeyboardEventPos::Key(KeyPos { row: 2, col: 1 }));
+            assert!(!event.pressed);
+        });
+    }
+
+    #[cfg(feature = "matrix_tester")]
+    #[test]
+    fn test_matrix_state_read_all() {
+        let mut state: MatrixState<2, 10> = MatrixState::new();
+        state.update(&KeyboardEvent::key(0, 0, true));
+        state.update(&KeyboardEvent::key(0, 9, true));
+        state.update(&KeyboardEvent::key(1, 3, true));
+        assert!(state.read(0, 9));
+        assert!(!state.read(1, 9));
+
+        // Each row takes 2 bytes, in big endian
+        let mut report = [0xFF; 6];
+        state.read_all(&mut report);
+        assert_eq!(report, [0b10, 0b1, 0, 0b1000, 0xFF, 0xFF]);
+
+        state.update(&KeyboardEvent::key(0, 9, false));
+        state.read_all(&mut report);
+        assert_eq!(report[..4], [0, 0b1, 0, 0b1000]);
+    }
+
+    #[cfg(feature = "matrix_tester")]
+    #[test]
+    fn test_matrix_state_full_byte_row() {
+        // 8 cols fit in exactly one byte per row
+        let mut state: MatrixState<3, 8> = MatrixState::new();
+        state.update(&KeyboardEvent::key(1, 7, true));
+        state.update(&KeyboardEvent::key(2, 0, true));
+
+        let mut report = [0; 3];
+        state.read_all(&mut report);
+        assert_eq!(report, [0, 0b1000_0000, 0b1]);
+    }
+}
@@ -0,0 +1,215 @@
+use core::sync::atomic::Ordering;
+
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal::digital::InputPin;
+use embedded_hal::spi::SpiDevice;
+#[cfg(feature = "async_matrix")]
+use {embassy_futures::select::select_slice, embedded_hal_async::digital::Wait, heapless::Vec};
+
+use crate::config::DiodeDirection;
+use crate::debounce::DebouncerTrait;
+use crate::event::Event;
+use crate::input_device::InputDevice;
+use crate::matrix::{DEFAULT_SCAN_INTERVAL, IDLE_SCAN_INTERVAL, MatrixScanner, MatrixTrait, wait_scan_interval};
+use crate::state::IDLE_STATE;
+
+/// Max number of bytes of the chained shift registers, which is 32 outputs
+const MAX_SHIFT_REGISTER_BYTES: usize = 4;
+
+/// Matrix whose output pins are driven by chained 74HC595-style shift registers.
+///
+/// The shift registers are written over SPI: SCK is the shift clock, MOSI is the serial data
+/// and CS is the latch, so that the outputs are updated when the CS is released after each transfer.
+/// Output 0~7 are `QA`~`QH` of the first shift register, output 8 is `QA` of the second one, etc.
+///
+/// The selected output is set to high, input pins should be pulled down, same as [`crate::matrix::Matrix`].
+pub struct ShiftRegisterMatrix<
+    #[cfg(feature = "async_matrix")] In: Wait + InputPin,
+    #[cfg(not(feature = "async_matrix"))] In: InputPin,
+    S: SpiDevice,
+    D: DebouncerTrait,
+    const INPUT_PIN_NUM: usize,
+    const OUTPUT_PIN_NUM: usize,
+> {
+    /// Input pins of the pcb matrix
+    input_pins: [In; INPUT_PIN_NUM],
+    /// SPI device which writes the shift registers
+    spi: S,
+    /// Time to wait after an output is selected, before reading the input pins
+    select_delay: Duration,
+    /// Debounces the pin states and produces the key events
+    scanner: MatrixScanner<D, INPUT_PIN_NUM, OUTPUT_PIN_NUM>,
+    /// Start scanning
+    scan_start: Option<Instant>,
+    /// Current scan pos: (out_idx, in_idx)
+    scan_pos: (usize, usize),
+    /// Interval between two scans when the keyboard isn't idle
+    scan_interval: Duration,
+}
+
+impl<
+    #[cfg(not(feature = "async_matrix"))] In: InputPin,
+    #[cfg(feature = "async_matrix")] In: Wait + InputPin,
+    S: SpiDevice,
+    D: DebouncerTrait,
+    const INPUT_PIN_NUM: usize,
+    const OUTPUT_PIN_NUM: usize,
+> ShiftRegisterMatrix<In, S, D, INPUT_PIN_NUM, OUTPUT_PIN_NUM>
+{
+    const BYTES: usize = OUTPUT_PIN_NUM.div_ceil(8);
+    const OUT_OF_BOUNDARY: () = if Self::BYTES > MAX_SHIFT_REGISTER_BYTES {
+        panic!("ShiftRegisterMatrix supports at most 32 outputs")
+    };
+
+    /// Create a matrix from input pins and the SPI device of shift registers.
+    ///
+    /// `select_delay` is the time waiting for the selected output to settle, 1us is enough for most boards.
+    /// Increase it if there are ghost keys at the next output.
+    pub fn new(input_pins: [In; INPUT_PIN_NUM], spi: S, debouncer: D, select_delay: Duration) -> Self {
+        // Associated consts are evaluated only when used, check the size at compile time here
+        let () = Self::OUT_OF_BOUNDARY;
+        ShiftRegisterMatrix {
+            input_pins,
+            spi,
+            select_delay,
+            scanner: MatrixScanner::new(debouncer),
+            scan_start: None,
+            scan_pos: (0, 0),
+            scan_interval: DEFAULT_SCAN_INTERVAL,
+        }
+    }
+
+    /// Set the diode direction, the default direction is selected by the `col2row` feature
+    pub fn with_diode_direction(mut self, diode_direction: DiodeDirection) -> Self {
+        self.scanner = self.scanner.with_diode_direction(diode_direction);
+        self
+    }
+
+    /// Set the interval between two scans, the default is 0, which scans the matrix continuously.
+    ///
+    /// In idle mode, the matrix is scanned every 20ms, or every `scan_interval` if it's longer.
+    pub fn with_scan_interval(mut self, scan_interval: Duration) -> Self {
+        self.scanner.check_scan_interval(scan_interval);
+        self.scan_interval = scan_interval;
+        self
+    }
+
+    /// Write the shift registers, set the output at `out_idx` to high and others to low.
+    /// All outputs are set to low if `out_idx` is `None`
+    fn select(&mut self, out_idx: Option<usize>) {
+        let mut buf = [0u8; MAX_SHIFT_REGISTER_BYTES];
+        if let Some(out_idx) = out_idx {
+            // The first byte is shifted to the last register
+            buf[Self::BYTES - 1 - out_idx / 8] = 1 << (out_idx % 8);
+        }
+        self.write(&buf[..Self::BYTES]);
+    }
+
+    fn write(&mut self, buf: &[u8]) {
+        if let Err(e) = self.spi.write(buf) {
+            #[cfg(feature = "defmt")]
+            let e = defmt::Debug2Format(&e);
+            error!("Write shift register error: {:?}", e);
+        }
+    }
+}
+
+impl<
+    #[cfg(not(feature = "async_matrix"))] In: InputPin,
+    #[cfg(feature = "async_matrix")] In: Wait + InputPin,
+    S: SpiDevice,
+    D: DebouncerTrait,
+    const INPUT_PIN_NUM: usize,
+    const OUTPUT_PIN_NUM: usize,
+> InputDevice for ShiftRegisterMatrix<In, S, D, INPUT_PIN_NUM, OUTPUT_PIN_NUM>
+{
+    async fn read_event(&mut self) -> Event {
+        loop {
+            let (out_idx_start, in_idx_start) = self.scan_pos;
+            #[cfg(feature = "async_matrix")]
+            self.wait_for_key().await;
+
+            // Scan matrix and send report
+            for out_idx in out_idx_start..OUTPUT_PIN_NUM {
+                // Select the output, wait for the change comes into effect
+                self.select(Some(out_idx));
+                Timer::after(self.select_delay).await;
+                let in_idx_start = if out_idx == out_idx_start { in_idx_start } else { 0 };
+                for in_idx in in_idx_start..INPUT_PIN_NUM {
+                    let in_pin = self.input_pins.get_mut(in_idx).unwrap();
+                    // Check input pins and debounce
+                    let pin_state = in_pin.is_high().ok().unwrap_or_default();
+                    if let Some(event) = self.scanner.scan_key(in_idx, out_idx, pin_state) {
+                        self.scan_pos = (out_idx, in_idx);
+                        return Event::Key(event);
+                    }
+
+                    // If there's key still pressed, always refresh the self.scan_start
+                    #[cfg(feature = "async_matrix")]
+                    if self.scanner.is_pressed(in_idx, out_idx) {
+                        self.scan_start = Some(Instant::now());
+                    }
+                }
+            }
+            // Deselect all outputs
+            self.select(None);
+            self.scan_pos = (0, 0);
+
+            // Scan at a slow rate in idle mode
+            if IDLE_STATE.load(Ordering::Acquire) {
+                Timer::after(IDLE_SCAN_INTERVAL.max(self.scan_interval)).await;
+            } else {
+                wait_scan_interval(self.scan_interval).await;
+            }
+        }
+    }
+}
+
+impl<
+    #[cfg(not(feature = "async_matrix"))] In: InputPin,
+    #[cfg(feature = "async_matrix")] In: Wait + InputPin,
+    S: SpiDevice,
+    D: DebouncerTrait,
+    const INPUT_PIN_NUM: usize,
+    const OUTPUT_PIN_NUM: usize,
+> MatrixTrait for ShiftRegisterMatrix<In, S, D, INPUT_PIN_NUM, OUTPUT_PIN_NUM>
+{
+    #[cfg(feature = "col2row")]
+    const ROW: usize = INPUT_PIN_NUM;
+    #[cfg(feature = "col2row")]
+    const COL: usize = OUTPUT_PIN_NUM;
+    #[cfg(not(feature = "col2row"))]
+    const ROW: usize = OUTPUT_PIN_NUM;
+    #[cfg(not(feature = "col2row"))]
+    const COL: usize = INPUT_PIN_NUM;
+
+    #[cfg(feature = "async_matrix")]
+    async fn wait_for_key(&mut self) {
+        use core::pin::pin;
+
+        if let Some(start_time) = self.scan_start {
+            // If no key press over 1ms, stop scanning and wait for interupt
+            if start_time.elapsed().as_millis() <= 1 {
+                return;
+            } else {
+                self.scan_start = None;
+            }
+        }
+        // First, set all outputs to high
+        self.write(&[0xFF; MAX_SHIFT_REGISTER_BYTES][..Self::BYTES]);
+        Timer::after(self.select_delay).await;
+        {
+            let mut futs: Vec<_, INPUT_PIN_NUM> = self
+                .input_pins
+                .iter_mut()
+                .map(|input_pin| input_pin.wait_for_high())
+                .collect();
+            let _ = select_slice(pin!(futs.as_mut_slice())).await;
+        }
+
+        // Set all outputs back to low
+        self.select(None);
+
+        self.scan_start = Some(Instant::now());
+    }
+}
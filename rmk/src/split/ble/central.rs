@@ -2,9 +2,9 @@ use core::sync::atomic::Ordering;
 
 use bt_hci::cmd::le::{LeReadLocalSupportedFeatures, LeSetPhy, LeSetScanParams};
 use bt_hci::controller::{ControllerCmdAsync, ControllerCmdSync};
-use embassy_futures::select::{Either, Either3, select, select3};
+use embassy_futures::select::{Either, Either4, select, select4};
 use embassy_sync::signal::Signal;
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Timer, with_timeout};
 use embedded_storage_async::nor_flash::NorFlash;
 use trouble_host::prelude::*;
 #[cfg(feature = "controller")]
@@ -13,6 +13,7 @@ use {
     crate::event::ControllerEvent,
 };
 
+use crate::ble::rssi::{PERIPHERAL_RSSI, RssiController, run_rssi_monitor};
 use crate::ble::{SLEEPING_STATE, update_ble_phy, update_conn_params};
 use crate::channel::FLASH_CHANNEL;
 #[cfg(feature = "storage")]
@@ -32,6 +33,26 @@ pub(crate) static PERIPHERAL_FOUND: Signal<crate::RawMutex, (u8, BdAddr)> = Sign
 /// - `signal(false)`: Indicates activity detected, wake up or reset sleep timer
 pub(crate) static CENTRAL_SLEEP: Signal<crate::RawMutex, bool> = Signal::new();
 
+/// Timeout of a single connection attempt to a peripheral.
+///
+/// Only one connection can be initiated at a time, so the attempt is restarted periodically,
+/// otherwise a powered-off peripheral would block the reconnection of other peripherals forever.
+const CONNECT_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Connection parameters used when the central is active.
+///
+/// The high latency lets an idle peripheral skip most connection events to save power, the supervision timeout
+/// should be larger than `(1 + max_latency) * connection_interval * 2`.
+fn active_conn_params() -> ConnectParams {
+    ConnectParams {
+        min_connection_interval: Duration::from_micros(7500),
+        max_connection_interval: Duration::from_micros(7500),
+        max_latency: 400, // 3s
+        supervision_timeout: Duration::from_secs(7),
+        ..Default::default()
+    }
+}
+
 /// Gatt service used in split central to send split message to peripheral
 #[gatt_service(uuid = "4dd5fbaa-18e5-4b07-bf0a-353698659946")]
 struct SplitBleCentralService {
@@ -110,13 +131,14 @@ pub(crate) async fn run_ble_peripheral_manager<
     C: Controller
         + ControllerCmdSync<LeSetScanParams>
         + ControllerCmdAsync<LeSetPhy>
-        + ControllerCmdSync<LeReadLocalSupportedFeatures>,
+        + ControllerCmdSync<LeReadLocalSupportedFeatures>
+        + RssiController,
     const ROW: usize,
     const COL: usize,
-    const ROW_OFFSET: usize,
-    const COL_OFFSET: usize,
 >(
     peripheral_id: usize,
+    row_offset: usize,
+    col_offset: usize,
     addr: Option<[u8; 6]>,
     stack: &'a Stack<'a, C, DefaultPacketPool>,
 ) {
@@ -163,15 +185,13 @@ pub(crate) async fn run_ble_peripheral_manager<
     let Host { mut central, .. } = stack.build();
     info!("Peripheral peer address: {:?}", address);
     let config = ConnectConfig {
-        connect_params: ConnectParams {
-            min_connection_interval: Duration::from_micros(7500),
-            max_connection_interval: Duration::from_micros(7500),
-            max_latency: 400, // 3s
-            supervision_timeout: Duration::from_secs(7),
-            ..Default::default()
-        },
+        connect_params: active_conn_params(),
+        // Scan half of the time, a reset peripheral advertises every 20ms so it's still re-acquired within one
+        // scan interval, while the radio of the central is idle for the other half
         scan_config: ScanConfig {
             filter_accept_list: &[(address.kind, &address.addr)],
+            interval: Duration::from_millis(60),
+            window: Duration::from_millis(30),
             ..Default::default()
         },
     };
@@ -187,8 +207,10 @@ pub(crate) async fn run_ble_peripheral_manager<
             ControllerEvent::SplitPeripheral(peripheral_id, false),
         );
         info!("Connecting peripheral");
-        if let Err(e) = connect_and_run_peripheral_manager::<_, _, ROW, COL, ROW_OFFSET, COL_OFFSET>(
+        if let Err(e) = connect_and_run_peripheral_manager::<_, _, ROW, COL>(
             peripheral_id,
+            row_offset,
+            col_offset,
             stack,
             &mut central,
             &config,
@@ -200,28 +222,37 @@ pub(crate) async fn run_ble_peripheral_manager<
             #[cfg(feature = "defmt")]
             let e = defmt::Debug2Format(&e);
             error!("BLE central error: {:?}", e);
-            // Reconnect after 500ms
-            embassy_time::Timer::after_millis(500).await;
+            // Reconnect after 100ms
+            embassy_time::Timer::after_millis(100).await;
         }
     }
 }
 
 async fn connect_and_run_peripheral_manager<
     'a,
-    C: Controller + ControllerCmdAsync<LeSetPhy> + ControllerCmdSync<LeReadLocalSupportedFeatures>,
+    C: Controller + ControllerCmdAsync<LeSetPhy> + ControllerCmdSync<LeReadLocalSupportedFeatures> + RssiController,
     P: PacketPool,
     const ROW: usize,
     const COL: usize,
-    const ROW_OFFSET: usize,
-    const COL_OFFSET: usize,
 >(
     id: usize,
+    row_offset: usize,
+    col_offset: usize,
     stack: &'a Stack<'a, C, P>,
     central: &mut Central<'a, C, P>,
     config: &ConnectConfig<'_>,
     #[cfg(feature = "controller")] controller_pub: &mut ControllerPub,
 ) -> Result<(), BleHostError<C::Error>> {
-    let conn = central.connect(config).await?;
+    let conn = loop {
+        match with_timeout(CONNECT_ATTEMPT_TIMEOUT, central.connect(config)).await {
+            Ok(conn) => break conn?,
+            Err(_) => {
+                // Give other peripherals a chance to connect
+                trace!("Peripheral {} isn't found, restart connecting", id);
+                Timer::after_millis(10).await;
+            }
+        }
+    };
 
     info!("Connected to peripheral");
 
@@ -234,29 +265,20 @@ async fn connect_and_run_peripheral_manager<
     update_ble_phy(stack, &conn).await;
 
     info!("Updating connection parameters for peripheral");
-    update_conn_params(
-        stack,
-        &conn,
-        &ConnectParams {
-            min_connection_interval: Duration::from_micros(7500),
-            max_connection_interval: Duration::from_micros(7500),
-            max_latency: 400, // 3s
-            supervision_timeout: Duration::from_secs(7),
-            ..Default::default()
-        },
-    )
-    .await;
+    update_conn_params(stack, &conn, &active_conn_params()).await;
 
-    match select3(
+    match select4(
         ble_central_task(&client, &conn),
-        run_peripheral_manager::<_, _, ROW, COL, ROW_OFFSET, COL_OFFSET>(id, &client),
+        run_peripheral_manager::<_, _, ROW, COL>(id, row_offset, col_offset, &client),
         sleep_manager_task(stack, &conn),
+        run_rssi_monitor(stack, &conn, &PERIPHERAL_RSSI[id]),
     )
     .await
     {
-        Either3::First(e) => e,
-        Either3::Second(e) => e,
-        Either3::Third(e) => e,
+        Either4::First(e) => e,
+        Either4::Second(e) => e,
+        Either4::Third(e) => e,
+        Either4::Fourth(_) => Ok(()),
     }
 }
 
@@ -264,10 +286,10 @@ async fn ble_central_task<'a, C: Controller + ControllerCmdAsync<LeSetPhy>, P: P
     client: &GattClient<'a, C, P, 10>,
     conn: &Connection<'a, P>,
 ) -> Result<(), BleHostError<C::Error>> {
-    // Simply monitor connection status
+    // Simply monitor connection status, check it frequently to reconnect the peripheral quickly
     let conn_check = async {
         while conn.is_connected() {
-            Timer::after_secs(5).await;
+            Timer::after_millis(100).await;
         }
     };
 
@@ -286,10 +308,10 @@ async fn run_peripheral_manager<
     P: PacketPool,
     const ROW: usize,
     const COL: usize,
-    const ROW_OFFSET: usize,
-    const COL_OFFSET: usize,
 >(
     id: usize,
+    row_offset: usize,
+    col_offset: usize,
     client: &GattClient<'a, C, P, 10>,
 ) -> Result<(), BleHostError<C::Error>> {
     let services = client
@@ -323,7 +345,8 @@ async fn run_peripheral_manager<
         info!("Subscribing notifications");
         let listener = client.subscribe(&message_to_central, false).await?;
         let split_ble_driver = BleSplitCentralDriver::new(listener, message_to_peripheral, client);
-        let peripheral_manager = PeripheralManager::<ROW, COL, ROW_OFFSET, COL_OFFSET, _>::new(split_ble_driver, id);
+        let mut peripheral_manager =
+            PeripheralManager::<ROW, COL, _>::new(split_ble_driver, id, row_offset, col_offset);
         peripheral_manager.run().await;
         info!("Peripheral manager stopped");
     };
@@ -372,7 +395,7 @@ impl<'a, 'b, 'c, C: Controller + ControllerCmdAsync<LeSetPhy>, P: PacketPool> Sp
 
         // Update last activity time when receiving key events from peripheral
         match &message {
-            SplitMessage::Key(_) => {
+            SplitMessage::Key { .. } | SplitMessage::ResolvedKey { .. } => {
                 debug!("Key activity detected from peripheral");
                 update_activity_time();
             }
@@ -509,18 +532,7 @@ async fn sleep_manager_task<
                 SLEEPING_STATE.store(false, Ordering::Release);
 
                 // Restore normal connection parameters
-                update_conn_params(
-                    stack,
-                    conn,
-                    &ConnectParams {
-                        min_connection_interval: Duration::from_micros(7500),
-                        max_connection_interval: Duration::from_micros(7500),
-                        max_latency: 400, // 3s
-                        supervision_timeout: Duration::from_secs(7),
-                        ..Default::default()
-                    },
-                )
-                .await;
+                update_conn_params(stack, conn, &active_conn_params()).await;
             }
         }
     }
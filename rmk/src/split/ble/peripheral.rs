@@ -2,12 +2,13 @@ use bt_hci::cmd::le::LeSetPhy;
 use bt_hci::controller::ControllerCmdAsync;
 use embassy_futures::join::join;
 use embassy_futures::select::select;
-use embassy_time::Timer;
+use embassy_time::{Duration, Timer, with_timeout};
 use trouble_host::prelude::*;
 #[cfg(feature = "storage")]
 use {super::PeerAddress, crate::storage::Storage, embedded_storage_async::nor_flash::NorFlash};
 
 use crate::CONNECTION_STATE;
+use crate::input_device::battery::BatteryLevelConverter;
 use crate::split::driver::{SplitDriverError, SplitReader, SplitWriter};
 use crate::split::peripheral::SplitPeripheral;
 use crate::split::{SPLIT_MESSAGE_MAX_SIZE, SplitMessage};
@@ -17,6 +18,10 @@ use crate::{
     event::ControllerEvent,
 };
 
+/// How long the peripheral advertises with a short interval after boot or disconnection, for fast reconnection.
+/// After that, the default advertising interval is used to save power
+const FAST_ADVERTISE_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Gatt service used in split peripheral to send split message to central
 #[gatt_service(uuid = "4dd5fbaa-18e5-4b07-bf0a-353698659946")]
 pub(crate) struct SplitBleService {
@@ -131,6 +136,8 @@ impl<'stack, 'server, 'c, P: PacketPool> SplitWriter for BleSplitPeripheralDrive
 /// * `id` - The id of the peripheral
 /// * `central_addr` - The address of the central
 /// * `stack` - The stack to use
+/// * `storage` - The storage to save the central address
+/// * `battery` - Converter of the battery ADC values, `None` if the battery isn't read
 pub async fn initialize_nrf_ble_split_peripheral_and_run<
     'stack,
     's,
@@ -144,6 +151,7 @@ pub async fn initialize_nrf_ble_split_peripheral_and_run<
     id: usize,
     stack: &'stack Stack<'stack, C, DefaultPacketPool>,
     storage: &'s mut Storage<F, ROW, COL, NUM_LAYER, NUM_ENCODER>,
+    battery: Option<BatteryLevelConverter>,
 ) {
     #[cfg(feature = "controller")]
     let mut controller_pub = unwrap!(CONTROLLER_CHANNEL.publisher());
@@ -174,12 +182,22 @@ pub async fn initialize_nrf_ble_split_peripheral_and_run<
             CONNECTION_STATE.store(false, core::sync::atomic::Ordering::Release);
             #[cfg(feature = "controller")]
             send_controller_event(&mut controller_pub, ControllerEvent::SplitCentral(false));
-            match split_peripheral_advertise(id, central_addr, &mut peripheral, &server).await {
+            // Advertise fast first, so that the central re-acquires the peripheral quickly after a reset
+            let advertise_result = match with_timeout(
+                FAST_ADVERTISE_TIMEOUT,
+                split_peripheral_advertise(id, central_addr, &mut peripheral, &server, true),
+            )
+            .await
+            {
+                Ok(result) => result,
+                Err(_) => split_peripheral_advertise(id, central_addr, &mut peripheral, &server, false).await,
+            };
+            match advertise_result {
                 Ok(conn) => {
                     info!("Connected to the central");
                     #[cfg(feature = "controller")]
                     send_controller_event(&mut controller_pub, ControllerEvent::SplitCentral(true));
-                    let mut peripheral = SplitPeripheral::new(BleSplitPeripheralDriver::new(&server, &conn));
+                    let mut peripheral = SplitPeripheral::new(BleSplitPeripheralDriver::new(&server, &conn), battery);
                     // Save central address to storage if the central address is not saved
                     if !central_saved || conn.raw().peer_address().into_inner() != central_addr.unwrap_or_default() {
                         info!("Saving central address to storage");
@@ -214,11 +232,14 @@ pub async fn initialize_nrf_ble_split_peripheral_and_run<
 }
 
 /// Create an advertiser to use to connect to a BLE Central, and wait for it to connect.
+///
+/// If `fast` is true, the advertising interval is 20ms instead of the default one.
 async fn split_peripheral_advertise<'a, 'b, C: Controller>(
     id: usize,
     central_addr: Option<[u8; 6]>,
     peripheral: &mut Peripheral<'a, C, DefaultPacketPool>,
     server: &'b BleSplitPeripheralServer<'_>,
+    fast: bool,
 ) -> Result<GattConnection<'a, 'b, DefaultPacketPool>, BleHostError<C::Error>> {
     let mut advertiser_data = [0; 31];
     let advertisement = match central_addr {
@@ -252,9 +273,16 @@ async fn split_peripheral_advertise<'a, 'b, C: Controller>(
         }
     };
 
-    let advertiser = peripheral
-        .advertise(&AdvertisementParameters::default(), advertisement)
-        .await?;
+    let params = if fast {
+        AdvertisementParameters {
+            interval_min: Duration::from_millis(20),
+            interval_max: Duration::from_millis(20),
+            ..Default::default()
+        }
+    } else {
+        AdvertisementParameters::default()
+    };
+    let advertiser = peripheral.advertise(&params, advertisement).await?;
 
     let conn = advertiser.accept().await?.with_attribute_server(server)?;
     info!("[adv] connection established");
@@ -1,23 +1,27 @@
 #[cfg(feature = "async_matrix")]
 use core::pin::pin;
 
-use embassy_time::{Instant, Timer};
+use embassy_time::{Duration, Instant, Timer};
 use embedded_hal::digital::{InputPin, OutputPin};
 #[cfg(feature = "async_matrix")]
 use embedded_hal_async::digital::Wait;
-#[cfg(not(feature = "_ble"))]
+#[cfg(any(not(feature = "_ble"), feature = "split_serial"))]
 use embedded_io_async::{Read, Write};
 #[cfg(feature = "_ble")]
 use {
+    crate::ble::rssi::RssiController,
     bt_hci::cmd::le::{LeReadLocalSupportedFeatures, LeSetPhy, LeSetScanParams},
     bt_hci::controller::{ControllerCmdAsync, ControllerCmdSync},
     trouble_host::prelude::*,
 };
 
-use crate::debounce::{DebounceState, DebouncerTrait};
-use crate::event::{Event, KeyboardEvent};
+use crate::config::DiodeDirection;
+use crate::debounce::DebouncerTrait;
+use crate::event::Event;
 use crate::input_device::InputDevice;
-use crate::matrix::{KeyState, MatrixTrait};
+use crate::matrix::{
+    DEFAULT_DIRECT_PIN_SCAN_INTERVAL, DEFAULT_SCAN_INTERVAL, MatrixScanner, MatrixTrait, wait_scan_interval,
+};
 
 /// Run central's peripheral manager task.
 ///
@@ -34,10 +38,47 @@ pub async fn run_peripheral_manager<
     #[cfg(feature = "_ble")] C: Controller
         + ControllerCmdSync<LeSetScanParams>
         + ControllerCmdAsync<LeSetPhy>
-        + ControllerCmdSync<LeReadLocalSupportedFeatures>,
+        + ControllerCmdSync<LeReadLocalSupportedFeatures>
+        + RssiController,
+    #[cfg(not(feature = "_ble"))] S: Read + Write,
+>(
+    id: usize,
+    #[cfg(feature = "_ble")] addr: Option<[u8; 6]>,
+    #[cfg(feature = "_ble")] stack: &'a Stack<'a, C, DefaultPacketPool>,
+    #[cfg(not(feature = "_ble"))] receiver: S,
+) {
+    #[cfg(feature = "_ble")]
+    run_peripheral_manager_with_offset::<ROW, COL, C>(id, ROW_OFFSET, COL_OFFSET, addr, stack).await;
+
+    #[cfg(not(feature = "_ble"))]
+    run_peripheral_manager_with_offset::<ROW, COL, S>(id, ROW_OFFSET, COL_OFFSET, receiver).await;
+}
+
+/// Run central's peripheral manager task, with the peripheral's matrix offsets given at runtime.
+///
+/// It's same as [`run_peripheral_manager`], but can be used when the position of the peripheral's matrix is only known at runtime,
+/// for example, the same firmware is used for mirrored halves and the side is selected by a pin.
+///
+/// # Arguments
+/// * `id` - peripheral id
+/// * `row_offset` - row offset of the peripheral's matrix in the whole matrix
+/// * `col_offset` - column offset of the peripheral's matrix in the whole matrix
+/// * `addr` - (optional) peripheral's BLE static address. This argument is enabled only for nRF BLE split now
+/// * `receiver` - (optional) serial port. This argument is enabled only for serial split now
+pub async fn run_peripheral_manager_with_offset<
+    'a,
+    const ROW: usize,
+    const COL: usize,
+    #[cfg(feature = "_ble")] C: Controller
+        + ControllerCmdSync<LeSetScanParams>
+        + ControllerCmdAsync<LeSetPhy>
+        + ControllerCmdSync<LeReadLocalSupportedFeatures>
+        + RssiController,
     #[cfg(not(feature = "_ble"))] S: Read + Write,
 >(
     id: usize,
+    row_offset: usize,
+    col_offset: usize,
     #[cfg(feature = "_ble")] addr: Option<[u8; 6]>,
     #[cfg(feature = "_ble")] stack: &'a Stack<'a, C, DefaultPacketPool>,
     #[cfg(not(feature = "_ble"))] receiver: S,
@@ -45,16 +86,55 @@ pub async fn run_peripheral_manager<
     #[cfg(feature = "_ble")]
     {
         use crate::split::ble::central::run_ble_peripheral_manager;
-        run_ble_peripheral_manager::<C, ROW, COL, ROW_OFFSET, COL_OFFSET>(id, addr, stack).await;
+        run_ble_peripheral_manager::<C, ROW, COL>(id, row_offset, col_offset, addr, stack).await;
     };
 
     #[cfg(not(feature = "_ble"))]
     {
         use crate::split::serial::run_serial_peripheral_manager;
-        run_serial_peripheral_manager::<ROW, COL, ROW_OFFSET, COL_OFFSET, S>(id, receiver).await;
+        run_serial_peripheral_manager::<ROW, COL, S>(id, row_offset, col_offset, receiver).await;
     };
 }
 
+/// Run central's peripheral manager task for a peripheral connected by serial, on a BLE split central.
+///
+/// The other peripherals are still run by [`run_peripheral_manager`] over BLE, so that one central can drive
+/// peripherals over serial and BLE at the same time. Each peripheral should have a distinct `id`.
+///
+/// # Arguments
+/// * `id` - peripheral id
+/// * `receiver` - serial port connected to the peripheral
+#[cfg(all(feature = "_ble", feature = "split_serial"))]
+pub async fn run_serial_peripheral_manager<
+    const ROW: usize,
+    const COL: usize,
+    const ROW_OFFSET: usize,
+    const COL_OFFSET: usize,
+    S: Read + Write,
+>(
+    id: usize,
+    receiver: S,
+) {
+    run_serial_peripheral_manager_with_offset::<ROW, COL, S>(id, ROW_OFFSET, COL_OFFSET, receiver).await;
+}
+
+/// Same as [`run_serial_peripheral_manager`], with the peripheral's matrix offsets given at runtime.
+///
+/// # Arguments
+/// * `id` - peripheral id
+/// * `row_offset` - row offset of the peripheral's matrix in the whole matrix
+/// * `col_offset` - column offset of the peripheral's matrix in the whole matrix
+/// * `receiver` - serial port connected to the peripheral
+#[cfg(all(feature = "_ble", feature = "split_serial"))]
+pub async fn run_serial_peripheral_manager_with_offset<const ROW: usize, const COL: usize, S: Read + Write>(
+    id: usize,
+    row_offset: usize,
+    col_offset: usize,
+    receiver: S,
+) {
+    crate::split::serial::run_serial_peripheral_manager::<ROW, COL, S>(id, row_offset, col_offset, receiver).await;
+}
+
 /// Matrix is the physical pcb layout of the keyboard matrix.
 pub struct CentralMatrix<
     #[cfg(feature = "async_matrix")] In: Wait + InputPin,
@@ -70,14 +150,14 @@ pub struct CentralMatrix<
     input_pins: [In; INPUT_PIN_NUM],
     /// Output pins of the pcb matrix
     output_pins: [Out; OUTPUT_PIN_NUM],
-    /// Debouncer
-    debouncer: D,
-    /// Key state matrix
-    key_states: [[KeyState; INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+    /// Debounces the pin states and produces the key events, with the offset of the central
+    scanner: MatrixScanner<D, INPUT_PIN_NUM, OUTPUT_PIN_NUM>,
     /// Start scanning
     scan_start: Option<Instant>,
     /// Current scan pos: (out_idx, in_idx)
     scan_pos: (usize, usize),
+    /// Interval between two scans
+    scan_interval: Duration,
 }
 
 impl<
@@ -108,38 +188,15 @@ impl<
                 for in_idx in in_idx_start..self.input_pins.len() {
                     let in_pin = self.input_pins.get_mut(in_idx).unwrap();
                     // Check input pins and debounce
-                    let debounce_state = self.debouncer.detect_change_with_debounce(
-                        in_idx,
-                        out_idx,
-                        in_pin.is_high().ok().unwrap_or_default(),
-                        &self.key_states[out_idx][in_idx],
-                    );
-
-                    match debounce_state {
-                        DebounceState::Debounced => {
-                            self.key_states[out_idx][in_idx].toggle_pressed();
-                            #[cfg(feature = "col2row")]
-                            let (row, col, key_state) = (
-                                (in_idx + ROW_OFFSET) as u8,
-                                (out_idx + COL_OFFSET) as u8,
-                                self.key_states[out_idx][in_idx],
-                            );
-                            #[cfg(not(feature = "col2row"))]
-                            let (row, col, key_state) = (
-                                (out_idx + ROW_OFFSET) as u8,
-                                (in_idx + COL_OFFSET) as u8,
-                                self.key_states[out_idx][in_idx],
-                            );
-
-                            self.scan_pos = (out_idx, in_idx);
-                            return Event::Key(KeyboardEvent::key(row, col, key_state.pressed));
-                        }
-                        _ => (),
+                    let pin_state = in_pin.is_high().ok().unwrap_or_default();
+                    if let Some(event) = self.scanner.scan_key(in_idx, out_idx, pin_state) {
+                        self.scan_pos = (out_idx, in_idx);
+                        return Event::Key(event);
                     }
 
                     // If there's key still pressed, always refresh the self.scan_start
                     #[cfg(feature = "async_matrix")]
-                    if self.key_states[out_idx][in_idx].pressed {
+                    if self.scanner.is_pressed(in_idx, out_idx) {
                         self.scan_start = Some(Instant::now());
                     }
                 }
@@ -150,7 +207,7 @@ impl<
             }
 
             self.scan_pos = (0, 0);
-            embassy_time::Timer::after_micros(100).await;
+            wait_scan_interval(self.scan_interval).await;
         }
     }
 }
@@ -226,12 +283,25 @@ impl<
         CentralMatrix {
             input_pins,
             output_pins,
-            debouncer,
-            key_states: [[KeyState::default(); INPUT_PIN_NUM]; OUTPUT_PIN_NUM],
+            scanner: MatrixScanner::new(debouncer).with_offset(ROW_OFFSET, COL_OFFSET),
             scan_start: None,
             scan_pos: (0, 0),
+            scan_interval: DEFAULT_SCAN_INTERVAL,
         }
     }
+
+    /// Set the diode direction, the default direction is selected by the `col2row` feature
+    pub fn with_diode_direction(mut self, diode_direction: DiodeDirection) -> Self {
+        self.scanner = self.scanner.with_diode_direction(diode_direction);
+        self
+    }
+
+    /// Set the interval between two scans, the default is 0, which scans the matrix continuously
+    pub fn with_scan_interval(mut self, scan_interval: Duration) -> Self {
+        self.scanner.check_scan_interval(scan_interval);
+        self.scan_interval = scan_interval;
+        self
+    }
 }
 
 /// DirectPinMartex only has input pins.
@@ -247,16 +317,17 @@ pub struct CentralDirectPinMatrix<
 > {
     /// Input pins of the pcb matrix
     direct_pins: [[Option<In>; COL]; ROW],
-    /// Debouncer
-    debouncer: D,
-    /// Key state matrix
-    key_states: [[KeyState; COL]; ROW],
+    /// Debounces the pin states and produces the key events with the offset of the central, the rows are scanned
+    /// as the output pins
+    scanner: MatrixScanner<D, COL, ROW>,
     /// Start scanning
     scan_start: Option<Instant>,
     /// Pin active level
     low_active: bool,
     /// Current scan pos: (out_idx, in_idx)
     scan_pos: (usize, usize),
+    /// Interval between two scans
+    scan_interval: Duration,
 }
 
 impl<
@@ -274,13 +345,22 @@ impl<
     pub fn new(direct_pins: [[Option<In>; COL]; ROW], debouncer: D, low_active: bool) -> Self {
         CentralDirectPinMatrix {
             direct_pins,
-            debouncer,
-            key_states: [[KeyState::new(); COL]; ROW],
+            scanner: MatrixScanner::new(debouncer)
+                .with_diode_direction(DiodeDirection::Row2Col)
+                .with_offset(ROW_OFFSET, COL_OFFSET),
             scan_start: None,
             low_active,
             scan_pos: (0, 0),
+            scan_interval: DEFAULT_DIRECT_PIN_SCAN_INTERVAL,
         }
     }
+
+    /// Set the interval between two scans, the default is 100us. 0 scans the pins continuously
+    pub fn with_scan_interval(mut self, scan_interval: Duration) -> Self {
+        self.scanner.check_scan_interval(scan_interval);
+        self.scan_interval = scan_interval;
+        self
+    }
 }
 
 impl<
@@ -305,6 +385,8 @@ impl<
             // Scan matrix and send report
             for row_idx in row_idx_start..self.direct_pins.len() {
                 let pins_row = self.direct_pins.get_mut(row_idx).unwrap();
+                // Only the row of the last reported key resumes from the middle
+                let col_idx_start = if row_idx == row_idx_start { col_idx_start } else { 0 };
                 for col_idx in col_idx_start..pins_row.len() {
                     let direct_pin = pins_row.get_mut(col_idx).unwrap();
                     if let Some(direct_pin) = direct_pin {
@@ -314,31 +396,14 @@ impl<
                             direct_pin.is_high().ok().unwrap_or_default()
                         };
 
-                        let debounce_state = self.debouncer.detect_change_with_debounce(
-                            col_idx,
-                            row_idx,
-                            pin_state,
-                            &self.key_states[row_idx][col_idx],
-                        );
-
-                        match debounce_state {
-                            DebounceState::Debounced => {
-                                self.key_states[row_idx][col_idx].toggle_pressed();
-                                let (col, row, key_state) = (
-                                    (col_idx + COL_OFFSET) as u8,
-                                    (row_idx + ROW_OFFSET) as u8,
-                                    self.key_states[row_idx][col_idx],
-                                );
-
-                                self.scan_pos = (row_idx, col_idx);
-                                return Event::Key(KeyboardEvent::key(row, col, key_state.pressed));
-                            }
-                            _ => (),
+                        if let Some(event) = self.scanner.scan_key(col_idx, row_idx, pin_state) {
+                            self.scan_pos = (row_idx, col_idx);
+                            return Event::Key(event);
                         }
 
                         // If there's key still pressed, always refresh the self.scan_start
                         #[cfg(feature = "async_matrix")]
-                        if self.key_states[row_idx][col_idx].pressed {
+                        if self.scanner.is_pressed(col_idx, row_idx) {
                             self.scan_start = Some(Instant::now());
                         }
                     }
@@ -346,7 +411,7 @@ impl<
             }
 
             self.scan_pos = (0, 0);
-            Timer::after_micros(100).await;
+            wait_scan_interval(self.scan_interval).await;
         }
     }
 }
@@ -404,3 +469,65 @@ impl<
         self.scan_start = Some(Instant::now());
     }
 }
+
+#[cfg(test)]
+mod test {
+    use embassy_time::Duration;
+
+    use super::*;
+    use crate::debounce::DebounceState;
+    use crate::matrix::KeyState;
+
+    /// A pin which is always low
+    struct LowPin;
+
+    impl embedded_hal::digital::ErrorType for LowPin {
+        type Error = core::convert::Infallible;
+    }
+
+    impl InputPin for LowPin {
+        fn is_high(&mut self) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+
+        fn is_low(&mut self) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+    }
+
+    impl OutputPin for LowPin {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    struct NoDebouncer;
+
+    impl DebouncerTrait for NoDebouncer {
+        fn detect_change_with_debounce(
+            &mut self,
+            _in_idx: usize,
+            _out_idx: usize,
+            _pin_state: bool,
+            _key_state: &KeyState,
+        ) -> DebounceState {
+            DebounceState::Ignored
+        }
+    }
+
+    #[cfg(not(feature = "async_matrix"))]
+    #[test]
+    fn test_central_scan_interval() {
+        // The central's matrix scans continuously like the matrix of the peripherals
+        let matrix = CentralMatrix::<_, _, _, 0, 0, 2, 1>::new([LowPin, LowPin], [LowPin], NoDebouncer);
+        assert_eq!(matrix.scan_interval, Duration::MIN);
+
+        // The direct pin matrix waits 100us between the scans
+        let matrix = CentralDirectPinMatrix::<_, _, 0, 0, 1, 2, 2>::new([[Some(LowPin), None]], NoDebouncer, true);
+        assert_eq!(matrix.scan_interval, Duration::from_micros(100));
+    }
+}
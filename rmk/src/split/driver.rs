@@ -3,15 +3,30 @@
 use core::sync::atomic::Ordering;
 
 use embassy_futures::select::{Either3, select3};
-use embassy_time::{Instant, Timer};
+use embassy_time::{Duration, Instant, Timer};
 #[cfg(all(feature = "storage", feature = "_ble"))]
 use {crate::channel::FLASH_CHANNEL, crate::split::ble::PeerAddress, crate::storage::FlashOperationMessage};
+#[cfg(feature = "controller")]
+use {
+    crate::channel::{CONTROLLER_CHANNEL, send_controller_event},
+    crate::event::ControllerEvent,
+};
 
-use super::SplitMessage;
-use crate::CONNECTION_STATE;
+use super::{PERIPHERAL_BATTERY_LEVEL, PERIPHERAL_BATTERY_UNKNOWN, SplitMessage};
 use crate::channel::{EVENT_CHANNEL, KEY_EVENT_CHANNEL, SPLIT_MESSAGE_PUBLISHER};
 use crate::event::{Event, KeyboardEvent, KeyboardEventPos};
 use crate::input_device::InputDevice;
+use crate::{CONNECTION_STATE, SPLIT_HEARTBEAT_INTERVAL};
+
+/// Interval of syncing the connection state to the peripheral, in milliseconds
+const CONNECTION_STATE_SYNC_INTERVAL: u64 = 3000;
+/// Interval of re-sending the connection state when the peripheral doesn't ack it, in milliseconds
+const CONNECTION_STATE_RETRY_INTERVAL: u64 = 200;
+/// Max number of re-sending the connection state without ack.
+/// After that, the connection state is only synced every `CONNECTION_STATE_SYNC_INTERVAL`
+const CONNECTION_STATE_MAX_RETRIES: u8 = 10;
+/// Number of missed heartbeats after which the peripheral is taken as disconnected
+const HEARTBEAT_MAX_MISSED: u64 = 3;
 
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -26,6 +41,11 @@ pub(crate) enum SplitDriverError {
 
 /// Split message reader from other split devices
 pub(crate) trait SplitReader {
+    /// Whether the link uses the heartbeat to find a dropped connection.
+    ///
+    /// A BLE link is monitored by the controller with the supervision timeout, so only the serial link needs it.
+    const HEARTBEAT: bool = false;
+
     async fn read(&mut self) -> Result<SplitMessage, SplitDriverError>;
 }
 
@@ -40,52 +60,99 @@ pub(crate) trait SplitWriter {
 /// When the central scans the matrix, the scanning thread sends sync signal and gets key state cache back.
 ///
 /// The `ROW` and `COL` are the number of rows and columns of the corresponding peripheral's keyboard matrix.
-/// The `ROW_OFFSET` and `COL_OFFSET` are the offset of the peripheral's matrix in the keyboard's matrix.
-pub(crate) struct PeripheralManager<
-    const ROW: usize,
-    const COL: usize,
-    const ROW_OFFSET: usize,
-    const COL_OFFSET: usize,
-    T: SplitReader + SplitWriter,
-> {
+/// The `row_offset` and `col_offset` are the offset of the peripheral's matrix in the keyboard's matrix.
+pub(crate) struct PeripheralManager<const ROW: usize, const COL: usize, T: SplitReader + SplitWriter> {
     /// Receiver
     transceiver: T,
     /// Peripheral id
     id: usize,
+    /// Row offset of the peripheral's matrix in the keyboard's matrix
+    row_offset: usize,
+    /// Column offset of the peripheral's matrix in the keyboard's matrix
+    col_offset: usize,
+    /// Whether the peripheral has acked the last sent connection state
+    conn_state_acked: bool,
+    /// Number of re-sending the connection state since the last ack
+    conn_state_retries: u8,
+    /// Time of the last message received from the peripheral, used to find a dropped link
+    last_message_time: Instant,
 }
 
-impl<const ROW: usize, const COL: usize, const ROW_OFFSET: usize, const COL_OFFSET: usize, T: SplitReader + SplitWriter>
-    PeripheralManager<ROW, COL, ROW_OFFSET, COL_OFFSET, T>
-{
-    pub(crate) fn new(transceiver: T, id: usize) -> Self {
-        Self { transceiver, id }
+impl<const ROW: usize, const COL: usize, T: SplitReader + SplitWriter> PeripheralManager<ROW, COL, T> {
+    pub(crate) fn new(transceiver: T, id: usize, row_offset: usize, col_offset: usize) -> Self {
+        Self {
+            transceiver,
+            id,
+            row_offset,
+            col_offset,
+            conn_state_acked: false,
+            conn_state_retries: 0,
+            last_message_time: Instant::now(),
+        }
+    }
+
+    /// Whether the peripheral has missed `HEARTBEAT_MAX_MISSED` heartbeats at `now`
+    fn heartbeat_lost(&self, now: Instant) -> bool {
+        T::HEARTBEAT
+            && SPLIT_HEARTBEAT_INTERVAL > 0
+            && now.saturating_duration_since(self.last_message_time)
+                >= Duration::from_millis(SPLIT_HEARTBEAT_INTERVAL as u64 * HEARTBEAT_MAX_MISSED)
+    }
+
+    /// Send the current connection state to the peripheral, the peripheral should ack it.
+    async fn sync_connection_state(&mut self) -> Result<(), SplitDriverError> {
+        let conn_state = CONNECTION_STATE.load(Ordering::Acquire);
+        trace!("Syncing connection state to peripheral: {}", conn_state);
+        self.conn_state_acked = false;
+        match self.transceiver.write(&SplitMessage::ConnectionState(conn_state)).await {
+            Ok(_) => Ok(()),
+            Err(SplitDriverError::Disconnected) => Err(SplitDriverError::Disconnected),
+            Err(e) => {
+                error!("SplitDriver write error: {:?}", e);
+                Ok(())
+            }
+        }
     }
 
     /// Run the manager.
     ///
     /// The manager receives from the peripheral and forward the message to `KEY_EVENT_CHANNEL`.
     /// It also sync the `ConnectionState` to the peripheral periodically.
-    pub(crate) async fn run(mut self) {
-        let mut conn_state = CONNECTION_STATE.load(Ordering::Acquire);
+    ///
+    /// It returns when the peripheral is disconnected, or it misses `HEARTBEAT_MAX_MISSED` heartbeats.
+    pub(crate) async fn run(&mut self) {
+        self.run_inner().await;
+        // The battery level of a disconnected peripheral is unknown
+        PERIPHERAL_BATTERY_LEVEL[self.id].store(PERIPHERAL_BATTERY_UNKNOWN, Ordering::Release);
+    }
+
+    async fn run_inner(&mut self) {
         // Send connection state once on start
-        if let Err(e) = self.transceiver.write(&SplitMessage::ConnectionState(conn_state)).await {
-            match e {
-                SplitDriverError::Disconnected => return,
-                _ => error!("SplitDriver write error: {:?}", e),
-            }
+        if self.sync_connection_state().await.is_err() {
+            return;
         }
 
         let mut last_sync_time = Instant::now();
+        self.last_message_time = Instant::now();
         let mut subscriber = SPLIT_MESSAGE_PUBLISHER
             .subscriber()
             .expect("Failed to create split message subscriber: MaximumSubscribersReached");
 
         loop {
-            // Calculate the time until the next 3000ms sync
+            // Re-send the connection state quickly if the peripheral doesn't ack it, otherwise sync it periodically
+            let sync_interval = if !self.conn_state_acked && self.conn_state_retries < CONNECTION_STATE_MAX_RETRIES {
+                CONNECTION_STATE_RETRY_INTERVAL
+            } else {
+                CONNECTION_STATE_SYNC_INTERVAL
+            };
             let elapsed = last_sync_time.elapsed().as_millis() as u64;
-            let wait_time = if elapsed >= 3000 { 1 } else { 3000 - elapsed };
+            let mut wait_time = sync_interval.saturating_sub(elapsed).max(1);
+            if T::HEARTBEAT && SPLIT_HEARTBEAT_INTERVAL > 0 {
+                // Check the heartbeat at least every heartbeat interval
+                wait_time = wait_time.min(SPLIT_HEARTBEAT_INTERVAL as u64);
+            }
 
-            // Read the message from peripheral, or sync the connection state every 1000ms.
+            // Read the message from peripheral, or sync the connection state.
             match select3(
                 self.read_event(),
                 subscriber.next_message_pure(),
@@ -126,15 +193,29 @@ impl<const ROW: usize, const COL: usize, const ROW_OFFSET: usize, const COL_OFFS
                     }
                 }
                 Either3::Third(_) => {
+                    if self.heartbeat_lost(Instant::now()) {
+                        warn!(
+                            "Peripheral {} missed {} heartbeats, disconnect it",
+                            self.id, HEARTBEAT_MAX_MISSED
+                        );
+                        return;
+                    }
+                    if last_sync_time.elapsed().as_millis() < sync_interval {
+                        continue;
+                    }
                     // Timer elapsed, sync the connection state
-                    conn_state = CONNECTION_STATE.load(Ordering::Acquire);
-                    trace!("Syncing connection state to peripheral: {}", conn_state);
-                    if let Err(e) = self.transceiver.write(&SplitMessage::ConnectionState(conn_state)).await {
-                        match e {
-                            SplitDriverError::Disconnected => return,
-                            _ => error!("SplitDriver write error: {:?}", e),
+                    if !self.conn_state_acked && self.conn_state_retries < CONNECTION_STATE_MAX_RETRIES {
+                        self.conn_state_retries += 1;
+                        if self.conn_state_retries == CONNECTION_STATE_MAX_RETRIES {
+                            warn!(
+                                "Peripheral {} doesn't ack the connection state, sync it every {}ms",
+                                self.id, CONNECTION_STATE_SYNC_INTERVAL
+                            );
                         }
                     }
+                    if self.sync_connection_state().await.is_err() {
+                        return;
+                    }
                     last_sync_time = Instant::now();
                 }
             }
@@ -142,28 +223,34 @@ impl<const ROW: usize, const COL: usize, const ROW_OFFSET: usize, const COL_OFFS
     }
 }
 
-impl<const ROW: usize, const COL: usize, const ROW_OFFSET: usize, const COL_OFFSET: usize, R: SplitReader + SplitWriter>
-    InputDevice for PeripheralManager<ROW, COL, ROW_OFFSET, COL_OFFSET, R>
-{
+impl<const ROW: usize, const COL: usize, R: SplitReader + SplitWriter> InputDevice for PeripheralManager<ROW, COL, R> {
     async fn read_event(&mut self) -> Event {
         loop {
-            match self.transceiver.read().await {
-                Ok(SplitMessage::Key(e)) => {
+            let message = self.transceiver.read().await;
+            if message.is_ok() {
+                self.last_message_time = Instant::now();
+            }
+            match message {
+                Ok(SplitMessage::Key { event: e, age_ms } | SplitMessage::ResolvedKey { event: e, age_ms }) => {
                     match e.pos {
                         KeyboardEventPos::Key(key_pos) => {
-                            // Verify the row/col
-                            if key_pos.row as usize > ROW || key_pos.col as usize > COL {
+                            // Verify the row/col, valid indices are `0..ROW` and `0..COL`
+                            if key_pos.row as usize >= ROW || key_pos.col as usize >= COL {
                                 error!("Invalid peripheral row/col: {} {}", key_pos.row, key_pos.col);
                                 continue;
                             }
 
                             if CONNECTION_STATE.load(core::sync::atomic::Ordering::Acquire) {
                                 // Only when the connection is established, send the key event.
-                                let adjusted_key_event = KeyboardEvent::key(
-                                    key_pos.row + ROW_OFFSET as u8,
-                                    key_pos.col + COL_OFFSET as u8,
+                                let mut adjusted_key_event = KeyboardEvent::key(
+                                    key_pos.row + self.row_offset as u8,
+                                    key_pos.col + self.col_offset as u8,
                                     e.pressed,
                                 );
+                                // The event happened `age_ms` before it's sent by the peripheral
+                                if let Some(time) = e.time.checked_sub(Duration::from_millis(age_ms as u64)) {
+                                    adjusted_key_event.time = time;
+                                }
                                 return Event::Key(adjusted_key_event);
                             } else {
                                 warn!(
@@ -186,6 +273,30 @@ impl<const ROW: usize, const COL: usize, const ROW_OFFSET: usize, const COL_OFFS
                         warn!("Event from peripheral is ignored because the connection is not established.");
                     }
                 }
+                Ok(SplitMessage::Battery(level)) => {
+                    debug!("Peripheral {} battery level: {}", self.id, level);
+                    PERIPHERAL_BATTERY_LEVEL[self.id].store(level, Ordering::Release);
+                    #[cfg(feature = "controller")]
+                    if let Ok(mut publisher) = CONTROLLER_CHANNEL.publisher() {
+                        send_controller_event(&mut publisher, ControllerEvent::SplitPeripheralBattery(self.id, level));
+                    }
+                }
+                Ok(SplitMessage::ConnectionStateAck(state)) => {
+                    // An ack of an outdated state is ignored, the latest state will be re-sent
+                    if state == CONNECTION_STATE.load(Ordering::Acquire) {
+                        trace!("Peripheral {} acked connection state: {}", self.id, state);
+                        self.conn_state_acked = true;
+                        self.conn_state_retries = 0;
+                    }
+                }
+                Ok(SplitMessage::Heartbeat) => trace!("Heartbeat from peripheral {}", self.id),
+                Ok(SplitMessage::ConnectionStateRequest) => {
+                    debug!("Peripheral {} requests connection state", self.id);
+                    self.conn_state_retries = 0;
+                    if let Err(e) = self.sync_connection_state().await {
+                        error!("Peripheral message write error: {:?}", e);
+                    }
+                }
                 Ok(_) => {
                     // Ignore other types of messages
                     debug!("Ignored non-event split message");
@@ -197,3 +308,140 @@ impl<const ROW: usize, const COL: usize, const ROW_OFFSET: usize, const COL_OFFS
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use embassy_futures::block_on;
+    use heapless::Deque;
+
+    use super::*;
+
+    /// Transceiver which reads the pre-filled messages, and records the written messages
+    struct MockTransceiver {
+        messages: Deque<SplitMessage, 4>,
+        written: Deque<SplitMessage, 4>,
+    }
+
+    impl MockTransceiver {
+        fn new(messages: &[SplitMessage]) -> Self {
+            let mut deque = Deque::new();
+            for m in messages {
+                deque.push_back(*m).unwrap();
+            }
+            Self {
+                messages: deque,
+                written: Deque::new(),
+            }
+        }
+    }
+
+    impl SplitReader for MockTransceiver {
+        const HEARTBEAT: bool = true;
+
+        async fn read(&mut self) -> Result<SplitMessage, SplitDriverError> {
+            self.messages.pop_front().ok_or(SplitDriverError::EmptyMessage)
+        }
+    }
+
+    impl SplitWriter for MockTransceiver {
+        async fn write(&mut self, message: &SplitMessage) -> Result<usize, SplitDriverError> {
+            self.written.push_back(*message).ok();
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_key_is_dropped() {
+        CONNECTION_STATE.store(true, Ordering::Release);
+        // Row == ROW and col == COL are both out of range, the last in-range one is forwarded
+        let transceiver = MockTransceiver::new(&[
+            SplitMessage::key(KeyboardEvent::key(2, 0, true)),
+            SplitMessage::key(KeyboardEvent::key(0, 3, true)),
+            SplitMessage::key(KeyboardEvent::key(1, 2, true)),
+        ]);
+        let mut manager = PeripheralManager::<2, 3, _>::new(transceiver, 0, 4, 5);
+
+        let event = block_on(manager.read_event());
+        assert!(matches!(event, Event::Key(e) if e == KeyboardEvent::key(5, 7, true)));
+        assert!(manager.transceiver.messages.is_empty());
+    }
+
+    #[test]
+    fn test_in_range_key_is_forwarded() {
+        CONNECTION_STATE.store(true, Ordering::Release);
+        let transceiver = MockTransceiver::new(&[SplitMessage::key(KeyboardEvent::key(0, 0, false))]);
+        let mut manager = PeripheralManager::<2, 3, _>::new(transceiver, 0, 4, 5);
+
+        let event = block_on(manager.read_event());
+        assert!(matches!(event, Event::Key(e) if e == KeyboardEvent::key(4, 5, false)));
+    }
+
+    #[test]
+    fn test_resolved_key_is_offset() {
+        CONNECTION_STATE.store(true, Ordering::Release);
+        let transceiver = MockTransceiver::new(&[SplitMessage::resolved_key(KeyboardEvent::key(1, 2, true))]);
+        let mut manager = PeripheralManager::<2, 3, _>::new(transceiver, 0, 4, 5);
+
+        let event = block_on(manager.read_event());
+        assert!(matches!(event, Event::Key(e) if e == KeyboardEvent::key(5, 7, true)));
+    }
+
+    #[test]
+    fn test_key_time_from_peripheral() {
+        CONNECTION_STATE.store(true, Ordering::Release);
+        // Make sure the age can be subtracted from the current time
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let transceiver = MockTransceiver::new(&[SplitMessage::Key {
+            event: KeyboardEvent::key(0, 0, true),
+            age_ms: 30,
+        }]);
+        let mut manager = PeripheralManager::<2, 3, _>::new(transceiver, 0, 0, 0);
+
+        let Event::Key(e) = block_on(manager.read_event()) else {
+            panic!("Unexpected event");
+        };
+        // The event time is the receive time minus the age sent by the peripheral
+        assert!(e.time.elapsed() >= Duration::from_millis(30));
+        assert!(e.time.elapsed() < Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        CONNECTION_STATE.store(true, Ordering::Release);
+        let transceiver = MockTransceiver::new(&[
+            SplitMessage::Heartbeat,
+            SplitMessage::key(KeyboardEvent::key(0, 0, true)),
+        ]);
+        let mut manager = PeripheralManager::<2, 3, _>::new(transceiver, 0, 0, 0);
+
+        // The heartbeat is consumed by the manager
+        let event = block_on(manager.read_event());
+        assert!(matches!(event, Event::Key(e) if e == KeyboardEvent::key(0, 0, true)));
+
+        let timeout = Duration::from_millis(SPLIT_HEARTBEAT_INTERVAL as u64 * HEARTBEAT_MAX_MISSED);
+        let last = manager.last_message_time;
+        assert!(!manager.heartbeat_lost(last + timeout / 2));
+        assert_eq!(manager.heartbeat_lost(last + timeout), SPLIT_HEARTBEAT_INTERVAL > 0);
+    }
+
+    #[test]
+    fn test_connection_state_request_and_ack() {
+        CONNECTION_STATE.store(true, Ordering::Release);
+        let transceiver = MockTransceiver::new(&[
+            SplitMessage::ConnectionStateRequest,
+            SplitMessage::ConnectionStateAck(true),
+            SplitMessage::key(KeyboardEvent::key(0, 0, true)),
+        ]);
+        let mut manager = PeripheralManager::<2, 3, _>::new(transceiver, 0, 0, 0);
+        assert!(!manager.conn_state_acked);
+
+        // The connection state is sent once requested, then it's acked by the peripheral
+        block_on(manager.read_event());
+        assert!(matches!(
+            manager.transceiver.written.pop_front(),
+            Some(SplitMessage::ConnectionState(true))
+        ));
+        assert!(manager.conn_state_acked);
+        assert_eq!(manager.conn_state_retries, 0);
+    }
+}
@@ -0,0 +1,300 @@
+//! Combos resolved on the split peripheral.
+//!
+//! Normally the peripheral sends raw key events and all behaviors run on the central. A local combo only contains
+//! keys of one peripheral, so it can be resolved right after the scan, without waiting for the key events of the
+//! combo to go through the split link one by one.
+//!
+//! A triggered combo is sent as [`SplitMessage::ResolvedKey`] at the output position. The output position is in the
+//! peripheral's matrix, usually a position without a switch, and its action is set in the central's keymap. So the
+//! central merges resolved keys with raw keys: both are offset to the central's keymap and processed in the order
+//! they're received, and the keymap of the output position can still be changed by Vial.
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{Duration, Instant, with_deadline};
+use heapless::Vec;
+
+use super::SplitMessage;
+use crate::channel::KEY_EVENT_CHANNEL;
+use crate::event::{KeyPos, KeyboardEvent, KeyboardEventPos};
+
+/// Max number of key presses buffered while waiting for a combo, it's also the max number of keys in a local combo
+const LOCAL_COMBO_BUFFER_SIZE: usize = 8;
+
+/// Max number of split messages produced by one key event
+pub(crate) const LOCAL_COMBO_MESSAGES_MAX_NUM: usize = LOCAL_COMBO_BUFFER_SIZE + 1;
+
+/// Local combos of the peripheral, set by `set_local_combos` before running the peripheral
+static LOCAL_COMBOS: Mutex<CriticalSectionRawMutex, Cell<Option<LocalCombosConfig>>> = Mutex::new(Cell::new(None));
+
+/// Combo whose keys are all on the peripheral
+#[derive(Clone, Copy, Debug)]
+pub struct LocalCombo {
+    /// Keys of the combo, (row, col) in the peripheral's matrix. At most 8 keys
+    pub keys: &'static [(u8, u8)],
+    /// Position sent to the central when the combo is triggered, (row, col) in the peripheral's matrix
+    pub output: (u8, u8),
+}
+
+/// Config of the local combos
+#[derive(Clone, Copy, Debug)]
+pub struct LocalCombosConfig {
+    pub combos: &'static [LocalCombo],
+    /// Max time between the first and the last key press of a combo
+    pub timeout: Duration,
+}
+
+/// Resolve the given combos on the peripheral, call it before `run_rmk_split_peripheral`
+pub fn set_local_combos(config: LocalCombosConfig) {
+    LOCAL_COMBOS.lock(|c| c.set(Some(config)));
+}
+
+/// Resolves the local combos from the key events of the peripheral
+pub(crate) struct LocalComboProcessor {
+    config: Option<LocalCombosConfig>,
+    /// Key presses which might be a part of a combo
+    buffer: Vec<KeyboardEvent, LOCAL_COMBO_BUFFER_SIZE>,
+    /// The triggered combo, and the bitmask of its keys which are still held
+    triggered: Option<(LocalCombo, u8)>,
+}
+
+impl LocalComboProcessor {
+    pub(crate) fn new() -> Self {
+        Self::with_config(LOCAL_COMBOS.lock(|c| c.get()))
+    }
+
+    fn with_config(config: Option<LocalCombosConfig>) -> Self {
+        Self {
+            config,
+            buffer: Vec::new(),
+            triggered: None,
+        }
+    }
+
+    /// Wait for the next key event, and get the split messages to send.
+    ///
+    /// If the buffered presses don't become a combo before the timeout, they're sent as raw key events.
+    pub(crate) async fn next(&mut self) -> Vec<SplitMessage, LOCAL_COMBO_MESSAGES_MAX_NUM> {
+        match self.next_timeout() {
+            Some(timeout) => match with_deadline(timeout, KEY_EVENT_CHANNEL.receive()).await {
+                Ok(event) => self.process(event),
+                Err(_timeout) => self.flush(),
+            },
+            None => self.process(KEY_EVENT_CHANNEL.receive().await),
+        }
+    }
+
+    fn next_timeout(&self) -> Option<Instant> {
+        let timeout = self.config?.timeout;
+        self.buffer.first().map(|e| e.time + timeout)
+    }
+
+    fn process(&mut self, event: KeyboardEvent) -> Vec<SplitMessage, LOCAL_COMBO_MESSAGES_MAX_NUM> {
+        let mut messages = Vec::new();
+        let Some(config) = self.config else {
+            messages.push(SplitMessage::key(event)).ok();
+            return messages;
+        };
+        let KeyboardEventPos::Key(pos) = event.pos else {
+            messages.push(SplitMessage::key(event)).ok();
+            return messages;
+        };
+
+        if !event.pressed {
+            if let Some((combo, held)) = self.triggered
+                && let Some(i) = combo.keys.iter().position(|k| *k == (pos.row, pos.col))
+            {
+                // The output is released with the first released key of the combo, other releases are dropped
+                if held == full_mask(combo) {
+                    let (row, col) = combo.output;
+                    messages
+                        .push(SplitMessage::resolved_key(KeyboardEvent {
+                            pos: key_pos(row, col),
+                            ..event
+                        }))
+                        .ok();
+                }
+                let held = held & !(1 << i);
+                self.triggered = if held == 0 { None } else { Some((combo, held)) };
+                return messages;
+            }
+            // A buffered key is released before the combo is completed
+            if self.buffer.iter().any(|e| e.pos == event.pos) {
+                messages = self.flush();
+            }
+            messages.push(SplitMessage::key(event)).ok();
+            return messages;
+        }
+
+        // Only one local combo is held at a time
+        if self.triggered.is_some() {
+            messages.push(SplitMessage::key(event)).ok();
+            return messages;
+        }
+        if !Self::is_candidate(config, &self.buffer, pos) {
+            messages = self.flush();
+            if !Self::is_candidate(config, &self.buffer, pos) {
+                messages.push(SplitMessage::key(event)).ok();
+                return messages;
+            }
+        }
+        self.buffer.push(event).ok();
+
+        if let Some(combo) = config
+            .combos
+            .iter()
+            .find(|c| c.keys.len() == self.buffer.len() && contains_all(c, &self.buffer))
+        {
+            // The combo is pressed at the time of its first key
+            let (row, col) = combo.output;
+            let time = self.buffer[0].time;
+            messages
+                .push(SplitMessage::resolved_key(KeyboardEvent {
+                    pos: key_pos(row, col),
+                    time,
+                    ..event
+                }))
+                .ok();
+            self.buffer.clear();
+            self.triggered = Some((*combo, full_mask(*combo)));
+        }
+        messages
+    }
+
+    /// Whether the buffered presses and the new one could still be a combo
+    fn is_candidate(config: LocalCombosConfig, buffer: &[KeyboardEvent], pos: KeyPos) -> bool {
+        buffer.len() < LOCAL_COMBO_BUFFER_SIZE
+            && config.combos.iter().any(|c| {
+                c.keys.len() <= LOCAL_COMBO_BUFFER_SIZE
+                    && c.keys.contains(&(pos.row, pos.col))
+                    && contains_all(c, buffer)
+            })
+    }
+
+    /// Send the buffered presses as raw key events
+    fn flush(&mut self) -> Vec<SplitMessage, LOCAL_COMBO_MESSAGES_MAX_NUM> {
+        let messages = self.buffer.iter().map(|e| SplitMessage::key(*e)).collect();
+        self.buffer.clear();
+        messages
+    }
+}
+
+fn key_pos(row: u8, col: u8) -> KeyboardEventPos {
+    KeyboardEventPos::Key(KeyPos { row, col })
+}
+
+/// Whether all the key events are keys of the combo
+fn contains_all(combo: &LocalCombo, events: &[KeyboardEvent]) -> bool {
+    events
+        .iter()
+        .all(|e| combo.keys.iter().any(|k| e.pos == key_pos(k.0, k.1)))
+}
+
+/// Bitmask of all keys of the combo
+fn full_mask(combo: LocalCombo) -> u8 {
+    (1_u16 << combo.keys.len()).wrapping_sub(1) as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const COMBOS: [LocalCombo; 2] = [
+        LocalCombo {
+            keys: &[(0, 0), (0, 1)],
+            output: (3, 0),
+        },
+        LocalCombo {
+            keys: &[(0, 1), (0, 2), (0, 3)],
+            output: (3, 1),
+        },
+    ];
+
+    fn processor() -> LocalComboProcessor {
+        LocalComboProcessor::with_config(Some(LocalCombosConfig {
+            combos: &COMBOS,
+            timeout: Duration::from_millis(50),
+        }))
+    }
+
+    /// (resolved, row, col, pressed) of the messages
+    fn keys(messages: Vec<SplitMessage, LOCAL_COMBO_MESSAGES_MAX_NUM>) -> std::vec::Vec<(bool, u8, u8, bool)> {
+        messages
+            .iter()
+            .map(|m| match m {
+                SplitMessage::Key { event, .. } | SplitMessage::ResolvedKey { event, .. } => {
+                    let KeyboardEventPos::Key(pos) = event.pos else {
+                        panic!("Unexpected event: {:?}", event);
+                    };
+                    (
+                        matches!(m, SplitMessage::ResolvedKey { .. }),
+                        pos.row,
+                        pos.col,
+                        event.pressed,
+                    )
+                }
+                _ => panic!("Unexpected split message: {:?}", m),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_local_combo_triggered() {
+        let mut p = processor();
+        assert!(keys(p.process(KeyboardEvent::key(0, 0, true))).is_empty());
+        assert_eq!(keys(p.process(KeyboardEvent::key(0, 1, true))), [(true, 3, 0, true)]);
+        // The output is released with the first released key
+        assert_eq!(keys(p.process(KeyboardEvent::key(0, 1, false))), [(true, 3, 0, false)]);
+        // Keys pressed while the combo is held are sent as-is
+        assert_eq!(keys(p.process(KeyboardEvent::key(0, 2, true))), [(false, 0, 2, true)]);
+        assert!(keys(p.process(KeyboardEvent::key(0, 0, false))).is_empty());
+        assert_eq!(keys(p.process(KeyboardEvent::key(0, 2, false))), [(false, 0, 2, false)]);
+        // Not a combo key
+        assert_eq!(keys(p.process(KeyboardEvent::key(1, 0, true))), [(false, 1, 0, true)]);
+    }
+
+    #[test]
+    fn test_local_combo_not_completed() {
+        let mut p = processor();
+        assert!(keys(p.process(KeyboardEvent::key(0, 1, true))).is_empty());
+        // Released before the combo is completed
+        assert_eq!(
+            keys(p.process(KeyboardEvent::key(0, 1, false))),
+            [(false, 0, 1, true), (false, 0, 1, false)]
+        );
+
+        // Interrupted by another key
+        assert!(keys(p.process(KeyboardEvent::key(0, 0, true))).is_empty());
+        assert_eq!(
+            keys(p.process(KeyboardEvent::key(1, 0, true))),
+            [(false, 0, 0, true), (false, 1, 0, true)]
+        );
+        assert_eq!(keys(p.process(KeyboardEvent::key(0, 0, false))), [(false, 0, 0, false)]);
+
+        // Timeout
+        assert!(keys(p.process(KeyboardEvent::key(0, 2, true))).is_empty());
+        assert!(p.next_timeout().is_some());
+        assert_eq!(keys(p.flush()), [(false, 0, 2, true)]);
+        assert!(p.next_timeout().is_none());
+    }
+
+    #[test]
+    fn test_local_combo_overlapped() {
+        let mut p = processor();
+        // (0, 0) can't be a part of the second combo, it's sent when (0, 2) is pressed
+        assert!(keys(p.process(KeyboardEvent::key(0, 0, true))).is_empty());
+        assert_eq!(keys(p.process(KeyboardEvent::key(0, 2, true))), [(false, 0, 0, true)]);
+        let mut p = processor();
+        assert!(keys(p.process(KeyboardEvent::key(0, 1, true))).is_empty());
+        assert!(keys(p.process(KeyboardEvent::key(0, 2, true))).is_empty());
+        assert_eq!(keys(p.process(KeyboardEvent::key(0, 3, true))), [(true, 3, 1, true)]);
+    }
+
+    #[test]
+    fn test_no_local_combo() {
+        let mut p = LocalComboProcessor::with_config(None);
+        assert_eq!(keys(p.process(KeyboardEvent::key(0, 0, true))), [(false, 0, 0, true)]);
+        assert!(p.next_timeout().is_none());
+    }
+}
@@ -1,6 +1,9 @@
+use core::sync::atomic::AtomicU8;
+
 use postcard::experimental::max_size::MaxSize;
 use serde::{Deserialize, Serialize};
 
+use crate::SPLIT_PERIPHERALS_NUM;
 use crate::event::{Event, KeyboardEvent};
 
 #[cfg(feature = "_ble")]
@@ -8,13 +11,21 @@ pub mod ble;
 pub mod central;
 /// Common abstraction layer of split driver
 pub(crate) mod driver;
+pub mod local_combo;
 pub mod peripheral;
 #[cfg(feature = "rp2040_pio")]
 pub mod rp;
-#[cfg(not(feature = "_ble"))]
+#[cfg(any(not(feature = "_ble"), feature = "split_serial"))]
 pub mod serial;
 
-/// Maximum size of a split message
+/// Battery level reported when the peripheral is disconnected, or hasn't reported its battery level yet
+pub const PERIPHERAL_BATTERY_UNKNOWN: u8 = 0xFF;
+
+/// Battery level(0~100) of each peripheral, cached in central
+pub(crate) static PERIPHERAL_BATTERY_LEVEL: [AtomicU8; SPLIT_PERIPHERALS_NUM] =
+    [const { AtomicU8::new(PERIPHERAL_BATTERY_UNKNOWN) }; SPLIT_PERIPHERALS_NUM];
+
+/// Maximum size of a split message, including the COBS overhead and the checksum byte of serial frames
 pub const SPLIT_MESSAGE_MAX_SIZE: usize = SplitMessage::POSTCARD_MAX_SIZE + 4;
 
 /// Message used from central & peripheral communication
@@ -22,7 +33,16 @@ pub const SPLIT_MESSAGE_MAX_SIZE: usize = SplitMessage::POSTCARD_MAX_SIZE + 4;
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, MaxSize)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub(crate) enum SplitMessage {
-    Key(KeyboardEvent),
+    /// Key event from peripheral to central.
+    ///
+    /// The clocks of the peripheral and the central aren't synchronized, so the peripheral sends the time the event
+    /// has waited since the scan in `age_ms`, and the central takes the receive time minus `age_ms` as the event time.
+    /// The latency of the transport itself isn't included.
+    Key { event: KeyboardEvent, age_ms: u16 },
+    /// Output of a combo resolved on the peripheral, see [`local_combo`].
+    ///
+    /// It's processed by the central in the same way as `Key`
+    ResolvedKey { event: KeyboardEvent, age_ms: u16 },
     /// Event from peripheral to central
     Event(Event),
     /// Led state, on/off, from central to peripheral
@@ -30,8 +50,33 @@ pub(crate) enum SplitMessage {
     /// The central connection state, true if central has been connected to host.
     /// This message is sync from central to peripheral
     ConnectionState(bool),
+    /// Ack of the received `ConnectionState`, from peripheral to central
+    ConnectionStateAck(bool),
+    /// Request the central to send the `ConnectionState`, from peripheral to central.
+    /// It's sent when the peripheral is (re)connected to the central
+    ConnectionStateRequest,
+    /// Sent by a serial peripheral every `split_heartbeat_interval`, so that the central can find a dropped link
+    /// before the next key press is lost
+    Heartbeat,
     /// BLE Address, used in syncing address between central and peripheral
     Address([u8; 6]),
     /// Clear the saved peer info
     ClearPeer,
+    /// RGB lighting state, from central to peripheral.
+    /// It's sent when the RGB config of the central changes
+    Lighting { hue: u8, sat: u8, val: u8, effect: u8 },
+}
+
+impl SplitMessage {
+    /// Key event message, the age is the time elapsed since the key change is detected
+    pub(crate) fn key(event: KeyboardEvent) -> Self {
+        let age_ms = event.time.elapsed().as_millis().min(u16::MAX as u64) as u16;
+        SplitMessage::Key { event, age_ms }
+    }
+
+    /// Resolved key message, the age is the time elapsed since the key change is detected
+    pub(crate) fn resolved_key(event: KeyboardEvent) -> Self {
+        let age_ms = event.time.elapsed().as_millis().min(u16::MAX as u64) as u16;
+        SplitMessage::ResolvedKey { event, age_ms }
+    }
 }
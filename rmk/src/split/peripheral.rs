@@ -1,20 +1,32 @@
 #[cfg(feature = "_ble")]
 use bt_hci::{cmd::le::LeSetPhy, controller::ControllerCmdAsync};
-use embassy_futures::select::select3;
+use embassy_futures::select::select4;
+use embassy_time::{Duration, Instant, Timer};
 #[cfg(not(feature = "_ble"))]
 use embedded_io_async::{Read, Write};
 #[cfg(all(feature = "_ble", feature = "storage"))]
 use {super::ble::PeerAddress, crate::channel::FLASH_CHANNEL};
+#[cfg(feature = "controller")]
+use {
+    crate::channel::{CONTROLLER_CHANNEL, send_controller_event},
+    crate::event::ControllerEvent,
+    crate::light::RgbLightConfig,
+};
 #[cfg(feature = "_ble")]
-use {crate::storage::Storage, embedded_storage_async::nor_flash::NorFlash, trouble_host::prelude::*};
+use {
+    crate::event::Event,
+    crate::input_device::battery::{BatteryLevelConfig, BatteryLevelConverter},
+    crate::storage::Storage,
+    embedded_storage_async::nor_flash::NorFlash,
+    trouble_host::prelude::*,
+};
 
 use super::SplitMessage;
 use super::driver::{SplitReader, SplitWriter};
-use crate::CONNECTION_STATE;
-use crate::channel::{EVENT_CHANNEL, KEY_EVENT_CHANNEL};
-#[cfg(not(feature = "_ble"))]
-use crate::split::serial::SerialSplitDriver;
+use super::local_combo::LocalComboProcessor;
+use crate::channel::EVENT_CHANNEL;
 use crate::state::ConnectionState;
+use crate::{CONNECTION_STATE, SPLIT_HEARTBEAT_INTERVAL};
 
 /// Run the split peripheral service.
 ///
@@ -24,6 +36,8 @@ use crate::state::ConnectionState;
 /// * `stack` - (optional) The TrouBLE stack
 /// * `serial` - (optional) serial port used to send peripheral split message. This argument is enabled only for serial split now
 /// * `storage` - (optional) The storage to save the central address
+///
+/// The battery level isn't sent to the central, use [`run_rmk_split_peripheral_with_battery`] for BLE peripherals which read the battery.
 pub async fn run_rmk_split_peripheral<
     'a,
     #[cfg(feature = "_ble")] 'b,
@@ -41,25 +55,63 @@ pub async fn run_rmk_split_peripheral<
     #[cfg(not(feature = "_ble"))] serial: S,
 ) {
     #[cfg(not(feature = "_ble"))]
-    {
-        let mut peripheral = SplitPeripheral::new(SerialSplitDriver::new(serial));
-        loop {
-            peripheral.run().await;
-        }
-    }
+    crate::split::serial::run_serial_peripheral(serial).await;
 
     #[cfg(feature = "_ble")]
-    crate::split::ble::peripheral::initialize_nrf_ble_split_peripheral_and_run(id, stack, storage).await;
+    crate::split::ble::peripheral::initialize_nrf_ble_split_peripheral_and_run(id, stack, storage, None).await;
+}
+
+/// Run the BLE split peripheral service, and send the battery level to the central.
+///
+/// # Arguments
+///
+/// * `id` - The id of the peripheral
+/// * `stack` - The TrouBLE stack
+/// * `storage` - The storage to save the central address
+/// * `battery_adc_divider` - `(adc_divider_measured, adc_divider_total)` of the battery ADC, same as `BatteryProcessor::new`.
+///   The battery ADC value is converted to the battery level and sent to the central. `None` if the peripheral doesn't read the battery
+/// * `battery_level_config` - The discharge curve and the filter used to convert the battery ADC value, same as
+///   `BatteryProcessor::with_level_config`
+#[cfg(feature = "_ble")]
+pub async fn run_rmk_split_peripheral_with_battery<
+    'a,
+    'b,
+    C: Controller + ControllerCmdAsync<LeSetPhy>,
+    F: NorFlash,
+    const ROW: usize,
+    const COL: usize,
+    const NUM_LAYER: usize,
+    const NUM_ENCODER: usize,
+>(
+    id: usize,
+    stack: &'a Stack<'a, C, DefaultPacketPool>,
+    storage: &'b mut Storage<F, ROW, COL, NUM_LAYER, NUM_ENCODER>,
+    battery_adc_divider: Option<(u32, u32)>,
+    battery_level_config: BatteryLevelConfig,
+) {
+    let battery =
+        battery_adc_divider.map(|(measured, total)| BatteryLevelConverter::new(measured, total, battery_level_config));
+    crate::split::ble::peripheral::initialize_nrf_ble_split_peripheral_and_run(id, stack, storage, battery).await;
 }
 
 /// The split peripheral instance.
 pub(crate) struct SplitPeripheral<S: SplitWriter + SplitReader> {
     split_driver: S,
+    /// Resolves the local combos, key events are forwarded as-is if there's no local combo
+    local_combo: LocalComboProcessor,
+    /// Converter of the battery ADC values, `None` if the battery isn't read
+    #[cfg(feature = "_ble")]
+    battery: Option<BatteryLevelConverter>,
 }
 
 impl<S: SplitWriter + SplitReader> SplitPeripheral<S> {
-    pub(crate) fn new(split_driver: S) -> Self {
-        Self { split_driver }
+    pub(crate) fn new(split_driver: S, #[cfg(feature = "_ble")] battery: Option<BatteryLevelConverter>) -> Self {
+        Self {
+            split_driver,
+            local_combo: LocalComboProcessor::new(),
+            #[cfg(feature = "_ble")]
+            battery,
+        }
     }
 
     /// Run the peripheral keyboard service.
@@ -68,20 +120,49 @@ impl<S: SplitWriter + SplitReader> SplitPeripheral<S> {
     /// If also receives split messages from the central through `SplitReader`.
     pub(crate) async fn run(&mut self) {
         CONNECTION_STATE.store(ConnectionState::Connected.into(), core::sync::atomic::Ordering::Release);
+        // Request the latest connection state after (re)connecting to the central
+        if let Err(e) = self.split_driver.write(&SplitMessage::ConnectionStateRequest).await {
+            error!("Split message write error: {:?}", e);
+        }
+        let heartbeat_interval = Duration::from_millis(SPLIT_HEARTBEAT_INTERVAL as u64);
+        let mut next_heartbeat = Instant::now() + heartbeat_interval;
         loop {
-            match select3(
+            match select4(
                 self.split_driver.read(),
-                KEY_EVENT_CHANNEL.receive(),
+                self.local_combo.next(),
                 EVENT_CHANNEL.receive(),
+                wait_heartbeat(next_heartbeat, S::HEARTBEAT),
             )
             .await
             {
-                embassy_futures::select::Either3::First(m) => match m {
-                    // Currently only handle the central state message
+                embassy_futures::select::Either4::First(m) => match m {
+                    // Handle the central state and lighting messages
                     Ok(split_message) => match split_message {
                         SplitMessage::ConnectionState(state) => {
                             trace!("Received connection state update: {}", state);
                             CONNECTION_STATE.store(state, core::sync::atomic::Ordering::Release);
+                            // Ack it, otherwise the central keeps re-sending the state
+                            self.split_driver
+                                .write(&SplitMessage::ConnectionStateAck(state))
+                                .await
+                                .ok();
+                        }
+                        SplitMessage::Lighting { hue, sat, val, effect } => {
+                            trace!("Received lighting update: {} {} {} {}", hue, sat, val, effect);
+                            // Forward to the lighting controller of the peripheral, the effect speed isn't synced
+                            #[cfg(feature = "controller")]
+                            if let Ok(mut publisher) = CONTROLLER_CHANNEL.publisher() {
+                                send_controller_event(
+                                    &mut publisher,
+                                    ControllerEvent::RgbLight(RgbLightConfig {
+                                        brightness: val,
+                                        effect,
+                                        hue,
+                                        saturation: sat,
+                                        ..Default::default()
+                                    }),
+                                );
+                            }
                         }
                         #[cfg(all(feature = "_ble", feature = "storage"))]
                         SplitMessage::ClearPeer => {
@@ -104,16 +185,29 @@ impl<S: SplitWriter + SplitReader> SplitPeripheral<S> {
                         }
                     }
                 },
-                embassy_futures::select::Either3::Second(e) => {
+                embassy_futures::select::Either4::Second(messages) => {
                     // Only send the key event if the connection is established
                     if CONNECTION_STATE.load(core::sync::atomic::Ordering::Acquire) {
                         debug!("Writing split key event to central");
-                        self.split_driver.write(&SplitMessage::Key(e)).await.ok();
+                        for message in messages {
+                            self.split_driver.write(&message).await.ok();
+                        }
                     } else {
                         debug!("Connection not established, skipping key event");
                     }
                 }
-                embassy_futures::select::Either3::Third(e) => {
+                embassy_futures::select::Either4::Third(e) => {
+                    // The battery level is sent to the central whatever the connection state is.
+                    // The raw ADC value isn't forwarded, otherwise the central would take it as its own battery
+                    #[cfg(feature = "_ble")]
+                    if let Event::Battery(val) = e {
+                        if let Some(battery) = self.battery.as_mut() {
+                            let level = battery.level(val);
+                            debug!("Writing battery level to central: {}", level);
+                            self.split_driver.write(&SplitMessage::Battery(level)).await.ok();
+                        }
+                        continue;
+                    }
                     if CONNECTION_STATE.load(core::sync::atomic::Ordering::Acquire) {
                         debug!("Writing split event to central: {:?}", e);
                         self.split_driver.write(&SplitMessage::Event(e)).await.ok();
@@ -121,7 +215,21 @@ impl<S: SplitWriter + SplitReader> SplitPeripheral<S> {
                         debug!("Connection not established, skipping event");
                     }
                 }
+                embassy_futures::select::Either4::Fourth(_) => {
+                    trace!("Writing heartbeat to central");
+                    self.split_driver.write(&SplitMessage::Heartbeat).await.ok();
+                    next_heartbeat = Instant::now() + heartbeat_interval;
+                }
             }
         }
     }
 }
+
+/// Wait for the next heartbeat, forever if the heartbeat is disabled or not used by the link
+async fn wait_heartbeat(next_heartbeat: Instant, enabled: bool) {
+    if !enabled || SPLIT_HEARTBEAT_INTERVAL == 0 {
+        core::future::pending::<()>().await;
+    } else {
+        Timer::at(next_heartbeat).await;
+    }
+}
@@ -2,6 +2,8 @@ use embedded_io_async::{Read, Write};
 
 use super::driver::SplitDriverError;
 use crate::split::driver::{PeripheralManager, SplitReader, SplitWriter};
+#[cfg(not(feature = "_ble"))]
+use crate::split::peripheral::SplitPeripheral;
 use crate::split::{SPLIT_MESSAGE_MAX_SIZE, SplitMessage};
 
 // Receive split message from peripheral via serial and process it
@@ -9,24 +11,109 @@ use crate::split::{SPLIT_MESSAGE_MAX_SIZE, SplitMessage};
 /// Generic parameters:
 /// - `const ROW`: row number of the peripheral's matrix
 /// - `const COL`: column number of the peripheral's matrix
-/// - `const ROW_OFFSET`: row offset of the peripheral's matrix in the whole matrix
-/// - `const COL_OFFSET`: column offset of the peripheral's matrix in the whole matrix
 /// - `S`: a serial port that implements `Read` and `Write` trait in embedded-io-async
-pub(crate) async fn run_serial_peripheral_manager<
-    const ROW: usize,
-    const COL: usize,
-    const ROW_OFFSET: usize,
-    const COL_OFFSET: usize,
-    S: Read + Write,
->(
+///
+/// Arguments:
+/// - `row_offset`: row offset of the peripheral's matrix in the whole matrix
+/// - `col_offset`: column offset of the peripheral's matrix in the whole matrix
+pub(crate) async fn run_serial_peripheral_manager<const ROW: usize, const COL: usize, S: Read + Write>(
     id: usize,
+    row_offset: usize,
+    col_offset: usize,
     receiver: S,
 ) {
     let split_serial_driver: SerialSplitDriver<S> = SerialSplitDriver::new(receiver);
-    let peripheral_manager = PeripheralManager::<ROW, COL, ROW_OFFSET, COL_OFFSET, _>::new(split_serial_driver, id);
+    let mut peripheral_manager = PeripheralManager::<ROW, COL, _>::new(split_serial_driver, id, row_offset, col_offset);
     info!("Running peripheral manager {}", id);
 
-    peripheral_manager.run().await;
+    // The serial link can't be reconnected, the manager restarts and waits for the peripheral after the heartbeat is lost
+    loop {
+        peripheral_manager.run().await;
+        info!("Peripheral manager {} restarted", id);
+    }
+}
+
+/// Run the split peripheral service over serial.
+///
+/// Events of the peripheral's input devices, such as the matrix, are read from `EVENT_CHANNEL`/`KEY_EVENT_CHANNEL` and sent to the central.
+/// So the local matrix should be run with `run_devices!` alongside this function:
+///
+/// ```ignore
+/// join(
+///     run_devices!((matrix) => EVENT_CHANNEL),
+///     run_serial_peripheral(uart_instance),
+/// )
+/// .await;
+/// ```
+///
+/// # Arguments
+///
+/// * `serial` - serial port used to communicate with the central, which implements `Read` and `Write` trait in embedded-io-async
+#[cfg(not(feature = "_ble"))]
+pub async fn run_serial_peripheral<S: Read + Write>(serial: S) {
+    let mut peripheral = SplitPeripheral::new(SerialSplitDriver::new(serial));
+    loop {
+        peripheral.run().await;
+    }
+}
+
+/// CRC-8/SMBUS checksum (polynomial 0x07, init 0x00) of the given bytes
+const fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0_u8;
+    let mut i = 0;
+    while i < bytes.len() {
+        crc ^= bytes[i];
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+            bit += 1;
+        }
+        i += 1;
+    }
+    crc
+}
+
+/// Checksum of the postcard serialized message
+fn message_crc(message: &SplitMessage) -> Result<u8, SplitDriverError> {
+    let mut buf = [0_u8; SplitMessage::POSTCARD_MAX_SIZE];
+    let bytes = postcard::to_slice(message, &mut buf).map_err(|e| {
+        error!("Postcard serialize split message error: {}", e);
+        SplitDriverError::SerializeError
+    })?;
+    Ok(crc8(bytes))
+}
+
+/// Encode a split message into a COBS frame: `cobs(postcard(message) + crc8)`, ended by the 0x00 sentinel.
+///
+/// Postcard serializes a tuple as its fields one after another, so `(message, crc)` is exactly the message bytes followed by the checksum.
+fn encode_frame<'a>(message: &SplitMessage, buf: &'a mut [u8]) -> Result<&'a mut [u8], SplitDriverError> {
+    let crc = message_crc(message)?;
+    postcard::to_slice_cobs(&(*message, crc), buf).map_err(|e| {
+        error!("Postcard serialize split message error: {}", e);
+        SplitDriverError::SerializeError
+    })
+}
+
+/// Decode the first COBS frame in the buffer and verify its checksum.
+///
+/// The checksum is verified over the received bytes before deserializing them, so a corrupted frame is never
+/// taken as a message. A frame that fails the check is dropped with `SplitDriverError::DeserializeError`.
+fn decode_frame(buf: &mut [u8]) -> Result<SplitMessage, SplitDriverError> {
+    let len = cobs::decode_in_place(buf).map_err(|_| {
+        error!("COBS decode split message error");
+        SplitDriverError::DeserializeError
+    })?;
+    let Some((&crc, payload)) = buf[..len].split_last() else {
+        return Err(SplitDriverError::EmptyMessage);
+    };
+    if crc8(payload) != crc {
+        error!("Split message checksum mismatch, dropping frame");
+        return Err(SplitDriverError::DeserializeError);
+    }
+    postcard::from_bytes(payload).map_err(|e| {
+        error!("Postcard deserialize split message error: {}", e);
+        SplitDriverError::DeserializeError
+    })
 }
 
 /// Serial driver for BOTH split central and peripheral
@@ -47,6 +134,8 @@ impl<S: Read + Write> SerialSplitDriver<S> {
 }
 
 impl<S: Read + Write> SplitReader for SerialSplitDriver<S> {
+    const HEARTBEAT: bool = true;
+
     async fn read(&mut self) -> Result<SplitMessage, SplitDriverError> {
         const SENTINEL: u8 = 0x00;
         while self.n_bytes_part < self.buffer.len() {
@@ -68,18 +157,12 @@ impl<S: Read + Write> SplitReader for SerialSplitDriver<S> {
             }
         }
 
-        let (result, n_bytes_unused) =
-            match postcard::take_from_bytes_cobs::<SplitMessage>(&mut self.buffer[..self.n_bytes_part]) {
-                Ok((message, unused_bytes)) => (Ok(message), unused_bytes.len()),
-                Err(e) => {
-                    error!("Postcard deserialize split message error: {}", e);
-                    let n_bytes_unused = self.buffer[..self.n_bytes_part]
-                        .iter()
-                        .position(|&x| x == SENTINEL)
-                        .map_or(0, |index| self.n_bytes_part - index - 1);
-                    (Err(SplitDriverError::SerializeError), n_bytes_unused)
-                }
-            };
+        // Find the end of frame before decoding, COBS decodes in place and may write zeros before the sentinel
+        let n_bytes_unused = self.buffer[..self.n_bytes_part]
+            .iter()
+            .position(|&x| x == SENTINEL)
+            .map_or(0, |index| self.n_bytes_part - index - 1);
+        let result = decode_frame(&mut self.buffer[..self.n_bytes_part]);
 
         self.buffer
             .copy_within(self.n_bytes_part - n_bytes_unused..self.n_bytes_part, 0);
@@ -92,10 +175,7 @@ impl<S: Read + Write> SplitReader for SerialSplitDriver<S> {
 impl<S: Read + Write> SplitWriter for SerialSplitDriver<S> {
     async fn write(&mut self, message: &SplitMessage) -> Result<usize, SplitDriverError> {
         let mut buf = [0_u8; SPLIT_MESSAGE_MAX_SIZE];
-        let bytes = postcard::to_slice_cobs(message, &mut buf).map_err(|e| {
-            error!("Postcard serialize split message error: {}", e);
-            SplitDriverError::SerializeError
-        })?;
+        let bytes = encode_frame(message, &mut buf)?;
         let mut remaining_bytes = bytes.len();
         while remaining_bytes > 0 {
             let sent_bytes = self
@@ -108,3 +188,64 @@ impl<S: Read + Write> SplitWriter for SerialSplitDriver<S> {
         Ok(bytes.len())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event::KeyboardEvent;
+
+    #[test]
+    fn test_crc8() {
+        // CRC-8/SMBUS check value
+        assert_eq!(crc8(b"123456789"), 0xF4);
+        assert_eq!(crc8(&[]), 0);
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        let mut buf = [0_u8; SPLIT_MESSAGE_MAX_SIZE];
+        let event = KeyboardEvent::key(2, 3, true);
+        let len = encode_frame(&SplitMessage::key(event), &mut buf).unwrap().len();
+        assert_eq!(buf[len - 1], 0x00);
+
+        let message = decode_frame(&mut buf[..len]).unwrap();
+        match message {
+            SplitMessage::Key { event: e, .. } => assert_eq!(e, event),
+            _ => panic!("Unexpected split message: {:?}", message),
+        }
+    }
+
+    #[test]
+    fn test_frame_flipped_bit_rejected() {
+        let mut buf = [0_u8; SPLIT_MESSAGE_MAX_SIZE];
+        let len = encode_frame(&SplitMessage::key(KeyboardEvent::key(0x12, 0x34, true)), &mut buf)
+            .unwrap()
+            .len();
+
+        // Non-zero bytes are kept as-is by COBS, flipping a bit of the row still gives a valid message
+        let row_index = buf[..len].iter().position(|&b| b == 0x12).unwrap();
+        buf[row_index] ^= 0x01;
+        assert!(matches!(
+            decode_frame(&mut buf[..len]),
+            Err(SplitDriverError::DeserializeError)
+        ));
+    }
+    #[test]
+    fn test_frame_checksum_of_raw_bytes() {
+        // The checksum is computed over the received bytes, a frame with a valid checksum but an invalid message is
+        // rejected by the deserialization
+        let payload = [0xFF, 0xFF, 0xFF];
+        let mut raw = [0_u8; 4];
+        raw[..3].copy_from_slice(&payload);
+        raw[3] = crc8(&payload);
+        let mut buf = [0_u8; SPLIT_MESSAGE_MAX_SIZE];
+        let len = cobs::encode(&raw, &mut buf);
+        assert!(matches!(
+            decode_frame(&mut buf[..len + 1]),
+            Err(SplitDriverError::DeserializeError)
+        ));
+
+        // A frame without the checksum
+        assert!(decode_frame(&mut [0x01, 0x00]).is_err());
+    }
+}
@@ -1,11 +1,17 @@
 use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
+use rmk_types::led_indicator::LedIndicator;
+
+use crate::keyboard::LOCK_LED_STATES;
+
 /// Current connection type:
 /// - 0: USB
 /// - 1: BLE
 /// - Other: reserved
 pub(crate) static CONNECTION_TYPE: AtomicU8 = AtomicU8::new(0);
 pub(crate) static CONNECTION_STATE: AtomicBool = AtomicBool::new(false);
+/// Whether the keyboard is in idle mode, the matrix is scanned at a slow rate when idle
+pub(crate) static IDLE_STATE: AtomicBool = AtomicBool::new(false);
 
 /// Current default connection type
 pub enum ConnectionType {
@@ -45,6 +51,11 @@ pub fn get_connection_state() -> ConnectionState {
     CONNECTION_STATE.load(Ordering::Acquire).into()
 }
 
+/// Current lock LED state(NumLock, CapsLock, ScrollLock, Compose and Kana) set by the host
+pub fn get_lock_led_state() -> LedIndicator {
+    LedIndicator::from_bits(LOCK_LED_STATES.load(Ordering::Relaxed))
+}
+
 impl From<bool> for ConnectionState {
     fn from(value: bool) -> Self {
         if value {
@@ -1,4 +1,7 @@
 pub mod dummy_flash;
+#[cfg(feature = "spi_flash")]
+pub mod spi_flash;
+mod stats;
 
 use core::fmt::Debug;
 use core::ops::Range;
@@ -6,7 +9,7 @@ use core::ops::Range;
 use byteorder::{BigEndian, ByteOrder};
 use embassy_embedded_hal::adapter::BlockingAsync;
 use embassy_sync::signal::Signal;
-use embassy_time::Duration;
+use embassy_time::{Duration, with_timeout};
 use embedded_storage::nor_flash::NorFlash;
 use embedded_storage_async::nor_flash::NorFlash as AsyncNorFlash;
 use heapless::Vec;
@@ -28,16 +31,45 @@ use crate::channel::FLASH_CHANNEL;
 use crate::combo::Combo;
 use crate::config::{self, StorageConfig};
 use crate::fork::{Fork, StateBits};
+use crate::light::{BacklightConfig, RgbLightConfig, RgbMatrixConfig};
 use crate::morse::{Morse, MorseMode, MorsePattern};
 #[cfg(all(feature = "_ble", feature = "split"))]
 use crate::split::ble::PeerAddress;
 use crate::via::keycode_convert::{from_via_keycode, to_via_keycode};
 use crate::{BUILD_HASH, COMBO_MAX_LENGTH, COMBO_MAX_NUM, FORK_MAX_NUM, MACRO_SPACE_SIZE, MORSE_MAX_NUM};
 
+pub use stats::{StorageStats, compact_storage, read_storage_stats};
+
+/// Version of the layout of `StorageData` in the flash.
+///
+/// Bump it when a saved item can't be read correctly by the new firmware, and add the migration from the previous
+/// version to `Storage::migrate`. Version 0 is the storage saved before the version is added.
+pub(crate) const STORAGE_SCHEMA_VERSION: u8 = 1;
+
+/// Max number of keys in a `FlashOperationMessage::KeymapBatch`, which is the max number of keys in a Via report
+pub(crate) const KEYMAP_BATCH_MAX_NUM: usize = 14;
+
 /// Signal to synchronize the flash operation status, usually used outside of the flash task.
 /// True if the flash operation is finished correctly, false if the flash operation is finished with error.
 pub(crate) static FLASH_OPERATION_FINISHED: Signal<crate::RawMutex, bool> = Signal::new();
 
+/// Signal that all flash operations sent before a `FlashOperationMessage::Flush` are done.
+static FLASH_FLUSHED: Signal<crate::RawMutex, ()> = Signal::new();
+
+/// Wait until all pending flash operations in `FLASH_CHANNEL` are done, for example before a reboot.
+///
+/// Gives up after 1s, in case the flash task isn't running.
+pub(crate) async fn flush_flash_operations() {
+    FLASH_FLUSHED.reset();
+    let flush = async {
+        FLASH_CHANNEL.send(FlashOperationMessage::Flush).await;
+        FLASH_FLUSHED.wait().await;
+    };
+    if with_timeout(Duration::from_secs(1), flush).await.is_err() {
+        warn!("Timeout waiting for pending flash operations");
+    }
+}
+
 // Message send from bonder to flash task, which will do saving or clearing operation
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -48,15 +80,26 @@ pub(crate) enum FlashOperationMessage {
     #[cfg(feature = "_ble")]
     // Current active BLE profile number
     ActiveBleProfile(u8),
+    #[cfg(feature = "_ble")]
+    // Connection type of a BLE profile: (profile, connection type)
+    ProfileConnectionType(u8, u8),
     #[cfg(all(feature = "_ble", feature = "split"))]
     // Peer address
     PeerAddress(PeerAddress),
     // Clear the storage
     Reset,
+    // Notify that all previous operations are done
+    Flush,
+    // Read the usage of the storage
+    ReadStats,
+    // Store the latest values again if the storage is more than half full
+    Compact,
     // Clear the layout info
     ResetLayout,
     // Clear info of given slot number
     ClearSlot(u8),
+    // Clear info of all slots
+    ClearAllSlots,
     // Layout option
     LayoutOptions(u32),
     // Default layer number
@@ -70,14 +113,17 @@ pub(crate) enum FlashOperationMessage {
         row: u8,
         action: KeyAction,
     },
+    // Write a contiguous run of keys in keymap, starting from the offset in the order of layer, row and col
+    KeymapBatch {
+        offset: u16,
+        actions: Vec<KeyAction, KEYMAP_BATCH_MAX_NUM>,
+    },
     // Write encoder configuration
     EncoderKey {
         idx: u8,
         layer: u8,
         action: EncoderAction,
     },
-    // Current saved connection type
-    ConnectionType(u8),
     // Write combo
     WriteCombo(ComboData),
     // Write fork
@@ -98,6 +144,16 @@ pub(crate) enum FlashOperationMessage {
     PriorIdleTime(u16),
     // Whether the unilateral tap is enabled
     UnilateralTap(bool),
+    // Whether the N-key rollover report is used
+    Nkro(bool),
+    // Whether the bypass mode is on
+    Bypass(bool),
+    // State of the RGB underglow
+    RgbConfig(RgbLightConfig),
+    // State of the backlight
+    BacklightConfig(BacklightConfig),
+    // State of the RGB matrix
+    RgbMatrixConfig(RgbMatrixConfig),
 }
 
 /// StorageKeys is the prefix digit stored in the flash, it's used to identify the type of the stored data.
@@ -116,6 +172,11 @@ pub(crate) enum StorageKeys {
     EncoderKeys = 7,
     ForkData = 8,
     MorseData = 9,
+    RgbLightConfig = 10,
+    BacklightConfig = 11,
+    RgbMatrixConfig = 12,
+    #[cfg(feature = "_ble")]
+    ProfileConnectionType = 0xEC,
     #[cfg(all(feature = "_ble", feature = "split"))]
     PeerAddress = 0xED,
     #[cfg(feature = "_ble")]
@@ -137,6 +198,11 @@ impl StorageKeys {
             7 => Some(StorageKeys::EncoderKeys),
             8 => Some(StorageKeys::ForkData),
             9 => Some(StorageKeys::MorseData),
+            10 => Some(StorageKeys::RgbLightConfig),
+            11 => Some(StorageKeys::BacklightConfig),
+            12 => Some(StorageKeys::RgbMatrixConfig),
+            #[cfg(feature = "_ble")]
+            0xEC => Some(StorageKeys::ProfileConnectionType),
             #[cfg(all(feature = "_ble", feature = "split"))]
             0xED => Some(StorageKeys::PeerAddress),
             #[cfg(feature = "_ble")]
@@ -158,15 +224,23 @@ pub(crate) enum StorageData {
     EncoderConfig(EncoderConfig),
     MacroData([u8; MACRO_SPACE_SIZE]),
     ComboData(ComboData),
+    /// Connection type shared by all profiles, saved by the old firmware.
+    /// It's used as the default connection type of profiles now.
     ConnectionType(u8),
     ForkData(ForkData),
     MorseData(Morse),
+    RgbLightConfig(RgbLightConfig),
+    BacklightConfig(BacklightConfig),
+    RgbMatrixConfig(RgbMatrixConfig),
     #[cfg(all(feature = "_ble", feature = "split"))]
     PeerAddress(PeerAddress),
     #[cfg(feature = "_ble")]
     BondInfo(ProfileInfo),
     #[cfg(feature = "_ble")]
     ActiveBleProfile(u8),
+    /// Connection type of a BLE profile: (profile, connection type)
+    #[cfg(feature = "_ble")]
+    ProfileConnectionType(u8, u8),
 }
 
 /// Get the key to retrieve the keymap key from the storage.
@@ -183,6 +257,18 @@ pub(crate) fn get_bond_info_key(slot_num: u8) -> u32 {
     0x2000 + slot_num as u32
 }
 
+/// Empty bond info which clears the given slot.
+///
+/// Remove item in `sequential-storage` is quite expensive, so just override the item with `removed = true`
+#[cfg(feature = "_ble")]
+fn removed_bond_info(slot_num: u8) -> StorageData {
+    StorageData::BondInfo(ProfileInfo {
+        slot_num,
+        removed: true,
+        ..Default::default()
+    })
+}
+
 /// Get the key to retrieve the combo from the storage.
 pub(crate) fn get_combo_key(idx: usize) -> u32 {
     0x3000 + idx as u32
@@ -207,6 +293,11 @@ pub(crate) fn get_morse_key(idx: u8) -> u32 {
     0x7000 + idx as u32
 }
 
+/// Get the key to retrieve the connection type of a BLE profile from the storage.
+pub(crate) fn get_profile_connection_type_key(profile: u8) -> u32 {
+    0x8000 + profile as u32
+}
+
 // TODO: Move ser/de code to corresponding structs
 impl Value<'_> for StorageData {
     fn serialize_into(&self, buffer: &mut [u8]) -> Result<usize, SerializationError> {
@@ -224,7 +315,8 @@ impl Value<'_> for StorageData {
                 }
                 // Save build_hash
                 BigEndian::write_u32(&mut buffer[2..6], c.build_hash);
-                Ok(6)
+                buffer[6] = c.schema_version;
+                Ok(7)
             }
             StorageData::LayoutConfig(c) => {
                 buffer[0] = StorageKeys::LayoutConfig as u8;
@@ -245,7 +337,17 @@ impl Value<'_> for StorageData {
                 } else {
                     buffer[13] = 0
                 }
-                Ok(14)
+                if c.nkro {
+                    buffer[14] = 1
+                } else {
+                    buffer[14] = 0
+                }
+                if c.bypass {
+                    buffer[15] = 1
+                } else {
+                    buffer[15] = 0
+                }
+                Ok(16)
             }
             StorageData::KeymapKey(k) => {
                 buffer[0] = StorageKeys::KeymapConfig as u8;
@@ -359,6 +461,30 @@ impl Value<'_> for StorageData {
                 buffer[1] = *ty;
                 Ok(2)
             }
+            StorageData::RgbLightConfig(c) => {
+                buffer[0] = StorageKeys::RgbLightConfig as u8;
+                buffer[1] = c.brightness;
+                buffer[2] = c.effect;
+                buffer[3] = c.effect_speed;
+                buffer[4] = c.hue;
+                buffer[5] = c.saturation;
+                Ok(6)
+            }
+            StorageData::BacklightConfig(c) => {
+                buffer[0] = StorageKeys::BacklightConfig as u8;
+                buffer[1] = c.enabled as u8;
+                buffer[2] = c.brightness;
+                Ok(3)
+            }
+            StorageData::RgbMatrixConfig(c) => {
+                buffer[0] = StorageKeys::RgbMatrixConfig as u8;
+                buffer[1] = c.brightness;
+                buffer[2] = c.effect;
+                buffer[3] = c.effect_speed;
+                buffer[4] = c.hue;
+                buffer[5] = c.saturation;
+                Ok(6)
+            }
             #[cfg(all(feature = "_ble", feature = "split"))]
             StorageData::PeerAddress(p) => {
                 if buffer.len() < 9 {
@@ -377,6 +503,13 @@ impl Value<'_> for StorageData {
                 Ok(2)
             }
             #[cfg(feature = "_ble")]
+            StorageData::ProfileConnectionType(profile, ty) => {
+                buffer[0] = StorageKeys::ProfileConnectionType as u8;
+                buffer[1] = *profile;
+                buffer[2] = *ty;
+                Ok(3)
+            }
+            #[cfg(feature = "_ble")]
             StorageData::BondInfo(b) => {
                 if buffer.len() < 40 + CCCD_TABLE_SIZE * 4 {
                     return Err(SerializationError::BufferTooSmall);
@@ -429,13 +562,17 @@ impl Value<'_> for StorageData {
                         Ok(StorageData::StorageConfig(LocalStorageConfig {
                             enable: false,
                             build_hash: BUILD_HASH,
+                            schema_version: STORAGE_SCHEMA_VERSION,
                         }))
                     } else {
                         // Enabled, read build hash
                         let build_hash = BigEndian::read_u32(&buffer[2..6]);
+                        // The schema version isn't saved by the old firmware
+                        let schema_version = buffer.get(6).copied().unwrap_or(0);
                         Ok(StorageData::StorageConfig(LocalStorageConfig {
                             enable: true,
                             build_hash,
+                            schema_version,
                         }))
                     }
                 }
@@ -473,6 +610,9 @@ impl Value<'_> for StorageData {
                         tap_capslock_interval: BigEndian::read_u16(&buffer[9..11]),
                         prior_idle_time: BigEndian::read_u16(&buffer[11..13]),
                         unilateral_tap: buffer[13] == 1,
+                        // Not present in the config saved by older versions
+                        nkro: buffer.get(14) == Some(&1),
+                        bypass: buffer.get(15) == Some(&1),
                     };
                     Ok(StorageData::BehaviorConfig(keymap_config))
                 }
@@ -507,6 +647,39 @@ impl Value<'_> for StorageData {
                     }))
                 }
                 StorageKeys::ConnectionType => Ok(StorageData::ConnectionType(buffer[1])),
+                StorageKeys::RgbLightConfig => {
+                    if buffer.len() < 6 {
+                        return Err(SerializationError::BufferTooSmall);
+                    }
+                    Ok(StorageData::RgbLightConfig(RgbLightConfig {
+                        brightness: buffer[1],
+                        effect: buffer[2],
+                        effect_speed: buffer[3],
+                        hue: buffer[4],
+                        saturation: buffer[5],
+                    }))
+                }
+                StorageKeys::BacklightConfig => {
+                    if buffer.len() < 3 {
+                        return Err(SerializationError::BufferTooSmall);
+                    }
+                    Ok(StorageData::BacklightConfig(BacklightConfig {
+                        enabled: buffer[1] != 0,
+                        brightness: buffer[2],
+                    }))
+                }
+                StorageKeys::RgbMatrixConfig => {
+                    if buffer.len() < 6 {
+                        return Err(SerializationError::BufferTooSmall);
+                    }
+                    Ok(StorageData::RgbMatrixConfig(RgbMatrixConfig {
+                        brightness: buffer[1],
+                        effect: buffer[2],
+                        effect_speed: buffer[3],
+                        hue: buffer[4],
+                        saturation: buffer[5],
+                    }))
+                }
                 StorageKeys::EncoderKeys => {
                     if buffer.len() < 7 {
                         return Err(SerializationError::BufferTooSmall);
@@ -615,6 +788,13 @@ impl Value<'_> for StorageData {
                     Ok(StorageData::ActiveBleProfile(buffer[1]))
                 }
                 #[cfg(feature = "_ble")]
+                StorageKeys::ProfileConnectionType => {
+                    if buffer.len() < 3 {
+                        return Err(SerializationError::BufferTooSmall);
+                    }
+                    Ok(StorageData::ProfileConnectionType(buffer[1], buffer[2]))
+                }
+                #[cfg(feature = "_ble")]
                 StorageKeys::BleBondInfo => {
                     if buffer.len() < 40 + CCCD_TABLE_SIZE * 4 {
                         return Err(SerializationError::BufferTooSmall);
@@ -679,6 +859,9 @@ impl StorageData {
                 panic!("To get combo key for ComboData, use `get_combo_key` instead");
             }
             StorageData::ConnectionType(_) => StorageKeys::ConnectionType as u32,
+            StorageData::RgbLightConfig(_) => StorageKeys::RgbLightConfig as u32,
+            StorageData::BacklightConfig(_) => StorageKeys::BacklightConfig as u32,
+            StorageData::RgbMatrixConfig(_) => StorageKeys::RgbMatrixConfig as u32,
             StorageData::ForkData(_) => {
                 panic!("To get fork key for ForkData, use `get_fork_key` instead");
             }
@@ -690,6 +873,8 @@ impl StorageData {
             #[cfg(feature = "_ble")]
             StorageData::ActiveBleProfile(_) => StorageKeys::ActiveBleProfile as u32,
             #[cfg(feature = "_ble")]
+            StorageData::ProfileConnectionType(profile, _) => get_profile_connection_type_key(*profile),
+            #[cfg(feature = "_ble")]
             StorageData::BondInfo(b) => get_bond_info_key(b.slot_num),
         }
     }
@@ -699,6 +884,8 @@ impl StorageData {
 pub(crate) struct LocalStorageConfig {
     enable: bool,
     build_hash: u32,
+    /// Version of the layout of the saved items, see `STORAGE_SCHEMA_VERSION`
+    schema_version: u8,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -761,6 +948,10 @@ pub(crate) struct BehaviorConfig {
     pub(crate) prior_idle_time: u16,
     // Whether the unilateral tap is enabled
     pub(crate) unilateral_tap: bool,
+    // Whether the N-key rollover report is used
+    pub(crate) nkro: bool,
+    // Whether the bypass mode is on
+    pub(crate) bypass: bool,
 }
 
 pub fn async_flash_wrapper<F: NorFlash>(flash: F) -> BlockingAsync<F> {
@@ -857,14 +1048,35 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
             start_addr as u32..(start_addr + storage_config.num_sectors as usize * F::ERASE_SIZE) as u32
         };
 
+        // All macros are saved as one item, which can't cross the sector boundary
+        const {
+            assert!(
+                get_buffer_size() <= max_item_size::<F>(),
+                "Macro space size is too large, the macros can't fit into a flash sector"
+            )
+        };
+
         let mut storage = Self {
             flash,
             storage_range,
             buffer: [0; get_buffer_size()],
         };
 
-        // Check whether keymap and configs have been storaged in flash
-        if !storage.check_enable().await || storage_config.clear_storage {
+        // Check whether keymap and configs have been storaged in flash, and whether they can be read by this firmware
+        let compatible = match storage.read_schema_version().await {
+            None => false,
+            Some(version) if version == STORAGE_SCHEMA_VERSION => true,
+            Some(version) if version < STORAGE_SCHEMA_VERSION => storage.migrate(version).await,
+            Some(version) => {
+                // Saved by a newer firmware, the items might be misinterpreted
+                warn!(
+                    "Storage schema version {} is newer than {}, clearing storage",
+                    version, STORAGE_SCHEMA_VERSION
+                );
+                false
+            }
+        };
+        if !compatible || storage_config.clear_storage {
             // Clear storage first
             debug!("Clearing storage!");
             let _ = sequential_storage::erase_all(&mut storage.flash, storage.storage_range.clone()).await;
@@ -885,6 +1097,7 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
                     &StorageData::StorageConfig(LocalStorageConfig {
                         enable: false,
                         build_hash: BUILD_HASH,
+                        schema_version: STORAGE_SCHEMA_VERSION,
                     }),
                 )
                 .await
@@ -921,6 +1134,12 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
                 FlashOperationMessage::Reset => {
                     sequential_storage::erase_all(&mut self.flash, self.storage_range.clone()).await
                 }
+                FlashOperationMessage::Flush => {
+                    FLASH_FLUSHED.signal(());
+                    Ok(())
+                }
+                FlashOperationMessage::ReadStats => self.send_stats().await,
+                FlashOperationMessage::Compact => self.compact().await,
                 FlashOperationMessage::ResetLayout => {
                     info!("Ignoring ResetLayout at runtime (handled at startup via clear_layout).");
                     Ok(())
@@ -971,6 +1190,9 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
                     )
                     .await
                 }
+                FlashOperationMessage::KeymapBatch { offset, actions } => {
+                    self.write_keymap_batch(offset as usize, &actions).await
+                }
                 FlashOperationMessage::WriteCombo(combo) => {
                     let key = get_combo_key(combo.idx);
                     store_item(
@@ -983,14 +1205,36 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
                     )
                     .await
                 }
-                FlashOperationMessage::ConnectionType(ty) => {
+                FlashOperationMessage::RgbConfig(rgb_light) => {
+                    store_item(
+                        &mut self.flash,
+                        self.storage_range.clone(),
+                        &mut storage_cache,
+                        &mut self.buffer,
+                        &(StorageKeys::RgbLightConfig as u32),
+                        &StorageData::RgbLightConfig(rgb_light),
+                    )
+                    .await
+                }
+                FlashOperationMessage::BacklightConfig(backlight) => {
+                    store_item(
+                        &mut self.flash,
+                        self.storage_range.clone(),
+                        &mut storage_cache,
+                        &mut self.buffer,
+                        &(StorageKeys::BacklightConfig as u32),
+                        &StorageData::BacklightConfig(backlight),
+                    )
+                    .await
+                }
+                FlashOperationMessage::RgbMatrixConfig(rgb_matrix) => {
                     store_item(
                         &mut self.flash,
                         self.storage_range.clone(),
                         &mut storage_cache,
                         &mut self.buffer,
-                        &(StorageKeys::ConnectionType as u32),
-                        &StorageData::ConnectionType(ty),
+                        &(StorageKeys::RgbMatrixConfig as u32),
+                        &StorageData::RgbMatrixConfig(rgb_matrix),
                     )
                     .await
                 }
@@ -1063,12 +1307,22 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
                     .await
                 }
                 #[cfg(feature = "_ble")]
+                FlashOperationMessage::ProfileConnectionType(profile, ty) => {
+                    let data = StorageData::ProfileConnectionType(profile, ty);
+                    store_item::<u32, StorageData, _>(
+                        &mut self.flash,
+                        self.storage_range.clone(),
+                        &mut storage_cache,
+                        &mut self.buffer,
+                        &data.key(),
+                        &data,
+                    )
+                    .await
+                }
+                #[cfg(feature = "_ble")]
                 FlashOperationMessage::ClearSlot(key) => {
                     info!("Clearing bond info slot_num: {}", key);
-                    // Remove item in `sequential-storage` is quite expensive, so just override the item with `removed = true`
-                    let mut empty = ProfileInfo::default();
-                    empty.removed = true;
-                    let data = StorageData::BondInfo(empty);
+                    let data = removed_bond_info(key);
                     store_item::<u32, StorageData, _>(
                         &mut self.flash,
                         self.storage_range.clone(),
@@ -1080,6 +1334,28 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
                     .await
                 }
                 #[cfg(feature = "_ble")]
+                FlashOperationMessage::ClearAllSlots => {
+                    info!("Clearing bond info of all slots");
+                    let mut result = Ok(());
+                    for slot_num in 0..crate::NUM_BLE_PROFILE as u8 {
+                        let data = removed_bond_info(slot_num);
+                        if let Err(e) = store_item::<u32, StorageData, _>(
+                            &mut self.flash,
+                            self.storage_range.clone(),
+                            &mut storage_cache,
+                            &mut self.buffer,
+                            &data.key(),
+                            &data,
+                        )
+                        .await
+                        {
+                            // Keep clearing the remaining slots
+                            result = Err(e);
+                        }
+                    }
+                    result
+                }
+                #[cfg(feature = "_ble")]
                 FlashOperationMessage::ProfileInfo(b) => {
                     debug!("Saving profile info: {:?}", b);
                     let data = StorageData::BondInfo(b);
@@ -1149,6 +1425,22 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
                     unilateral_tap,
                     self.storage_range.clone()
                 ),
+                FlashOperationMessage::Nkro(nkro) => update_storage_field!(
+                    &mut self.flash,
+                    &mut self.buffer,
+                    &mut storage_cache,
+                    BehaviorConfig,
+                    nkro,
+                    self.storage_range.clone()
+                ),
+                FlashOperationMessage::Bypass(bypass) => update_storage_field!(
+                    &mut self.flash,
+                    &mut self.buffer,
+                    &mut storage_cache,
+                    BehaviorConfig,
+                    bypass,
+                    self.storage_range.clone()
+                ),
                 #[cfg(not(feature = "_ble"))]
                 _ => Ok(()),
             } {
@@ -1205,6 +1497,54 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
         Ok(())
     }
 
+    /// Write a contiguous run of keys, starting from the offset in the order of layer, row and col.
+    ///
+    /// Keys which are not changed are skipped, so importing a keymap which is mostly the same doesn't wear the flash.
+    pub(crate) async fn write_keymap_batch(
+        &mut self,
+        offset: usize,
+        actions: &[KeyAction],
+    ) -> Result<(), SSError<F::Error>> {
+        let mut storage_cache = NoCache::new();
+        for (i, action) in actions.iter().enumerate() {
+            let idx = offset + i;
+            if idx >= ROW * COL * NUM_LAYER {
+                warn!("Keymap batch out of range, offset: {}, len: {}", offset, actions.len());
+                break;
+            }
+            let (layer, row, col) = (idx / (ROW * COL), idx % (ROW * COL) / COL, idx % COL);
+            let key = get_keymap_key::<ROW, COL, NUM_LAYER>(row, col, layer);
+            if let Ok(Some(StorageData::KeymapKey(saved))) = fetch_item::<u32, StorageData, _>(
+                &mut self.flash,
+                self.storage_range.clone(),
+                &mut storage_cache,
+                &mut self.buffer,
+                &key,
+            )
+            .await
+                && saved.action == *action
+            {
+                continue;
+            }
+            let data = StorageData::KeymapKey(KeymapKey {
+                row,
+                col,
+                layer,
+                action: *action,
+            });
+            store_item(
+                &mut self.flash,
+                self.storage_range.clone(),
+                &mut storage_cache,
+                &mut self.buffer,
+                &key,
+                &data,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
     pub(crate) async fn read_macro_cache(&mut self, macro_cache: &mut [u8]) -> Result<(), ()> {
         // Read storage and send back from send_channel
         let read_data = fetch_item::<u32, StorageData, _>(
@@ -1309,6 +1649,10 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
             behavior_config.tap_hold.timeout = Duration::from_millis(c.morse_timeout as u64);
             behavior_config.tap_hold.prior_idle_time = Duration::from_millis(c.prior_idle_time as u64);
             behavior_config.tap_hold.unilateral_tap = c.unilateral_tap;
+            behavior_config.nkro = c.nkro;
+            if behavior_config.bypass.persist {
+                behavior_config.bypass.enable = c.bypass;
+            }
             behavior_config.combo.timeout = Duration::from_millis(c.combo_timeout as u64);
             behavior_config.one_shot.timeout = Duration::from_millis(c.one_shot_timeout as u64);
             behavior_config.tap.tap_interval = c.tap_interval;
@@ -1318,6 +1662,82 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
         Ok(())
     }
 
+    pub(crate) async fn read_rgb_light_config(&mut self, rgb_light: &mut RgbLightConfig) -> Result<(), ()> {
+        if let Some(StorageData::RgbLightConfig(c)) = fetch_item::<u32, StorageData, _>(
+            &mut self.flash,
+            self.storage_range.clone(),
+            &mut NoCache::new(),
+            &mut self.buffer,
+            &(StorageKeys::RgbLightConfig as u32),
+        )
+        .await
+        .map_err(|e| print_storage_error::<F>(e))?
+        {
+            *rgb_light = c;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn read_backlight_config(&mut self, backlight: &mut BacklightConfig) -> Result<(), ()> {
+        if let Some(StorageData::BacklightConfig(c)) = fetch_item::<u32, StorageData, _>(
+            &mut self.flash,
+            self.storage_range.clone(),
+            &mut NoCache::new(),
+            &mut self.buffer,
+            &(StorageKeys::BacklightConfig as u32),
+        )
+        .await
+        .map_err(|e| print_storage_error::<F>(e))?
+        {
+            *backlight = c;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn read_rgb_matrix_config(&mut self, rgb_matrix: &mut RgbMatrixConfig) -> Result<(), ()> {
+        if let Some(StorageData::RgbMatrixConfig(c)) = fetch_item::<u32, StorageData, _>(
+            &mut self.flash,
+            self.storage_range.clone(),
+            &mut NoCache::new(),
+            &mut self.buffer,
+            &(StorageKeys::RgbMatrixConfig as u32),
+        )
+        .await
+        .map_err(|e| print_storage_error::<F>(e))?
+        {
+            *rgb_matrix = c;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn read_layout_config(
+        &mut self,
+        layout_option: &mut u32,
+        default_layer: &mut u8,
+    ) -> Result<(), ()> {
+        if let Some(StorageData::LayoutConfig(c)) = fetch_item::<u32, StorageData, _>(
+            &mut self.flash,
+            self.storage_range.clone(),
+            &mut NoCache::new(),
+            &mut self.buffer,
+            &(StorageKeys::LayoutConfig as u32),
+        )
+        .await
+        .map_err(|e| print_storage_error::<F>(e))?
+        {
+            *layout_option = c.layout_option;
+            // The number of layers might be changed by a new firmware
+            if (c.default_layer as usize) < NUM_LAYER {
+                *default_layer = c.default_layer;
+            }
+        }
+
+        Ok(())
+    }
+
     async fn initialize_storage_with_config(
         &mut self,
         keymap: &[[[KeyAction; COL]; ROW]; NUM_LAYER],
@@ -1329,6 +1749,7 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
         let storage_config = StorageData::StorageConfig(LocalStorageConfig {
             enable: true,
             build_hash: BUILD_HASH,
+            schema_version: STORAGE_SCHEMA_VERSION,
         });
         store_item(
             &mut self.flash,
@@ -1366,6 +1787,8 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
             tap_capslock_interval: behavior.tap.tap_capslock_interval,
             prior_idle_time: behavior.tap_hold.prior_idle_time.as_millis() as u16,
             unilateral_tap: behavior.tap_hold.unilateral_tap,
+            nkro: behavior.nkro,
+            bypass: behavior.bypass.enable,
         });
 
         store_item(
@@ -1464,6 +1887,8 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
             tap_capslock_interval: behavior.tap.tap_capslock_interval,
             prior_idle_time: behavior.tap_hold.prior_idle_time.as_millis() as u16,
             unilateral_tap: behavior.tap_hold.unilateral_tap,
+            nkro: behavior.nkro,
+            bypass: behavior.bypass.enable,
         });
         store_item(
             &mut self.flash,
@@ -1523,7 +1948,8 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
         Ok(())
     }
 
-    async fn check_enable(&mut self) -> bool {
+    /// Schema version of the saved items, `None` if the storage isn't initialized
+    async fn read_schema_version(&mut self) -> Option<u8> {
         if let Ok(Some(StorageData::StorageConfig(config))) = fetch_item::<u32, StorageData, _>(
             &mut self.flash,
             self.storage_range.clone(),
@@ -1535,10 +1961,64 @@ impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usiz
         {
             // if config.enable && config.build_hash == BUILD_HASH {
             if config.enable {
-                return true;
+                return Some(config.schema_version);
             }
         }
-        false
+        None
+    }
+
+    /// Migrate the items saved by an older firmware, returns false if they can't be migrated and should be cleared.
+    ///
+    /// The new version is saved after all steps are done, so the migration is run again if it's interrupted.
+    /// Every step must be safe to run twice.
+    async fn migrate(&mut self, from: u8) -> bool {
+        info!("Migrating storage schema from {} to {}", from, STORAGE_SCHEMA_VERSION);
+        for version in from..STORAGE_SCHEMA_VERSION {
+            match version {
+                // The version is added without changing the layout of items
+                0 => (),
+                _ => {
+                    warn!("No migration from storage schema version {}", version);
+                    return false;
+                }
+            }
+        }
+
+        let storage_config = StorageData::StorageConfig(LocalStorageConfig {
+            enable: true,
+            build_hash: BUILD_HASH,
+            schema_version: STORAGE_SCHEMA_VERSION,
+        });
+        store_item(
+            &mut self.flash,
+            self.storage_range.clone(),
+            &mut NoCache::new(),
+            &mut self.buffer,
+            &storage_config.key(),
+            &storage_config,
+        )
+        .await
+        .map_err(|e| print_storage_error::<F>(e))
+        .is_ok()
+    }
+
+    #[cfg(feature = "_ble")]
+    pub(crate) async fn read_profile_connection_type(&mut self, profile: u8) -> Result<Option<u8>, ()> {
+        let read_data = fetch_item::<u32, StorageData, _>(
+            &mut self.flash,
+            self.storage_range.clone(),
+            &mut NoCache::new(),
+            &mut self.buffer,
+            &get_profile_connection_type_key(profile),
+        )
+        .await
+        .map_err(|e| print_storage_error::<F>(e))?;
+
+        if let Some(StorageData::ProfileConnectionType(_, ty)) = read_data {
+            Ok(Some(ty))
+        } else {
+            Ok(None)
+        }
     }
 
     #[cfg(feature = "_ble")]
@@ -1627,6 +2107,16 @@ const fn get_buffer_size() -> usize {
     (buffer_size + 31) & !31
 }
 
+/// Max size of an item in a flash sector, the sector also holds two page state words and the header of the item
+const fn max_item_size<F: AsyncNorFlash>() -> usize {
+    let word_size = if F::READ_SIZE > F::WRITE_SIZE {
+        F::READ_SIZE
+    } else {
+        F::WRITE_SIZE
+    };
+    F::ERASE_SIZE - 2 * word_size - sequential_storage::item_overhead_size::<F>() as usize
+}
+
 #[macro_export]
 /// Helper macro for reading storage config
 macro_rules! read_storage {
@@ -1644,13 +2134,347 @@ macro_rules! read_storage {
 
 #[cfg(test)]
 mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use embassy_futures::block_on;
+    use embedded_storage::nor_flash::{ErrorType, NorFlashErrorKind, ReadNorFlash};
     use rmk_types::action::Action;
     use rmk_types::keycode::KeyCode;
     use sequential_storage::map::Value;
 
     use super::*;
+    use crate::k;
     use crate::morse::{HOLD, MorseMode, TAP};
 
+    /// Flash in RAM with 2 sectors
+    struct RamFlash {
+        data: [u8; 2 * 4096],
+    }
+
+    impl ErrorType for RamFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for RamFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl NorFlash for RamFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 4096;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            self.data[from as usize..to as usize].fill(0xFF);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            // Writing can only clear bits
+            for (d, b) in self.data[offset..offset + bytes.len()].iter_mut().zip(bytes) {
+                *d &= *b;
+            }
+            Ok(())
+        }
+    }
+
+    /// `RamFlash` which loses the power after some writes: the next write is incomplete and fails
+    struct PowerLossFlash {
+        flash: RamFlash,
+        writes_left: Rc<Cell<usize>>,
+    }
+
+    impl ErrorType for PowerLossFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for PowerLossFlash {
+        const READ_SIZE: usize = RamFlash::READ_SIZE;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            self.flash.read(offset, bytes)
+        }
+
+        fn capacity(&self) -> usize {
+            self.flash.capacity()
+        }
+    }
+
+    impl NorFlash for PowerLossFlash {
+        const WRITE_SIZE: usize = RamFlash::WRITE_SIZE;
+        const ERASE_SIZE: usize = RamFlash::ERASE_SIZE;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            if self.writes_left.get() == 0 {
+                return Err(NorFlashErrorKind::Other);
+            }
+            self.flash.erase(from, to)
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            match self.writes_left.get() {
+                0 => {
+                    // Only a part of the data is written
+                    let half = (bytes.len() / 2).next_multiple_of(Self::WRITE_SIZE).min(bytes.len());
+                    let _ = self.flash.write(offset, &bytes[..half]);
+                    Err(NorFlashErrorKind::Other)
+                }
+                n => {
+                    self.writes_left.set(n - 1);
+                    self.flash.write(offset, bytes)
+                }
+            }
+        }
+    }
+
+    /// Edit the first key repeatedly until less than half of the storage is free, the last written action is `A`
+    async fn fill_storage<F: AsyncNorFlash>(storage: &mut Storage<F, 2, 2, 2>) {
+        loop {
+            let stats = storage.read_stats().await.unwrap();
+            if stats.free_bytes <= stats.used_bytes {
+                break;
+            }
+            storage.write_keymap_batch(0, &[k!(B)]).await.unwrap();
+            storage.write_keymap_batch(0, &[k!(A)]).await.unwrap();
+        }
+    }
+
+    async fn new_test_storage<F: AsyncNorFlash>(flash: F, keymap: &[[[KeyAction; 2]; 2]; 2]) -> Storage<F, 2, 2, 2> {
+        Storage::new(
+            flash,
+            keymap,
+            &None,
+            &StorageConfig::default(),
+            &config::BehaviorConfig::default(),
+        )
+        .await
+    }
+
+    async fn write_schema_version<F: AsyncNorFlash>(storage: &mut Storage<F, 2, 2, 2>, schema_version: u8) {
+        let data = StorageData::StorageConfig(LocalStorageConfig {
+            enable: true,
+            build_hash: BUILD_HASH,
+            schema_version,
+        });
+        store_item(
+            &mut storage.flash,
+            storage.storage_range.clone(),
+            &mut NoCache::new(),
+            &mut storage.buffer,
+            &data.key(),
+            &data,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[cfg(feature = "_ble")]
+    #[test]
+    fn test_clear_bond_info_slot() {
+        block_on(async {
+            let flash = async_flash_wrapper(RamFlash { data: [0xFF; 2 * 4096] });
+            let mut storage = new_test_storage(flash, &[[[KeyAction::No; 2]; 2]; 2]).await;
+            for slot_num in [0, 2] {
+                let mut info = ProfileInfo {
+                    slot_num,
+                    ..Default::default()
+                };
+                info.info.ltk = LongTermKey(0x1234);
+                let data = StorageData::BondInfo(info);
+                store_item(
+                    &mut storage.flash,
+                    storage.storage_range.clone(),
+                    &mut NoCache::new(),
+                    &mut storage.buffer,
+                    &data.key(),
+                    &data,
+                )
+                .await
+                .unwrap();
+            }
+
+            // Only the cleared slot is overridden
+            let data = removed_bond_info(2);
+            store_item(
+                &mut storage.flash,
+                storage.storage_range.clone(),
+                &mut NoCache::new(),
+                &mut storage.buffer,
+                &data.key(),
+                &data,
+            )
+            .await
+            .unwrap();
+            let info = storage.read_trouble_bond_info(0).await.unwrap().unwrap();
+            assert_eq!(info.info.ltk, LongTermKey(0x1234));
+            let info = storage.read_trouble_bond_info(2).await.unwrap().unwrap();
+            assert_eq!(info.slot_num, 2);
+            assert_eq!(info.info.ltk, LongTermKey(0));
+        });
+    }
+
+    #[test]
+    fn test_storage_config_without_schema_version() {
+        // Saved by the firmware before the schema version is added
+        let mut buffer = [0u8; 6];
+        buffer[0] = StorageKeys::StorageConfig as u8;
+        match StorageData::deserialize_from(&buffer).unwrap() {
+            StorageData::StorageConfig(config) => {
+                assert!(config.enable);
+                assert_eq!(config.schema_version, 0);
+            }
+            _ => panic!("Expected StorageConfig"),
+        }
+    }
+
+    #[test]
+    fn test_storage_schema_migration() {
+        block_on(async {
+            let default_keymap = [[[KeyAction::No; 2]; 2]; 2];
+            let flash = async_flash_wrapper(RamFlash { data: [0xFF; 2 * 4096] });
+            let mut storage = new_test_storage(flash, &default_keymap).await;
+            storage.write_keymap_batch(0, &[k!(A)]).await.unwrap();
+
+            // Items of an older version are migrated and kept
+            write_schema_version(&mut storage, 0).await;
+            let mut storage = new_test_storage(storage.flash, &default_keymap).await;
+            assert_eq!(storage.read_schema_version().await, Some(STORAGE_SCHEMA_VERSION));
+            let mut keymap = [[[KeyAction::No; 2]; 2]; 2];
+            storage.read_keymap(&mut keymap, &mut None).await.unwrap();
+            assert_eq!(keymap[0][0][0], k!(A));
+
+            // Items of a newer version are cleared
+            write_schema_version(&mut storage, STORAGE_SCHEMA_VERSION + 1).await;
+            let mut storage = new_test_storage(storage.flash, &default_keymap).await;
+            assert_eq!(storage.read_schema_version().await, Some(STORAGE_SCHEMA_VERSION));
+            storage.read_keymap(&mut keymap, &mut None).await.unwrap();
+            assert_eq!(keymap, default_keymap);
+        });
+    }
+
+    #[test]
+    fn test_keymap_batch_write_read_back() {
+        block_on(async {
+            let keymap = [[[KeyAction::No; 2]; 2]; 2];
+            let flash = async_flash_wrapper(RamFlash { data: [0xFF; 2 * 4096] });
+            let mut storage = new_test_storage(flash, &keymap).await;
+
+            // The batch crosses layer 0 and layer 1
+            let actions = [k!(A), k!(B), k!(C), k!(D), k!(E)];
+            storage.write_keymap_batch(2, &actions).await.unwrap();
+
+            // Keys which are not changed aren't written again
+            let stats = storage.read_stats().await.unwrap();
+            storage.write_keymap_batch(2, &actions[..3]).await.unwrap();
+            assert_eq!(storage.read_stats().await.unwrap(), stats);
+
+            // Keys out of the keymap are dropped
+            storage.write_keymap_batch(7, &[k!(F), k!(G)]).await.unwrap();
+
+            let mut read_back = [[[KeyAction::No; 2]; 2]; 2];
+            storage.read_keymap(&mut read_back, &mut None).await.unwrap();
+            assert_eq!(
+                read_back,
+                [
+                    [[KeyAction::No, KeyAction::No], [k!(A), k!(B)]],
+                    [[k!(C), k!(D)], [k!(E), k!(F)]]
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_storage_stats() {
+        block_on(async {
+            let keymap = [[[KeyAction::No; 2]; 2]; 2];
+            let flash = async_flash_wrapper(RamFlash { data: [0xFF; 2 * 4096] });
+            let mut storage = new_test_storage(flash, &keymap).await;
+
+            let stats = storage.read_stats().await.unwrap();
+            assert_eq!(stats.sector_size, 4096);
+            assert_eq!(stats.total_sectors, 2);
+            // One sector is kept erased
+            assert_eq!(stats.used_bytes + stats.free_bytes, 4096);
+
+            storage.write_keymap_batch(0, &[k!(A)]).await.unwrap();
+            let new_stats = storage.read_stats().await.unwrap();
+            assert_eq!(new_stats.stored_items, stats.stored_items + 1);
+            assert!(new_stats.used_bytes > stats.used_bytes);
+            assert_eq!(new_stats.used_bytes + new_stats.free_bytes, 4096);
+        });
+    }
+
+    #[test]
+    fn test_storage_compaction() {
+        block_on(async {
+            let keymap = [[[KeyAction::No; 2]; 2]; 2];
+            let flash = async_flash_wrapper(RamFlash { data: [0xFF; 2 * 4096] });
+            let mut storage = new_test_storage(flash, &keymap).await;
+            storage.write_keymap_batch(1, &[k!(C), k!(D)]).await.unwrap();
+
+            // Nothing is written if more than half of the storage is free
+            let stats = storage.read_stats().await.unwrap();
+            storage.compact().await.unwrap();
+            assert_eq!(storage.read_stats().await.unwrap(), stats);
+
+            fill_storage(&mut storage).await;
+            let stats = storage.read_stats().await.unwrap();
+            storage.compact().await.unwrap();
+            // The latest values are stored again
+            assert!(storage.read_stats().await.unwrap().stored_items > stats.stored_items);
+
+            let mut read_back = [[[KeyAction::No; 2]; 2]; 2];
+            storage.read_keymap(&mut read_back, &mut None).await.unwrap();
+            assert_eq!(
+                read_back,
+                [
+                    [[k!(A), k!(C)], [k!(D), KeyAction::No]],
+                    [[KeyAction::No, KeyAction::No], [KeyAction::No, KeyAction::No]]
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_storage_compaction_with_power_loss() {
+        block_on(async {
+            let keymap = [[[KeyAction::No; 2]; 2]; 2];
+            let writes_left = Rc::new(Cell::new(usize::MAX));
+            let flash = async_flash_wrapper(PowerLossFlash {
+                flash: RamFlash { data: [0xFF; 2 * 4096] },
+                writes_left: writes_left.clone(),
+            });
+            let mut storage = new_test_storage(flash, &keymap).await;
+            storage.write_keymap_batch(1, &[k!(C), k!(D)]).await.unwrap();
+            fill_storage(&mut storage).await;
+
+            // The power is lost in the middle of the compaction
+            writes_left.set(5);
+            assert!(storage.compact().await.is_err());
+
+            // No setting is lost after reboot, and the compaction can be run again
+            writes_left.set(usize::MAX);
+            let mut storage = new_test_storage(storage.flash, &keymap).await;
+            let mut read_back = [[[KeyAction::No; 2]; 2]; 2];
+            storage.read_keymap(&mut read_back, &mut None).await.unwrap();
+            assert_eq!(read_back[0], [[k!(A), k!(C)], [k!(D), KeyAction::No]]);
+            storage.compact().await.unwrap();
+            storage.read_keymap(&mut read_back, &mut None).await.unwrap();
+            assert_eq!(read_back[0], [[k!(A), k!(C)], [k!(D), KeyAction::No]]);
+        });
+    }
+
     #[test]
     fn test_morse_serialization_deserialization() {
         let morse = Morse::new_from_vial(
@@ -1686,6 +2510,66 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_rgb_light_config_serialization_deserialization() {
+        let rgb_light = RgbLightConfig {
+            brightness: 128,
+            effect: 3,
+            effect_speed: 10,
+            hue: 200,
+            saturation: 50,
+        };
+
+        let mut buffer = [0u8; 8];
+        let storage_data = StorageData::RgbLightConfig(rgb_light);
+        let serialized_size = Value::serialize_into(&storage_data, &mut buffer).unwrap();
+        assert_eq!(serialized_size, 6);
+
+        match StorageData::deserialize_from(&buffer[..serialized_size]).unwrap() {
+            StorageData::RgbLightConfig(deserialized) => assert_eq!(deserialized, rgb_light),
+            _ => panic!("Expected RgbLightConfig"),
+        }
+    }
+
+    #[test]
+    fn test_backlight_config_serialization_deserialization() {
+        let backlight = BacklightConfig {
+            enabled: false,
+            brightness: 100,
+        };
+
+        let mut buffer = [0u8; 8];
+        let storage_data = StorageData::BacklightConfig(backlight);
+        let serialized_size = Value::serialize_into(&storage_data, &mut buffer).unwrap();
+        assert_eq!(serialized_size, 3);
+
+        match StorageData::deserialize_from(&buffer[..serialized_size]).unwrap() {
+            StorageData::BacklightConfig(deserialized) => assert_eq!(deserialized, backlight),
+            _ => panic!("Expected BacklightConfig"),
+        }
+    }
+
+    #[test]
+    fn test_rgb_matrix_config_serialization_deserialization() {
+        let rgb_matrix = RgbMatrixConfig {
+            brightness: 200,
+            effect: 3,
+            effect_speed: 64,
+            hue: 85,
+            saturation: 128,
+        };
+
+        let mut buffer = [0u8; 8];
+        let storage_data = StorageData::RgbMatrixConfig(rgb_matrix);
+        let serialized_size = Value::serialize_into(&storage_data, &mut buffer).unwrap();
+        assert_eq!(serialized_size, 6);
+
+        match StorageData::deserialize_from(&buffer[..serialized_size]).unwrap() {
+            StorageData::RgbMatrixConfig(deserialized) => assert_eq!(deserialized, rgb_matrix),
+            _ => panic!("Expected RgbMatrixConfig"),
+        }
+    }
+
     #[test]
     fn test_morse_with_partial_actions() {
         // Create a Morse with partial actions
@@ -0,0 +1,205 @@
+//! Driver of external SPI NOR flash, such as W25Q series.
+//!
+//! Only the standard commands with 3-byte addresses are used, so chips up to 16MB are supported. The driver is
+//! async: while the chip is erasing or programming, the busy bit is polled with a timer, so other tasks such as the
+//! matrix scanning and the report sending keep running during a sector erase.
+use embassy_time::{Duration, Timer};
+use embedded_hal_async::spi::{Operation, SpiDevice};
+use embedded_storage_async::nor_flash::{ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash};
+
+const CMD_PAGE_PROGRAM: u8 = 0x02;
+const CMD_READ: u8 = 0x03;
+const CMD_READ_STATUS: u8 = 0x05;
+const CMD_WRITE_ENABLE: u8 = 0x06;
+const CMD_SECTOR_ERASE: u8 = 0x20;
+const CMD_JEDEC_ID: u8 = 0x9F;
+
+/// Busy bit of the status register
+const STATUS_BUSY: u8 = 0x01;
+
+/// Max number of bytes in a page program, a write can't cross the page boundary
+const PAGE_SIZE: usize = 256;
+
+/// Max capacity which can be addressed with 3-byte addresses
+const MAX_CAPACITY: usize = 1 << 24;
+
+/// Interval of polling the busy bit, a page program takes about 1ms and a sector erase takes tens of ms
+const BUSY_POLL_INTERVAL: Duration = Duration::from_micros(500);
+
+/// Errors of the SPI flash
+#[derive(Debug)]
+pub enum SpiFlashError<E> {
+    /// Error of the SPI bus
+    Spi(E),
+    /// The address or length is out of bounds or not aligned
+    Flash(NorFlashErrorKind),
+}
+
+impl<E: core::fmt::Debug> NorFlashError for SpiFlashError<E> {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            SpiFlashError::Spi(_) => NorFlashErrorKind::Other,
+            SpiFlashError::Flash(kind) => *kind,
+        }
+    }
+}
+
+/// External SPI NOR flash
+pub struct SpiNorFlash<S: SpiDevice> {
+    spi: S,
+    capacity: usize,
+}
+
+impl<S: SpiDevice> SpiNorFlash<S> {
+    /// Create the flash with its capacity in bytes, e.g. `16 * 1024 * 1024` for W25Q128.
+    ///
+    /// Panics if the capacity is larger than 16MB, which can't be addressed with 3-byte addresses. For a larger chip,
+    /// pass 16MB so that only the first 16MB is used.
+    pub fn new(spi: S, capacity: usize) -> Self {
+        assert!(
+            capacity <= MAX_CAPACITY,
+            "SPI flash capacity must not be larger than 16MB, only 3-byte addresses are supported"
+        );
+        Self { spi, capacity }
+    }
+
+    /// Read the JEDEC id: manufacturer id, memory type and capacity
+    pub async fn read_jedec_id(&mut self) -> Result<[u8; 3], S::Error> {
+        let mut id = [0; 3];
+        self.spi
+            .transaction(&mut [Operation::Write(&[CMD_JEDEC_ID]), Operation::Read(&mut id)])
+            .await?;
+        Ok(id)
+    }
+
+    async fn write_enable(&mut self) -> Result<(), S::Error> {
+        self.spi.write(&[CMD_WRITE_ENABLE]).await
+    }
+
+    /// Wait until the program or erase is done
+    async fn wait_idle(&mut self) -> Result<(), S::Error> {
+        let mut status = [0; 1];
+        loop {
+            self.spi
+                .transaction(&mut [Operation::Write(&[CMD_READ_STATUS]), Operation::Read(&mut status)])
+                .await?;
+            if status[0] & STATUS_BUSY == 0 {
+                return Ok(());
+            }
+            Timer::after(BUSY_POLL_INTERVAL).await;
+        }
+    }
+}
+
+impl<S: SpiDevice> ErrorType for SpiNorFlash<S> {
+    type Error = SpiFlashError<S::Error>;
+}
+
+impl<S: SpiDevice> ReadNorFlash for SpiNorFlash<S> {
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_bounds(offset, bytes.len(), self.capacity).map_err(SpiFlashError::Flash)?;
+        self.spi
+            .transaction(&mut [Operation::Write(&command(CMD_READ, offset)), Operation::Read(bytes)])
+            .await
+            .map_err(SpiFlashError::Spi)
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<S: SpiDevice> NorFlash for SpiNorFlash<S> {
+    const WRITE_SIZE: usize = 1;
+    const ERASE_SIZE: usize = 4096;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if from > to {
+            return Err(SpiFlashError::Flash(NorFlashErrorKind::OutOfBounds));
+        }
+        check_bounds(from, (to - from) as usize, self.capacity).map_err(SpiFlashError::Flash)?;
+        if from as usize % Self::ERASE_SIZE != 0 || to as usize % Self::ERASE_SIZE != 0 {
+            return Err(SpiFlashError::Flash(NorFlashErrorKind::NotAligned));
+        }
+        for addr in (from..to).step_by(Self::ERASE_SIZE) {
+            self.write_enable().await.map_err(SpiFlashError::Spi)?;
+            self.spi
+                .write(&command(CMD_SECTOR_ERASE, addr))
+                .await
+                .map_err(SpiFlashError::Spi)?;
+            self.wait_idle().await.map_err(SpiFlashError::Spi)?;
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_bounds(offset, bytes.len(), self.capacity).map_err(SpiFlashError::Flash)?;
+        let mut addr = offset as usize;
+        let mut bytes = bytes;
+        while !bytes.is_empty() {
+            let (chunk, rest) = bytes.split_at(page_chunk_len(addr, bytes.len()));
+            self.write_enable().await.map_err(SpiFlashError::Spi)?;
+            self.spi
+                .transaction(&mut [
+                    Operation::Write(&command(CMD_PAGE_PROGRAM, addr as u32)),
+                    Operation::Write(chunk),
+                ])
+                .await
+                .map_err(SpiFlashError::Spi)?;
+            self.wait_idle().await.map_err(SpiFlashError::Spi)?;
+            addr += chunk.len();
+            bytes = rest;
+        }
+        Ok(())
+    }
+}
+
+/// Check that `len` bytes from `offset` are in the flash
+fn check_bounds(offset: u32, len: usize, capacity: usize) -> Result<(), NorFlashErrorKind> {
+    match (offset as usize).checked_add(len) {
+        Some(end) if end <= capacity => Ok(()),
+        _ => Err(NorFlashErrorKind::OutOfBounds),
+    }
+}
+
+/// A command with a 3-byte address
+fn command(cmd: u8, addr: u32) -> [u8; 4] {
+    let addr = addr.to_be_bytes();
+    [cmd, addr[1], addr[2], addr[3]]
+}
+
+/// Number of bytes which can be programmed at `addr` without crossing the page boundary
+fn page_chunk_len(addr: usize, len: usize) -> usize {
+    len.min(PAGE_SIZE - addr % PAGE_SIZE)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_command() {
+        assert_eq!(command(CMD_READ, 0x12_3456), [0x03, 0x12, 0x34, 0x56]);
+    }
+
+    #[test]
+    fn test_check_bounds() {
+        assert_eq!(check_bounds(0, 4096, 4096), Ok(()));
+        assert_eq!(check_bounds(1, 4096, 4096), Err(NorFlashErrorKind::OutOfBounds));
+        assert_eq!(
+            check_bounds(u32::MAX, 2, MAX_CAPACITY),
+            Err(NorFlashErrorKind::OutOfBounds)
+        );
+    }
+
+    #[test]
+    fn test_page_chunk_len() {
+        assert_eq!(page_chunk_len(0, 16), 16);
+        assert_eq!(page_chunk_len(0, 300), 256);
+        // Stops at the page boundary
+        assert_eq!(page_chunk_len(250, 16), 6);
+        assert_eq!(page_chunk_len(256, 16), 16);
+    }
+}
@@ -0,0 +1,185 @@
+//! Usage of the storage and the compaction.
+//!
+//! Both are done with the public API of `sequential-storage`, so they don't depend on how the items are laid out in
+//! the flash. The stats are read from the flash only when they're requested.
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, with_timeout};
+use embedded_storage_async::nor_flash::NorFlash as AsyncNorFlash;
+use heapless::Vec;
+use sequential_storage::Error as SSError;
+use sequential_storage::cache::NoCache;
+use sequential_storage::item_overhead_size;
+use sequential_storage::map::{fetch_all_items, fetch_item, store_item};
+
+use super::{FlashOperationMessage, Storage, StorageData};
+use crate::channel::FLASH_CHANNEL;
+
+/// Stats read by the storage task, `None` if the read failed
+static STORAGE_STATS: Signal<crate::RawMutex, Option<StorageStats>> = Signal::new();
+
+/// Number of keys which are stored again in one pass of the compaction
+const COMPACTION_BATCH_SIZE: usize = 32;
+
+/// Usage of the storage
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StorageStats {
+    /// Size of a sector in bytes
+    pub sector_size: u32,
+    /// Number of sectors used by the storage
+    pub total_sectors: u8,
+    /// Number of items in the storage, including the outdated values which are dropped when their sector is erased
+    pub stored_items: u16,
+    /// Bytes taken by the stored items, including the outdated values
+    pub used_bytes: u32,
+    /// Bytes which are not taken by the stored items.
+    ///
+    /// One sector is always kept erased for moving the latest values when the oldest sector is erased, it's not counted.
+    pub free_bytes: u32,
+}
+
+/// Read the usage of the storage from the flash.
+///
+/// The read is done by the storage task after the pending flash operations. Returns `None` if the read fails, or
+/// the storage task doesn't respond in 1s.
+pub async fn read_storage_stats() -> Option<StorageStats> {
+    STORAGE_STATS.reset();
+    let read = async {
+        FLASH_CHANNEL.send(FlashOperationMessage::ReadStats).await;
+        STORAGE_STATS.wait().await
+    };
+    with_timeout(Duration::from_secs(1), read).await.ok().flatten()
+}
+
+/// Compact the storage if less than half of it is free.
+///
+/// The latest value of every setting is stored again after the outdated values, so that erasing the old sectors
+/// doesn't need to move any setting. Call it when the keyboard is idle, for example after some time without key
+/// presses, so that the flash operations don't happen while typing or editing the keymap.
+///
+/// It's safe to lose the power during the compaction: the old value of a setting is kept until the new one is
+/// completely written, and the incomplete item is discarded at the next boot.
+pub async fn compact_storage() {
+    FLASH_CHANNEL.send(FlashOperationMessage::Compact).await;
+}
+
+impl<F: AsyncNorFlash, const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>
+    Storage<F, ROW, COL, NUM_LAYER, NUM_ENCODER>
+{
+    /// Read the usage of the storage, and send it to `read_storage_stats`
+    pub(crate) async fn send_stats(&mut self) -> Result<(), SSError<F::Error>> {
+        let stats = self.read_stats().await;
+        STORAGE_STATS.signal(stats.as_ref().ok().copied());
+        stats.map(|_| ())
+    }
+
+    /// Read the usage of the storage from the flash
+    pub(crate) async fn read_stats(&mut self) -> Result<StorageStats, SSError<F::Error>> {
+        let mut storage_cache = NoCache::new();
+        let mut item_iterator = fetch_all_items::<u32, _, _>(
+            &mut self.flash,
+            self.storage_range.clone(),
+            &mut storage_cache,
+            &mut self.buffer,
+        )
+        .await?;
+        let mut stored_items: u16 = 0;
+        let mut used_bytes: u32 = 0;
+        while let Some((_key, value)) = item_iterator.next::<&[u8]>(&mut self.buffer).await? {
+            stored_items = stored_items.saturating_add(1);
+            used_bytes += Self::item_size(value.len());
+        }
+
+        let total_sectors = ((self.storage_range.end - self.storage_range.start) as usize / F::ERASE_SIZE) as u8;
+        let capacity = total_sectors.saturating_sub(1) as u32 * F::ERASE_SIZE as u32;
+        Ok(StorageStats {
+            sector_size: F::ERASE_SIZE as u32,
+            total_sectors,
+            stored_items,
+            used_bytes,
+            free_bytes: capacity.saturating_sub(used_bytes),
+        })
+    }
+
+    /// Store the latest value of every key again, if less than half of the storage is free.
+    ///
+    /// Each value is stored by `store_item`, which keeps the old value until the new one is completely written, so
+    /// every key always has a valid value even if the compaction is interrupted.
+    pub(crate) async fn compact(&mut self) -> Result<(), SSError<F::Error>> {
+        let stats = self.read_stats().await?;
+        if stats.free_bytes > stats.used_bytes {
+            debug!("Skip storage compaction, {} bytes free", stats.free_bytes);
+            return Ok(());
+        }
+
+        // The keys are stored again in ascending order, a batch at a time, so that they don't need to be kept in RAM
+        let mut last_key = None;
+        loop {
+            let keys = self.next_keys(last_key).await?;
+            let Some(&max_key) = keys.last() else {
+                break;
+            };
+            let mut storage_cache = NoCache::new();
+            for key in keys {
+                let Some(data) = fetch_item::<u32, StorageData, _>(
+                    &mut self.flash,
+                    self.storage_range.clone(),
+                    &mut storage_cache,
+                    &mut self.buffer,
+                    &key,
+                )
+                .await?
+                else {
+                    continue;
+                };
+                store_item(
+                    &mut self.flash,
+                    self.storage_range.clone(),
+                    &mut storage_cache,
+                    &mut self.buffer,
+                    &key,
+                    &data,
+                )
+                .await?;
+            }
+            last_key = Some(max_key);
+        }
+        let stats = self.read_stats().await?;
+        info!("Storage compacted: {:?}", stats);
+        Ok(())
+    }
+
+    /// The smallest keys in the storage which are larger than `after`, in ascending order
+    async fn next_keys(&mut self, after: Option<u32>) -> Result<Vec<u32, COMPACTION_BATCH_SIZE>, SSError<F::Error>> {
+        let mut keys = Vec::new();
+        let mut storage_cache = NoCache::new();
+        let mut item_iterator = fetch_all_items::<u32, _, _>(
+            &mut self.flash,
+            self.storage_range.clone(),
+            &mut storage_cache,
+            &mut self.buffer,
+        )
+        .await?;
+        while let Some((key, _)) = item_iterator.next::<&[u8]>(&mut self.buffer).await? {
+            if after.is_some_and(|after| key <= after) {
+                continue;
+            }
+            if let Err(pos) = keys.binary_search(&key) {
+                if keys.is_full() {
+                    if pos == keys.len() {
+                        continue;
+                    }
+                    keys.pop();
+                }
+                let _ = keys.insert(pos, key);
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Size of an item in the flash, with the serialized `u32` key and a value of `value_len` bytes
+    fn item_size(value_len: usize) -> u32 {
+        let word_size = F::READ_SIZE.max(F::WRITE_SIZE);
+        item_overhead_size::<F>() + (size_of::<u32>() + value_len).next_multiple_of(word_size) as u32
+    }
+}
@@ -0,0 +1,202 @@
+//! Time driver of `std` builds, which can be switched to a virtual clock for deterministic tests.
+//!
+//! By default, the time is the real time, like the `std` driver of `embassy-time`. Tests of the timing based
+//! behaviors, such as tap-hold, combos and one-shot keys, can run with [`VirtualClock::block_on`] instead. The
+//! virtual time only moves when all futures are waiting, and it jumps to the next expired timer at once. So
+//! "press, wait 250ms, release" is always exactly 250ms, no matter how slow the host is, and the test finishes
+//! without waiting for the real time.
+extern crate std;
+
+use core::future::Future;
+use core::pin::pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::Wake;
+use std::thread;
+use std::time::{Duration as StdDuration, Instant as StdInstant};
+
+use embassy_time::Duration;
+use embassy_time_driver::Driver;
+use embassy_time_queue_utils::Queue;
+
+struct TimeDriver {
+    signaler: Signaler,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    zero_instant: Option<StdInstant>,
+    /// Current virtual time in ticks, `None` if the real time is used
+    virtual_now: Option<u64>,
+    queue: Queue,
+}
+
+embassy_time_driver::time_driver_impl!(static DRIVER: TimeDriver = TimeDriver {
+    inner: Mutex::new(Inner {
+        zero_instant: None,
+        virtual_now: None,
+        queue: Queue::new(),
+    }),
+    signaler: Signaler::new(),
+});
+
+impl Inner {
+    fn init(&mut self) -> StdInstant {
+        *self.zero_instant.get_or_insert_with(|| {
+            thread::spawn(alarm_thread);
+            StdInstant::now()
+        })
+    }
+
+    fn real_now(&mut self) -> u64 {
+        self.init().elapsed().as_micros() as u64
+    }
+
+    fn now(&mut self) -> u64 {
+        match self.virtual_now {
+            Some(now) => now,
+            None => self.real_now(),
+        }
+    }
+}
+
+impl Driver for TimeDriver {
+    fn now(&self) -> u64 {
+        self.inner.lock().unwrap().now()
+    }
+
+    fn schedule_wake(&self, at: u64, waker: &Waker) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.init();
+        if inner.queue.schedule_wake(at, waker) {
+            self.signaler.signal();
+        }
+    }
+}
+
+/// Wake the expired timers in real time
+fn alarm_thread() {
+    let zero = DRIVER.inner.lock().unwrap().zero_instant.unwrap();
+    loop {
+        let until = {
+            let mut inner = DRIVER.inner.lock().unwrap();
+            match inner.virtual_now {
+                // The timers are woken when the virtual clock moves, just check the mode later
+                Some(_) => StdInstant::now() + StdDuration::from_secs(1),
+                None => {
+                    let now = inner.real_now();
+                    let next_alarm = inner.queue.next_expiration(now);
+                    zero.checked_add(StdDuration::from_micros(next_alarm))
+                        .unwrap_or_else(|| StdInstant::now() + StdDuration::from_secs(1))
+                }
+            }
+        };
+        DRIVER.signaler.wait_until(until);
+    }
+}
+
+struct Signaler {
+    mutex: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Signaler {
+    const fn new() -> Self {
+        Self {
+            mutex: Mutex::new(false),
+            condvar: Condvar::new(),
+        }
+    }
+
+    fn wait_until(&self, until: StdInstant) {
+        let mut signaled = self.mutex.lock().unwrap();
+        while !*signaled {
+            let now = StdInstant::now();
+            if now >= until {
+                break;
+            }
+            let (guard, timeout) = self.condvar.wait_timeout(signaled, until - now).unwrap();
+            signaled = guard;
+            if timeout.timed_out() {
+                break;
+            }
+        }
+        *signaled = false;
+    }
+
+    fn signal(&self) {
+        *self.mutex.lock().unwrap() = true;
+        self.condvar.notify_one();
+    }
+}
+
+/// Virtual clock of the `std` time driver.
+///
+/// Once started, `embassy_time::Instant::now()` returns the virtual time, and timers expire only when the virtual
+/// time is advanced. The clock can't be stopped, run each test in its own process, e.g. with `rusty_fork_test!`.
+pub struct VirtualClock;
+
+impl VirtualClock {
+    /// Switch to the virtual time, which starts from the current real time
+    pub fn start() {
+        let mut inner = DRIVER.inner.lock().unwrap();
+        if inner.virtual_now.is_none() {
+            inner.virtual_now = Some(inner.real_now());
+        }
+    }
+
+    /// Whether the virtual clock is started
+    pub fn is_started() -> bool {
+        DRIVER.inner.lock().unwrap().virtual_now.is_some()
+    }
+
+    /// Advance the virtual time, and wake the expired timers
+    pub fn advance(duration: Duration) {
+        let mut inner = DRIVER.inner.lock().unwrap();
+        let now = inner.virtual_now.expect("The virtual clock isn't started") + duration.as_ticks();
+        inner.virtual_now = Some(now);
+        inner.queue.next_expiration(now);
+    }
+
+    /// Advance the virtual time to the next timer and wake it, returns false if there's no timer
+    fn advance_to_next_timer() -> bool {
+        let mut inner = DRIVER.inner.lock().unwrap();
+        let now = inner.virtual_now.expect("The virtual clock isn't started");
+        let next = inner.queue.next_expiration(now);
+        if next == u64::MAX {
+            return false;
+        }
+        inner.virtual_now = Some(next.max(now));
+        inner.queue.next_expiration(next.max(now));
+        true
+    }
+
+    /// Start the virtual clock, and run the future to completion.
+    ///
+    /// The future is polled as long as it's woken, the virtual time is advanced to the next timer only when all
+    /// futures are waiting. It panics if all futures are waiting but there's no timer, which never completes.
+    pub fn block_on<F: Future>(fut: F) -> F::Output {
+        struct Flag(AtomicBool);
+        impl Wake for Flag {
+            fn wake(self: Arc<Self>) {
+                self.0.store(true, Ordering::Release);
+            }
+        }
+
+        Self::start();
+        let woken = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(woken.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = pin!(fut);
+        loop {
+            woken.0.store(false, Ordering::Release);
+            if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+                return output;
+            }
+            if !woken.0.load(Ordering::Acquire) && !Self::advance_to_next_timer() {
+                panic!("All futures are waiting, but there's no timer to wake them");
+            }
+        }
+    }
+}
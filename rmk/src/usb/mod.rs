@@ -1,6 +1,7 @@
-use core::sync::atomic::Ordering;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use embassy_sync::signal::Signal;
+use embassy_time::Duration;
 use embassy_usb::class::hid::{HidWriter, ReportId, RequestHandler};
 use embassy_usb::control::OutResponse;
 use embassy_usb::driver::Driver;
@@ -8,14 +9,17 @@ use embassy_usb::{Builder, Handler};
 use ssmarshal::serialize;
 use static_cell::StaticCell;
 
-use crate::channel::KEYBOARD_REPORT_CHANNEL;
 use crate::config::KeyboardUsbConfig;
 use crate::descriptor::CompositeReportType;
-use crate::hid::{HidError, HidWriterTrait, Report, RunnableHidWriter};
+use crate::hid::{HidError, HidWriterTrait, MouseReportCoalescer, Report, RunnableHidWriter};
 use crate::state::ConnectionState;
 use crate::{CONNECTION_STATE, RawMutex};
 
 pub(crate) static USB_REMOTE_WAKEUP: Signal<RawMutex, ()> = Signal::new();
+/// Whether the USB bus is suspended by the host
+pub(crate) static USB_BUS_SUSPENDED: AtomicBool = AtomicBool::new(false);
+/// Whether the remote wakeup is enabled by the host
+pub(crate) static USB_REMOTE_WAKEUP_ENABLED: AtomicBool = AtomicBool::new(false);
 
 /// USB state
 #[repr(u8)]
@@ -43,19 +47,32 @@ impl From<u8> for UsbState {
 pub(crate) struct UsbKeyboardWriter<'a, 'd, D: Driver<'d>> {
     pub(crate) keyboard_writer: &'a mut HidWriter<'d, D, 8>,
     pub(crate) other_writer: &'a mut HidWriter<'d, D, 9>,
+    /// Writer of the NKRO keyboard interface
+    #[cfg(feature = "nkro")]
+    pub(crate) nkro_writer: &'a mut HidWriter<'d, D, 29>,
+    /// Rate limiter of mouse reports in the composite reports
+    mouse_coalescer: MouseReportCoalescer,
 }
 impl<'a, 'd, D: Driver<'d>> UsbKeyboardWriter<'a, 'd, D> {
-    pub(crate) fn new(keyboard_writer: &'a mut HidWriter<'d, D, 8>, other_writer: &'a mut HidWriter<'d, D, 9>) -> Self {
+    pub(crate) fn new(
+        keyboard_writer: &'a mut HidWriter<'d, D, 8>,
+        other_writer: &'a mut HidWriter<'d, D, 9>,
+        #[cfg(feature = "nkro")] nkro_writer: &'a mut HidWriter<'d, D, 29>,
+        composite_report_interval: Duration,
+    ) -> Self {
         Self {
             keyboard_writer,
             other_writer,
+            #[cfg(feature = "nkro")]
+            nkro_writer,
+            mouse_coalescer: MouseReportCoalescer::new(composite_report_interval),
         }
     }
 }
 
 impl<'d, D: Driver<'d>> RunnableHidWriter for UsbKeyboardWriter<'_, 'd, D> {
     async fn get_report(&mut self) -> Self::ReportType {
-        KEYBOARD_REPORT_CHANNEL.receive().await
+        self.mouse_coalescer.get_report().await
     }
 }
 
@@ -63,6 +80,10 @@ impl<'d, D: Driver<'d>> HidWriterTrait for UsbKeyboardWriter<'_, 'd, D> {
     type ReportType = Report;
 
     async fn write_report(&mut self, report: Self::ReportType) -> Result<usize, HidError> {
+        // Wake up the host, the report is sent after the bus is resumed
+        if USB_BUS_SUSPENDED.load(Ordering::Acquire) && USB_REMOTE_WAKEUP_ENABLED.load(Ordering::Acquire) {
+            USB_REMOTE_WAKEUP.signal(());
+        }
         // Write report to USB
         match report {
             Report::KeyboardReport(keyboard_report) => {
@@ -72,6 +93,23 @@ impl<'d, D: Driver<'d>> HidWriterTrait for UsbKeyboardWriter<'_, 'd, D> {
                     .map_err(HidError::UsbEndpointError)?;
                 Ok(8)
             }
+            #[cfg(feature = "nkro")]
+            Report::NkroKeyboardReport(nkro_report) => {
+                self.nkro_writer
+                    .write_serialize(&nkro_report)
+                    .await
+                    .map_err(HidError::UsbEndpointError)?;
+                Ok(29)
+            }
+            #[cfg(not(feature = "nkro"))]
+            Report::NkroKeyboardReport(nkro_report) => {
+                // No NKRO interface, fallback to 6KRO
+                self.keyboard_writer
+                    .write_serialize(&nkro_report.to_6kro())
+                    .await
+                    .map_err(HidError::UsbEndpointError)?;
+                Ok(8)
+            }
             Report::MouseReport(mouse_report) => {
                 let mut buf: [u8; 9] = [0; 9];
                 buf[0] = CompositeReportType::Mouse as u8;
@@ -113,7 +151,7 @@ pub(crate) fn new_usb_builder<'d, D: Driver<'d>>(driver: D, keyboard_config: Key
     usb_config.product = Some(keyboard_config.product_name);
     usb_config.serial_number = Some(keyboard_config.serial_number);
     usb_config.max_power = 450;
-    usb_config.supports_remote_wakeup = true;
+    usb_config.supports_remote_wakeup = keyboard_config.remote_wakeup;
 
     // Required for windows compatibility.
     usb_config.max_packet_size_0 = 64;
@@ -122,9 +160,9 @@ pub(crate) fn new_usb_builder<'d, D: Driver<'d>>(driver: D, keyboard_config: Key
     usb_config.device_protocol = 0x01;
     usb_config.composite_with_iads = true;
 
-    #[cfg(feature = "usb_log")]
+    #[cfg(any(feature = "usb_log", feature = "nkro"))]
     const USB_BUF_SIZE: usize = 256;
-    #[cfg(not(feature = "usb_log"))]
+    #[cfg(not(any(feature = "usb_log", feature = "nkro")))]
     const USB_BUF_SIZE: usize = 128;
 
     // Create embassy-usb DeviceBuilder using the driver and config.
@@ -163,7 +201,7 @@ macro_rules! add_usb_logger {
 }
 
 macro_rules! add_usb_writer {
-    ($usb_builder:expr, $descriptor:ty, $n:expr) => {{
+    ($usb_builder:expr, $descriptor:ty, $n:expr, $poll_ms:expr) => {{
         // Initialize hid writer
         // Current implementation requires the static STATE, so we need to use the paste crate to generate the static variable name.
         use usbd_hid::descriptor::SerializedDescriptor;
@@ -178,7 +216,7 @@ macro_rules! add_usb_writer {
         let hid_config = ::embassy_usb::class::hid::Config {
             report_descriptor: <$descriptor>::desc(),
             request_handler: Some(request_handler),
-            poll_ms: 1,
+            poll_ms: $poll_ms,
             max_packet_size: 64,
         };
 
@@ -188,7 +226,7 @@ macro_rules! add_usb_writer {
 }
 
 macro_rules! add_usb_reader_writer {
-    ($usb_builder:expr, $descriptor:ty, $read_n:expr, $write_n:expr) => {{
+    ($usb_builder:expr, $descriptor:ty, $read_n:expr, $write_n:expr, $poll_ms:expr) => {{
         // Initialize hid reader writer
         // Current implementation requires the static STATE, so we need to use the paste crate to generate the static variable name.
         use usbd_hid::descriptor::SerializedDescriptor;
@@ -203,7 +241,7 @@ macro_rules! add_usb_reader_writer {
         let hid_config = ::embassy_usb::class::hid::Config {
             report_descriptor: <$descriptor>::desc(),
             request_handler: Some(request_handler),
-            poll_ms: 1,
+            poll_ms: $poll_ms,
             max_packet_size: 64,
         };
 
@@ -243,6 +281,7 @@ impl Handler for UsbDeviceHandler {
             USB_ENABLED.signal(());
         } else {
             info!("Device disabled");
+            USB_BUS_SUSPENDED.store(false, Ordering::Release);
             if USB_ENABLED.signaled() {
                 USB_ENABLED.reset();
                 USB_SUSPENDED.signal(());
@@ -251,6 +290,7 @@ impl Handler for UsbDeviceHandler {
     }
 
     fn reset(&mut self) {
+        USB_BUS_SUSPENDED.store(false, Ordering::Release);
         info!("Bus reset, the Vbus current limit is 100mA");
     }
 
@@ -273,16 +313,19 @@ impl Handler for UsbDeviceHandler {
             info!(
                 "Device suspended, the Vbus current limit is 500µA (or 2.5mA for high-power devices with remote wakeup enabled)."
             );
+            USB_BUS_SUSPENDED.store(true, Ordering::Release);
             USB_SUSPENDED.signal(());
         } else {
             info!(
                 "Device resumed, the Vbus current limit is 500µA (or 2.5mA for high-power devices with remote wakeup enabled)."
             );
+            USB_BUS_SUSPENDED.store(false, Ordering::Release);
             USB_SUSPENDED.reset();
         }
     }
 
     fn remote_wakeup_enabled(&mut self, enabled: bool) {
         info!("Remote wakeup enabled state: {}", enabled);
+        USB_REMOTE_WAKEUP_ENABLED.store(enabled, Ordering::Release);
     }
 }
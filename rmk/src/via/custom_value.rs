@@ -0,0 +1,396 @@
+//! Via custom values, used by `CustomSetValue`/`CustomGetValue`/`CustomSave`
+//!
+//! Layout of the report: `[command_id, channel_id, value_id, value_data...]`
+
+use core::cell::RefCell;
+#[cfg(any(feature = "_ble", feature = "split"))]
+use core::sync::atomic::Ordering;
+
+use rmk_types::action::KeyAction;
+use rmk_types::protocol::vial::{
+    RmkCustomValue, ViaBacklightValue, ViaChannelId, ViaCommand, ViaRgbLightValue, ViaRgbMatrixValue,
+};
+#[cfg(feature = "_ble")]
+use {
+    crate::NUM_BLE_PROFILE, crate::ble::ACTIVE_PROFILE, crate::ble::profile::BleProfileAction,
+    crate::ble::rssi::HOST_RSSI, crate::channel::BLE_PROFILE_CHANNEL,
+};
+#[cfg(feature = "storage")]
+use {
+    crate::channel::FLASH_CHANNEL,
+    crate::storage::{FlashOperationMessage, compact_storage, read_storage_stats},
+};
+
+#[cfg(all(feature = "_ble", feature = "split"))]
+use crate::ble::rssi::PERIPHERAL_RSSI;
+#[cfg(feature = "debounce_stats")]
+use crate::debounce::stats::DEBOUNCE_STATS;
+use crate::descriptor::ViaReport;
+use crate::event::KeyboardEventPos;
+use crate::keymap::KeyMap;
+#[cfg(feature = "split")]
+use crate::split::PERIPHERAL_BATTERY_LEVEL;
+use crate::via::keycode_convert::to_via_keycode;
+use crate::via::keymap_backup::{get_keymap_checksum, get_keymap_chunk, set_keymap_chunk};
+
+pub(crate) async fn process_custom_set_value<
+    const ROW: usize,
+    const COL: usize,
+    const NUM_LAYER: usize,
+    const NUM_ENCODER: usize,
+>(
+    report: &mut ViaReport,
+    keymap: &RefCell<KeyMap<'_, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
+) {
+    match ViaChannelId::try_from(report.output_data[1]) {
+        Ok(ViaChannelId::Custom) => match RmkCustomValue::try_from(report.output_data[2]) {
+            #[cfg(feature = "_ble")]
+            Ok(RmkCustomValue::BleProfile) => {
+                let profile = report.output_data[3];
+                if (profile as usize) < NUM_BLE_PROFILE {
+                    // Profile manager persists the new active profile and updates the BLE stack
+                    BLE_PROFILE_CHANNEL.send(BleProfileAction::SwitchProfile(profile)).await;
+                } else {
+                    warn!("Invalid BLE profile: {}, profile number: {}", profile, NUM_BLE_PROFILE);
+                    report.input_data[0] = ViaCommand::Unhandled as u8;
+                }
+            }
+            #[cfg(feature = "_ble")]
+            Ok(RmkCustomValue::ClearBleBond) => {
+                let profile = report.output_data[3];
+                if (profile as usize) < NUM_BLE_PROFILE {
+                    // If the cleared profile is active, the keyboard re-advertises for a new pairing
+                    BLE_PROFILE_CHANNEL.send(BleProfileAction::ClearBond(profile)).await;
+                } else {
+                    warn!("Invalid BLE profile: {}, profile number: {}", profile, NUM_BLE_PROFILE);
+                    report.input_data[0] = ViaCommand::Unhandled as u8;
+                }
+            }
+            Ok(RmkCustomValue::KeymapChunk) => set_keymap_chunk(report, keymap).await,
+            #[cfg(feature = "debounce_stats")]
+            Ok(RmkCustomValue::DebounceStats) => DEBOUNCE_STATS.lock(|s| s.borrow_mut().reset()),
+            #[cfg(feature = "storage")]
+            Ok(RmkCustomValue::StorageCompaction) => compact_storage().await,
+            Ok(_) => {
+                warn!("Custom set value {} -- not supported", report.output_data[2]);
+                report.input_data[0] = ViaCommand::Unhandled as u8;
+            }
+            Err(e) => {
+                warn!("Invalid custom value: {}", e);
+                report.input_data[0] = ViaCommand::Unhandled as u8;
+            }
+        },
+        Ok(ViaChannelId::RgbLight) => {
+            let mut rgb_light = keymap.borrow().get_rgb_light();
+            match ViaRgbLightValue::try_from(report.output_data[2]) {
+                Ok(ViaRgbLightValue::Brightness) => rgb_light.brightness = report.output_data[3],
+                Ok(ViaRgbLightValue::Effect) => rgb_light.effect = report.output_data[3],
+                Ok(ViaRgbLightValue::EffectSpeed) => rgb_light.effect_speed = report.output_data[3],
+                Ok(ViaRgbLightValue::Color) => {
+                    rgb_light.hue = report.output_data[3];
+                    rgb_light.saturation = report.output_data[4];
+                }
+                Err(e) => {
+                    warn!("Invalid rgblight value: {}", e);
+                    report.input_data[0] = ViaCommand::Unhandled as u8;
+                    return;
+                }
+            }
+            // The new state is saved to storage when the host sends `CustomSave`
+            keymap.borrow_mut().set_rgb_light(rgb_light);
+        }
+        Ok(ViaChannelId::RgbMatrix) => {
+            let mut rgb_matrix = keymap.borrow().get_rgb_matrix();
+            match ViaRgbMatrixValue::try_from(report.output_data[2]) {
+                Ok(ViaRgbMatrixValue::Brightness) => rgb_matrix.brightness = report.output_data[3],
+                Ok(ViaRgbMatrixValue::Effect) => rgb_matrix.effect = report.output_data[3],
+                Ok(ViaRgbMatrixValue::EffectSpeed) => rgb_matrix.effect_speed = report.output_data[3],
+                Ok(ViaRgbMatrixValue::Color) => {
+                    rgb_matrix.hue = report.output_data[3];
+                    rgb_matrix.saturation = report.output_data[4];
+                }
+                Err(e) => {
+                    warn!("Invalid rgb matrix value: {}", e);
+                    report.input_data[0] = ViaCommand::Unhandled as u8;
+                    return;
+                }
+            }
+            // The new state is saved to storage when the host sends `CustomSave`
+            keymap.borrow_mut().set_rgb_matrix(rgb_matrix);
+        }
+        Ok(ViaChannelId::Backlight) => match ViaBacklightValue::try_from(report.output_data[2]) {
+            Ok(ViaBacklightValue::Brightness) => {
+                let mut backlight = keymap.borrow().get_backlight();
+                backlight.brightness = report.output_data[3];
+                keymap.borrow_mut().set_backlight(backlight);
+            }
+            Ok(ViaBacklightValue::Effect) => {
+                warn!("Backlight effect -- not supported");
+                report.input_data[0] = ViaCommand::Unhandled as u8;
+            }
+            Err(e) => {
+                warn!("Invalid backlight value: {}", e);
+                report.input_data[0] = ViaCommand::Unhandled as u8;
+            }
+        },
+        _ => {
+            // led matrix/audio settings here
+            warn!("Custom set value -- not supported");
+            report.input_data[0] = ViaCommand::Unhandled as u8;
+        }
+    }
+}
+
+pub(crate) async fn process_custom_get_value<
+    const ROW: usize,
+    const COL: usize,
+    const NUM_LAYER: usize,
+    const NUM_ENCODER: usize,
+>(
+    report: &mut ViaReport,
+    keymap: &RefCell<KeyMap<'_, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
+) {
+    match ViaChannelId::try_from(report.output_data[1]) {
+        Ok(ViaChannelId::Custom) => match RmkCustomValue::try_from(report.output_data[2]) {
+            #[cfg(feature = "_ble")]
+            Ok(RmkCustomValue::BleProfile) => {
+                report.input_data[3] = ACTIVE_PROFILE.load(Ordering::SeqCst);
+            }
+            #[cfg(feature = "split")]
+            Ok(RmkCustomValue::PeripheralBattery) => {
+                let id = report.output_data[3] as usize;
+                match PERIPHERAL_BATTERY_LEVEL.get(id) {
+                    // `PERIPHERAL_BATTERY_UNKNOWN` if the peripheral is disconnected
+                    Some(level) => report.input_data[4] = level.load(Ordering::Acquire),
+                    None => {
+                        warn!("Invalid peripheral id: {}", id);
+                        report.input_data[0] = ViaCommand::Unhandled as u8;
+                    }
+                }
+            }
+            #[cfg(feature = "_ble")]
+            Ok(RmkCustomValue::BleRssi) => {
+                // `[.., index]` -> `[.., index, rssi]`, index 0 is the host, index `id + 1` is the peripheral `id`
+                let rssi = match report.output_data[3] {
+                    0 => Some(&HOST_RSSI),
+                    #[cfg(feature = "split")]
+                    index => PERIPHERAL_RSSI.get(index as usize - 1),
+                    #[cfg(not(feature = "split"))]
+                    _ => None,
+                };
+                match rssi {
+                    // `RSSI_UNKNOWN` if the connection is lost
+                    Some(rssi) => report.input_data[4] = rssi.load(Ordering::Acquire) as u8,
+                    None => {
+                        warn!("Invalid BLE connection index: {}", report.output_data[3]);
+                        report.input_data[0] = ViaCommand::Unhandled as u8;
+                    }
+                }
+            }
+            Ok(RmkCustomValue::KeymapChunk) => get_keymap_chunk(report, keymap),
+            Ok(RmkCustomValue::KeymapChecksum) => get_keymap_checksum(report, keymap),
+            Ok(RmkCustomValue::EffectiveKeyCode) => {
+                // `[.., row, col]` -> `[.., row, col, keycode(2 bytes), layer]`
+                let row = report.output_data[3];
+                let col = report.output_data[4];
+                if row as usize >= ROW || col as usize >= COL {
+                    warn!("Invalid key position: ({}, {})", row, col);
+                    report.input_data[0] = ViaCommand::Unhandled as u8;
+                    return;
+                }
+                let keymap = keymap.borrow();
+                // A key which is transparent on all active layers does nothing, it's reported as `KC_NO` on the default layer
+                let (action, layer) = keymap
+                    .get_effective_action(KeyboardEventPos::key_pos(col, row))
+                    .unwrap_or((KeyAction::No, keymap.get_default_layer()));
+                report.input_data[5..7].copy_from_slice(&to_via_keycode(action).to_be_bytes());
+                report.input_data[7] = layer;
+            }
+            #[cfg(feature = "debounce_stats")]
+            Ok(RmkCustomValue::DebounceStats) => DEBOUNCE_STATS.lock(|s| {
+                // `[.., index]` -> `[.., index, count, row, col, rejections(2 bytes), chatters(2 bytes)]`
+                let stats = s.borrow();
+                let stats = stats.stats();
+                report.input_data[4] = stats.len() as u8;
+                if let Some(stat) = stats.get(report.output_data[3] as usize) {
+                    report.input_data[5] = stat.row;
+                    report.input_data[6] = stat.col;
+                    report.input_data[7..9].copy_from_slice(&stat.rejections.to_be_bytes());
+                    report.input_data[9..11].copy_from_slice(&stat.chatters.to_be_bytes());
+                }
+            }),
+            #[cfg(feature = "storage")]
+            Ok(RmkCustomValue::StorageStats) => match read_storage_stats().await {
+                // `[..]` -> `[.., total_sectors, sector_size(4 bytes), stored_items(2 bytes), used_bytes(4 bytes), free_bytes(4 bytes)]`
+                Some(stats) => {
+                    report.input_data[3] = stats.total_sectors;
+                    report.input_data[4..8].copy_from_slice(&stats.sector_size.to_be_bytes());
+                    report.input_data[8..10].copy_from_slice(&stats.stored_items.to_be_bytes());
+                    report.input_data[10..14].copy_from_slice(&stats.used_bytes.to_be_bytes());
+                    report.input_data[14..18].copy_from_slice(&stats.free_bytes.to_be_bytes());
+                }
+                None => report.input_data[0] = ViaCommand::Unhandled as u8,
+            },
+            Ok(_) => {
+                warn!("Custom get value {} -- not supported", report.output_data[2]);
+                report.input_data[0] = ViaCommand::Unhandled as u8;
+            }
+            Err(e) => {
+                warn!("Invalid custom value: {}", e);
+                report.input_data[0] = ViaCommand::Unhandled as u8;
+            }
+        },
+        Ok(ViaChannelId::RgbLight) => {
+            let rgb_light = keymap.borrow().get_rgb_light();
+            match ViaRgbLightValue::try_from(report.output_data[2]) {
+                Ok(ViaRgbLightValue::Brightness) => report.input_data[3] = rgb_light.brightness,
+                Ok(ViaRgbLightValue::Effect) => report.input_data[3] = rgb_light.effect,
+                Ok(ViaRgbLightValue::EffectSpeed) => report.input_data[3] = rgb_light.effect_speed,
+                Ok(ViaRgbLightValue::Color) => {
+                    report.input_data[3] = rgb_light.hue;
+                    report.input_data[4] = rgb_light.saturation;
+                }
+                Err(e) => {
+                    warn!("Invalid rgblight value: {}", e);
+                    report.input_data[0] = ViaCommand::Unhandled as u8;
+                }
+            }
+        }
+        Ok(ViaChannelId::RgbMatrix) => {
+            let rgb_matrix = keymap.borrow().get_rgb_matrix();
+            match ViaRgbMatrixValue::try_from(report.output_data[2]) {
+                Ok(ViaRgbMatrixValue::Brightness) => report.input_data[3] = rgb_matrix.brightness,
+                Ok(ViaRgbMatrixValue::Effect) => report.input_data[3] = rgb_matrix.effect,
+                Ok(ViaRgbMatrixValue::EffectSpeed) => report.input_data[3] = rgb_matrix.effect_speed,
+                Ok(ViaRgbMatrixValue::Color) => {
+                    report.input_data[3] = rgb_matrix.hue;
+                    report.input_data[4] = rgb_matrix.saturation;
+                }
+                Err(e) => {
+                    warn!("Invalid rgb matrix value: {}", e);
+                    report.input_data[0] = ViaCommand::Unhandled as u8;
+                }
+            }
+        }
+        Ok(ViaChannelId::Backlight) => match ViaBacklightValue::try_from(report.output_data[2]) {
+            Ok(ViaBacklightValue::Brightness) => report.input_data[3] = keymap.borrow().get_backlight().brightness,
+            Ok(ViaBacklightValue::Effect) => {
+                warn!("Backlight effect -- not supported");
+                report.input_data[0] = ViaCommand::Unhandled as u8;
+            }
+            Err(e) => {
+                warn!("Invalid backlight value: {}", e);
+                report.input_data[0] = ViaCommand::Unhandled as u8;
+            }
+        },
+        _ => {
+            // led matrix/audio settings here
+            warn!("Custom get value -- not supported");
+            report.input_data[0] = ViaCommand::Unhandled as u8;
+        }
+    }
+}
+
+pub(crate) async fn process_custom_save<
+    const ROW: usize,
+    const COL: usize,
+    const NUM_LAYER: usize,
+    const NUM_ENCODER: usize,
+>(
+    report: &mut ViaReport,
+    keymap: &RefCell<KeyMap<'_, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
+) {
+    match ViaChannelId::try_from(report.output_data[1]) {
+        // Values on the custom channel are saved when they are set
+        Ok(ViaChannelId::Custom) => (),
+        Ok(ViaChannelId::RgbLight) => {
+            let _rgb_light = keymap.borrow().get_rgb_light();
+            #[cfg(feature = "storage")]
+            FLASH_CHANNEL.send(FlashOperationMessage::RgbConfig(_rgb_light)).await;
+        }
+        Ok(ViaChannelId::RgbMatrix) => {
+            let _rgb_matrix = keymap.borrow().get_rgb_matrix();
+            #[cfg(feature = "storage")]
+            FLASH_CHANNEL
+                .send(FlashOperationMessage::RgbMatrixConfig(_rgb_matrix))
+                .await;
+        }
+        Ok(ViaChannelId::Backlight) => {
+            let _backlight = keymap.borrow().get_backlight();
+            #[cfg(feature = "storage")]
+            FLASH_CHANNEL
+                .send(FlashOperationMessage::BacklightConfig(_backlight))
+                .await;
+        }
+        _ => {
+            warn!("Custom save -- not supported");
+            report.input_data[0] = ViaCommand::Unhandled as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::config::BehaviorConfig;
+    use crate::k;
+    use crate::light::{RgbMatrixConfig, RgbMatrixEffect};
+
+    fn rgb_matrix_report(command: ViaCommand, value_id: u8, data: &[u8]) -> ViaReport {
+        let mut report = ViaReport {
+            input_data: [0; 32],
+            output_data: [0; 32],
+        };
+        report.output_data[0] = command as u8;
+        report.output_data[1] = ViaChannelId::RgbMatrix as u8;
+        report.output_data[2] = value_id;
+        report.output_data[3..3 + data.len()].copy_from_slice(data);
+        report.input_data = report.output_data;
+        report
+    }
+
+    #[test]
+    fn test_rgb_matrix_value_round_trip() {
+        let layers = Box::leak(Box::new([[[k!(A)]]]));
+        let behavior_config = Box::leak(Box::new(BehaviorConfig::default()));
+        let keymap: RefCell<KeyMap<1, 1, 1>> = RefCell::new(block_on(KeyMap::new(layers, None, behavior_config)));
+
+        let values: [(ViaRgbMatrixValue, &[u8]); 4] = [
+            (ViaRgbMatrixValue::Brightness, &[100]),
+            (ViaRgbMatrixValue::Effect, &[RgbMatrixEffect::Reactive as u8]),
+            (ViaRgbMatrixValue::EffectSpeed, &[200]),
+            (ViaRgbMatrixValue::Color, &[170, 128]),
+        ];
+        for (value, data) in values {
+            let mut report = rgb_matrix_report(ViaCommand::CustomSetValue, value as u8, data);
+            block_on(process_custom_set_value(&mut report, &keymap));
+            assert_eq!(report.input_data[0], ViaCommand::CustomSetValue as u8);
+
+            let mut report = rgb_matrix_report(ViaCommand::CustomGetValue, value as u8, &[]);
+            block_on(process_custom_get_value(&mut report, &keymap));
+            assert_eq!(report.input_data[0], ViaCommand::CustomGetValue as u8);
+            assert_eq!(&report.input_data[3..3 + data.len()], data);
+        }
+        assert_eq!(
+            keymap.borrow().get_rgb_matrix(),
+            RgbMatrixConfig {
+                brightness: 100,
+                effect: RgbMatrixEffect::Reactive as u8,
+                effect_speed: 200,
+                hue: 170,
+                saturation: 128,
+            }
+        );
+
+        // Unknown values are unhandled, the state isn't changed
+        let mut report = rgb_matrix_report(ViaCommand::CustomSetValue, 0x7F, &[1]);
+        block_on(process_custom_set_value(&mut report, &keymap));
+        assert_eq!(report.input_data[0], ViaCommand::Unhandled as u8);
+        let mut report = rgb_matrix_report(ViaCommand::CustomGetValue, 0x7F, &[]);
+        block_on(process_custom_get_value(&mut report, &keymap));
+        assert_eq!(report.input_data[0], ViaCommand::Unhandled as u8);
+        assert_eq!(keymap.borrow().get_rgb_matrix().brightness, 100);
+    }
+}
@@ -9,13 +9,15 @@ pub(crate) fn to_via_keycode(key_action: KeyAction) -> u16 {
         KeyAction::Single(a) => match a {
             Action::Key(KeyCode::GraveEscape) => 0x7c16,
             Action::Key(KeyCode::RepeatKey) => 0x7c79,
+            Action::Key(KeyCode::AltRepeatKey) => 0x7c7a,
+            Action::Key(KeyCode::LayerLock) => 0x7c7b,
             Action::Key(KeyCode::CapsWordToggle) => 0x7c73,
             Action::Key(k) => {
                 if k.is_macro() {
                     k as u16 & 0xFF | 0x7700
                 } else if k.is_user() {
                     k as u16 & 0xF | 0x7E00
-                } else if k.is_combo() || k.is_boot() {
+                } else if k.is_combo() || k.is_boot() || k.is_space_cadet() {
                     // is_rmk() 's subset
                     k as u16 & 0xFF | 0x7C00
                 } else {
@@ -44,37 +46,56 @@ pub(crate) fn to_via_keycode(key_action: KeyAction) -> u16 {
                 let modifier_bits = m.into_packed_bits();
                 0x52A0 | modifier_bits as u16
             }
+            // BLE profile actions use RMK keycodes in QMK's user range: 0x7E40..=0x7E52
+            Action::ProfileSelect(p) => {
+                if p < 16 {
+                    0x7E40 | p as u16
+                } else {
+                    0x0000
+                }
+            }
+            Action::ProfileNext => 0x7E50,
+            Action::ProfilePrevious => 0x7E51,
+            Action::PairingMode => 0x7E52,
+            Action::Unicode(code_point) => {
+                // Same as QMK's `UC(c)`, only code points up to 0x7FFF can be encoded
+                if code_point <= 0x7FFF {
+                    0x8000 | code_point as u16
+                } else {
+                    warn!("Unicode code point {:#X} is not supported by via", code_point);
+                    0x0000
+                }
+            }
             _ => 0x0000,
         },
         KeyAction::Tap(_) => {
             warn!("Tap action is not supported by via");
             0
         }
-        KeyAction::TapHold(tap, hold) => match hold {
-            Action::LayerOn(l) => {
-                if l > 16 {
-                    0
-                } else {
-                    let keycode = match tap {
-                        Action::Key(k) => k as u16,
-                        _ => 0,
-                    };
-                    0x4000 | ((l as u16) << 8) | keycode
+        KeyAction::TapHold(tap, hold) => {
+            // Via only supports basic keycodes as the tap action of LT/MT
+            let keycode = match tap {
+                Action::Key(k) if (k as u16) <= 0xFF => k as u16,
+                _ => 0,
+            };
+            match hold {
+                Action::LayerOn(l) => {
+                    // Only 4 bits are available for the layer number
+                    if l >= 16 {
+                        0
+                    } else {
+                        0x4000 | ((l as u16) << 8) | keycode
+                    }
                 }
+                Action::Modifier(m) => 0x2000 | ((m.into_packed_bits() as u16) << 8) | keycode,
+                _ => 0x0000,
             }
-            Action::Modifier(m) => {
-                let keycode = match tap {
-                    Action::Key(k) => k as u16,
-                    _ => 0,
-                };
-                0x2000 | ((m.into_packed_bits() as u16) << 8) | keycode
-            }
-            _ => 0x0000,
-        },
+        }
         KeyAction::Morse(index) => {
             // Tap dance keycodes: 0x5700..=0x57FF
             0x5700 | (index as u16)
         }
+        KeyAction::LayerTapToggle(l) => 0x52C0 | l as u16,
     }
 }
 
@@ -136,9 +157,9 @@ pub(crate) fn from_via_keycode(via_keycode: u16) -> KeyAction {
             KeyAction::Single(Action::OneShotModifier(m))
         }
         0x52C0..=0x52DF => {
-            // TODO: Layer tap toggle
-            warn!("Layer tap toggle {:#X} not supported", via_keycode);
-            KeyAction::No
+            // Layer tap toggle
+            let layer = via_keycode as u8 & 0x0F;
+            KeyAction::LayerTapToggle(layer)
         }
         0x5700..=0x57FF => {
             // Tap dance
@@ -160,8 +181,8 @@ pub(crate) fn from_via_keycode(via_keycode: u16) -> KeyAction {
             warn!("Backlight and RGB configuration key not supported");
             KeyAction::No
         }
-        // boot related | combo related
-        0x7C00..=0x7C01 | 0x7C50..=0x7C52 => {
+        // boot related | space cadet | combo related
+        0x7C00..=0x7C01 | 0x7C18..=0x7C1E | 0x7C50..=0x7C52 => {
             // is_rmk() 's related
             let keycode = via_keycode & 0xFF | 0x700;
             KeyAction::Single(Action::Key(keycode.into()))
@@ -170,14 +191,17 @@ pub(crate) fn from_via_keycode(via_keycode: u16) -> KeyAction {
         0x7C16 => KeyAction::Single(Action::Key(KeyCode::GraveEscape)),
         // RepeatKey
         0x7C79 => KeyAction::Single(Action::Key(KeyCode::RepeatKey)),
+        // AltRepeatKey
+        0x7C7A => KeyAction::Single(Action::Key(KeyCode::AltRepeatKey)),
+        // Layer lock
+        0x7C7B => KeyAction::Single(Action::Key(KeyCode::LayerLock)),
         // Caps Word
         0x7C73 => KeyAction::Single(Action::Key(KeyCode::CapsWordToggle)),
         0x7C00..=0x7C5F => {
-            // TODO: Reset/GESC/Space Cadet/Haptic/Auto shift(AS)/Dynamic macro
+            // TODO: Reset/GESC/Haptic/Auto shift(AS)/Dynamic macro
             // - [GESC](https://docs.qmk.fm/#/feature_grave_esc)
-            // - [Space Cadet](https://docs.qmk.fm/#/feature_space_cadet)
             warn!(
-                "Reset/GESC/Space Cadet/Haptic/Auto shift(AS)/Dynamic macro not supported: {:#X}",
+                "Reset/GESC/Haptic/Auto shift(AS)/Dynamic macro not supported: {:#X}",
                 via_keycode
             );
             KeyAction::No
@@ -187,6 +211,18 @@ pub(crate) fn from_via_keycode(via_keycode: u16) -> KeyAction {
             let keycode = via_keycode & 0xFF | 0x840;
             KeyAction::Single(Action::Key(keycode.into()))
         }
+        0x7E40..=0x7E4F => {
+            // Select BLE profile
+            let profile = via_keycode as u8 & 0x0F;
+            KeyAction::Single(Action::ProfileSelect(profile))
+        }
+        0x7E50 => KeyAction::Single(Action::ProfileNext),
+        0x7E51 => KeyAction::Single(Action::ProfilePrevious),
+        0x7E52 => KeyAction::Single(Action::PairingMode),
+        0x8000..=0xFFFF => {
+            // Unicode, aka `UC(c)`
+            KeyAction::Single(Action::Unicode((via_keycode & 0x7FFF) as u32))
+        }
         _ => {
             warn!("Via keycode {:#X} is not processed", via_keycode);
             KeyAction::No
@@ -567,6 +603,27 @@ mod test {
             from_via_keycode(via_keycode)
         );
 
+        // Space cadet
+        let via_keycode = 0x7C1A;
+        assert_eq!(
+            KeyAction::Single(Action::Key(KeyCode::SpaceCadetLShiftParenthesisOpen)),
+            from_via_keycode(via_keycode)
+        );
+
+        // AltRepeatKey
+        let via_keycode = 0x7C7A;
+        assert_eq!(
+            KeyAction::Single(Action::Key(KeyCode::AltRepeatKey)),
+            from_via_keycode(via_keycode)
+        );
+
+        // Layer lock
+        let via_keycode = 0x7C7B;
+        assert_eq!(
+            KeyAction::Single(Action::Key(KeyCode::LayerLock)),
+            from_via_keycode(via_keycode)
+        );
+
         // Morse(0)
         let via_keycode = 0x5700;
         assert_eq!(KeyAction::Morse(0), from_via_keycode(via_keycode));
@@ -578,6 +635,10 @@ mod test {
         // Morse(255)
         let via_keycode = 0x57FF;
         assert_eq!(KeyAction::Morse(255), from_via_keycode(via_keycode));
+
+        // TT(3)
+        let via_keycode = 0x52C3;
+        assert_eq!(KeyAction::LayerTapToggle(3), from_via_keycode(via_keycode));
     }
 
     #[test]
@@ -680,6 +741,18 @@ mod test {
         let a = KeyAction::Single(Action::Key(KeyCode::RepeatKey));
         assert_eq!(0x7C79, to_via_keycode(a));
 
+        // Space cadet
+        let a = KeyAction::Single(Action::Key(KeyCode::SpaceCadetRShiftEnter));
+        assert_eq!(0x7C1E, to_via_keycode(a));
+
+        // AltRepeatKey
+        let a = KeyAction::Single(Action::Key(KeyCode::AltRepeatKey));
+        assert_eq!(0x7C7A, to_via_keycode(a));
+
+        // Layer lock
+        let a = KeyAction::Single(Action::Key(KeyCode::LayerLock));
+        assert_eq!(0x7C7B, to_via_keycode(a));
+
         // Morse
         let a = KeyAction::Morse(0);
         assert_eq!(0x5700, to_via_keycode(a));
@@ -689,6 +762,62 @@ mod test {
 
         let a = KeyAction::Morse(255);
         assert_eq!(0x57FF, to_via_keycode(a));
+
+        // TT(3)
+        let a = KeyAction::LayerTapToggle(3);
+        assert_eq!(0x52C3, to_via_keycode(a));
+    }
+
+    #[test]
+    fn test_tap_hold_via_keycode_round_trip() {
+        let actions = [
+            KeyAction::TapHold(Action::Key(KeyCode::A), Action::LayerOn(0)),
+            KeyAction::TapHold(Action::Key(KeyCode::Space), Action::LayerOn(15)),
+            KeyAction::TapHold(
+                Action::Key(KeyCode::F),
+                Action::Modifier(ModifierCombination::new_from(false, false, false, true, false)),
+            ),
+            KeyAction::TapHold(
+                Action::Key(KeyCode::J),
+                Action::Modifier(ModifierCombination::new_from(true, false, false, false, true)),
+            ),
+        ];
+        for a in actions {
+            assert_eq!(a, from_via_keycode(to_via_keycode(a)));
+        }
+
+        // Layer 16 cannot be encoded as LT
+        let a = KeyAction::TapHold(Action::Key(KeyCode::A), Action::LayerOn(16));
+        assert_eq!(0x0000, to_via_keycode(a));
+
+        // Non-basic keycode cannot be used as the tap action of LT/MT
+        let a = KeyAction::TapHold(Action::Key(KeyCode::Macro0), Action::LayerOn(1));
+        assert_eq!(0x4100, to_via_keycode(a));
+    }
+
+    #[test]
+    fn test_profile_and_unicode_via_keycode_round_trip() {
+        let actions = [
+            KeyAction::Single(Action::ProfileSelect(0)),
+            KeyAction::Single(Action::ProfileSelect(7)),
+            KeyAction::Single(Action::ProfileNext),
+            KeyAction::Single(Action::ProfilePrevious),
+            KeyAction::Single(Action::PairingMode),
+            KeyAction::Single(Action::Unicode(0x00E9)),
+            KeyAction::Single(Action::Unicode(0x7FFF)),
+        ];
+        for a in actions {
+            let via_keycode = to_via_keycode(a);
+            assert_ne!(0x0000, via_keycode);
+            assert_eq!(a, from_via_keycode(via_keycode));
+        }
+
+        assert_eq!(0x7E43, to_via_keycode(KeyAction::Single(Action::ProfileSelect(3))));
+        assert_eq!(0x80E9, to_via_keycode(KeyAction::Single(Action::Unicode(0x00E9))));
+
+        // Code points above 0x7FFF cannot be encoded
+        let a = KeyAction::Single(Action::Unicode(0x1F600));
+        assert_eq!(0x0000, to_via_keycode(a));
     }
 
     #[test]
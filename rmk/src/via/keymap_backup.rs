@@ -0,0 +1,150 @@
+//! Keymap backup and restore through Via custom values on the custom channel.
+//!
+//! The keymap is transferred as Via keycodes in chunks, in the order of layer, row and col.
+//! Keycodes are big-endian, the offset is the index of the first key in the chunk.
+//!
+//! - `KeymapChunk` get: `[0x08, 0x00, 0x04, offset_hi, offset_lo, count]`, returns `count` keycodes from the 7th byte.
+//!   `count` is truncated at the end of the keymap, the actual count is returned in the 6th byte.
+//! - `KeymapChunk` set: `[0x07, 0x00, 0x04, offset_hi, offset_lo, count, keycodes...]`, the keys are saved to the storage.
+//! - `KeymapChecksum` get: `[0x08, 0x00, 0x05]`, returns the number of keys(u16) from the 4th byte,
+//!   and the CRC-32 of all keycodes(u32) from the 6th byte. The host compares it with its own data to detect partial transfers.
+
+use core::cell::RefCell;
+
+use byteorder::{BigEndian, ByteOrder};
+use rmk_types::protocol::vial::ViaCommand;
+
+use crate::descriptor::ViaReport;
+use crate::keymap::KeyMap;
+use crate::via::keycode_convert::{from_via_keycode, to_via_keycode};
+#[cfg(feature = "storage")]
+use {crate::channel::FLASH_CHANNEL, crate::storage::FlashOperationMessage};
+
+/// Max number of keycodes in a chunk, 6 bytes of the report are used by the header
+pub(crate) const KEYMAP_CHUNK_MAX_LEN: usize = 13;
+
+/// Read a chunk of the keymap
+pub(crate) fn get_keymap_chunk<const ROW: usize, const COL: usize, const NUM_LAYER: usize, const NUM_ENCODER: usize>(
+    report: &mut ViaReport,
+    keymap: &RefCell<KeyMap<'_, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
+) {
+    let total = ROW * COL * NUM_LAYER;
+    let offset = BigEndian::read_u16(&report.output_data[3..5]) as usize;
+    let count = report.output_data[5] as usize;
+    if offset >= total || count > KEYMAP_CHUNK_MAX_LEN {
+        warn!("Invalid keymap chunk, offset: {}, count: {}", offset, count);
+        report.input_data[0] = ViaCommand::Unhandled as u8;
+        return;
+    }
+    let count = count.min(total - offset);
+    report.input_data[5] = count as u8;
+    keymap
+        .borrow()
+        .layers
+        .iter()
+        .flatten()
+        .flatten()
+        .skip(offset)
+        .take(count)
+        .enumerate()
+        .for_each(|(i, a)| BigEndian::write_u16(&mut report.input_data[6 + i * 2..8 + i * 2], to_via_keycode(*a)));
+}
+
+/// Write a chunk of the keymap and save the keys to the storage
+pub(crate) async fn set_keymap_chunk<
+    const ROW: usize,
+    const COL: usize,
+    const NUM_LAYER: usize,
+    const NUM_ENCODER: usize,
+>(
+    report: &mut ViaReport,
+    keymap: &RefCell<KeyMap<'_, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
+) {
+    let offset = BigEndian::read_u16(&report.output_data[3..5]) as usize;
+    let count = report.output_data[5] as usize;
+    // The whole chunk is rejected if it's out of range, so a chunk is never written partially
+    if count > KEYMAP_CHUNK_MAX_LEN || offset + count > ROW * COL * NUM_LAYER {
+        warn!("Invalid keymap chunk, offset: {}, count: {}", offset, count);
+        report.input_data[0] = ViaCommand::Unhandled as u8;
+        return;
+    }
+    #[cfg(feature = "storage")]
+    let mut actions = heapless::Vec::new();
+    for i in 0..count {
+        let via_keycode = BigEndian::read_u16(&report.output_data[6 + i * 2..8 + i * 2]);
+        let action = from_via_keycode(via_keycode);
+        let idx = offset + i;
+        let (layer, row, col) = (idx / (ROW * COL), idx % (ROW * COL) / COL, idx % COL);
+        keymap.borrow_mut().layers[layer][row][col] = action;
+        #[cfg(feature = "storage")]
+        let _ = actions.push(action);
+    }
+    #[cfg(feature = "storage")]
+    FLASH_CHANNEL
+        .send(FlashOperationMessage::KeymapBatch {
+            offset: offset as u16,
+            actions,
+        })
+        .await;
+}
+
+/// Get the number of keys and the checksum of the keymap
+pub(crate) fn get_keymap_checksum<
+    const ROW: usize,
+    const COL: usize,
+    const NUM_LAYER: usize,
+    const NUM_ENCODER: usize,
+>(
+    report: &mut ViaReport,
+    keymap: &RefCell<KeyMap<'_, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
+) {
+    let checksum = keymap
+        .borrow()
+        .layers
+        .iter()
+        .flatten()
+        .flatten()
+        .fold(Crc32::new(), |crc, a| crc.update(&to_via_keycode(*a).to_be_bytes()));
+    BigEndian::write_u16(&mut report.input_data[3..5], (ROW * COL * NUM_LAYER) as u16);
+    BigEndian::write_u32(&mut report.input_data[5..9], checksum.finish());
+}
+
+/// CRC-32(IEEE 802.3), the same as `zlib.crc32`, so the checksum can be calculated easily by a host script
+struct Crc32(u32);
+
+impl Crc32 {
+    fn new() -> Self {
+        Self(0xFFFF_FFFF)
+    }
+
+    fn update(mut self, data: &[u8]) -> Self {
+        for byte in data {
+            self.0 ^= *byte as u32;
+            for _ in 0..8 {
+                self.0 = if self.0 & 1 != 0 {
+                    (self.0 >> 1) ^ 0xEDB8_8320
+                } else {
+                    self.0 >> 1
+                };
+            }
+        }
+        self
+    }
+
+    fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(Crc32::new().finish(), 0);
+        assert_eq!(Crc32::new().update(b"123456789").finish(), 0xCBF4_3926);
+        // Updating in chunks is the same as updating at once
+        assert_eq!(Crc32::new().update(b"1234").update(b"56789").finish(), 0xCBF4_3926);
+    }
+}
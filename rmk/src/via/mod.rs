@@ -1,7 +1,9 @@
 use core::cell::RefCell;
+use core::ops::Range;
 use core::sync::atomic::Ordering;
 
-use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use byteorder::{BigEndian, ByteOrder};
+use custom_value::{process_custom_get_value, process_custom_save, process_custom_set_value};
 use embassy_time::{Instant, Timer};
 use embassy_usb::class::hid::HidReaderWriter;
 use embassy_usb::driver::Driver;
@@ -15,10 +17,15 @@ use crate::hid::{HidError, HidReaderTrait, HidWriterTrait};
 use crate::keymap::KeyMap;
 use crate::state::ConnectionState;
 use crate::via::keycode_convert::{from_via_keycode, to_via_keycode};
-use crate::{CONNECTION_STATE, MACRO_SPACE_SIZE, boot};
+use crate::{CONNECTION_STATE, MACRO_SPACE_SIZE, NUM_MACRO, boot};
 #[cfg(feature = "storage")]
-use crate::{channel::FLASH_CHANNEL, storage::FlashOperationMessage};
+use crate::{
+    channel::FLASH_CHANNEL,
+    storage::{FlashOperationMessage, KEYMAP_BATCH_MAX_NUM},
+};
+mod custom_value;
 pub(crate) mod keycode_convert;
+mod keymap_backup;
 mod vial;
 #[cfg(feature = "vial_lock")]
 mod vial_lock;
@@ -92,10 +99,17 @@ impl<
         let mut via_report = self.reader_writer.read_report().await?;
 
         self.process_via_packet(&mut via_report, self.keymap).await;
+        let command_id = via_report.output_data[0];
 
         // Send via report back after processing
         self.reader_writer.write_report(via_report).await?;
 
+        // Reboot after the host receives the response of the eeprom reset
+        #[cfg(feature = "storage")]
+        if self.vial_config.reboot_after_reset && ViaCommand::from(command_id) == ViaCommand::EepromReset {
+            boot::reboot_keyboard();
+        }
+
         Ok(())
     }
 
@@ -122,8 +136,7 @@ impl<
                             BigEndian::write_u32(&mut report.input_data[2..6], value);
                         }
                         ViaKeyboardInfo::LayoutOptions => {
-                            // TODO: retrieve layout option from storage
-                            let layout_option: u32 = 0;
+                            let layout_option = keymap.borrow().get_layout_option();
                             BigEndian::write_u32(&mut report.input_data[2..6], layout_option);
                         }
                         ViaKeyboardInfo::SwitchMatrixState => {
@@ -153,9 +166,10 @@ impl<
                 // Check the second u8
                 match report.output_data[1].try_into() {
                     Ok(v) => match v {
-                        #[cfg(feature = "storage")]
                         ViaKeyboardInfo::LayoutOptions => {
                             let layout_option = BigEndian::read_u32(&report.output_data[2..6]);
+                            keymap.borrow_mut().set_layout_option(layout_option);
+                            #[cfg(feature = "storage")]
                             FLASH_CHANNEL
                                 .send(FlashOperationMessage::LayoutOptions(layout_option))
                                 .await;
@@ -173,6 +187,11 @@ impl<
                 let layer = report.output_data[1] as usize;
                 let row = report.output_data[2] as usize;
                 let col = report.output_data[3] as usize;
+                if layer >= NUM_LAYER || row >= ROW || col >= COL {
+                    warn!("Invalid key position: ({}, {}), layer {}", row, col, layer);
+                    report.input_data[0] = ViaCommand::Unhandled as u8;
+                    return;
+                }
                 let action = keymap
                     .borrow_mut()
                     .get_action_at(KeyboardEventPos::key_pos(col as u8, row as u8), layer);
@@ -184,6 +203,11 @@ impl<
                 let layer = report.output_data[1];
                 let row = report.output_data[2];
                 let col = report.output_data[3];
+                if layer as usize >= NUM_LAYER || row as usize >= ROW || col as usize >= COL {
+                    warn!("Invalid key position: ({}, {}), layer {}", row, col, layer);
+                    report.input_data[0] = ViaCommand::Unhandled as u8;
+                    return;
+                }
                 let keycode = BigEndian::read_u16(&report.output_data[4..6]);
                 let action = from_via_keycode(keycode);
                 info!(
@@ -209,55 +233,54 @@ impl<
                 warn!("Dynamic keymap reset -- not supported")
             }
             ViaCommand::CustomSetValue => {
-                // backlight/rgblight/rgb matrix/led matrix/audio settings here
-                warn!("Custom set value -- not supported")
+                process_custom_set_value(report, keymap).await;
             }
             ViaCommand::CustomGetValue => {
-                // backlight/rgblight/rgb matrix/led matrix/audio settings here
-                warn!("Custom get value -- not supported")
+                process_custom_get_value(report, keymap).await;
             }
             ViaCommand::CustomSave => {
-                // backlight/rgblight/rgb matrix/led matrix/audio settings here
-                warn!("Custom get value -- not supported")
+                process_custom_save(report, keymap).await;
             }
             ViaCommand::EepromReset => {
                 warn!("Reseting storage..");
                 #[cfg(feature = "storage")]
-                FLASH_CHANNEL.send(FlashOperationMessage::Reset).await
-                // TODO: Reboot after a eeprom reset?
+                {
+                    FLASH_CHANNEL.send(FlashOperationMessage::Reset).await;
+                    // Make sure that the storage is cleared before rebooting
+                    if self.vial_config.reboot_after_reset {
+                        crate::storage::flush_flash_operations().await;
+                    }
+                }
             }
             ViaCommand::BootloaderJump => {
                 warn!("Bootloader jumping");
+                // Make sure that pending keymap/config changes are saved before the reset
+                #[cfg(feature = "storage")]
+                crate::storage::flush_flash_operations().await;
                 boot::jump_to_bootloader();
             }
             ViaCommand::DynamicKeymapMacroGetCount => {
-                report.input_data[1] = 32;
-                warn!("Macro get count -- to be implemented")
+                // Each macro takes at least one byte(the terminating 0) in the macro buffer,
+                // so the reported macro count cannot exceed the buffer size
+                report.input_data[1] = NUM_MACRO.min(MACRO_SPACE_SIZE) as u8;
             }
             ViaCommand::DynamicKeymapMacroGetBufferSize => {
-                report.input_data[1] = (MACRO_SPACE_SIZE as u16 >> 8) as u8;
-                report.input_data[2] = (MACRO_SPACE_SIZE & 0xFF) as u8;
-                warn!("Macro get buffer size -- to be implemented")
+                BigEndian::write_u16(&mut report.input_data[1..3], MACRO_SPACE_SIZE as u16);
             }
             ViaCommand::DynamicKeymapMacroGetBuffer => {
-                let offset = BigEndian::read_u16(&report.output_data[1..3]) as usize;
-                let size = report.output_data[3] as usize;
-                if size <= 28 {
-                    report.input_data[4..4 + size].copy_from_slice(
-                        &self.keymap.borrow().behavior.keyboard_macros.macro_sequences[offset..offset + size],
-                    );
-                    debug!("Get macro buffer: offset: {}, data: {:?}", offset, report.input_data);
-                } else {
-                    report.input_data[0] = 0xFF;
-                }
+                get_macro_buffer(report, &self.keymap.borrow().behavior.keyboard_macros.macro_sequences)
             }
             ViaCommand::DynamicKeymapMacroSetBuffer => {
                 // Every write writes all buffer space of the macro(if it's not empty)
-                let offset = BigEndian::read_u16(&report.output_data[1..3]);
+                let offset = BigEndian::read_u16(&report.output_data[1..3]) as usize;
                 // Current sequence size, <= 28
-                let size = report.output_data[3];
-                // End of current sequence in the macro cache
-                let end = offset + size as u16;
+                let size = report.output_data[3] as usize;
+                // A bad packet is rejected, the macro cache and the storage are not changed
+                let Some(range) = macro_buffer_range(offset, size) else {
+                    warn!("Invalid macro buffer, offset: {}, size: {}", offset, size);
+                    report.input_data[0] = ViaCommand::Unhandled as u8;
+                    return;
+                };
 
                 // The first sequence, reset the macro cache
                 if offset == 0 {
@@ -266,8 +289,8 @@ impl<
 
                 // Update macro cache
                 info!("Setting macro buffer, offset: {}, size: {}", offset, size);
-                self.keymap.borrow_mut().behavior.keyboard_macros.macro_sequences[offset as usize..end as usize]
-                    .copy_from_slice(&report.output_data[4..4 + size as usize]);
+                self.keymap.borrow_mut().behavior.keyboard_macros.macro_sequences[range]
+                    .copy_from_slice(&report.output_data[4..4 + size]);
 
                 // Then flush macros to storage
                 #[cfg(feature = "storage")]
@@ -288,8 +311,7 @@ impl<
             }
             ViaCommand::DynamicKeymapGetBuffer => {
                 let offset = BigEndian::read_u16(&report.output_data[1..3]);
-                // size <= 28
-                let size = report.output_data[3];
+                let size = keymap_buffer_key_num(&report);
                 debug!("Getting keymap buffer, offset: {}, size: {}", offset, size);
                 let mut idx = 4;
                 keymap
@@ -299,7 +321,7 @@ impl<
                     .flatten()
                     .flatten()
                     .skip((offset / 2) as usize)
-                    .take((size / 2) as usize)
+                    .take(size)
                     .for_each(|a| {
                         let kc = to_via_keycode(*a);
                         BigEndian::write_u16(&mut report.input_data[idx..idx + 2], kc);
@@ -307,12 +329,13 @@ impl<
                     });
             }
             ViaCommand::DynamicKeymapSetBuffer => {
-                debug!("Dynamic keymap set buffer");
-                let offset = BigEndian::read_u16(&report.output_data[1..3]);
-                // size <= 28
-                let size = report.output_data[3];
-                let mut idx = 4;
-                let (row_num, col_num, _layer_num) = keymap.borrow().get_keymap_config();
+                // Same as `DynamicKeymapGetBuffer`, the offset and size are in bytes, 2 bytes per key
+                let offset = BigEndian::read_u16(&report.output_data[1..3]) / 2;
+                let size = keymap_buffer_key_num(&report);
+                debug!("Setting keymap buffer, offset: {}, size: {}", offset, size);
+                // All keys in the buffer are saved in one flash operation
+                #[cfg(feature = "storage")]
+                let mut actions = heapless::Vec::<_, KEYMAP_BATCH_MAX_NUM>::new();
                 keymap
                     .borrow_mut()
                     .layers
@@ -320,35 +343,83 @@ impl<
                     .flatten()
                     .flatten()
                     .skip(offset as usize)
-                    .take(size as usize)
+                    .take(size)
                     .enumerate()
                     .for_each(|(i, a)| {
-                        let via_keycode = LittleEndian::read_u16(&report.output_data[idx..idx + 2]);
-                        let action: rmk_types::action::KeyAction = from_via_keycode(via_keycode);
-                        *a = action;
-                        idx += 2;
-                        let current_offset = offset as usize + i;
-                        let (row, col, layer) = get_position_from_offset(current_offset, row_num, col_num);
-                        info!(
-                            "Setting keymap buffer of offset: {}, row,col,layer: {},{},{}",
-                            offset, row, col, layer
-                        );
+                        *a = from_via_keycode(BigEndian::read_u16(&report.output_data[4 + i * 2..6 + i * 2]));
                         #[cfg(feature = "storage")]
-                        if let Err(_e) = FLASH_CHANNEL.try_send(FlashOperationMessage::KeymapKey {
-                            layer: layer as u8,
-                            col: col as u8,
-                            row: row as u8,
-                            action,
-                        }) {
-                            error!("Send keymap setting command error")
-                        }
+                        let _ = actions.push(*a);
                     });
+                #[cfg(feature = "storage")]
+                if !actions.is_empty() {
+                    FLASH_CHANNEL
+                        .send(FlashOperationMessage::KeymapBatch { offset, actions })
+                        .await;
+                }
             }
             ViaCommand::DynamicKeymapGetEncoder => {
-                warn!("Keymap get encoder -- not supported");
+                let layer = report.output_data[1];
+                let index = report.output_data[2];
+                let clockwise = report.output_data[3];
+                debug!(
+                    "Getting encoder: idx {} at layer {}, clockwise: {}",
+                    index, layer, clockwise
+                );
+
+                let keycode = keymap
+                    .borrow()
+                    .encoders
+                    .as_ref()
+                    .and_then(|encoder_map| encoder_map.get(layer as usize))
+                    .and_then(|encoder_layer| encoder_layer.get(index as usize))
+                    .map(|encoder| {
+                        if clockwise == 1 {
+                            to_via_keycode(encoder.clockwise())
+                        } else {
+                            to_via_keycode(encoder.counter_clockwise())
+                        }
+                    })
+                    // Return `KeyAction::No` for non-existing encoders
+                    .unwrap_or(0);
+                BigEndian::write_u16(&mut report.input_data[4..6], keycode);
             }
             ViaCommand::DynamicKeymapSetEncoder => {
-                warn!("Keymap set encoder -- not supported");
+                let layer = report.output_data[1];
+                let index = report.output_data[2];
+                let clockwise = report.output_data[3];
+                let keycode = BigEndian::read_u16(&report.output_data[4..6]);
+                let action = from_via_keycode(keycode);
+                info!(
+                    "Setting encoder: idx {} at layer {}, clockwise: {} as {:?}",
+                    index, layer, clockwise, action
+                );
+
+                let _encoder = match keymap.borrow_mut().encoders {
+                    Some(ref mut encoder_map) => encoder_map
+                        .get_mut(layer as usize)
+                        .and_then(|encoder_layer| encoder_layer.get_mut(index as usize))
+                        .map(|encoder| {
+                            if clockwise == 1 {
+                                encoder.set_clockwise(action);
+                            } else {
+                                encoder.set_counter_clockwise(action);
+                            }
+                            *encoder
+                        }),
+                    None => None,
+                };
+
+                #[cfg(feature = "storage")]
+                // Save the encoder action to the storage after the RefCell is released
+                if let Some(encoder) = _encoder {
+                    FLASH_CHANNEL
+                        .send(FlashOperationMessage::EncoderKey {
+                            idx: index,
+                            layer,
+                            action: encoder,
+                        })
+                        .await;
+                }
             }
             ViaCommand::Vial => {
                 process_vial(
@@ -368,12 +439,40 @@ impl<
     }
 }
 
-fn get_position_from_offset(offset: usize, max_row: usize, max_col: usize) -> (usize, usize, usize) {
-    let layer = offset / (max_col * max_row);
-    let current_layer_offset = offset % (max_col * max_row);
-    let row = current_layer_offset / max_col;
-    let col = current_layer_offset % max_col;
-    (row, col, layer)
+/// Read a chunk of the macro cache, a chunk which is out of the macro space is rejected with `0xFF`
+fn get_macro_buffer(report: &mut ViaReport, macro_sequences: &[u8]) {
+    let offset = BigEndian::read_u16(&report.output_data[1..3]) as usize;
+    let size = report.output_data[3] as usize;
+    if let Some(range) = macro_buffer_range(offset, size) {
+        report.input_data[4..4 + size].copy_from_slice(&macro_sequences[range]);
+        debug!("Get macro buffer: offset: {}, data: {:?}", offset, report.input_data);
+    } else {
+        warn!("Invalid macro buffer, offset: {}, size: {}", offset, size);
+        report.input_data[0] = ViaCommand::Unhandled as u8;
+    }
+}
+
+// The macro buffer size is reported to Vial as u16, and the offsets of the macro buffer commands are u16 too
+const _: () = assert!(
+    MACRO_SPACE_SIZE > 0 && MACRO_SPACE_SIZE <= u16::MAX as usize,
+    "MACRO_SPACE_SIZE must be between 1 and 65535"
+);
+
+/// Range of a macro buffer read or write in the macro cache, `None` if it's out of the report or the macro space
+fn macro_buffer_range(offset: usize, size: usize) -> Option<Range<usize>> {
+    // The data in a report starts from the 5th byte
+    if size > 28 || offset + size > MACRO_SPACE_SIZE {
+        return None;
+    }
+    Some(offset..offset + size)
+}
+
+/// Number of keys read or written by a `DynamicKeymapGetBuffer` or `DynamicKeymapSetBuffer` report.
+///
+/// The size is sent by the host, it's clamped to the keys which fit in the report, 14 at most.
+fn keymap_buffer_key_num(report: &ViaReport) -> usize {
+    // The keycodes start from the 5th byte, 2 bytes per key
+    (report.output_data[3] as usize / 2).min((report.output_data.len() - 4) / 2)
 }
 
 fn count_zeros(data: &[u8]) -> usize {
@@ -418,3 +517,68 @@ impl<'d, D: Driver<'d>> HidReaderTrait for UsbVialReaderWriter<'_, 'd, D> {
         Ok(read_report)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_macro_buffer_range() {
+        assert_eq!(macro_buffer_range(0, 28), Some(0..28));
+        assert_eq!(
+            macro_buffer_range(MACRO_SPACE_SIZE - 4, 4),
+            Some(MACRO_SPACE_SIZE - 4..MACRO_SPACE_SIZE)
+        );
+        // Larger than a report
+        assert_eq!(macro_buffer_range(0, 29), None);
+        // Out of the macro space
+        assert_eq!(macro_buffer_range(MACRO_SPACE_SIZE - 4, 5), None);
+        assert_eq!(macro_buffer_range(u16::MAX as usize, 28), None);
+    }
+
+    #[test]
+    fn test_keymap_buffer_key_num() {
+        let mut report = ViaReport {
+            input_data: [0; 32],
+            output_data: [0; 32],
+        };
+        report.output_data[0] = ViaCommand::DynamicKeymapSetBuffer as u8;
+        report.output_data[3] = 4;
+        assert_eq!(keymap_buffer_key_num(&report), 2);
+        report.output_data[3] = 28;
+        assert_eq!(keymap_buffer_key_num(&report), 14);
+        // The size sent by the host is larger than the report
+        report.output_data[3] = 0xFF;
+        assert_eq!(keymap_buffer_key_num(&report), 14);
+    }
+
+    fn macro_get_buffer_report(offset: usize, size: u8) -> ViaReport {
+        let mut report = ViaReport {
+            input_data: [0; 32],
+            output_data: [0; 32],
+        };
+        report.output_data[0] = ViaCommand::DynamicKeymapMacroGetBuffer as u8;
+        BigEndian::write_u16(&mut report.output_data[1..3], offset as u16);
+        report.output_data[3] = size;
+        report.input_data = report.output_data;
+        report
+    }
+
+    #[test]
+    fn test_get_macro_buffer_near_the_end() {
+        let mut macro_sequences = [0u8; MACRO_SPACE_SIZE];
+        macro_sequences[MACRO_SPACE_SIZE - 4..].copy_from_slice(&[1, 2, 3, 4]);
+
+        // The last 4 bytes
+        let mut report = macro_get_buffer_report(MACRO_SPACE_SIZE - 4, 4);
+        get_macro_buffer(&mut report, &macro_sequences);
+        assert_eq!(report.input_data[0], ViaCommand::DynamicKeymapMacroGetBuffer as u8);
+        assert_eq!(report.input_data[4..8], [1, 2, 3, 4]);
+
+        // Crossing the end of the macro space
+        let mut report = macro_get_buffer_report(MACRO_SPACE_SIZE - 4, 28);
+        get_macro_buffer(&mut report, &macro_sequences);
+        assert_eq!(report.input_data[0], 0xFF);
+        assert_eq!(report.input_data[4..8], [0; 4]);
+    }
+}
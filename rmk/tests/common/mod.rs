@@ -49,6 +49,7 @@ pub async fn run_key_sequence_test<'a, const ROW: usize, const COL: usize, const
     keyboard: &mut Keyboard<'a, ROW, COL, NUM_LAYER>,
     key_sequence: &[TestKeyPress],
     expected_reports: &[KeyboardReport],
+    expected_media_reports: &[u16],
 ) {
     static REPORTS_DONE: Mutex<CriticalSectionRawMutex, bool> = Mutex::new(false);
     static SEQ_SEND_DONE: Mutex<CriticalSectionRawMutex, bool> = Mutex::new(false);
@@ -90,18 +91,29 @@ pub async fn run_key_sequence_test<'a, const ROW: usize, const COL: usize, const
         // Verify reports
         async {
             match select(Timer::after(MAX_TEST_TIMEOUT), async {
-                let mut report_index = -1;
-                for expected in expected_reports {
+                let mut report_index = 0;
+                let mut media_report_index = 0;
+                while report_index < expected_reports.len() || media_report_index < expected_media_reports.len() {
                     match select(Timer::after(Duration::from_secs(2)), KEYBOARD_REPORT_CHANNEL.receive()).await {
                         Either::First(_) => panic!("ERROR: report wait timeout reached"),
-                        Either::Second(Report::KeyboardReport(report)) => {
-                            report_index += 1;
+                        Either::Second(Report::KeyboardReport(report)) if report_index < expected_reports.len() => {
                             // println!("Received {}th report from channel: {:?}", report_index, report);
                             assert_eq!(
-                                *expected, report,
+                                expected_reports[report_index], report,
                                 "on #{} reports, expected left but actually right",
                                 report_index
                             );
+                            report_index += 1;
+                        }
+                        Either::Second(Report::MediaKeyboardReport(report))
+                            if media_report_index < expected_media_reports.len() =>
+                        {
+                            assert_eq!(
+                                expected_media_reports[media_report_index], report.usage_id,
+                                "on #{} media reports, expected left but actually right",
+                                media_report_index
+                            );
+                            media_report_index += 1;
                         }
                         Either::Second(report) => {
                             debug!("Other reports {:?}", report)
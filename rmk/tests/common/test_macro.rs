@@ -6,12 +6,50 @@ extern crate rmk;
 ///     - keyboard: keyboard initialization
 ///     - sequence: key sequence: [row, col, pressed, press_delay], where press_delay is the time interval in ms between last key action and current key
 ///     - expected_reports: [modifiers, [keycodes; 6]], represents the hid report which will be sent to the host
+///     - expected_media_reports(optional): [usage_id], the consumer control reports which will be sent to the host
 #[macro_export]
 macro_rules! key_sequence_test {
-    (keyboard: $keyboard:expr, sequence: [$([$row:expr, $col:expr, $pressed:expr, $delay:expr]),* $(,)?], expected_reports: [$([$modifier:expr, $keys:expr]),* $(,)?]) => {
-        ::embassy_futures::block_on(async {
+    (keyboard: $keyboard:expr, sequence: $sequence:tt, expected_reports: $expected_reports:tt $(, expected_media_reports: $expected_media_reports:tt)?) => {
+        ::embassy_futures::block_on($crate::key_sequence!(
+            keyboard: $keyboard,
+            sequence: $sequence,
+            expected_reports: $expected_reports
+            $(, expected_media_reports: $expected_media_reports)?
+        ))
+    };
+}
+
+/// Same as `key_sequence_test!`, but runs with the virtual clock.
+///
+/// The delays in the sequence are exact and don't take the real time, use it to test the behaviors at the boundary
+/// of a timeout. Run it in `rusty_fork_test!`, the virtual clock can't be stopped once started.
+#[macro_export]
+macro_rules! virtual_key_sequence_test {
+    (keyboard: $keyboard:expr, sequence: $sequence:tt, expected_reports: $expected_reports:tt $(, expected_media_reports: $expected_media_reports:tt)?) => {
+        ::rmk::time_driver::VirtualClock::block_on($crate::key_sequence!(
+            keyboard: $keyboard,
+            sequence: $sequence,
+            expected_reports: $expected_reports
+            $(, expected_media_reports: $expected_media_reports)?
+        ))
+    };
+}
+
+/// The future of running a key sequence test
+#[macro_export]
+macro_rules! key_sequence {
+    (keyboard: $keyboard:expr, sequence: $sequence:tt, expected_reports: $expected_reports:tt) => {
+        $crate::key_sequence!(
+            keyboard: $keyboard,
+            sequence: $sequence,
+            expected_reports: $expected_reports,
+            expected_media_reports: []
+        )
+    };
+    (keyboard: $keyboard:expr, sequence: [$([$row:expr, $col:expr, $pressed:expr, $delay:expr]),* $(,)?], expected_reports: [$([$modifier:expr, $keys:expr]),* $(,)?], expected_media_reports: [$($usage_id:expr),* $(,)?]) => {
+        async {
             let mut keyboard = $keyboard;
-            let sequence = vec![
+            let sequence: ::std::vec::Vec<$crate::common::TestKeyPress> = vec![
                 $(
                     $crate::common::TestKeyPress {
                         row: $row,
@@ -21,7 +59,7 @@ macro_rules! key_sequence_test {
                     },
                 )*
             ];
-            let expected_reports = vec![
+            let expected_reports: ::std::vec::Vec<rmk::descriptor::KeyboardReport> = vec![
                 $(
                     rmk::descriptor::KeyboardReport {
                         modifier: $modifier,
@@ -31,9 +69,11 @@ macro_rules! key_sequence_test {
                     },
                 )*
             ];
+            let expected_media_reports: ::std::vec::Vec<u16> = vec![$($usage_id),*];
 
-            $crate::common::run_key_sequence_test(&mut keyboard, &sequence, &expected_reports).await;
-        });
+            $crate::common::run_key_sequence_test(&mut keyboard, &sequence, &expected_reports, &expected_media_reports)
+                .await;
+        }
     };
 }
 
@@ -0,0 +1,99 @@
+pub mod common;
+
+use embassy_time::Duration;
+use rmk::config::{AutoShiftConfig, BehaviorConfig};
+
+mod auto_shift_test {
+    use std::cell::RefCell;
+
+    use rmk::k;
+    use rmk::keyboard::Keyboard;
+    use rmk::keymap::KeyMap;
+    use rmk::types::action::KeyAction;
+    use rusty_fork::rusty_fork_test;
+
+    use super::*;
+    use crate::common::{KC_LSHIFT, wrap_keymap};
+
+    const KEYMAP: [[[KeyAction; 3]; 1]; 1] = [[[k!(A), k!(Kc1), k!(Space)]]];
+
+    fn create_test_keyboard() -> Keyboard<'static, 1, 3, 1> {
+        static BEHAVIOR_CONFIG: static_cell::StaticCell<BehaviorConfig> = static_cell::StaticCell::new();
+        let behavior_config = BEHAVIOR_CONFIG.init(BehaviorConfig {
+            auto_shift: AutoShiftConfig {
+                enable: true,
+                timeout: Duration::from_millis(150),
+                ..AutoShiftConfig::default()
+            },
+            ..BehaviorConfig::default()
+        });
+        let keymap: &RefCell<KeyMap<1, 3, 1>> = wrap_keymap(KEYMAP, behavior_config);
+        Keyboard::new(keymap)
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_auto_shift_tap() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],  // Press A
+                    [0, 0, false, 50], // Release A before timeout
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_auto_shift_hold() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],   // Press A
+                    [0, 0, false, 300], // Release A after timeout
+                ],
+                expected_reports: [
+                    [KC_LSHIFT, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_auto_shift_not_applied_to_other_keys() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 2, true, 10],   // Press Space
+                    [0, 2, false, 300], // Release Space
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(Space), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_auto_shift_rolling() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],  // Press A
+                    [0, 1, true, 50],  // Press 1, A is sent without shift
+                    [0, 0, false, 10], // Release A, 1 is sent without shift
+                    [0, 1, false, 10], // Release 1
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(A), kc_to_u8!(Kc1), 0, 0, 0, 0]],
+                    [0, [0, kc_to_u8!(Kc1), 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+    }
+}
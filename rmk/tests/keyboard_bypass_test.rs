@@ -0,0 +1,91 @@
+pub mod common;
+
+mod bypass_test {
+    use std::cell::RefCell;
+
+    use rmk::config::{BehaviorConfig, BypassConfig};
+    use rmk::keyboard::Keyboard;
+    use rmk::keymap::KeyMap;
+    use rmk::types::action::KeyAction;
+    use rmk::types::modifier::ModifierCombination;
+    use rmk::{k, mo, mt};
+    use rusty_fork::rusty_fork_test;
+
+    use crate::common::{KC_LSHIFT, wrap_keymap};
+
+    const KEYMAP: [[[KeyAction; 4]; 1]; 2] = [
+        [[mt!(A, ModifierCombination::LSHIFT), k!(B), k!(BypassToggle), mo!(1)]],
+        [[k!(C), k!(BypassToggle), k!(D), k!(No)]],
+    ];
+
+    fn create_test_keyboard(enable: bool) -> Keyboard<'static, 1, 4, 2> {
+        static BEHAVIOR_CONFIG: static_cell::StaticCell<BehaviorConfig> = static_cell::StaticCell::new();
+        let behavior_config = BEHAVIOR_CONFIG.init(BehaviorConfig {
+            bypass: BypassConfig { enable, persist: false },
+            ..BehaviorConfig::default()
+        });
+        let keymap: &RefCell<KeyMap<1, 4, 2>> = wrap_keymap(KEYMAP, behavior_config);
+        Keyboard::new(keymap)
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_bypass_tap_hold_sends_tap() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(true),
+                sequence: [
+                    [0, 0, true, 10],   // Press mt!(A, LShift)
+                    [0, 0, false, 300], // Release after the hold timeout
+                ],
+                expected_reports: [
+                    // The tap action is sent immediately
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_bypass_uses_default_layer() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(true),
+                sequence: [
+                    [0, 3, true, 10],  // Press MO(1)
+                    [0, 0, true, 10],  // Press mt!(A, LShift), C on layer 1
+                    [0, 0, false, 10], // Release A
+                    [0, 3, false, 10], // Release MO(1)
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_bypass_toggle() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(false),
+                sequence: [
+                    [0, 2, true, 10],   // Turn on the bypass mode
+                    [0, 2, false, 10],
+                    [0, 0, true, 10],   // Press mt!(A, LShift)
+                    [0, 0, false, 300], // Release after the hold timeout
+                    [0, 3, true, 10],   // Hold MO(1)
+                    [0, 1, true, 10],   // Turn off the bypass mode on layer 1
+                    [0, 1, false, 10],
+                    [0, 3, false, 10],  // Release MO(1)
+                    [0, 0, true, 10],   // Hold mt!(A, LShift)
+                    [0, 0, false, 300],
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    // The tap-hold key works normally again
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+    }
+}
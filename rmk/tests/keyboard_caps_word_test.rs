@@ -0,0 +1,113 @@
+pub mod common;
+
+use embassy_time::Duration;
+use rmk::config::{BehaviorConfig, CapsWordConfig};
+
+mod caps_word_test {
+    use std::cell::RefCell;
+
+    use rmk::k;
+    use rmk::keyboard::Keyboard;
+    use rmk::keymap::KeyMap;
+    use rmk::types::action::KeyAction;
+    use rusty_fork::rusty_fork_test;
+
+    use super::*;
+    use crate::common::{KC_LSHIFT, wrap_keymap};
+
+    const KEYMAP: [[[KeyAction; 6]; 1]; 1] =
+        [[[k!(CapsWordToggle), k!(A), k!(Kc1), k!(Minus), k!(Backspace), k!(Space)]]];
+
+    fn create_test_keyboard(caps_word: CapsWordConfig) -> Keyboard<'static, 1, 6, 1> {
+        static BEHAVIOR_CONFIG: static_cell::StaticCell<BehaviorConfig> = static_cell::StaticCell::new();
+        let behavior_config = BEHAVIOR_CONFIG.init(BehaviorConfig {
+            caps_word,
+            ..BehaviorConfig::default()
+        });
+        let keymap: &RefCell<KeyMap<1, 6, 1>> = wrap_keymap(KEYMAP, behavior_config);
+        Keyboard::new(keymap)
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_caps_word_shifts_letters_and_minus_only() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(CapsWordConfig::default()),
+                sequence: [
+                    [0, 0, true, 10],   // Press CapsWordToggle
+                    [0, 0, false, 10],  // Release CapsWordToggle
+                    [0, 1, true, 10],   // Press A
+                    [0, 1, false, 10],  // Release A
+                    [0, 2, true, 10],   // Press 1
+                    [0, 2, false, 10],  // Release 1
+                    [0, 3, true, 10],   // Press Minus
+                    [0, 3, false, 10],  // Release Minus
+                    [0, 4, true, 10],   // Press Backspace
+                    [0, 4, false, 10],  // Release Backspace
+                    [0, 1, true, 10],   // Press A
+                    [0, 1, false, 10],  // Release A
+                ],
+                expected_reports: [
+                    [KC_LSHIFT, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(Kc1), 0, 0, 0, 0, 0]], // Digits are not shifted
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [KC_LSHIFT, [kc_to_u8!(Minus), 0, 0, 0, 0, 0]], // Underscore
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(Backspace), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [KC_LSHIFT, [kc_to_u8!(A), 0, 0, 0, 0, 0]], // Caps word is still active
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_caps_word_ends_on_word_breaking_key() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(CapsWordConfig::default()),
+                sequence: [
+                    [0, 0, true, 10],   // Press CapsWordToggle
+                    [0, 0, false, 10],  // Release CapsWordToggle
+                    [0, 1, true, 10],   // Press A
+                    [0, 1, false, 10],  // Release A
+                    [0, 5, true, 10],   // Press Space, caps word ends
+                    [0, 5, false, 10],  // Release Space
+                    [0, 1, true, 10],   // Press A
+                    [0, 1, false, 10],  // Release A
+                ],
+                expected_reports: [
+                    [KC_LSHIFT, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(Space), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_caps_word_idle_timeout() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(CapsWordConfig {
+                    timeout: Duration::from_millis(100),
+                }),
+                sequence: [
+                    [0, 0, true, 10],   // Press CapsWordToggle
+                    [0, 0, false, 10],  // Release CapsWordToggle
+                    [0, 1, true, 50],   // Press A within timeout
+                    [0, 1, false, 10],  // Release A
+                    [0, 1, true, 200],  // Press A after timeout
+                    [0, 1, false, 10],  // Release A
+                ],
+                expected_reports: [
+                    [KC_LSHIFT, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+    }
+}
@@ -50,6 +50,7 @@ pub fn get_combos_config() -> CombosConfig {
             ),
         ]),
         timeout: Duration::from_millis(100),
+        ..Default::default()
     }
 }
 
@@ -197,6 +198,7 @@ mod combo_test {
                                 )
                             ]),
                             timeout: Duration::from_millis(50),
+                            ..Default::default()
                         },
                         ..BehaviorConfig::default()
                     };
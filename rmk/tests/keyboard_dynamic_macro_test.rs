@@ -0,0 +1,121 @@
+pub mod common;
+
+mod dynamic_macro_test {
+    use std::cell::RefCell;
+
+    use rmk::config::BehaviorConfig;
+    use rmk::k;
+    use rmk::keyboard::Keyboard;
+    use rmk::keymap::KeyMap;
+    use rmk::types::action::KeyAction;
+    use rusty_fork::rusty_fork_test;
+
+    use crate::common::{KC_LSHIFT, wrap_keymap};
+
+    const KEYMAP: [[[KeyAction; 6]; 1]; 1] = [[[
+        k!(DynamicMacroRecordStart1),
+        k!(DynamicMacroRecordStop),
+        k!(DynamicMacroPlay1),
+        k!(A),
+        k!(B),
+        k!(LShift),
+    ]]];
+
+    fn create_test_keyboard() -> Keyboard<'static, 1, 6, 1> {
+        static BEHAVIOR_CONFIG: static_cell::StaticCell<BehaviorConfig> = static_cell::StaticCell::new();
+        let behavior_config = BEHAVIOR_CONFIG.init(BehaviorConfig::default());
+        let keymap: &RefCell<KeyMap<1, 6, 1>> = wrap_keymap(KEYMAP, behavior_config);
+        Keyboard::new(keymap)
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_dynamic_macro_record_and_play() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],  // Start recording
+                    [0, 0, false, 10],
+                    [0, 3, true, 10],  // Press A
+                    [0, 3, false, 10], // Release A
+                    [0, 4, true, 10],  // Press B
+                    [0, 4, false, 10], // Release B
+                    [0, 1, true, 10],  // Stop recording
+                    [0, 1, false, 10],
+                    [0, 2, true, 10],  // Play
+                    [0, 2, false, 50],
+                ],
+                expected_reports: [
+                    // Keys are sent normally while recording
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(B), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    // Play
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(B), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_dynamic_macro_with_modifier() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],  // Start recording
+                    [0, 0, false, 10],
+                    [0, 5, true, 10],  // Press LShift
+                    [0, 3, true, 10],  // Press A
+                    [0, 3, false, 10], // Release A
+                    [0, 5, false, 10], // Release LShift
+                    [0, 1, true, 10],  // Stop recording
+                    [0, 1, false, 10],
+                    [0, 2, true, 10],  // Play
+                    [0, 2, false, 50],
+                ],
+                expected_reports: [
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    [KC_LSHIFT, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    // Play
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    [KC_LSHIFT, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_dynamic_macro_drops_held_key() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],  // Start recording
+                    [0, 0, false, 10],
+                    [0, 4, true, 10],  // Press B
+                    [0, 4, false, 10], // Release B
+                    [0, 3, true, 10],  // Press A
+                    [0, 1, true, 10],  // Stop recording while A is held
+                    [0, 1, false, 10],
+                    [0, 3, false, 10], // Release A
+                    [0, 2, true, 10],  // Play
+                    [0, 2, false, 50],
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(B), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    // Play, the press of A is dropped since its release is not recorded
+                    [0, [kc_to_u8!(B), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+    }
+}
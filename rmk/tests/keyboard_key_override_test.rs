@@ -0,0 +1,107 @@
+pub mod common;
+
+use heapless::Vec;
+use rmk::config::{BehaviorConfig, KeyOverridesConfig};
+use rmk::key_override::KeyOverride;
+use rmk::types::keycode::KeyCode;
+use rmk::types::modifier::ModifierCombination;
+
+mod key_override_test {
+    use std::cell::RefCell;
+
+    use rmk::k;
+    use rmk::keyboard::Keyboard;
+    use rmk::keymap::KeyMap;
+    use rmk::types::action::KeyAction;
+    use rusty_fork::rusty_fork_test;
+
+    use super::*;
+    use crate::common::{KC_LSHIFT, wrap_keymap};
+
+    const KEYMAP: [[[KeyAction; 3]; 1]; 1] = [[[k!(Backspace), k!(Dot), k!(LShift)]]];
+
+    fn create_test_keyboard() -> Keyboard<'static, 1, 3, 1> {
+        let shift = ModifierCombination::LSHIFT | ModifierCombination::RSHIFT;
+        let overrides = Vec::from_iter([
+            // Shift + Backspace -> Delete
+            KeyOverride::new(
+                KeyCode::Backspace,
+                shift,
+                ModifierCombination::new(),
+                KeyCode::Delete,
+                ModifierCombination::new(),
+            ),
+            // Shift + Dot -> Shift + Comma, the replacement is a trigger of the next override
+            KeyOverride::new(
+                KeyCode::Dot,
+                shift,
+                ModifierCombination::new(),
+                KeyCode::Comma,
+                ModifierCombination::LSHIFT,
+            ),
+            KeyOverride::new(
+                KeyCode::Comma,
+                shift,
+                ModifierCombination::new(),
+                KeyCode::Dot,
+                ModifierCombination::new(),
+            ),
+        ]);
+        static BEHAVIOR_CONFIG: static_cell::StaticCell<BehaviorConfig> = static_cell::StaticCell::new();
+        let behavior_config = BEHAVIOR_CONFIG.init(BehaviorConfig {
+            key_override: KeyOverridesConfig { overrides },
+            ..BehaviorConfig::default()
+        });
+        let keymap: &RefCell<KeyMap<1, 3, 1>> = wrap_keymap(KEYMAP, behavior_config);
+        Keyboard::new(keymap)
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_key_override_suppresses_trigger_modifiers() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],  // Press Backspace
+                    [0, 0, false, 10], // Release Backspace
+                    [0, 2, true, 10],  // Press LShift
+                    [0, 0, true, 10],  // Press Backspace
+                    [0, 0, false, 10], // Release Backspace
+                    [0, 2, false, 10], // Release LShift
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(Backspace), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    // Shift is released while Delete is sent
+                    [0, [kc_to_u8!(Delete), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    // Shift is restored after the override
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_key_override_does_not_loop() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 2, true, 10],  // Press LShift
+                    [0, 1, true, 10],  // Press Dot
+                    [0, 1, false, 10], // Release Dot
+                    [0, 2, false, 10], // Release LShift
+                ],
+                expected_reports: [
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    // The replacement is not overridden again
+                    [KC_LSHIFT, [kc_to_u8!(Comma), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+    }
+}
@@ -0,0 +1,77 @@
+pub mod common;
+
+use rmk::config::{BehaviorConfig, TapHoldConfig};
+use rmk::keyboard::Keyboard;
+use rmk::morse::MorseMode;
+use rusty_fork::rusty_fork_test;
+
+use crate::common::morse::create_simple_morse_keyboard;
+
+fn create_layer_tap_keyboard(layer_tap_mode: MorseMode) -> Keyboard<'static, 1, 5, 2> {
+    create_simple_morse_keyboard(BehaviorConfig {
+        tap_hold: TapHoldConfig {
+            enable_hrm: false,
+            mode: MorseMode::Normal,
+            layer_tap_mode: Some(layer_tap_mode),
+            unilateral_tap: false,
+            ..TapHoldConfig::default()
+        },
+        ..BehaviorConfig::default()
+    })
+}
+
+rusty_fork_test! {
+    #[test]
+    fn test_layer_tap_permissive_hold_nested_tap() {
+        // The global mode is `Normal`, but layer-tap keys use permissive hold
+        key_sequence_test! {
+            keyboard: create_layer_tap_keyboard(MorseMode::PermissiveHold),
+            sequence: [
+                [0, 3, true, 10], // Press lt!(1, D)
+                [0, 0, true, 10], // Press A
+                [0, 0, false, 10], // Release A, within the tap-hold window
+                [0, 3, false, 10], // Release lt!(1, D)
+            ],
+            expected_reports: [
+                [0, [kc_to_u8!(Kp1), 0, 0, 0, 0, 0]], // Press Kp1
+                [0, [0, 0, 0, 0, 0, 0]], // Release Kp1
+            ]
+        };
+    }
+
+    #[test]
+    fn test_layer_tap_permissive_hold_rolling() {
+        key_sequence_test! {
+            keyboard: create_layer_tap_keyboard(MorseMode::PermissiveHold),
+            sequence: [
+                [0, 3, true, 10], // Press lt!(1, D)
+                [0, 0, true, 10], // Press A
+                [0, 3, false, 10], // Release lt!(1, D)
+                [0, 0, false, 10], // Release A
+            ],
+            expected_reports: [
+                [0, [kc_to_u8!(D), 0, 0, 0, 0, 0]], // Press D
+                [0, [kc_to_u8!(D), kc_to_u8!(A), 0, 0, 0, 0]], // Press A
+                [0, [0, kc_to_u8!(A), 0, 0, 0, 0]], // Release D
+                [0, [0, 0, 0, 0, 0, 0]], // Release A
+            ]
+        };
+    }
+
+    #[test]
+    fn test_layer_tap_hold_on_other_press_rolling() {
+        key_sequence_test! {
+            keyboard: create_layer_tap_keyboard(MorseMode::HoldOnOtherPress),
+            sequence: [
+                [0, 3, true, 10], // Press lt!(1, D)
+                [0, 0, true, 10], // Press A, layer 1 is activated immediately
+                [0, 3, false, 10], // Release lt!(1, D)
+                [0, 0, false, 10], // Release A
+            ],
+            expected_reports: [
+                [0, [kc_to_u8!(Kp1), 0, 0, 0, 0, 0]], // Press Kp1
+                [0, [0, 0, 0, 0, 0, 0]], // Release Kp1
+            ]
+        };
+    }
+}
@@ -0,0 +1,98 @@
+pub mod common;
+
+mod layer_tap_toggle_test {
+    use std::cell::RefCell;
+
+    use rmk::config::{BehaviorConfig, TapHoldConfig};
+    use rmk::keyboard::Keyboard;
+    use rmk::keymap::KeyMap;
+    use rmk::types::action::KeyAction;
+    use rmk::{a, k, tg, tt};
+    use rusty_fork::rusty_fork_test;
+
+    use crate::common::wrap_keymap;
+
+    const KEYMAP: [[[KeyAction; 3]; 1]; 2] = [[[tt!(1), k!(A), tg!(1)]], [[a!(Transparent), k!(B), a!(Transparent)]]];
+
+    fn create_test_keyboard() -> Keyboard<'static, 1, 3, 2> {
+        static BEHAVIOR_CONFIG: static_cell::StaticCell<BehaviorConfig> = static_cell::StaticCell::new();
+        let behavior_config = BEHAVIOR_CONFIG.init(BehaviorConfig {
+            tap_hold: TapHoldConfig {
+                tap_toggle_count: 2,
+                ..TapHoldConfig::default()
+            },
+            ..BehaviorConfig::default()
+        });
+        let keymap: &RefCell<KeyMap<1, 3, 2>> = wrap_keymap(KEYMAP, behavior_config);
+        Keyboard::new(keymap)
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_layer_tap_toggle_hold() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],  // Press TT(1)
+                    [0, 1, true, 10],  // Press B on layer 1
+                    [0, 1, false, 10], // Release B
+                    [0, 0, false, 10], // Release TT(1)
+                    [0, 1, true, 10],  // Press A on layer 0
+                    [0, 1, false, 10], // Release A
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(B), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_layer_tap_toggle_taps() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],  // Tap TT(1) twice, toggle layer 1 on
+                    [0, 0, false, 10],
+                    [0, 0, true, 10],
+                    [0, 0, false, 10],
+                    [0, 1, true, 10],  // Press B on layer 1
+                    [0, 1, false, 10], // Release B
+                    [0, 0, true, 10],  // Tap TT(1) twice, toggle layer 1 off
+                    [0, 0, false, 10],
+                    [0, 0, true, 10],
+                    [0, 0, false, 10],
+                    [0, 1, true, 10],  // Press A on layer 0
+                    [0, 1, false, 10], // Release A
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(B), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_layer_tap_toggle_keeps_toggled_layer() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 2, true, 10],  // Toggle layer 1 on by TG(1)
+                    [0, 2, false, 10],
+                    [0, 0, true, 10],  // Tap TT(1) once
+                    [0, 0, false, 10],
+                    [0, 1, true, 300], // Press B, layer 1 is still active
+                    [0, 1, false, 10], // Release B
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(B), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+    }
+}
@@ -0,0 +1,104 @@
+pub mod common;
+
+use embassy_time::Duration;
+use heapless::Vec;
+use rmk::config::{BehaviorConfig, LeadersConfig};
+use rmk::leader::Leader;
+use rmk::types::keycode::KeyCode;
+
+mod leader_test {
+    use std::cell::RefCell;
+
+    use rmk::k;
+    use rmk::keyboard::Keyboard;
+    use rmk::keymap::KeyMap;
+    use rmk::types::action::KeyAction;
+    use rusty_fork::rusty_fork_test;
+
+    use super::*;
+    use crate::common::wrap_keymap;
+
+    const KEYMAP: [[[KeyAction; 5]; 1]; 1] = [[[k!(Leader), k!(W), k!(M), k!(A), k!(X)]]];
+
+    fn create_test_keyboard() -> Keyboard<'static, 1, 5, 1> {
+        static BEHAVIOR_CONFIG: static_cell::StaticCell<BehaviorConfig> = static_cell::StaticCell::new();
+        let behavior_config = BEHAVIOR_CONFIG.init(BehaviorConfig {
+            leader: LeadersConfig {
+                leaders: Vec::from_slice(&[
+                    Leader::new([KeyCode::W, KeyCode::M], k!(C)),
+                    Leader::new([KeyCode::W], k!(E)),
+                ])
+                .unwrap(),
+                timeout: Duration::from_millis(200),
+            },
+            ..BehaviorConfig::default()
+        });
+        let keymap: &RefCell<KeyMap<1, 5, 1>> = wrap_keymap(KEYMAP, behavior_config);
+        Keyboard::new(keymap)
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_leader_sequence() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],   // Press Leader
+                    [0, 0, false, 10],  // Release Leader
+                    [0, 1, true, 10],   // Press W
+                    [0, 1, false, 10],  // Release W
+                    [0, 2, true, 10],   // Press M, sequence completed
+                    [0, 2, false, 10],  // Release M
+                    [0, 3, true, 10],   // Press A
+                    [0, 3, false, 10],  // Release A
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(C), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_leader_ambiguous_prefix_timeout() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],   // Press Leader
+                    [0, 0, false, 10],  // Release Leader
+                    [0, 1, true, 10],   // Press W, wait for a possible M
+                    [0, 1, false, 10],  // Release W
+                    [0, 3, true, 300],  // Press A after timeout
+                    [0, 3, false, 10],  // Release A
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(E), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_leader_no_match() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],   // Press Leader
+                    [0, 0, false, 10],  // Release Leader
+                    [0, 4, true, 10],   // Press X, no sequence matched
+                    [0, 4, false, 10],  // Release X
+                    [0, 3, true, 10],   // Press A
+                    [0, 3, false, 10],  // Release A
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+    }
+}
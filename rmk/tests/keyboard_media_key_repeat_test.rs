@@ -0,0 +1,95 @@
+pub mod common;
+
+use embassy_time::Duration;
+use rmk::config::{BehaviorConfig, MediaKeyRepeatConfig};
+use rmk::types::keycode::ConsumerKey;
+
+mod media_key_repeat_test {
+    use std::cell::RefCell;
+
+    use rmk::k;
+    use rmk::keyboard::Keyboard;
+    use rmk::keymap::KeyMap;
+    use rmk::types::action::KeyAction;
+    use rusty_fork::rusty_fork_test;
+
+    use super::*;
+    use crate::common::wrap_keymap;
+
+    const KEYMAP: [[[KeyAction; 2]; 1]; 1] = [[[k!(AudioVolDown), k!(A)]]];
+
+    const VOLUME_DOWN: u16 = ConsumerKey::VolumeDecrement as u16;
+
+    fn create_test_keyboard(interval: Duration) -> Keyboard<'static, 1, 2, 1> {
+        static BEHAVIOR_CONFIG: static_cell::StaticCell<BehaviorConfig> = static_cell::StaticCell::new();
+        let behavior_config = BEHAVIOR_CONFIG.init(BehaviorConfig {
+            media_key_repeat: MediaKeyRepeatConfig {
+                delay: Duration::from_millis(300),
+                interval,
+            },
+            ..BehaviorConfig::default()
+        });
+        let keymap: &RefCell<KeyMap<1, 2, 1>> = wrap_keymap(KEYMAP, behavior_config);
+        Keyboard::new(keymap)
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_held_media_key_is_repeated() {
+            virtual_key_sequence_test! {
+                keyboard: create_test_keyboard(Duration::from_millis(100)),
+                sequence: [
+                    [0, 0, true, 10],   // Press AudioVolDown
+                    [0, 0, false, 550], // Release after 3 repeats, at 300ms, 400ms and 500ms
+                ],
+                expected_reports: [],
+                expected_media_reports: [
+                    VOLUME_DOWN,
+                    0,
+                    VOLUME_DOWN,
+                    0,
+                    VOLUME_DOWN,
+                    0,
+                    VOLUME_DOWN,
+                    0,
+                ]
+            };
+        }
+
+        #[test]
+        fn test_media_key_released_before_delay() {
+            virtual_key_sequence_test! {
+                keyboard: create_test_keyboard(Duration::from_millis(100)),
+                sequence: [
+                    [0, 0, true, 10],   // Press AudioVolDown
+                    [0, 0, false, 250], // Release before the first repeat
+                    [0, 1, true, 200],  // Press A after the repeat delay
+                    [0, 1, false, 10],  // Release A
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ],
+                expected_media_reports: [VOLUME_DOWN, 0]
+            };
+        }
+
+        #[test]
+        fn test_media_key_repeat_disabled() {
+            virtual_key_sequence_test! {
+                keyboard: create_test_keyboard(Duration::from_millis(0)),
+                sequence: [
+                    [0, 0, true, 10],   // Press AudioVolDown
+                    [0, 0, false, 550], // Release, the host repeats the key instead
+                    [0, 1, true, 10],   // Press A
+                    [0, 1, false, 10],  // Release A
+                ],
+                expected_reports: [
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ],
+                expected_media_reports: [VOLUME_DOWN, 0]
+            };
+        }
+    }
+}
@@ -0,0 +1,109 @@
+/// Test cases for chordal hold
+///
+/// The tap-hold key is resolved as tap when a key on the same hand is pressed,
+/// keys on the opposite hand or without a hand are decided by the tap-hold mode.
+pub mod common;
+
+use rmk::config::{BehaviorConfig, TapHoldConfig};
+use rmk::event::Hand;
+use rmk::keyboard::Keyboard;
+use rmk::morse::MorseMode;
+use rusty_fork::rusty_fork_test;
+
+use crate::common::KC_LSHIFT;
+use crate::common::morse::create_simple_morse_keyboard;
+
+const L: Option<Hand> = Some(Hand::Left);
+const R: Option<Hand> = Some(Hand::Right);
+
+/// Keymap: A, mt!(B, LShift), mt!(C, LGui), lt!(1, D), td!(0)
+fn create_chordal_hold_keyboard(hands: &'static [&'static [Option<Hand>]]) -> Keyboard<'static, 1, 5, 2> {
+    create_simple_morse_keyboard(BehaviorConfig {
+        tap_hold: TapHoldConfig {
+            enable_hrm: false,
+            mode: MorseMode::HoldOnOtherPress,
+            chordal_hold: true,
+            hands,
+            ..TapHoldConfig::default()
+        },
+        ..BehaviorConfig::default()
+    })
+}
+
+rusty_fork_test! {
+    #[test]
+    fn test_same_hand_tap() {
+        key_sequence_test! {
+            keyboard: create_chordal_hold_keyboard(&[&[L, L, R, R, None]]),
+            sequence: [
+                [0, 1, true, 10], // Press mt!(B, LShift)
+                [0, 0, true, 10], // Press A on the same hand -> tap
+                [0, 0, false, 10], // Release A
+                [0, 1, false, 10], // Release mt!(B, LShift)
+            ],
+            expected_reports: [
+                [0, [kc_to_u8!(B), 0, 0, 0, 0, 0]], // Chordal hold tap
+                [0, [kc_to_u8!(B), kc_to_u8!(A), 0, 0, 0, 0]], // Press A
+                [0, [kc_to_u8!(B), 0, 0, 0, 0, 0]], // Release A
+                [0, [0, 0, 0, 0, 0, 0]], // Release mt!(B, LShift)
+            ]
+        };
+    }
+
+    #[test]
+    fn test_opposite_hand_hold() {
+        key_sequence_test! {
+            keyboard: create_chordal_hold_keyboard(&[&[R, L, L, R, None]]),
+            sequence: [
+                [0, 1, true, 10], // Press mt!(B, LShift)
+                [0, 0, true, 10], // Press A on the opposite hand -> hold on other press
+                [0, 0, false, 10], // Release A
+                [0, 1, false, 10], // Release mt!(B, LShift)
+            ],
+            expected_reports: [
+                [KC_LSHIFT, [0, 0, 0, 0, 0, 0]], // Hold LShift
+                [KC_LSHIFT, [kc_to_u8!(A), 0, 0, 0, 0, 0]], // Press A
+                [KC_LSHIFT, [0, 0, 0, 0, 0, 0]], // Release A
+                [0, [0, 0, 0, 0, 0, 0]], // Release mt!(B, LShift)
+            ]
+        };
+    }
+
+    #[test]
+    fn test_no_hand_fallback() {
+        key_sequence_test! {
+            keyboard: create_chordal_hold_keyboard(&[&[None, L, L, R, None]]),
+            sequence: [
+                [0, 1, true, 10], // Press mt!(B, LShift)
+                [0, 0, true, 10], // Press A without a hand -> hold on other press
+                [0, 0, false, 10], // Release A
+                [0, 1, false, 10], // Release mt!(B, LShift)
+            ],
+            expected_reports: [
+                [KC_LSHIFT, [0, 0, 0, 0, 0, 0]], // Hold LShift
+                [KC_LSHIFT, [kc_to_u8!(A), 0, 0, 0, 0, 0]], // Press A
+                [KC_LSHIFT, [0, 0, 0, 0, 0, 0]], // Release A
+                [0, [0, 0, 0, 0, 0, 0]], // Release mt!(B, LShift)
+            ]
+        };
+    }
+
+    #[test]
+    fn test_same_hand_tap_hold_keys() {
+        key_sequence_test! {
+            keyboard: create_chordal_hold_keyboard(&[&[R, L, L, R, None]]),
+            sequence: [
+                [0, 1, true, 10], // Press mt!(B, LShift)
+                [0, 2, true, 10], // Press mt!(C, LGui) on the same hand, not affected by chordal hold
+                [0, 2, false, 10], // Release mt!(C, LGui)
+                [0, 1, false, 10], // Release mt!(B, LShift)
+            ],
+            expected_reports: [
+                [KC_LSHIFT, [0, 0, 0, 0, 0, 0]], // Hold LShift
+                [KC_LSHIFT, [kc_to_u8!(C), 0, 0, 0, 0, 0]], // Tap C
+                [KC_LSHIFT, [0, 0, 0, 0, 0, 0]], // Release C
+                [0, [0, 0, 0, 0, 0, 0]], // Release mt!(B, LShift)
+            ]
+        };
+    }
+}
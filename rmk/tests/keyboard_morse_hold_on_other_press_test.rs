@@ -44,6 +44,7 @@ fn create_hold_on_other_key_press_keyboard_with_combo() -> Keyboard<'static, 1,
                 Combo::new([combo_key, combo_key_2, combo_key_3], k!(Z), None),
             ]),
             timeout: Duration::from_millis(50),
+            ..Default::default()
         },
         ..BehaviorConfig::default()
     })
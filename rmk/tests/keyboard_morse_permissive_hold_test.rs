@@ -44,6 +44,7 @@ fn create_permissive_hold_keyboard_with_combo() -> Keyboard<'static, 1, 5, 2> {
                 Combo::new([combo_key, combo_key_2, combo_key_3], k!(Z), None),
             ]),
             timeout: Duration::from_millis(50),
+            ..Default::default()
         },
         ..BehaviorConfig::default()
     })
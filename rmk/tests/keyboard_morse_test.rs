@@ -2,7 +2,7 @@ pub mod common;
 
 use embassy_time::Duration;
 use rmk::combo::Combo;
-use rmk::config::{BehaviorConfig, CombosConfig};
+use rmk::config::{BehaviorConfig, CombosConfig, PerKeyTimeout, TapHoldConfig};
 use rmk::k;
 use rmk::types::action::{Action, KeyAction};
 use rmk::types::keycode::KeyCode;
@@ -44,6 +44,90 @@ rusty_fork_test! {
         };
     }
 
+    #[test]
+    fn test_morse_per_key_timeout() {
+        key_sequence_test! {
+            keyboard: create_simple_morse_keyboard(BehaviorConfig {
+                tap_hold: TapHoldConfig {
+                    per_key_timeouts: heapless::Vec::from_slice(&[PerKeyTimeout::new(0, 1, Duration::from_millis(500))])
+                        .unwrap(),
+                    ..TapHoldConfig::default()
+                },
+                ..BehaviorConfig::default()
+            }),
+            sequence: [
+                [0, 1, true, 10],  // Press mt!(B, LShift)
+                [0, 1, false, 300], // Release B after the default timeout, but before the per-key timeout
+                [0, 2, true, 10],  // Press mt!(C, LGui), which has no override
+                [0, 2, false, 300], // Release C after the default timeout
+            ],
+            expected_reports: [
+                [0, [kc_to_u8!(B), 0, 0, 0, 0, 0]], // Press B
+                [0, [0, 0, 0, 0, 0, 0]], // Release B
+                [KC_LGUI, [0, 0, 0, 0, 0, 0]], // Hold LGui
+                [0, [0, 0, 0, 0, 0, 0]], // All released
+            ]
+        };
+    }
+
+    #[test]
+    fn test_morse_quick_tap() {
+        key_sequence_test! {
+            keyboard: create_simple_morse_keyboard(BehaviorConfig {
+                tap_hold: TapHoldConfig {
+                    quick_tap_term: Duration::from_millis(150),
+                    ..TapHoldConfig::default()
+                },
+                ..BehaviorConfig::default()
+            }),
+            sequence: [
+                [0, 1, true, 10],  // Press mt!(B, LShift)
+                [0, 1, false, 50], // Release B, it's a tap
+                [0, 1, true, 50],  // Press B again within the quick tap term
+                [0, 1, false, 500], // Release B after hold timeout, B is kept pressed
+                [0, 1, true, 200],  // Press B after the quick tap term
+                [0, 1, false, 300], // Release B after hold timeout
+            ],
+            expected_reports: [
+                [0, [kc_to_u8!(B), 0, 0, 0, 0, 0]], // Tap B
+                [0, [0, 0, 0, 0, 0, 0]],
+                [0, [kc_to_u8!(B), 0, 0, 0, 0, 0]], // Quick tap, hold B
+                [0, [0, 0, 0, 0, 0, 0]],
+                [KC_LSHIFT, [0, 0, 0, 0, 0, 0]], // Hold LShift
+                [0, [0, 0, 0, 0, 0, 0]],
+            ]
+        };
+    }
+
+    #[test]
+    fn test_morse_quick_tap_interrupted() {
+        key_sequence_test! {
+            keyboard: create_simple_morse_keyboard(BehaviorConfig {
+                tap_hold: TapHoldConfig {
+                    quick_tap_term: Duration::from_millis(150),
+                    ..TapHoldConfig::default()
+                },
+                ..BehaviorConfig::default()
+            }),
+            sequence: [
+                [0, 1, true, 10],  // Press mt!(B, LShift)
+                [0, 1, false, 50], // Release B, it's a tap
+                [0, 0, true, 10],  // Press A
+                [0, 0, false, 10], // Release A
+                [0, 1, true, 10],  // Press B within the quick tap term, but another key is pressed in between
+                [0, 1, false, 300], // Release B after hold timeout
+            ],
+            expected_reports: [
+                [0, [kc_to_u8!(B), 0, 0, 0, 0, 0]], // Tap B
+                [0, [0, 0, 0, 0, 0, 0]],
+                [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]], // Tap A
+                [0, [0, 0, 0, 0, 0, 0]],
+                [KC_LSHIFT, [0, 0, 0, 0, 0, 0]], // Hold LShift
+                [0, [0, 0, 0, 0, 0, 0]],
+            ]
+        };
+    }
+
     #[test]
     fn test_morse_mt_1() {
         key_sequence_test! {
@@ -735,6 +819,7 @@ rusty_fork_test! {
                             )
                         ]),
                         timeout: Duration::from_millis(50),
+                        ..Default::default()
                     },
                     ..BehaviorConfig::default()
                 }),
@@ -767,6 +852,7 @@ rusty_fork_test! {
                             )
                         ]),
                         timeout: Duration::from_millis(50),
+                        ..Default::default()
                     },
                     ..BehaviorConfig::default()
                 }),
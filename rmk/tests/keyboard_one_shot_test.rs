@@ -154,6 +154,33 @@ mod one_shot_test {
             };
         }
 
+        #[test]
+        fn test_osm_double_tap_lock() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],   // Press OSM LShift
+                    [0, 0, false, 10],  // Release OSM LShift
+                    [0, 0, true, 10],   // Press OSM LShift again, lock it
+                    [0, 0, false, 10],  // Release OSM LShift
+                    [0, 2, true, 10],   // Press A
+                    [0, 2, false, 10],  // Release A
+                    [0, 3, true, 10],   // Press B, still shifted
+                    [0, 3, false, 10],  // Release B
+                    [0, 0, true, 10],   // Press OSM LShift, unlock it
+                    [0, 0, false, 10],  // Release OSM LShift
+                ],
+                expected_reports: [
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]], // LShift locked
+                    [KC_LSHIFT, [kc_to_u8!(A), 0, 0, 0, 0, 0]], // A with LShift
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]], // Release A
+                    [KC_LSHIFT, [kc_to_u8!(B), 0, 0, 0, 0, 0]], // B with LShift
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]], // Release B
+                    [0, [0, 0, 0, 0, 0, 0]], // LShift unlocked
+                ]
+            };
+        }
+
         // OSL Tests
         #[test]
         fn test_osl_basic_single_behavior() {
@@ -0,0 +1,87 @@
+pub mod common;
+
+mod space_cadet_test {
+    use std::cell::RefCell;
+
+    use rmk::config::BehaviorConfig;
+    use rmk::k;
+    use rmk::keyboard::Keyboard;
+    use rmk::keymap::KeyMap;
+    use rmk::types::action::KeyAction;
+    use rusty_fork::rusty_fork_test;
+
+    use crate::common::{KC_LSHIFT, wrap_keymap};
+
+    const KC_RSHIFT: u8 = 1 << 5;
+
+    const KEYMAP: [[[KeyAction; 3]; 1]; 1] = [[[
+        k!(SpaceCadetLShiftParenthesisOpen),
+        k!(SpaceCadetRShiftParenthesisClose),
+        k!(A),
+    ]]];
+
+    fn create_test_keyboard() -> Keyboard<'static, 1, 3, 1> {
+        static BEHAVIOR_CONFIG: static_cell::StaticCell<BehaviorConfig> = static_cell::StaticCell::new();
+        let behavior_config = BEHAVIOR_CONFIG.init(BehaviorConfig::default());
+        let keymap: &RefCell<KeyMap<1, 3, 1>> = wrap_keymap(KEYMAP, behavior_config);
+        Keyboard::new(keymap)
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_space_cadet_tap() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],  // Press LSPO
+                    [0, 0, false, 50], // Release LSPO
+                    [0, 1, true, 10],  // Press RSPC
+                    [0, 1, false, 50], // Release RSPC
+                ],
+                expected_reports: [
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    [KC_LSHIFT, [kc_to_u8!(Kc9), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [KC_RSHIFT, [0, 0, 0, 0, 0, 0]],
+                    [KC_RSHIFT, [kc_to_u8!(Kc0), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_space_cadet_rapid_shift_letter() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],  // Press LSPO
+                    [0, 2, true, 10],  // Press A
+                    [0, 0, false, 10], // Release LSPO before A
+                    [0, 2, false, 10], // Release A
+                ],
+                expected_reports: [
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    // A capital letter, no paren
+                    [KC_LSHIFT, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_space_cadet_hold_timeout() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(),
+                sequence: [
+                    [0, 0, true, 10],   // Press LSPO
+                    [0, 0, false, 300], // Release LSPO after the tap-hold timeout
+                ],
+                expected_reports: [
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+    }
+}
@@ -0,0 +1,118 @@
+pub mod common;
+
+use embassy_time::Duration;
+use rmk::config::{BehaviorConfig, UnicodeConfig, UnicodeMode};
+
+mod unicode_test {
+    use std::cell::RefCell;
+
+    use rmk::keyboard::Keyboard;
+    use rmk::keymap::KeyMap;
+    use rmk::types::action::KeyAction;
+    use rmk::{k, uc};
+    use rusty_fork::rusty_fork_test;
+
+    use super::*;
+    use crate::common::{KC_LALT, KC_LCTRL, KC_LSHIFT, wrap_keymap};
+
+    const KEYMAP: [[[KeyAction; 3]; 1]; 1] = [[[uc!(0xE9), uc!(0x1F600), k!(LShift)]]];
+
+    fn create_test_keyboard(mode: UnicodeMode) -> Keyboard<'static, 1, 3, 1> {
+        static BEHAVIOR_CONFIG: static_cell::StaticCell<BehaviorConfig> = static_cell::StaticCell::new();
+        let behavior_config = BEHAVIOR_CONFIG.init(BehaviorConfig {
+            unicode: UnicodeConfig {
+                mode,
+                key_delay: Duration::from_millis(1),
+            },
+            ..BehaviorConfig::default()
+        });
+        let keymap: &RefCell<KeyMap<1, 3, 1>> = wrap_keymap(KEYMAP, behavior_config);
+        Keyboard::new(keymap)
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn test_unicode_linux() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(UnicodeMode::Linux),
+                sequence: [
+                    [0, 0, true, 10],  // Press é
+                    [0, 0, false, 10], // Release é
+                ],
+                expected_reports: [
+                    [KC_LCTRL | KC_LSHIFT, [kc_to_u8!(U), 0, 0, 0, 0, 0]],
+                    [KC_LCTRL | KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(E), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(Kc9), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(Space), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]], // Restore the held keys
+                ]
+            };
+        }
+
+        #[test]
+        fn test_unicode_restores_held_modifiers() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(UnicodeMode::Linux),
+                sequence: [
+                    [0, 2, true, 10],  // Press LShift
+                    [0, 0, true, 10],  // Press é
+                    [0, 0, false, 10], // Release é
+                    [0, 2, false, 10], // Release LShift
+                ],
+                expected_reports: [
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    // Shift is released during the input sequence
+                    [KC_LCTRL | KC_LSHIFT, [kc_to_u8!(U), 0, 0, 0, 0, 0]],
+                    [KC_LCTRL | KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(E), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(Kc9), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [kc_to_u8!(Space), 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                ]
+            };
+        }
+
+        #[test]
+        fn test_unicode_macos_surrogate_pair() {
+            key_sequence_test! {
+                keyboard: create_test_keyboard(UnicodeMode::MacOs),
+                sequence: [
+                    [0, 1, true, 10],  // Press 😀
+                    [0, 1, false, 10], // Release 😀
+                ],
+                expected_reports: [
+                    [KC_LALT, [0, 0, 0, 0, 0, 0]],
+                    // U+1F600 is typed as D83D DE00
+                    [KC_LALT, [kc_to_u8!(D), 0, 0, 0, 0, 0]],
+                    [KC_LALT, [0, 0, 0, 0, 0, 0]],
+                    [KC_LALT, [kc_to_u8!(Kc8), 0, 0, 0, 0, 0]],
+                    [KC_LALT, [0, 0, 0, 0, 0, 0]],
+                    [KC_LALT, [kc_to_u8!(Kc3), 0, 0, 0, 0, 0]],
+                    [KC_LALT, [0, 0, 0, 0, 0, 0]],
+                    [KC_LALT, [kc_to_u8!(D), 0, 0, 0, 0, 0]],
+                    [KC_LALT, [0, 0, 0, 0, 0, 0]],
+                    [KC_LALT, [kc_to_u8!(D), 0, 0, 0, 0, 0]],
+                    [KC_LALT, [0, 0, 0, 0, 0, 0]],
+                    [KC_LALT, [kc_to_u8!(E), 0, 0, 0, 0, 0]],
+                    [KC_LALT, [0, 0, 0, 0, 0, 0]],
+                    [KC_LALT, [kc_to_u8!(Kc0), 0, 0, 0, 0, 0]],
+                    [KC_LALT, [0, 0, 0, 0, 0, 0]],
+                    [KC_LALT, [kc_to_u8!(Kc0), 0, 0, 0, 0, 0]],
+                    [KC_LALT, [0, 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]],
+                    [0, [0, 0, 0, 0, 0, 0]], // Restore the held keys
+                ]
+            };
+        }
+    }
+}
@@ -0,0 +1,38 @@
+/// Test cases of the timeouts at the millisecond boundary, which run with the virtual clock
+pub mod common;
+
+use rusty_fork::rusty_fork_test;
+
+use crate::common::{KC_LSHIFT, create_test_keyboard};
+
+rusty_fork_test! {
+    #[test]
+    fn test_tap_just_before_timeout() {
+        virtual_key_sequence_test! {
+            keyboard: create_test_keyboard(),
+            sequence: [
+                [2, 1, true, 10], // Press th!(A, LShift)
+                [2, 1, false, 249], // Release 1ms before the 250ms timeout
+            ],
+            expected_reports: [
+                [0, [kc_to_u8!(A), 0, 0, 0, 0, 0]],
+                [0, [0, 0, 0, 0, 0, 0]],
+            ]
+        };
+    }
+
+    #[test]
+    fn test_hold_just_after_timeout() {
+        virtual_key_sequence_test! {
+            keyboard: create_test_keyboard(),
+            sequence: [
+                [2, 1, true, 10], // Press th!(A, LShift)
+                [2, 1, false, 251], // Release 1ms after the 250ms timeout
+            ],
+            expected_reports: [
+                [KC_LSHIFT, [0, 0, 0, 0, 0, 0]],
+                [0, [0, 0, 0, 0, 0, 0]],
+            ]
+        };
+    }
+}
@@ -0,0 +1,58 @@
+/// Test cases of recording the reports with `MockHidWriter`
+pub mod common;
+
+use embassy_futures::block_on;
+use embassy_futures::join::join;
+use embassy_futures::select::select;
+use embassy_time::Timer;
+use rmk::channel::{KEY_EVENT_CHANNEL, KEYBOARD_REPORT_CHANNEL};
+use rmk::descriptor::KeyboardReport;
+use rmk::event::KeyboardEvent;
+use rmk::hid::{MockHidWriter, RunnableHidWriter};
+use rmk::input_device::Runnable;
+use rusty_fork::rusty_fork_test;
+
+use crate::common::create_test_keyboard;
+
+fn report(modifier: u8, keycodes: [u8; 6]) -> KeyboardReport {
+    KeyboardReport {
+        modifier,
+        reserved: 0,
+        leds: 0,
+        keycodes,
+    }
+}
+
+rusty_fork_test! {
+    #[test]
+    fn test_mock_writer_records_tap_hold_tap() {
+        block_on(async {
+            KEY_EVENT_CHANNEL.clear();
+            KEYBOARD_REPORT_CHANNEL.clear();
+            let mut keyboard = create_test_keyboard();
+            let mut writer: MockHidWriter = MockHidWriter::new();
+
+            select(
+                join(keyboard.run(), writer.run_writer()),
+                async {
+                    // Tap th!(A, LShift) within the hold timeout
+                    KEY_EVENT_CHANNEL.send(KeyboardEvent::key(2, 1, true)).await;
+                    Timer::after_millis(50).await;
+                    KEY_EVENT_CHANNEL.send(KeyboardEvent::key(2, 1, false)).await;
+                    Timer::after_millis(100).await;
+                },
+            )
+            .await;
+
+            // The tap keycode is pressed and released, LShift is never reported
+            let reports: Vec<KeyboardReport> = writer.keyboard_reports().copied().collect();
+            assert_eq!(
+                reports,
+                [
+                    report(0, [kc_to_u8!(A), 0, 0, 0, 0, 0]),
+                    report(0, [0, 0, 0, 0, 0, 0]),
+                ]
+            );
+        });
+    }
+}